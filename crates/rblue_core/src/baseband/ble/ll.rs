@@ -1,3 +1,9 @@
+use alloc::vec::Vec;
+
+use crate::host::hci_cmd::{take_bytes, RBlueFromU8Array, RBlueToU8Array};
+use crate::host::LEAddressType2;
+use crate::{BDAddr, Error, Result};
+
 pub struct LL {
     // ...
 }
@@ -5,3 +11,167 @@ pub struct LL {
 impl LL {
     // ...
 }
+
+/// A device address and whether it is public or random, TxAdd/RxAdd on air
+pub type AirAddress = (LEAddressType2, BDAddr);
+
+/// Advertising channel PDUs exchanged between simulated controllers
+#[derive(Clone, Debug, PartialEq)]
+pub enum AdvPdu {
+    AdvInd {
+        adv_a: AirAddress,
+        data: Vec<u8>,
+    },
+    AdvDirectInd {
+        adv_a: AirAddress,
+        target_a: AirAddress,
+    },
+    AdvNonconnInd {
+        adv_a: AirAddress,
+        data: Vec<u8>,
+    },
+    ScanReq {
+        scan_a: AirAddress,
+        adv_a: AirAddress,
+    },
+    ScanRsp {
+        adv_a: AirAddress,
+        data: Vec<u8>,
+    },
+    /// Only the link parameters the host sees are carried, unit as in the HCI commands
+    ConnectInd {
+        init_a: AirAddress,
+        adv_a: AirAddress,
+        interval: u16,
+        latency: u16,
+        timeout: u16,
+    },
+    AdvScanInd {
+        adv_a: AirAddress,
+        data: Vec<u8>,
+    },
+}
+
+impl AdvPdu {
+    fn pdu_type(&self) -> u8 {
+        match self {
+            Self::AdvInd { .. } => 0x00,
+            Self::AdvDirectInd { .. } => 0x01,
+            Self::AdvNonconnInd { .. } => 0x02,
+            Self::ScanReq { .. } => 0x03,
+            Self::ScanRsp { .. } => 0x04,
+            Self::ConnectInd { .. } => 0x05,
+            Self::AdvScanInd { .. } => 0x06,
+        }
+    }
+
+    /// The sender address and the address the PDU is sent to, if any
+    fn addresses(&self) -> (AirAddress, Option<AirAddress>) {
+        match self {
+            Self::AdvInd { adv_a, .. }
+            | Self::AdvNonconnInd { adv_a, .. }
+            | Self::ScanRsp { adv_a, .. }
+            | Self::AdvScanInd { adv_a, .. } => (*adv_a, None),
+            Self::AdvDirectInd { adv_a, target_a } => (*adv_a, Some(*target_a)),
+            Self::ScanReq { scan_a, adv_a } => (*scan_a, Some(*adv_a)),
+            Self::ConnectInd { init_a, adv_a, .. } => (*init_a, Some(*adv_a)),
+        }
+    }
+}
+
+fn air_address(random: bool, address: &[u8]) -> Result<AirAddress> {
+    let address_type = if random {
+        LEAddressType2::RandomDeviceOrRandomIdentity
+    } else {
+        LEAddressType2::PublicDeviceOrPublicIdentity
+    };
+    let address = address.try_into().map_err(|_| Error::InvalidParameter)?;
+    Ok((address_type, address))
+}
+
+impl RBlueToU8Array for AdvPdu {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let (tx_a, rx_a) = self.addresses();
+        let mut header = self.pdu_type();
+        if tx_a.0 == LEAddressType2::RandomDeviceOrRandomIdentity {
+            header |= 0x40;
+        }
+        if rx_a.is_some_and(|rx_a| rx_a.0 == LEAddressType2::RandomDeviceOrRandomIdentity) {
+            header |= 0x80;
+        }
+        let mut payload = Vec::from(tx_a.1);
+        if let Some(rx_a) = rx_a {
+            payload.extend(rx_a.1);
+        }
+        match self {
+            Self::AdvInd { data, .. }
+            | Self::AdvNonconnInd { data, .. }
+            | Self::ScanRsp { data, .. }
+            | Self::AdvScanInd { data, .. } => payload.extend(data),
+            Self::ConnectInd {
+                interval,
+                latency,
+                timeout,
+                ..
+            } => {
+                payload.extend(interval.to_le_bytes());
+                payload.extend(latency.to_le_bytes());
+                payload.extend(timeout.to_le_bytes());
+            }
+            Self::AdvDirectInd { .. } | Self::ScanReq { .. } => {}
+        }
+        let mut array = alloc::vec![header, payload.len() as u8];
+        array.extend(payload);
+        array
+    }
+}
+
+impl RBlueFromU8Array for AdvPdu {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let header = take_bytes(bytes, 0, 2)?;
+        let payload = take_bytes(bytes, 2, header[1] as usize)?;
+        let tx_random = header[0] & 0x40 != 0;
+        let rx_random = header[0] & 0x80 != 0;
+        let tx_a = air_address(tx_random, take_bytes(payload, 0, 6)?)?;
+        let rx_a = || air_address(rx_random, take_bytes(payload, 6, 6)?);
+        let data = || payload[6..].to_vec();
+        let pdu = match header[0] & 0x0F {
+            0x00 => Self::AdvInd {
+                adv_a: tx_a,
+                data: data(),
+            },
+            0x01 => Self::AdvDirectInd {
+                adv_a: tx_a,
+                target_a: rx_a()?,
+            },
+            0x02 => Self::AdvNonconnInd {
+                adv_a: tx_a,
+                data: data(),
+            },
+            0x03 => Self::ScanReq {
+                scan_a: tx_a,
+                adv_a: rx_a()?,
+            },
+            0x04 => Self::ScanRsp {
+                adv_a: tx_a,
+                data: data(),
+            },
+            0x05 => {
+                let params = take_bytes(payload, 12, 6)?;
+                Self::ConnectInd {
+                    init_a: tx_a,
+                    adv_a: rx_a()?,
+                    interval: u16::from_le_bytes([params[0], params[1]]),
+                    latency: u16::from_le_bytes([params[2], params[3]]),
+                    timeout: u16::from_le_bytes([params[4], params[5]]),
+                }
+            }
+            0x06 => Self::AdvScanInd {
+                adv_a: tx_a,
+                data: data(),
+            },
+            _ => return Err(Error::InvalidParameter),
+        };
+        Ok(pdu)
+    }
+}
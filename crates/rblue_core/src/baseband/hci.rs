@@ -1,21 +1,37 @@
-use crate::baseband::Control;
+use crate::baseband::ble::ll::AirAddress;
 use crate::baseband::ControllerErrorCode;
+use crate::baseband::{Control, LELink};
+use crate::baseband::{LE_FILTER_ACCEPT_LIST_SIZE, LE_RESOLVING_LIST_SIZE};
+use crate::host::connection::is_valid_data_length;
+use crate::host::filter_list::ResolvingListEntry;
+use crate::host::{
+    EventMask, LEAddressType, LEAddressType2, LEEventMask, LEFeatures, LEPhy, LEPhys, Role,
+    LE_ADV_DATA_MAX_LEN,
+};
 
+use alloc::vec;
+use alloc::vec::Vec;
 use log::info;
 
-use crate::host::hci::HCIEvent;
+use crate::host::hci::{HCIEvent, LESubevent};
 use crate::host::hci_cmd::*;
+use crate::host::hci_event::*;
+use crate::host::l2cap::*;
+use crate::host::{ControllerAndBaseband, InformationalParam, LEController, LinkControl};
+use crate::Result;
 
 macro_rules! create_hci_cmd_table {
-    ($num:expr, $bit:expr, $handler:ident) => {
-        Some(HCICmdTable {
+    ($ocf:expr, $num:expr, $bit:expr, $handler:ident) => {
+        HCICmdTable {
+            ocf: $ocf as u16,
             flag: compute_hci_cmd_flag($num, $bit),
             handle: $handler,
-        })
+        }
     };
 }
 
 pub struct HCICmdTable {
+    pub ocf: u16,
     flag: u16,
     pub handle: fn(bb: &mut Control, opcode: u16, data: &[u8]),
 }
@@ -27,13 +43,17 @@ const fn compute_hci_cmd_flag(byten: u8, bit: u8) -> u16 {
 // byte0
 // const HCI_INQUIRY_BIT: u8 = 0x01;
 // const HCI_INQUIRY_CANCEL_BIT: u8 = 0x02;
+const HCI_DISCONNECT_BIT: u8 = 0x20;
 
 // byte5
 const HCI_SET_EVENT_MASK_BIT: u8 = 0x40;
 const HCI_RESET_BIT: u8 = 0x80;
 
+// byte7
+const HCI_WRITE_SCAN_ENABLE_BIT: u8 = 0x80;
+
 // byte14
-// const HCI_READ_LOCAL_VERSION_INFORMATION_BIT: u8 = 0x08;
+const HCI_READ_LOCAL_VERSION_INFORMATION_BIT: u8 = 0x08;
 const HCI_READ_LOCAL_SUPPORTED_COMMANDS_BIT: u8 = 0x10;
 const HCI_READ_LOCAL_SUPPORTED_FEATURES_BIT: u8 = 0x20;
 // const HCI_READ_LOCAL_EXTENDED_FEATURES_BIT: u8 = 0x40;
@@ -42,11 +62,14 @@ const HCI_READ_BUFFER_SIZE_BIT: u8 = 0x80;
 // byte15
 const HCI_READ_BD_ADDR_BIT: u8 = 0x02;
 
+// byte24
+const HCI_WRITE_LE_HOST_SUPPORT_BIT: u8 = 0x40;
+
 // byte25
 const HCI_LE_SET_EVENT_MASK_BIT: u8 = 0x01;
 const HCI_LE_READ_BUFFER_SIZE_BIT: u8 = 0x02;
 const HCI_LE_READ_LOCAL_SUPPORTED_FEATURES_BIT: u8 = 0x04;
-// const HCI_LE_SET_RANDOM_ADDRESS_BIT: u8 = 0x10;
+const HCI_LE_SET_RANDOM_ADDRESS_BIT: u8 = 0x10;
 const HCI_LE_SET_ADVERTISING_PARAMETERS_BIT: u8 = 0x20;
 const HCI_LE_READ_ADVERTISING_PHYSICAL_CHANNEL_TX_POWER_BIT: u8 = 0x40;
 const HCI_LE_SET_ADVERTISING_DATA_BIT: u8 = 0x80;
@@ -54,76 +77,269 @@ const HCI_LE_SET_ADVERTISING_DATA_BIT: u8 = 0x80;
 // byte26
 const HCI_LE_SET_SCAN_RESPONSE_DATA_BIT: u8 = 0x01;
 const HCI_LE_SET_ADVERTISING_ENABLE_BIT: u8 = 0x02;
-// const HCI_LE_SET_SCAN_PARAMETERS_BIT: u8 = 0x04;
-// const HCI_LE_SET_SCAN_ENABLE_BIT: u8 = 0x08;
-// const HCI_LE_CREATE_CONNECTION_BIT: u8 = 0x10;
-// const HCI_LE_CREATE_CONNECTION_CANCEL_BIT: u8 = 0x20;
-// const HCI_LE_READ_FILTER_ACCEPT_LIST_SIZE_BIT: u8 = 0x40;
-// const HCI_LE_CLEAR_FILTER_ACCEPT_LIST_BIT: u8 = 0x80;
-
-const TABLE_LINK_CONTROL: &[Option<HCICmdTable>] = &[];
-const TABLE_LINK_POLICY: &[Option<HCICmdTable>] = &[];
-const TABLE_CONTROLLER_AND_BASEBAND: &[Option<HCICmdTable>] = &[
-    create_hci_cmd_table!(5, HCI_SET_EVENT_MASK_BIT, set_event_mask),
-    None,
-    create_hci_cmd_table!(5, HCI_RESET_BIT, reset),
+const HCI_LE_SET_SCAN_PARAMETERS_BIT: u8 = 0x04;
+const HCI_LE_SET_SCAN_ENABLE_BIT: u8 = 0x08;
+const HCI_LE_CREATE_CONNECTION_BIT: u8 = 0x10;
+const HCI_LE_CREATE_CONNECTION_CANCEL_BIT: u8 = 0x20;
+const HCI_LE_READ_FILTER_ACCEPT_LIST_SIZE_BIT: u8 = 0x40;
+const HCI_LE_CLEAR_FILTER_ACCEPT_LIST_BIT: u8 = 0x80;
+
+// byte27
+const HCI_LE_ADD_DEVICE_TO_FILTER_ACCEPT_LIST_BIT: u8 = 0x01;
+const HCI_LE_REMOVE_DEVICE_FROM_FILTER_ACCEPT_LIST_BIT: u8 = 0x02;
+const HCI_LE_RAND_BIT: u8 = 0x80;
+
+// byte33
+const HCI_LE_SET_DATA_LENGTH_BIT: u8 = 0x40;
+const HCI_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH_BIT: u8 = 0x80;
+
+// byte34
+const HCI_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH_BIT: u8 = 0x01;
+const HCI_LE_ADD_DEVICE_TO_RESOLVING_LIST_BIT: u8 = 0x08;
+const HCI_LE_REMOVE_DEVICE_FROM_RESOLVING_LIST_BIT: u8 = 0x10;
+const HCI_LE_CLEAR_RESOLVING_LIST_BIT: u8 = 0x20;
+const HCI_LE_READ_RESOLVING_LIST_SIZE_BIT: u8 = 0x40;
+
+// byte35
+const HCI_LE_SET_ADDRESS_RESOLUTION_ENABLE_BIT: u8 = 0x02;
+const HCI_LE_READ_PHY_BIT: u8 = 0x10;
+const HCI_LE_SET_DEFAULT_PHY_BIT: u8 = 0x20;
+const HCI_LE_SET_PHY_BIT: u8 = 0x40;
+
+const HCI_CONNECTION_HANDLE_MAX: u16 = 0x0EFF;
+
+const TABLE_LINK_CONTROL: &[HCICmdTable] = &[create_hci_cmd_table!(
+    LinkControl::Disconnect,
+    0,
+    HCI_DISCONNECT_BIT,
+    disconnect
+)];
+const TABLE_LINK_POLICY: &[HCICmdTable] = &[];
+const TABLE_CONTROLLER_AND_BASEBAND: &[HCICmdTable] = &[
+    create_hci_cmd_table!(
+        ControllerAndBaseband::SetEventMask,
+        5,
+        HCI_SET_EVENT_MASK_BIT,
+        set_event_mask
+    ),
+    create_hci_cmd_table!(ControllerAndBaseband::Reset, 5, HCI_RESET_BIT, reset),
+    create_hci_cmd_table!(
+        ControllerAndBaseband::WriteScanEnable,
+        7,
+        HCI_WRITE_SCAN_ENABLE_BIT,
+        write_scan_enable
+    ),
+    create_hci_cmd_table!(
+        ControllerAndBaseband::WriteLEHostSupport,
+        24,
+        HCI_WRITE_LE_HOST_SUPPORT_BIT,
+        write_le_host_support
+    ),
 ];
-const TABLE_INFORMATIONAL_PARAM: &[Option<HCICmdTable>] = &[
-    None,
+const TABLE_INFORMATIONAL_PARAM: &[HCICmdTable] = &[
+    create_hci_cmd_table!(
+        InformationalParam::ReadLocalVersionInformation,
+        14,
+        HCI_READ_LOCAL_VERSION_INFORMATION_BIT,
+        read_local_version_information
+    ),
     create_hci_cmd_table!(
+        InformationalParam::ReadLocalSupportedCommands,
         14,
         HCI_READ_LOCAL_SUPPORTED_COMMANDS_BIT,
         read_local_supported_commands
     ),
     create_hci_cmd_table!(
+        InformationalParam::ReadLocalSupportedFeatures,
         14,
         HCI_READ_LOCAL_SUPPORTED_FEATURES_BIT,
         read_local_supported_features
     ),
-    None,
-    create_hci_cmd_table!(14, HCI_READ_BUFFER_SIZE_BIT, read_buffer_size),
-    None,
-    None,
-    None,
-    create_hci_cmd_table!(15, HCI_READ_BD_ADDR_BIT, read_bd_address),
+    create_hci_cmd_table!(
+        InformationalParam::ReadBufferSize,
+        14,
+        HCI_READ_BUFFER_SIZE_BIT,
+        read_buffer_size
+    ),
+    create_hci_cmd_table!(
+        InformationalParam::ReadBDAddr,
+        15,
+        HCI_READ_BD_ADDR_BIT,
+        read_bd_address
+    ),
 ];
-const TABLE_STATUS_PARAM: &[Option<HCICmdTable>] = &[];
-const TABLE_TESTING_COMMAND: &[Option<HCICmdTable>] = &[];
-const TABLE_REVERSE: &[Option<HCICmdTable>] = &[];
-const TABLE_LE_CONTROLLER: &[Option<HCICmdTable>] = &[
-    create_hci_cmd_table!(25, HCI_LE_SET_EVENT_MASK_BIT, le_set_event_mask),
-    create_hci_cmd_table!(25, HCI_LE_READ_BUFFER_SIZE_BIT, le_read_buffer_size),
+const TABLE_STATUS_PARAM: &[HCICmdTable] = &[];
+const TABLE_TESTING_COMMAND: &[HCICmdTable] = &[];
+const TABLE_REVERSE: &[HCICmdTable] = &[];
+const TABLE_LE_CONTROLLER: &[HCICmdTable] = &[
+    create_hci_cmd_table!(
+        LEController::LESetEventMask,
+        25,
+        HCI_LE_SET_EVENT_MASK_BIT,
+        le_set_event_mask
+    ),
+    create_hci_cmd_table!(
+        LEController::LEReadBufferSize,
+        25,
+        HCI_LE_READ_BUFFER_SIZE_BIT,
+        le_read_buffer_size
+    ),
     create_hci_cmd_table!(
+        LEController::LEReadLocalSupportedFeatures,
         25,
         HCI_LE_READ_LOCAL_SUPPORTED_FEATURES_BIT,
         le_read_local_supported_features
     ),
-    None,
-    None,
     create_hci_cmd_table!(
+        LEController::LESetRandomAddress,
+        25,
+        HCI_LE_SET_RANDOM_ADDRESS_BIT,
+        le_set_random_address
+    ),
+    create_hci_cmd_table!(
+        LEController::LESetAdvertisingParameters,
         25,
         HCI_LE_SET_ADVERTISING_PARAMETERS_BIT,
         le_set_advertising_parameters
     ),
     create_hci_cmd_table!(
+        LEController::LEReadAdvertisingPhysicalChannelTxPower,
         25,
         HCI_LE_READ_ADVERTISING_PHYSICAL_CHANNEL_TX_POWER_BIT,
         le_read_advertising_physical_channel_tx_power
     ),
-    create_hci_cmd_table!(25, HCI_LE_SET_ADVERTISING_DATA_BIT, le_set_advertising_data),
     create_hci_cmd_table!(
+        LEController::LESetAdvertisingData,
+        25,
+        HCI_LE_SET_ADVERTISING_DATA_BIT,
+        le_set_advertising_data
+    ),
+    create_hci_cmd_table!(
+        LEController::LESetScanResponseData,
         26,
         HCI_LE_SET_SCAN_RESPONSE_DATA_BIT,
         le_set_scan_response_data
     ),
     create_hci_cmd_table!(
+        LEController::LESetAdvertisingEnable,
         26,
         HCI_LE_SET_ADVERTISING_ENABLE_BIT,
         le_set_advertising_enable
     ),
+    create_hci_cmd_table!(
+        LEController::LESetScanParameters,
+        26,
+        HCI_LE_SET_SCAN_PARAMETERS_BIT,
+        le_set_scan_parameters
+    ),
+    create_hci_cmd_table!(
+        LEController::LESetScanEnable,
+        26,
+        HCI_LE_SET_SCAN_ENABLE_BIT,
+        le_set_scan_enable
+    ),
+    create_hci_cmd_table!(
+        LEController::LECreateConnection,
+        26,
+        HCI_LE_CREATE_CONNECTION_BIT,
+        le_create_connection
+    ),
+    create_hci_cmd_table!(
+        LEController::LECreateConnectionCancel,
+        26,
+        HCI_LE_CREATE_CONNECTION_CANCEL_BIT,
+        le_create_connection_cancel
+    ),
+    create_hci_cmd_table!(
+        LEController::LEReadFilterAcceptListSize,
+        26,
+        HCI_LE_READ_FILTER_ACCEPT_LIST_SIZE_BIT,
+        le_read_filter_accept_list_size
+    ),
+    create_hci_cmd_table!(
+        LEController::LEClearFilterAcceptList,
+        26,
+        HCI_LE_CLEAR_FILTER_ACCEPT_LIST_BIT,
+        le_clear_filter_accept_list
+    ),
+    create_hci_cmd_table!(
+        LEController::LEAddDeviceToFilterAcceptList,
+        27,
+        HCI_LE_ADD_DEVICE_TO_FILTER_ACCEPT_LIST_BIT,
+        le_add_device_to_filter_accept_list
+    ),
+    create_hci_cmd_table!(
+        LEController::LERemoveDeviceFromFilterAcceptList,
+        27,
+        HCI_LE_REMOVE_DEVICE_FROM_FILTER_ACCEPT_LIST_BIT,
+        le_remove_device_from_filter_accept_list
+    ),
+    create_hci_cmd_table!(LEController::LERand, 27, HCI_LE_RAND_BIT, le_rand),
+    create_hci_cmd_table!(
+        LEController::LESetDataLength,
+        33,
+        HCI_LE_SET_DATA_LENGTH_BIT,
+        le_set_data_length
+    ),
+    create_hci_cmd_table!(
+        LEController::LEReadSuggestedDefaultDataLength,
+        33,
+        HCI_LE_READ_SUGGESTED_DEFAULT_DATA_LENGTH_BIT,
+        le_read_suggested_default_data_length
+    ),
+    create_hci_cmd_table!(
+        LEController::LEWriteSuggestedDefaultDataLength,
+        34,
+        HCI_LE_WRITE_SUGGESTED_DEFAULT_DATA_LENGTH_BIT,
+        le_write_suggested_default_data_length
+    ),
+    create_hci_cmd_table!(
+        LEController::LEAddDeviceToResolvingList,
+        34,
+        HCI_LE_ADD_DEVICE_TO_RESOLVING_LIST_BIT,
+        le_add_device_to_resolving_list
+    ),
+    create_hci_cmd_table!(
+        LEController::LERemoveDeviceFromResolvingList,
+        34,
+        HCI_LE_REMOVE_DEVICE_FROM_RESOLVING_LIST_BIT,
+        le_remove_device_from_resolving_list
+    ),
+    create_hci_cmd_table!(
+        LEController::LEClearResolvingList,
+        34,
+        HCI_LE_CLEAR_RESOLVING_LIST_BIT,
+        le_clear_resolving_list
+    ),
+    create_hci_cmd_table!(
+        LEController::LEReadResolvingListSize,
+        34,
+        HCI_LE_READ_RESOLVING_LIST_SIZE_BIT,
+        le_read_resolving_list_size
+    ),
+    create_hci_cmd_table!(
+        LEController::LESetAddressResolutionEnable,
+        35,
+        HCI_LE_SET_ADDRESS_RESOLUTION_ENABLE_BIT,
+        le_set_address_resolution_enable
+    ),
+    create_hci_cmd_table!(
+        LEController::LEReadPHY,
+        35,
+        HCI_LE_READ_PHY_BIT,
+        le_read_phy
+    ),
+    create_hci_cmd_table!(
+        LEController::LESetDefaultPHY,
+        35,
+        HCI_LE_SET_DEFAULT_PHY_BIT,
+        le_set_default_phy
+    ),
+    create_hci_cmd_table!(LEController::LESetPHY, 35, HCI_LE_SET_PHY_BIT, le_set_phy),
 ];
 
-pub const HCI_CMD_TABLE: &[&[Option<HCICmdTable>]; 8] = &[
+/// Commands per ogf, looked up by ocf
+pub const HCI_CMD_TABLE: &[&[HCICmdTable]; 8] = &[
     TABLE_LINK_CONTROL,
     TABLE_LINK_POLICY,
     TABLE_CONTROLLER_AND_BASEBAND,
@@ -134,18 +350,16 @@ pub const HCI_CMD_TABLE: &[&[Option<HCICmdTable>]; 8] = &[
     TABLE_LE_CONTROLLER,
 ];
 
-const fn compute_hci_cmd_support(table: &[&[Option<HCICmdTable>]; 8]) -> [u8; 64] {
+const fn compute_hci_cmd_support(table: &[&[HCICmdTable]; 8]) -> [u8; 64] {
     let mut support = [0; 64];
     let mut i = 0;
     while i < table.len() {
         let sub = table[i];
         let mut j = 0;
         while j < sub.len() {
-            if let Some(cmd) = &sub[j] {
-                let byte = cmd.flag >> 8;
-                let bit = cmd.flag & 0xff;
-                support[byte as usize] |= bit as u8;
-            }
+            let byte = sub[j].flag >> 8;
+            let bit = sub[j].flag & 0xff;
+            support[byte as usize] |= bit as u8;
             j += 1;
         }
         i += 1;
@@ -155,7 +369,8 @@ const fn compute_hci_cmd_support(table: &[&[Option<HCICmdTable>]; 8]) -> [u8; 64
 
 const HCI_CMD_SUPPORTED_BYTES: [u8; 64] = compute_hci_cmd_support(HCI_CMD_TABLE);
 
-const LMP_SUPPORTED_FEATURES_BYTES: [u8; 8] = [0; 8];
+/// LE Supported (Controller)
+const LMP_SUPPORTED_FEATURES_BYTES: [u8; 8] = [0, 0, 0, 0, 0x40, 0, 0, 0];
 
 fn bb_send_event<T>(bb: &mut Control, opcode: u16, ret: T)
 where
@@ -169,12 +384,83 @@ where
     bb.send_event(HCIEvent::CommandComplete as u8, evt.to_u8_array());
 }
 
-// Controller and Baseband Commands
+fn bb_send_status(bb: &mut Control, opcode: u16, status: ControllerErrorCode) {
+    let evt = CommandStatusEvt {
+        status,
+        num_hci_command_packets: 5,
+        opcode,
+    };
+    bb.send_event(HCIEvent::CommandStatus as u8, evt.to_u8_array());
+}
 
-fn set_event_mask(bb: &mut Control, opcode: u16, _data: &[u8]) {
-    let ret = SetEventMaskRet {
+fn bb_send_le_event<T>(bb: &mut Control, subevent: LESubevent, evt: T)
+where
+    T: RBlueToU8Array,
+{
+    let mut packet = vec![subevent as u8];
+    packet.extend(evt.to_u8_array());
+    bb.send_event(HCIEvent::LEMeta as u8, packet);
+}
+
+/// A link the link layer created, both ends use the parameters of the connect request
+pub(super) fn le_connection_complete(
+    bb: &mut Control,
+    role: Role,
+    peer: AirAddress,
+    (connection_interval, peripheral_latency, supervision_timeout): (u16, u16, u16),
+) {
+    let connection_handle = bb.le_next_connection_handle;
+    bb.le_next_connection_handle = (connection_handle + 1) % (HCI_CONNECTION_HANDLE_MAX + 1);
+    let (peer_address_type, peer_address) = bb.le_resolved_address(peer);
+    bb.le_links.insert(connection_handle, LELink::default());
+    let evt = LEConnectionCompleteEvt {
         status: ControllerErrorCode::Ok,
+        connection_handle,
+        role,
+        peer_address_type,
+        peer_address,
+        connection_interval,
+        peripheral_latency,
+        supervision_timeout,
+        central_clock_accuracy: 0,
+    };
+    bb_send_le_event(bb, LESubevent::ConnectionComplete, evt);
+}
+
+pub(super) fn le_advertising_report(bb: &mut Control, report: LEAdvertisingReport) {
+    let evt = LEAdvertisingReportEvt {
+        reports: vec![report],
+    };
+    bb_send_le_event(bb, LESubevent::AdvertisingReport, evt);
+}
+
+pub(super) fn unknown_command(bb: &mut Control, opcode: u16) {
+    bb_send_status(bb, opcode, ControllerErrorCode::UnknownHCICommand);
+}
+
+pub(super) fn acl_data(bb: &mut Control, data: &[u8]) -> Result<()> {
+    let header = ACLHeader::from_u8_array(data)?;
+    take_bytes(data, HCI_ACL_HEADER_SIZE, header.data_total_length as usize)?;
+
+    // nothing to transmit over the air yet, free the buffer right away
+    let evt = NumberOfCompletedPacketsEvt {
+        completed_packets: alloc::vec![(header.handle, 1)],
+    };
+    bb.send_event(HCIEvent::NumberOfCompletedPackets as u8, evt.to_u8_array());
+    Ok(())
+}
+
+// Controller and Baseband Commands
+
+fn set_event_mask(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match SetEventMaskCmd::from_u8_array(data) {
+        Ok(arg) => {
+            bb.event_mask = EventMask::from_bits_retain(arg.event_mask);
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
     };
+    let ret = SetEventMaskRet { status };
 
     bb_send_event(bb, opcode, ret);
 }
@@ -191,8 +477,42 @@ fn reset(bb: &mut Control, opcode: u16, _data: &[u8]) {
     bb_send_event(bb, opcode, ret);
 }
 
+fn write_scan_enable(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match WriteScanEnableCmd::from_u8_array(data) {
+        Ok(_) => ControllerErrorCode::Ok,
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = WriteScanEnableRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn write_le_host_support(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match WriteLEHostSupportCmd::from_u8_array(data) {
+        Ok(_) => ControllerErrorCode::Ok,
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = WriteLEHostSupportRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
 // Informational Parameters
 
+fn read_local_version_information(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    // Bluetooth Core 5.3, no company assigned
+    let ret = ReadLocalVersionInformationRet {
+        status: ControllerErrorCode::Ok,
+        hci_version: 0x0C,
+        hci_subversion: 0,
+        lmp_version: 0x0C,
+        company_identifier: 0xFFFF,
+        lmp_subversion: 0,
+    };
+
+    bb_send_event(bb, opcode, ret);
+}
+
 fn read_local_supported_commands(bb: &mut Control, opcode: u16, _data: &[u8]) {
     let ret = ReadLocalSupportedCommandsRet {
         status: ControllerErrorCode::Ok,
@@ -214,10 +534,10 @@ fn read_local_supported_features(bb: &mut Control, opcode: u16, _data: &[u8]) {
 fn read_buffer_size(bb: &mut Control, opcode: u16, _data: &[u8]) {
     let ret = ReadBufferSizeRet {
         status: ControllerErrorCode::Ok,
-        acl_data_packet_length: 0,
-        synchronous_data_packet_length: 0,
-        total_num_acl_data_packets: 0,
-        total_num_synchronous_data_packets: 0,
+        acl_data_packet_length: 1021,
+        synchronous_data_packet_length: 64,
+        total_num_acl_data_packets: 8,
+        total_num_synchronous_data_packets: 8,
     };
 
     bb_send_event(bb, opcode, ret);
@@ -226,18 +546,45 @@ fn read_buffer_size(bb: &mut Control, opcode: u16, _data: &[u8]) {
 fn read_bd_address(bb: &mut Control, opcode: u16, _data: &[u8]) {
     let ret = ReadBDAddrRet {
         status: ControllerErrorCode::Ok,
-        bd_addr: [0; 6],
+        bd_addr: bb.bd_addr,
     };
 
     bb_send_event(bb, opcode, ret);
 }
 
-// LE Controller Commands
+// Link Control Commands
 
-fn le_set_event_mask(bb: &mut Control, opcode: u16, _data: &[u8]) {
-    let ret = LESetEventMaskRet {
+fn disconnect(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let arg = match DisconnectCmd::from_u8_array(data) {
+        Ok(arg) if arg.reason.is_disconnect_reason() => arg,
+        _ => {
+            bb_send_status(bb, opcode, ControllerErrorCode::InvalidHCICommandParameters);
+            return;
+        }
+    };
+    bb_send_status(bb, opcode, ControllerErrorCode::Ok);
+
+    // nothing to tell the peer over the air yet, the link is gone right away
+    bb.le_links.remove(&arg.connection_handle);
+    let evt = DisconnectionCompleteEvt {
         status: ControllerErrorCode::Ok,
+        connection_handle: arg.connection_handle,
+        reason: ControllerErrorCode::ConnectionTerminatedByLocalHost,
     };
+    bb.send_event(HCIEvent::DisconnectionComplete as u8, evt.to_u8_array());
+}
+
+// LE Controller Commands
+
+fn le_set_event_mask(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetEventMaskCmd::from_u8_array(data) {
+        Ok(arg) => {
+            bb.le_event_mask = LEEventMask::from_bits_retain(arg.le_event_mask);
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetEventMaskRet { status };
 
     bb_send_event(bb, opcode, ret);
 }
@@ -245,8 +592,8 @@ fn le_set_event_mask(bb: &mut Control, opcode: u16, _data: &[u8]) {
 fn le_read_buffer_size(bb: &mut Control, opcode: u16, _data: &[u8]) {
     let ret = LEReadBufferSizeRet {
         status: ControllerErrorCode::Ok,
-        le_acl_data_packet_length: 0,
-        total_num_le_acl_data_packets: 0,
+        le_acl_data_packet_length: 251,
+        total_num_le_acl_data_packets: 8,
     };
 
     bb_send_event(bb, opcode, ret);
@@ -255,16 +602,47 @@ fn le_read_buffer_size(bb: &mut Control, opcode: u16, _data: &[u8]) {
 fn le_read_local_supported_features(bb: &mut Control, opcode: u16, _data: &[u8]) {
     let ret = LEReadLocalSupportedFeaturesRet {
         status: ControllerErrorCode::Ok,
-        le_features: 0,
+        le_features: (LEFeatures::LEDataPacketLengthExtension
+            | LEFeatures::LLPrivacy
+            | LEFeatures::LE2MPHY
+            | LEFeatures::LECodedPHY)
+            .bits(),
     };
 
     bb_send_event(bb, opcode, ret);
 }
 
-fn le_set_advertising_parameters(bb: &mut Control, opcode: u16, _data: &[u8]) {
-    let ret = LESetAdvertisingParametersRet {
-        status: ControllerErrorCode::Ok,
+fn le_set_random_address(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetRandomAddressCmd::from_u8_array(data) {
+        Ok(_) if bb.le_advertising || bb.le_scanning || bb.le_initiating => {
+            ControllerErrorCode::CommandDisallowed
+        }
+        Ok(arg) => {
+            bb.le_random_address = Some(arg.random_address);
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
     };
+    let ret = LESetRandomAddressRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_advertising_parameters(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetAdvertisingParametersCmd::from_u8_array(data) {
+        Ok(_) if bb.le_advertising => ControllerErrorCode::CommandDisallowed,
+        Ok(arg) => {
+            bb.le_advertising_type = arg.advertising_type;
+            bb.le_advertising_peer = (arg.peer_address_type, arg.peer_address);
+            bb.le_advertising_filter_policy = arg.advertising_filter_policy;
+            bb.le_advertising_own_address_type = arg.own_address_type;
+            // unit: 0.625ms
+            bb.le_advertising_interval_ms = arg.advertising_interval_min as u64 * 5 / 8;
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetAdvertisingParametersRet { status };
 
     bb_send_event(bb, opcode, ret);
 }
@@ -278,28 +656,476 @@ fn le_read_advertising_physical_channel_tx_power(bb: &mut Control, opcode: u16,
     bb_send_event(bb, opcode, ret);
 }
 
-fn le_set_advertising_data(bb: &mut Control, opcode: u16, _data: &[u8]) {
-    let ret = LESetAdvertisingDataRet {
+/// The significant part of the advertising or scan response data parameters
+fn le_adv_data(data: &[u8]) -> Option<Vec<u8>> {
+    let len = *data.first()? as usize;
+    if len > LE_ADV_DATA_MAX_LEN {
+        return None;
+    }
+    take_bytes(data, 1, len).ok().map(|data| data.to_vec())
+}
+
+fn le_set_advertising_data(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match le_adv_data(data) {
+        Some(data) => {
+            bb.le_advertising_data = data;
+            ControllerErrorCode::Ok
+        }
+        None => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetAdvertisingDataRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_scan_response_data(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match le_adv_data(data) {
+        Some(data) => {
+            bb.le_scan_response_data = data;
+            ControllerErrorCode::Ok
+        }
+        None => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetScanResponseDataRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_advertising_enable(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetAdvertisingEnableCmd::from_u8_array(data) {
+        Ok(arg) => {
+            if arg.advertiseing_enable && !bb.le_advertising {
+                // the first advertising event goes out on the next poll
+                bb.le_advertising_deadline = bb.now_ms;
+            }
+            bb.le_advertising = arg.advertiseing_enable;
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetAdvertisingEnableRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_scan_parameters(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetScanParametersCmd::from_u8_array(data) {
+        Ok(_) if bb.le_scanning => ControllerErrorCode::CommandDisallowed,
+        Ok(arg)
+            if (0x0004..=0x4000).contains(&arg.le_scan_interval)
+                && (0x0004..=arg.le_scan_interval).contains(&arg.le_scan_window) =>
+        {
+            bb.le_scan_type = arg.le_scan_type;
+            bb.le_scan_filter_policy = arg.scanning_filter_policy;
+            bb.le_scan_own_address_type = arg.own_address_type;
+            ControllerErrorCode::Ok
+        }
+        _ => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetScanParametersRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_scan_enable(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetScanEnableCmd::from_u8_array(data) {
+        Ok(arg) => {
+            bb.le_scanning = arg.le_scan_enable;
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetScanEnableRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_create_connection(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LECreateConnectionCmd::from_u8_array(data) {
+        Ok(_) if bb.le_initiating => ControllerErrorCode::CommandDisallowed,
+        Ok(arg) => {
+            // no peer on air yet, keep initiating until cancelled
+            bb.le_initiating = true;
+            bb.le_initiator_filter_policy = arg.initiator_filter_policy;
+            bb.le_initiator_own_address_type = arg.own_address_type;
+            bb.le_initiator_peer = (
+                LEAddressType2::from(arg.peer_address_type),
+                arg.peer_address,
+            );
+            bb.le_initiator_link_params = (
+                arg.conn_interval_max,
+                arg.max_latency,
+                arg.supervision_timeout,
+            );
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+
+    bb_send_status(bb, opcode, status);
+}
+
+fn le_create_connection_cancel(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    if !bb.le_initiating {
+        let ret = LECreateConnectionCancelRet {
+            status: ControllerErrorCode::CommandDisallowed,
+        };
+        bb_send_event(bb, opcode, ret);
+        return;
+    }
+    bb.le_initiating = false;
+    let ret = LECreateConnectionCancelRet {
         status: ControllerErrorCode::Ok,
     };
+    bb_send_event(bb, opcode, ret);
+
+    let evt = LEConnectionCompleteEvt {
+        status: ControllerErrorCode::OperationCancelledByHost,
+        connection_handle: 0,
+        role: Role::Central,
+        peer_address_type: LEAddressType::PublicDevice,
+        peer_address: [0; 6],
+        connection_interval: 0,
+        peripheral_latency: 0,
+        supervision_timeout: 0,
+        central_clock_accuracy: 0,
+    };
+    bb_send_le_event(bb, LESubevent::ConnectionComplete, evt);
+}
+
+fn le_rand(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    let ret = LERandRet {
+        status: ControllerErrorCode::Ok,
+        random_number: bb.le_rand(),
+    };
 
     bb_send_event(bb, opcode, ret);
 }
 
-fn le_set_scan_response_data(bb: &mut Control, opcode: u16, _data: &[u8]) {
-    let ret = LESetScanResponseDataRet {
+fn le_read_filter_accept_list_size(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    let ret = LEReadFilterAcceptListSizeRet {
         status: ControllerErrorCode::Ok,
+        filter_accept_list_size: LE_FILTER_ACCEPT_LIST_SIZE,
     };
 
     bb_send_event(bb, opcode, ret);
 }
 
-fn le_set_advertising_enable(bb: &mut Control, opcode: u16, data: &[u8]) {
-    let _arg = LESetAdvertisingEnableCmd::from_u8_array(data);
+fn le_clear_filter_accept_list(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    let status = if bb.le_filter_accept_list_in_use() {
+        ControllerErrorCode::CommandDisallowed
+    } else {
+        bb.le_filter_accept_list.clear();
+        ControllerErrorCode::Ok
+    };
+    let ret = LEClearFilterAcceptListRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_add_device_to_filter_accept_list(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LEAddDeviceToFilterAcceptListCmd::from_u8_array(data) {
+        Ok(_) if bb.le_filter_accept_list_in_use() => ControllerErrorCode::CommandDisallowed,
+        Ok(arg) => {
+            let device = (arg.address_type, arg.address);
+            if bb.le_filter_accept_list.contains(&device) {
+                ControllerErrorCode::Ok
+            } else if bb.le_filter_accept_list.len() >= LE_FILTER_ACCEPT_LIST_SIZE as usize {
+                ControllerErrorCode::MemoryCapacityExceeded
+            } else {
+                bb.le_filter_accept_list.push(device);
+                ControllerErrorCode::Ok
+            }
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LEAddDeviceToFilterAcceptListRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_remove_device_from_filter_accept_list(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LERemoveDeviceFromFilterAcceptListCmd::from_u8_array(data) {
+        Ok(_) if bb.le_filter_accept_list_in_use() => ControllerErrorCode::CommandDisallowed,
+        Ok(arg) => {
+            let device = (arg.address_type, arg.address);
+            bb.le_filter_accept_list.retain(|d| *d != device);
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LERemoveDeviceFromFilterAcceptListRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_add_device_to_resolving_list(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LEAddDeviceToResolvingListCmd::from_u8_array(data) {
+        Ok(_) if bb.le_address_resolution_enabled && bb.le_air_activity() => {
+            ControllerErrorCode::CommandDisallowed
+        }
+        Ok(arg) => {
+            let entry = ResolvingListEntry {
+                identity_address_type: arg.peer_identity_address_type,
+                identity_address: arg.peer_identity_address,
+                peer_irk: arg.peer_irk,
+                local_irk: arg.local_irk,
+            };
+            if bb.le_resolving_list.iter().any(|e| {
+                (e.identity_address_type, e.identity_address)
+                    == (entry.identity_address_type, entry.identity_address)
+            }) {
+                ControllerErrorCode::InvalidHCICommandParameters
+            } else if bb.le_resolving_list.len() >= LE_RESOLVING_LIST_SIZE as usize {
+                ControllerErrorCode::MemoryCapacityExceeded
+            } else {
+                bb.le_resolving_list.push(entry);
+                ControllerErrorCode::Ok
+            }
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LEAddDeviceToResolvingListRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_remove_device_from_resolving_list(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LERemoveDeviceFromResolvingListCmd::from_u8_array(data) {
+        Ok(_) if bb.le_address_resolution_enabled && bb.le_air_activity() => {
+            ControllerErrorCode::CommandDisallowed
+        }
+        Ok(arg) => {
+            let identity = (arg.peer_identity_address_type, arg.peer_identity_address);
+            match bb
+                .le_resolving_list
+                .iter()
+                .position(|e| (e.identity_address_type, e.identity_address) == identity)
+            {
+                Some(index) => {
+                    bb.le_resolving_list.remove(index);
+                    ControllerErrorCode::Ok
+                }
+                None => ControllerErrorCode::UnknownConnectionIdentifier,
+            }
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LERemoveDeviceFromResolvingListRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_clear_resolving_list(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    let status = if bb.le_address_resolution_enabled && bb.le_air_activity() {
+        ControllerErrorCode::CommandDisallowed
+    } else {
+        bb.le_resolving_list.clear();
+        ControllerErrorCode::Ok
+    };
+    let ret = LEClearResolvingListRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_read_resolving_list_size(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    let ret = LEReadResolvingListSizeRet {
+        status: ControllerErrorCode::Ok,
+        resolving_list_size: LE_RESOLVING_LIST_SIZE,
+    };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_address_resolution_enable(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetAddressResolutionEnableCmd::from_u8_array(data) {
+        Ok(_) if bb.le_air_activity() => ControllerErrorCode::CommandDisallowed,
+        Ok(arg) => {
+            bb.le_address_resolution_enabled = arg.address_resolution_enable;
+            ControllerErrorCode::Ok
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetAddressResolutionEnableRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_data_length(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let arg = match LESetDataLengthCmd::from_u8_array(data) {
+        Ok(arg) => arg,
+        Err(_) => {
+            let ret = LESetDataLengthRet {
+                status: ControllerErrorCode::InvalidHCICommandParameters,
+                connection_handle: 0,
+            };
+            bb_send_event(bb, opcode, ret);
+            return;
+        }
+    };
+    let connection_handle = arg.connection_handle;
+    // the peer takes whatever is asked for
+    let (status, changed) = match bb.le_links.get_mut(&connection_handle) {
+        _ if !is_valid_data_length(arg.tx_octets, arg.tx_time) => {
+            (ControllerErrorCode::InvalidHCICommandParameters, None)
+        }
+        None => (ControllerErrorCode::UnknownConnectionIdentifier, None),
+        Some(link) => {
+            let old = link.data_length;
+            link.data_length.max_tx_octets = arg.tx_octets;
+            link.data_length.max_tx_time = arg.tx_time;
+            let new = link.data_length;
+            (ControllerErrorCode::Ok, (new != old).then_some(new))
+        }
+    };
+    let ret = LESetDataLengthRet {
+        status,
+        connection_handle,
+    };
+    bb_send_event(bb, opcode, ret);
 
-    let ret = LESetAdvertisingEnableRet {
+    if let Some(data_length) = changed {
+        let evt = LEDataLengthChangeEvt {
+            connection_handle,
+            max_tx_octets: data_length.max_tx_octets,
+            max_tx_time: data_length.max_tx_time,
+            max_rx_octets: data_length.max_rx_octets,
+            max_rx_time: data_length.max_rx_time,
+        };
+        bb_send_le_event(bb, LESubevent::DataLengthChange, evt);
+    }
+}
+
+fn le_read_suggested_default_data_length(bb: &mut Control, opcode: u16, _data: &[u8]) {
+    let (suggested_max_tx_octets, suggested_max_tx_time) = bb.le_suggested_data_length;
+    let ret = LEReadSuggestedDefaultDataLengthRet {
         status: ControllerErrorCode::Ok,
+        suggested_max_tx_octets,
+        suggested_max_tx_time,
     };
 
     bb_send_event(bb, opcode, ret);
 }
+
+fn le_write_suggested_default_data_length(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LEWriteSuggestedDefaultDataLengthCmd::from_u8_array(data) {
+        Ok(arg) if is_valid_data_length(arg.suggested_max_tx_octets, arg.suggested_max_tx_time) => {
+            bb.le_suggested_data_length = (arg.suggested_max_tx_octets, arg.suggested_max_tx_time);
+            ControllerErrorCode::Ok
+        }
+        _ => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LEWriteSuggestedDefaultDataLengthRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+/// Status of the PHY preferences of LE Set Default PHY and LE Set PHY
+fn le_phy_preference_status(all_phys: u8, tx_phys: u8, rx_phys: u8) -> ControllerErrorCode {
+    let (tx_phys, rx_phys) = match (LEPhys::from_bits(tx_phys), LEPhys::from_bits(rx_phys)) {
+        (Some(tx_phys), Some(rx_phys)) => (tx_phys, rx_phys),
+        _ => return ControllerErrorCode::UnsupportedFeatureOrParameterValue,
+    };
+    let no_tx_preference = all_phys & 0x01 != 0;
+    let no_rx_preference = all_phys & 0x02 != 0;
+    if (!no_tx_preference && tx_phys.is_empty()) || (!no_rx_preference && rx_phys.is_empty()) {
+        return ControllerErrorCode::InvalidHCICommandParameters;
+    }
+    ControllerErrorCode::Ok
+}
+
+fn le_read_phy(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let ret = match LEReadPHYCmd::from_u8_array(data) {
+        Ok(arg) => match bb.le_links.get(&arg.connection_handle) {
+            Some(link) => LEReadPHYRet {
+                status: ControllerErrorCode::Ok,
+                connection_handle: arg.connection_handle,
+                tx_phy: link.tx_phy,
+                rx_phy: link.rx_phy,
+            },
+            None => LEReadPHYRet {
+                status: ControllerErrorCode::UnknownConnectionIdentifier,
+                connection_handle: arg.connection_handle,
+                tx_phy: LEPhy::LE1M,
+                rx_phy: LEPhy::LE1M,
+            },
+        },
+        Err(_) => LEReadPHYRet {
+            status: ControllerErrorCode::InvalidHCICommandParameters,
+            connection_handle: 0,
+            tx_phy: LEPhy::LE1M,
+            rx_phy: LEPhy::LE1M,
+        },
+    };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_default_phy(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let status = match LESetDefaultPHYCmd::from_u8_array(data) {
+        Ok(arg) => {
+            let status = le_phy_preference_status(arg.all_phys, arg.tx_phys, arg.rx_phys);
+            if status == ControllerErrorCode::Ok {
+                bb.le_default_phy = (arg.all_phys, arg.tx_phys, arg.rx_phys);
+            }
+            status
+        }
+        Err(_) => ControllerErrorCode::InvalidHCICommandParameters,
+    };
+    let ret = LESetDefaultPHYRet { status };
+
+    bb_send_event(bb, opcode, ret);
+}
+
+fn le_set_phy(bb: &mut Control, opcode: u16, data: &[u8]) {
+    let arg = match LESetPHYCmd::from_u8_array(data) {
+        Ok(arg) if arg.phy_options <= 0x0002 => arg,
+        _ => {
+            bb_send_status(bb, opcode, ControllerErrorCode::InvalidHCICommandParameters);
+            return;
+        }
+    };
+    let status = match le_phy_preference_status(arg.all_phys, arg.tx_phys, arg.rx_phys) {
+        ControllerErrorCode::Ok if !bb.le_links.contains_key(&arg.connection_handle) => {
+            ControllerErrorCode::UnknownConnectionIdentifier
+        }
+        status => status,
+    };
+    bb_send_status(bb, opcode, status);
+    if status != ControllerErrorCode::Ok {
+        return;
+    }
+
+    // the peer supports every PHY, the procedure completes even if nothing changed
+    let link = match bb.le_links.get_mut(&arg.connection_handle) {
+        Some(link) => link,
+        None => return,
+    };
+    if arg.all_phys & 0x01 == 0 {
+        link.tx_phy = le_preferred_phy(arg.tx_phys, link.tx_phy);
+    }
+    if arg.all_phys & 0x02 == 0 {
+        link.rx_phy = le_preferred_phy(arg.rx_phys, link.rx_phy);
+    }
+    let evt = LEPHYUpdateCompleteEvt {
+        status,
+        connection_handle: arg.connection_handle,
+        tx_phy: link.tx_phy,
+        rx_phy: link.rx_phy,
+    };
+    bb_send_le_event(bb, LESubevent::PHYUpdateComplete, evt);
+}
+
+/// The fastest PHY the host allows
+fn le_preferred_phy(phys: u8, current: LEPhy) -> LEPhy {
+    let phys = LEPhys::from_bits_truncate(phys);
+    let allowed = |phy: LEPhy| match phy {
+        LEPhy::LE1M => phys.contains(LEPhys::LE1M),
+        LEPhy::LE2M => phys.contains(LEPhys::LE2M),
+        LEPhy::LECoded => phys.contains(LEPhys::LECoded),
+    };
+    [LEPhy::LE2M, LEPhy::LE1M, LEPhy::LECoded]
+        .into_iter()
+        .find(|phy| allowed(*phy))
+        .unwrap_or(current)
+}
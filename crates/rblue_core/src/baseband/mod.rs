@@ -2,23 +2,102 @@ pub mod ble;
 pub mod bt;
 mod hci;
 
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
 use alloc::vec;
 use alloc::vec::Vec;
 use log::info;
 
+use ble::ll::{AdvPdu, AirAddress};
 use hci::HCI_CMD_TABLE;
 use rblue_proc_macro::EnumU8ToLeBytes;
+use rblue_proc_macro::FromBytes;
 use rblue_proc_macro::ToU8Array;
 
 use crate::host::{
-    hci::{opcode_to_ocf, opcode_to_ogf, HCIPacket},
-    hci_cmd::RBlueToU8Array,
+    connection::{DataLength, LE_DATA_LENGTH_MIN_OCTETS, LE_DATA_LENGTH_MIN_TIME},
+    filter_list::ResolvingListEntry,
+    hci::{opcode_to_ocf, opcode_to_ogf, HCIEvent, HCIPacket},
+    hci_cmd::{RBlueFromU8Array, RBlueToU8Array},
+    hci_event::LEAdvertisingReport,
+    privacy::resolve_private_address,
+    AdvertisingFilterPolicy, AdvertisingReportType, AdvertisingType, EventMask, LEAddressType,
+    LEAddressType2, LEEventMask, LEPhy, LEScanType, Role, ScanningFilterPolicy,
 };
+use crate::{BDAddr, Error, Result};
+
+const LE_FILTER_ACCEPT_LIST_SIZE: u8 = 8;
+const LE_RESOLVING_LIST_SIZE: u8 = 8;
+
+/// Signal strength of every simulated advertisement, unit: dBm
+const LE_SIM_RSSI: i8 = -40;
+/// Advertising interval after reset, 0x0800 in 0.625ms units
+const LE_ADVERTISING_INTERVAL_DEFAULT_MS: u64 = 1280;
+
+pub type ControlSendPacket = Box<dyn Fn(&Control, Vec<u8>)>;
+
+/// What the host can change on a link the link layer created
+struct LELink {
+    tx_phy: LEPhy,
+    rx_phy: LEPhy,
+    data_length: DataLength,
+}
+
+impl Default for LELink {
+    fn default() -> Self {
+        LELink {
+            tx_phy: LEPhy::LE1M,
+            rx_phy: LEPhy::LE1M,
+            data_length: DataLength::default(),
+        }
+    }
+}
 
 pub struct Control {
     pub id: u8,
-    upper_send_packet: Option<fn(&Self, Vec<u8>)>,
-    lower_send_packet: Option<fn(&Self, Vec<u8>)>,
+    upper_send_packet: Option<ControlSendPacket>,
+    lower_send_packet: Option<ControlSendPacket>,
+    /// Public address, used on air unless the own address type asks for the random one
+    bd_addr: BDAddr,
+    /// Set by the host with LE Set Random Address
+    le_random_address: Option<BDAddr>,
+    /// State of the LE Rand generator
+    le_rand_state: u64,
+    now_ms: u64,
+
+    event_mask: EventMask,
+    le_event_mask: LEEventMask,
+    /// An LE create connection is running
+    le_initiating: bool,
+    /// Without the initiator filter policy only this device is connected
+    le_initiator_peer: (LEAddressType2, BDAddr),
+    le_initiator_filter_policy: bool,
+    /// Connection interval, latency and supervision timeout sent in the connect request
+    le_initiator_link_params: (u16, u16, u16),
+    le_initiator_own_address_type: LEAddressType,
+    le_advertising: bool,
+    le_advertising_type: AdvertisingType,
+    /// Only directed advertising is sent to this device
+    le_advertising_peer: AirAddress,
+    le_advertising_filter_policy: AdvertisingFilterPolicy,
+    le_advertising_own_address_type: LEAddressType,
+    le_advertising_interval_ms: u64,
+    le_advertising_deadline: u64,
+    le_advertising_data: Vec<u8>,
+    le_scan_response_data: Vec<u8>,
+    le_scanning: bool,
+    le_scan_type: LEScanType,
+    le_scan_filter_policy: ScanningFilterPolicy,
+    le_scan_own_address_type: LEAddressType,
+    le_next_connection_handle: u16,
+    le_links: BTreeMap<u16, LELink>,
+    le_filter_accept_list: Vec<(LEAddressType2, BDAddr)>,
+    le_resolving_list: Vec<ResolvingListEntry>,
+    le_address_resolution_enabled: bool,
+    /// All PHYs, tx PHYs and rx PHYs preferred on new links
+    le_default_phy: (u8, u8, u8),
+    /// Max tx octets and time new links ask for
+    le_suggested_data_length: (u16, u16),
 }
 
 impl Control {
@@ -27,58 +106,529 @@ impl Control {
             id,
             upper_send_packet: None,
             lower_send_packet: None,
+            bd_addr: [0; 6],
+            le_random_address: None,
+            le_rand_state: 0x2545_F491_4F6C_DD1D ^ id as u64,
+            now_ms: 0,
+
+            event_mask: EventMask::DEFAULT,
+            le_event_mask: LEEventMask::DEFAULT,
+            le_initiating: false,
+            le_initiator_peer: (LEAddressType2::PublicDeviceOrPublicIdentity, [0; 6]),
+            le_initiator_filter_policy: false,
+            le_initiator_link_params: (0, 0, 0),
+            le_initiator_own_address_type: LEAddressType::PublicDevice,
+            le_advertising: false,
+            le_advertising_type: AdvertisingType::ConnectableAndScannnable,
+            le_advertising_peer: (LEAddressType2::PublicDeviceOrPublicIdentity, [0; 6]),
+            le_advertising_filter_policy: AdvertisingFilterPolicy::UnFilter,
+            le_advertising_own_address_type: LEAddressType::PublicDevice,
+            le_advertising_interval_ms: LE_ADVERTISING_INTERVAL_DEFAULT_MS,
+            le_advertising_deadline: 0,
+            le_advertising_data: Vec::new(),
+            le_scan_response_data: Vec::new(),
+            le_scanning: false,
+            le_scan_type: LEScanType::Passive,
+            le_scan_filter_policy: ScanningFilterPolicy::BasicUnfiltered,
+            le_scan_own_address_type: LEAddressType::PublicDevice,
+            le_next_connection_handle: 0,
+            le_links: BTreeMap::new(),
+            le_filter_accept_list: Vec::new(),
+            le_resolving_list: Vec::new(),
+            le_address_resolution_enabled: false,
+            le_default_phy: (0x03, 0, 0),
+            le_suggested_data_length: (LE_DATA_LENGTH_MIN_OCTETS, LE_DATA_LENGTH_MIN_TIME),
         }
     }
 
-    pub fn set_upper_send_packet(&mut self, send_packet: fn(&Self, Vec<u8>)) {
-        self.upper_send_packet = Some(send_packet);
+    pub fn set_upper_send_packet<F: Fn(&Self, Vec<u8>) + 'static>(&mut self, send_packet: F) {
+        self.upper_send_packet = Some(Box::new(send_packet));
     }
 
-    pub fn set_lower_send_packet(&mut self, send_packet: fn(&Self, Vec<u8>)) {
-        self.lower_send_packet = Some(send_packet);
+    pub fn set_lower_send_packet<F: Fn(&Self, Vec<u8>) + 'static>(&mut self, send_packet: F) {
+        self.lower_send_packet = Some(Box::new(send_packet));
     }
 
+    pub fn set_bd_addr(&mut self, bd_addr: BDAddr) {
+        self.bd_addr = bd_addr;
+    }
+
+    /// Advertising channel PDUs sent by the other simulated controllers
     pub fn recv_phy_packet(&mut self, packet: Vec<u8>) {
-        if let Some(send) = self.upper_send_packet {
-            send(&self, packet);
+        match AdvPdu::from_u8_array(&packet) {
+            Ok(pdu) => self.le_recv_adv_pdu(pdu),
+            Err(err) => info!("bb drop phy packet {:?}: {}", packet, err),
         }
     }
 
-    pub fn recv_host_packet(&mut self, packet: Vec<u8>) {
+    /// Sends the advertising events, `now_ms` never goes backwards
+    pub fn poll_timers(&mut self, now_ms: u64) {
+        self.now_ms = now_ms;
+        if self.le_advertising && self.le_advertising_deadline <= now_ms {
+            self.le_advertising_event();
+        }
+    }
+
+    pub fn recv_host_packet(&mut self, packet: Vec<u8>) -> Result<()> {
         if packet.len() < 3 {
+            return Err(Error::Truncated {
+                expected: 3,
+                actual: packet.len(),
+            });
+        }
+        match packet[0] {
+            x if x == HCIPacket::Command as u8 => {}
+            x if x == HCIPacket::ACL as u8 => return hci::acl_data(self, &packet[1..]),
+            x => return Err(Error::UnknownPacketType(x)),
+        }
+        let opcode = u16::from_le_bytes([packet[1], packet[2]]);
+        let ogf = opcode_to_ogf(opcode);
+        let ocf = opcode_to_ocf(opcode);
+        info!("bb {} {}", ogf, ocf);
+
+        // ogf counts from 1
+        let cmd = (ogf as usize)
+            .checked_sub(1)
+            .and_then(|ogf| HCI_CMD_TABLE.get(ogf))
+            .and_then(|table| table.iter().find(|cmd| cmd.ocf == ocf));
+        match cmd {
+            Some(cmd) => {
+                (cmd.handle)(self, opcode, &packet[3..]);
+                Ok(())
+            }
+            None => {
+                hci::unknown_command(self, opcode);
+                Err(Error::UnknownCommand(opcode))
+            }
+        }
+    }
+
+    fn power_on(&mut self) {
+        self.event_mask = EventMask::DEFAULT;
+        self.le_event_mask = LEEventMask::DEFAULT;
+        self.le_initiating = false;
+        self.le_initiator_filter_policy = false;
+        self.le_advertising = false;
+        self.le_advertising_type = AdvertisingType::ConnectableAndScannnable;
+        self.le_advertising_filter_policy = AdvertisingFilterPolicy::UnFilter;
+        self.le_advertising_interval_ms = LE_ADVERTISING_INTERVAL_DEFAULT_MS;
+        self.le_advertising_data.clear();
+        self.le_scan_response_data.clear();
+        self.le_scanning = false;
+        self.le_scan_type = LEScanType::Passive;
+        self.le_scan_filter_policy = ScanningFilterPolicy::BasicUnfiltered;
+        self.le_filter_accept_list.clear();
+        self.le_resolving_list.clear();
+        self.le_address_resolution_enabled = false;
+        self.le_default_phy = (0x03, 0, 0);
+        self.le_suggested_data_length = (LE_DATA_LENGTH_MIN_OCTETS, LE_DATA_LENGTH_MIN_TIME);
+    }
+
+    /// The filter accept list can not change while a filter policy uses it
+    fn le_filter_accept_list_in_use(&self) -> bool {
+        (self.le_advertising
+            && self.le_advertising_filter_policy != AdvertisingFilterPolicy::UnFilter)
+            || (self.le_scanning && self.le_scan_filter_accept_list_used())
+            || (self.le_initiating && self.le_initiator_filter_policy)
+    }
+
+    fn le_scan_filter_accept_list_used(&self) -> bool {
+        matches!(
+            self.le_scan_filter_policy,
+            ScanningFilterPolicy::BasicFiltered | ScanningFilterPolicy::ExtendedFiltered
+        )
+    }
+
+    /// Address resolution and the resolving list can not change while advertising,
+    /// scanning or initiating
+    fn le_air_activity(&self) -> bool {
+        self.le_advertising || self.le_scanning || self.le_initiating
+    }
+
+    /// The identity of a resolvable private address on the resolving list, other addresses as they are
+    fn le_resolve(
+        &self,
+        address_type: LEAddressType2,
+        address: &BDAddr,
+    ) -> (LEAddressType2, BDAddr) {
+        if self.le_address_resolution_enabled
+            && address_type == LEAddressType2::RandomDeviceOrRandomIdentity
+        {
+            if let Some(entry) = self
+                .le_resolving_list
+                .iter()
+                .find(|entry| resolve_private_address(&entry.peer_irk, address))
+            {
+                return (entry.identity_address_type, entry.identity_address);
+            }
+        }
+        (address_type, *address)
+    }
+
+    fn le_filter_accept_list_contains(
+        &self,
+        address_type: LEAddressType2,
+        address: &BDAddr,
+    ) -> bool {
+        let device = self.le_resolve(address_type, address);
+        self.le_filter_accept_list.contains(&device)
+    }
+
+    /// The random address once the host set one, the public address otherwise
+    fn le_own_address(&self, own_address_type: LEAddressType) -> AirAddress {
+        match (own_address_type, self.le_random_address) {
+            (LEAddressType::RandomDevice | LEAddressType::RandomIdentity, Some(address)) => {
+                (LEAddressType2::RandomDeviceOrRandomIdentity, address)
+            }
+            _ => (LEAddressType2::PublicDeviceOrPublicIdentity, self.bd_addr),
+        }
+    }
+
+    /// xorshift64, good enough for the addresses of a simulation
+    fn le_rand(&mut self) -> u64 {
+        let mut x = self.le_rand_state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.le_rand_state = x;
+        x
+    }
+
+    /// One advertising PDU per advertising event
+    fn le_advertising_event(&mut self) {
+        self.le_advertising_deadline = self.now_ms + self.le_advertising_interval_ms;
+        let adv_a = self.le_own_address(self.le_advertising_own_address_type);
+        let data = self.le_advertising_data.clone();
+        let pdu = match self.le_advertising_type {
+            AdvertisingType::ConnectableAndScannnable => AdvPdu::AdvInd { adv_a, data },
+            AdvertisingType::ConnectableHighDuty | AdvertisingType::ConnectableLowDuty => {
+                AdvPdu::AdvDirectInd {
+                    adv_a,
+                    target_a: self.le_advertising_peer,
+                }
+            }
+            AdvertisingType::Scannable => AdvPdu::AdvScanInd { adv_a, data },
+            AdvertisingType::NonConnectable => AdvPdu::AdvNonconnInd { adv_a, data },
+        };
+        self.send_to_lower(pdu.to_u8_array());
+    }
+
+    fn le_recv_adv_pdu(&mut self, pdu: AdvPdu) {
+        let own = self.le_own_address(self.le_advertising_own_address_type);
+        // directed advertising targets the initiator or the scanner
+        let target = self.le_own_address(if self.le_initiating {
+            self.le_initiator_own_address_type
+        } else {
+            self.le_scan_own_address_type
+        });
+        match pdu {
+            AdvPdu::AdvInd { adv_a, data } => {
+                if !self.le_initiate(adv_a) {
+                    self.le_scan(adv_a, AdvertisingReportType::ConnectableAndScannable, data);
+                }
+            }
+            AdvPdu::AdvDirectInd { adv_a, target_a } => {
+                if target_a.1 == target.1 && !self.le_initiate(adv_a) {
+                    self.le_scan(
+                        adv_a,
+                        AdvertisingReportType::ConnectableDirected,
+                        Vec::new(),
+                    );
+                }
+            }
+            AdvPdu::AdvNonconnInd { adv_a, data } => {
+                self.le_scan(adv_a, AdvertisingReportType::NonConnectable, data)
+            }
+            AdvPdu::AdvScanInd { adv_a, data } => {
+                self.le_scan(adv_a, AdvertisingReportType::Scannable, data)
+            }
+            AdvPdu::ScanReq { scan_a, adv_a } => {
+                let scannable = matches!(
+                    self.le_advertising_type,
+                    AdvertisingType::ConnectableAndScannnable | AdvertisingType::Scannable
+                );
+                if adv_a.1 == own.1
+                    && scannable
+                    && self.le_advertising_accepts(scan_a.0, &scan_a.1, false)
+                {
+                    let data = self.le_scan_response_data.clone();
+                    self.send_to_lower(AdvPdu::ScanRsp { adv_a: own, data }.to_u8_array());
+                }
+            }
+            AdvPdu::ScanRsp { adv_a, data } => {
+                if self.le_scanning_accepts(adv_a.0, &adv_a.1) {
+                    self.le_advertising_report(adv_a, AdvertisingReportType::ScanResponse, data);
+                }
+            }
+            AdvPdu::ConnectInd {
+                init_a,
+                adv_a,
+                interval,
+                latency,
+                timeout,
+            } => {
+                if adv_a.1 != own.1 || !self.le_advertising {
+                    return;
+                }
+                // directed advertising only connects to its peer, whatever the filter policy
+                let accepted = match self.le_advertising_type {
+                    AdvertisingType::ConnectableAndScannnable => {
+                        self.le_advertising_accepts(init_a.0, &init_a.1, true)
+                    }
+                    AdvertisingType::ConnectableHighDuty | AdvertisingType::ConnectableLowDuty => {
+                        self.le_resolve(init_a.0, &init_a.1) == self.le_advertising_peer
+                    }
+                    AdvertisingType::Scannable | AdvertisingType::NonConnectable => false,
+                };
+                if accepted {
+                    self.le_advertising = false;
+                    hci::le_connection_complete(
+                        self,
+                        Role::Peripheral,
+                        init_a,
+                        (interval, latency, timeout),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Connects to a connectable advertiser the initiator accepts
+    fn le_initiate(&mut self, adv_a: AirAddress) -> bool {
+        if !self.le_initiating_accepts(adv_a.0, &adv_a.1) {
+            return false;
+        }
+        self.le_initiating = false;
+        let link_params = self.le_initiator_link_params;
+        let (interval, latency, timeout) = link_params;
+        let pdu = AdvPdu::ConnectInd {
+            init_a: self.le_own_address(self.le_initiator_own_address_type),
+            adv_a,
+            interval,
+            latency,
+            timeout,
+        };
+        self.send_to_lower(pdu.to_u8_array());
+        hci::le_connection_complete(self, Role::Central, adv_a, link_params);
+        true
+    }
+
+    /// Reports an advertisement the scanner accepts, an active scanner asks for the scan response
+    fn le_scan(&mut self, adv_a: AirAddress, event_type: AdvertisingReportType, data: Vec<u8>) {
+        if !self.le_scanning_accepts(adv_a.0, &adv_a.1) {
             return;
         }
-        let _packet_type = packet[0];
-        let opcode = u16::from_le_bytes(packet[1..3].try_into().unwrap());
-        let ogf = opcode_to_ogf(opcode) - 1;
-        let ocf = opcode_to_ocf(opcode) - 1;
-        info!("bb {} {}", ogf + 1, ocf + 1);
+        let scannable = matches!(
+            event_type,
+            AdvertisingReportType::ConnectableAndScannable | AdvertisingReportType::Scannable
+        );
+        self.le_advertising_report(adv_a, event_type, data);
+        if scannable && self.le_scan_type == LEScanType::Active {
+            let pdu = AdvPdu::ScanReq {
+                scan_a: self.le_own_address(self.le_scan_own_address_type),
+                adv_a,
+            };
+            self.send_to_lower(pdu.to_u8_array());
+        }
+    }
+
+    fn le_advertising_report(
+        &mut self,
+        adv_a: AirAddress,
+        event_type: AdvertisingReportType,
+        data: Vec<u8>,
+    ) {
+        let (address_type, address) = self.le_resolved_address(adv_a);
+        let report = LEAdvertisingReport {
+            event_type,
+            address_type,
+            address,
+            data,
+            rssi: LE_SIM_RSSI,
+        };
+        hci::le_advertising_report(self, report);
+    }
 
-        if let Some(cmd) = &HCI_CMD_TABLE[ogf as usize][ocf as usize] {
-            (cmd.handle)(self, opcode, &packet[3..]);
+    /// The address reported to the host, identity types once resolved
+    fn le_resolved_address(&self, device: AirAddress) -> (LEAddressType, BDAddr) {
+        let resolved = self.le_resolve(device.0, &device.1);
+        let address_type = match (resolved.0, resolved != device) {
+            (LEAddressType2::PublicDeviceOrPublicIdentity, false) => LEAddressType::PublicDevice,
+            (LEAddressType2::RandomDeviceOrRandomIdentity, false) => LEAddressType::RandomDevice,
+            (LEAddressType2::PublicDeviceOrPublicIdentity, true) => LEAddressType::PublicIdentity,
+            (LEAddressType2::RandomDeviceOrRandomIdentity, true) => LEAddressType::RandomIdentity,
+        };
+        (address_type, resolved.1)
+    }
+
+    /// Whether advertising answers the scan request, or the connect request if `connect`, of a device
+    fn le_advertising_accepts(
+        &self,
+        address_type: LEAddressType2,
+        address: &BDAddr,
+        connect: bool,
+    ) -> bool {
+        use AdvertisingFilterPolicy as F;
+        let filtered = match self.le_advertising_filter_policy {
+            F::UnFilter => false,
+            F::FilterOnlyScan => !connect,
+            F::FilterOnlyConnect => connect,
+            F::FilterBoth => true,
+        };
+        self.le_advertising
+            && (!filtered || self.le_filter_accept_list_contains(address_type, address))
+    }
+
+    /// Whether scanning reports the advertisements of a device
+    fn le_scanning_accepts(&self, address_type: LEAddressType2, address: &BDAddr) -> bool {
+        self.le_scanning
+            && (!self.le_scan_filter_accept_list_used()
+                || self.le_filter_accept_list_contains(address_type, address))
+    }
+
+    /// Whether initiating connects to an advertising device
+    fn le_initiating_accepts(&self, address_type: LEAddressType2, address: &BDAddr) -> bool {
+        if !self.le_initiating {
+            return false;
         }
+        if self.le_initiator_filter_policy {
+            return self.le_filter_accept_list_contains(address_type, address);
+        }
+        let (peer_type, peer_address) = self.le_initiator_peer;
+        self.le_resolve(address_type, address) == (peer_type, peer_address)
+            || (address_type, *address) == (peer_type, peer_address)
     }
 
-    fn power_on(&mut self) {}
+    /// `packet` holds the event parameters, starting with the subevent code for LE Meta
+    fn is_event_unmasked(&self, code: u8, packet: &[u8]) -> bool {
+        if code == HCIEvent::CommandComplete as u8
+            || code == HCIEvent::CommandStatus as u8
+            || code == HCIEvent::NumberOfCompletedPackets as u8
+        {
+            return true;
+        }
+        let unmasked = |mask: u64, code: u8| match code.checked_sub(1) {
+            Some(bit) if bit < 64 => mask & (1 << bit) != 0,
+            _ => true,
+        };
+        if !unmasked(self.event_mask.bits(), code) {
+            return false;
+        }
+        if code == HCIEvent::LEMeta as u8 {
+            return match packet.first() {
+                Some(&subevent) => unmasked(self.le_event_mask.bits(), subevent),
+                None => true,
+            };
+        }
+        true
+    }
 
     fn send_event(&mut self, code: u8, packet: Vec<u8>) {
-        if let Some(send) = self.upper_send_packet {
+        if !self.is_event_unmasked(code, &packet) {
+            info!("bb masked event: {} {:?}", code, packet);
+            return;
+        }
+        if let Some(send) = &self.upper_send_packet {
             info!("bb send: {} {:?}", code, packet);
             let mut tmp = vec![HCIPacket::Event as u8, code, packet.len() as u8];
             tmp.extend(packet);
-            send(&self, tmp);
+            send(self, tmp);
         }
     }
 
-    // fn send_to_lower(&mut self, packet: Vec<u8>) {
-    //     if let Some(send) = self.lower_send_packet {
-    //         send(&self, packet);
-    //     }
-    // }
+    fn send_to_lower(&mut self, packet: Vec<u8>) {
+        if let Some(send) = &self.lower_send_packet {
+            send(self, packet);
+        }
+    }
 }
 
-#[derive(EnumU8ToLeBytes, ToU8Array)]
+#[derive(EnumU8ToLeBytes, ToU8Array, FromBytes, Debug, Clone, Copy, PartialEq)]
 #[repr(u8)]
 pub enum ControllerErrorCode {
     Ok,
+    UnknownHCICommand,
+    UnknownConnectionIdentifier,
+    HardwareFailure,
+    PageTimeout,
+    AuthenticationFailure,
+    PINOrKeyMissing,
+    MemoryCapacityExceeded,
+    ConnectionTimeout,
+    ConnectionLimitExceeded,
+    SynchronousConnectionLimitToADeviceExceeded,
+    ConnectionAlreadyExists,
+    CommandDisallowed,
+    ConnectionRejectedDueToLimitedResources,
+    ConnectionRejectedDueToSecurityReasons,
+    ConnectionRejectedDueToUnacceptableBDAddr,
+    ConnectionAcceptTimeoutExceeded,
+    UnsupportedFeatureOrParameterValue,
+    InvalidHCICommandParameters,
+    RemoteUserTerminatedConnection,
+    RemoteDeviceTerminatedConnectionDueToLowResources,
+    RemoteDeviceTerminatedConnectionDueToPowerOff,
+    ConnectionTerminatedByLocalHost,
+    RepeatedAttempts,
+    PairingNotAllowed,
+    UnknownLMPPDU,
+    UnsupportedRemoteFeature,
+    SCOOffsetRejected,
+    SCOIntervalRejected,
+    SCOAirModeRejected,
+    InvalidLMPOrLLParameters,
+    UnspecifiedError,
+    UnsupportedLMPOrLLParameterValue,
+    RoleChangeNotAllowed,
+    LMPOrLLResponseTimeout,
+    LMPErrorTransactionCollisionOrLLProcedureCollision,
+    LMPPDUNotAllowed,
+    EncryptionModeNotAcceptable,
+    LinkKeyCannotBeChanged,
+    RequestedQoSNotSupported,
+    InstantPassed,
+    PairingWithUnitKeyNotSupported,
+    DifferentTransactionCollision,
+    QoSUnacceptableParameter = 0x2C,
+    QoSRejected,
+    ChannelClassificationNotSupported,
+    InsufficientSecurity,
+    ParameterOutOfMandatoryRange,
+    RoleSwitchPending = 0x32,
+    ReservedSlotViolation = 0x34,
+    RoleSwitchFailed,
+    ExtendedInquiryResponseTooLarge,
+    SecureSimplePairingNotSupportedByHost,
+    HostBusyPairing,
+    ConnectionRejectedDueToNoSuitableChannelFound,
+    ControllerBusy,
+    UnacceptableConnectionParameters,
+    AdvertisingTimeout,
+    ConnectionTerminatedDueToMICFailure,
+    ConnectionFailedToBeEstablished,
+    CoarseClockAdjustmentRejected = 0x40,
+    Type0SubmapNotDefined,
+    UnknownAdvertisingIdentifier,
+    LimitReached,
+    OperationCancelledByHost,
+    PacketTooLong,
+    /// Any code this host does not know, e.g. one added by a newer spec
+    #[from_bytes(other)]
+    Unknown = 0xFF,
+}
+
+impl ControllerErrorCode {
+    /// Reasons the host may give in an HCI_Disconnect
+    pub fn is_disconnect_reason(&self) -> bool {
+        matches!(
+            self,
+            Self::AuthenticationFailure
+                | Self::RemoteUserTerminatedConnection
+                | Self::RemoteDeviceTerminatedConnectionDueToLowResources
+                | Self::RemoteDeviceTerminatedConnectionDueToPowerOff
+                | Self::UnsupportedRemoteFeature
+                | Self::PairingWithUnitKeyNotSupported
+                | Self::UnacceptableConnectionParameters
+        )
+    }
 }
@@ -0,0 +1,51 @@
+use crate::baseband::ControllerErrorCode;
+
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Error {
+    /// The packet ends before all fields could be read
+    Truncated { expected: usize, actual: usize },
+    UnknownPacketType(u8),
+    UnknownEvent(u8),
+    UnknownLESubevent(u8),
+    UnknownCommand(u16),
+    UnknownConnectionHandle(u16),
+    /// A field holds a value outside of its defined range
+    InvalidParameter,
+    /// The controller answered a command with a non-success status
+    CommandFailed {
+        opcode: u16,
+        status: ControllerErrorCode,
+    },
+    /// The controller did not answer a command in time
+    CommandTimeout(u16),
+    /// The same operation is still running
+    Busy,
+    /// The controller has not reported the size of its ACL buffers yet
+    BufferSizeUnknown,
+}
+
+pub type Result<T> = core::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Truncated { expected, actual } => {
+                write!(f, "truncated packet: need {} bytes, got {}", expected, actual)
+            }
+            Error::UnknownPacketType(x) => write!(f, "unknown packet type {:#04x}", x),
+            Error::UnknownEvent(x) => write!(f, "unknown event {:#04x}", x),
+            Error::UnknownLESubevent(x) => write!(f, "unknown le subevent {:#04x}", x),
+            Error::UnknownCommand(x) => write!(f, "unknown command {:#06x}", x),
+            Error::UnknownConnectionHandle(x) => write!(f, "unknown connection handle {}", x),
+            Error::InvalidParameter => write!(f, "invalid parameter"),
+            Error::CommandFailed { opcode, status } => {
+                write!(f, "command {:#06x} failed: {:?}", opcode, status)
+            }
+            Error::CommandTimeout(x) => write!(f, "command {:#06x} timeout", x),
+            Error::Busy => write!(f, "busy"),
+            Error::BufferSizeUnknown => write!(f, "acl buffer size unknown"),
+        }
+    }
+}
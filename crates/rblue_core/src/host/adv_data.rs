@@ -0,0 +1,460 @@
+use super::hci_cmd::*;
+use super::hci_event::LEAdvertisingReport;
+use super::LE_ADV_DATA_MAX_LEN;
+use crate::{Error, Result};
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use bitflags::bitflags;
+use num::FromPrimitive;
+use num_derive::{FromPrimitive, ToPrimitive};
+
+/// Extended advertising data and scan response size
+pub const LE_EXT_ADV_DATA_MAX_LEN: usize = 1650;
+/// The length byte of an AD structure covers the type and the data
+pub const AD_STRUCTURE_DATA_MAX_LEN: usize = u8::MAX as usize - 1;
+
+/// Data types of the AD structures, from the assigned numbers
+#[derive(FromPrimitive, ToPrimitive, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum ADType {
+    Flags = 0x01,
+    IncompleteServiceUUIDs16 = 0x02,
+    CompleteServiceUUIDs16 = 0x03,
+    IncompleteServiceUUIDs32 = 0x04,
+    CompleteServiceUUIDs32 = 0x05,
+    IncompleteServiceUUIDs128 = 0x06,
+    CompleteServiceUUIDs128 = 0x07,
+    ShortenedLocalName = 0x08,
+    CompleteLocalName = 0x09,
+    TxPowerLevel = 0x0A,
+    ServiceData16 = 0x16,
+    Appearance = 0x19,
+    ServiceData32 = 0x20,
+    ServiceData128 = 0x21,
+    URI = 0x24,
+    ManufacturerSpecificData = 0xFF,
+}
+
+bitflags! {
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct ADFlags: u8 {
+        const LELimitedDiscoverable = 1 << 0;
+        const LEGeneralDiscoverable = 1 << 1;
+        const BREDRNotSupported = 1 << 2;
+        const SimultaneousLEAndBREDR = 1 << 3;
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ADStructure {
+    Flags(ADFlags),
+    ServiceUUIDs16 {
+        complete: bool,
+        uuids: Vec<u16>,
+    },
+    ServiceUUIDs32 {
+        complete: bool,
+        uuids: Vec<u32>,
+    },
+    ServiceUUIDs128 {
+        complete: bool,
+        uuids: Vec<u128>,
+    },
+    LocalName {
+        complete: bool,
+        name: String,
+    },
+    /// Range: -127 to +127, unit: dBm
+    TxPowerLevel(i8),
+    Appearance(u16),
+    ManufacturerData {
+        company_identifier: u16,
+        data: Vec<u8>,
+    },
+    ServiceData16 {
+        uuid: u16,
+        data: Vec<u8>,
+    },
+    ServiceData32 {
+        uuid: u32,
+        data: Vec<u8>,
+    },
+    ServiceData128 {
+        uuid: u128,
+        data: Vec<u8>,
+    },
+    /// The scheme is encoded as a single code point from the assigned numbers
+    URI(String),
+    /// Kept as is, so reports with newer types still decode
+    Unknown {
+        ad_type: u8,
+        data: Vec<u8>,
+    },
+}
+
+impl ADStructure {
+    pub fn ad_type(&self) -> u8 {
+        let ad_type = match self {
+            Self::Flags(_) => ADType::Flags,
+            Self::ServiceUUIDs16 { complete: true, .. } => ADType::CompleteServiceUUIDs16,
+            Self::ServiceUUIDs16 { .. } => ADType::IncompleteServiceUUIDs16,
+            Self::ServiceUUIDs32 { complete: true, .. } => ADType::CompleteServiceUUIDs32,
+            Self::ServiceUUIDs32 { .. } => ADType::IncompleteServiceUUIDs32,
+            Self::ServiceUUIDs128 { complete: true, .. } => ADType::CompleteServiceUUIDs128,
+            Self::ServiceUUIDs128 { .. } => ADType::IncompleteServiceUUIDs128,
+            Self::LocalName { complete: true, .. } => ADType::CompleteLocalName,
+            Self::LocalName { .. } => ADType::ShortenedLocalName,
+            Self::TxPowerLevel(_) => ADType::TxPowerLevel,
+            Self::Appearance(_) => ADType::Appearance,
+            Self::ManufacturerData { .. } => ADType::ManufacturerSpecificData,
+            Self::ServiceData16 { .. } => ADType::ServiceData16,
+            Self::ServiceData32 { .. } => ADType::ServiceData32,
+            Self::ServiceData128 { .. } => ADType::ServiceData128,
+            Self::URI(_) => ADType::URI,
+            Self::Unknown { ad_type, .. } => return *ad_type,
+        };
+        ad_type as u8
+    }
+
+    /// The AD data without the length and type bytes
+    pub fn data(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        match self {
+            Self::Flags(flags) => data.push(flags.bits()),
+            Self::ServiceUUIDs16 { uuids, .. } => uuids
+                .iter()
+                .for_each(|uuid| data.extend(uuid.to_le_bytes())),
+            Self::ServiceUUIDs32 { uuids, .. } => uuids
+                .iter()
+                .for_each(|uuid| data.extend(uuid.to_le_bytes())),
+            Self::ServiceUUIDs128 { uuids, .. } => uuids
+                .iter()
+                .for_each(|uuid| data.extend(uuid.to_le_bytes())),
+            Self::LocalName { name, .. } => data.extend(name.as_bytes()),
+            Self::TxPowerLevel(level) => data.push(*level as u8),
+            Self::Appearance(appearance) => data.extend(appearance.to_le_bytes()),
+            Self::ManufacturerData {
+                company_identifier,
+                data: payload,
+            } => {
+                data.extend(company_identifier.to_le_bytes());
+                data.extend(payload);
+            }
+            Self::ServiceData16 {
+                uuid,
+                data: payload,
+            } => {
+                data.extend(uuid.to_le_bytes());
+                data.extend(payload);
+            }
+            Self::ServiceData32 {
+                uuid,
+                data: payload,
+            } => {
+                data.extend(uuid.to_le_bytes());
+                data.extend(payload);
+            }
+            Self::ServiceData128 {
+                uuid,
+                data: payload,
+            } => {
+                data.extend(uuid.to_le_bytes());
+                data.extend(payload);
+            }
+            Self::URI(uri) => data.extend(uri.as_bytes()),
+            Self::Unknown { data: payload, .. } => data.extend(payload),
+        }
+        data
+    }
+
+    /// Decode the data of one AD structure of type `ad_type`
+    pub fn parse(ad_type: u8, data: &[u8]) -> Result<Self> {
+        let ad = match ADType::from_u8(ad_type) {
+            Some(ADType::Flags) => {
+                Self::Flags(ADFlags::from_bits_retain(take_bytes(data, 0, 1)?[0]))
+            }
+            Some(t @ (ADType::IncompleteServiceUUIDs16 | ADType::CompleteServiceUUIDs16)) => {
+                Self::ServiceUUIDs16 {
+                    complete: t == ADType::CompleteServiceUUIDs16,
+                    uuids: parse_list(data, u16::from_le_bytes)?,
+                }
+            }
+            Some(t @ (ADType::IncompleteServiceUUIDs32 | ADType::CompleteServiceUUIDs32)) => {
+                Self::ServiceUUIDs32 {
+                    complete: t == ADType::CompleteServiceUUIDs32,
+                    uuids: parse_list(data, u32::from_le_bytes)?,
+                }
+            }
+            Some(t @ (ADType::IncompleteServiceUUIDs128 | ADType::CompleteServiceUUIDs128)) => {
+                Self::ServiceUUIDs128 {
+                    complete: t == ADType::CompleteServiceUUIDs128,
+                    uuids: parse_list(data, u128::from_le_bytes)?,
+                }
+            }
+            Some(t @ (ADType::ShortenedLocalName | ADType::CompleteLocalName)) => Self::LocalName {
+                complete: t == ADType::CompleteLocalName,
+                name: String::from_utf8_lossy(data).into_owned(),
+            },
+            Some(ADType::TxPowerLevel) => Self::TxPowerLevel(take_bytes(data, 0, 1)?[0] as i8),
+            Some(ADType::Appearance) => Self::Appearance(u16::from_le_bytes(take_array(data)?)),
+            Some(ADType::ManufacturerSpecificData) => Self::ManufacturerData {
+                company_identifier: u16::from_le_bytes(take_array(data)?),
+                data: data[2..].to_vec(),
+            },
+            Some(ADType::ServiceData16) => Self::ServiceData16 {
+                uuid: u16::from_le_bytes(take_array(data)?),
+                data: data[2..].to_vec(),
+            },
+            Some(ADType::ServiceData32) => Self::ServiceData32 {
+                uuid: u32::from_le_bytes(take_array(data)?),
+                data: data[4..].to_vec(),
+            },
+            Some(ADType::ServiceData128) => Self::ServiceData128 {
+                uuid: u128::from_le_bytes(take_array(data)?),
+                data: data[16..].to_vec(),
+            },
+            Some(ADType::URI) => Self::URI(String::from_utf8_lossy(data).into_owned()),
+            None => Self::Unknown {
+                ad_type,
+                data: data.to_vec(),
+            },
+        };
+        Ok(ad)
+    }
+}
+
+impl ADStructure {
+    /// Length, type and data, fails if the data exceeds `AD_STRUCTURE_DATA_MAX_LEN`
+    pub fn encode(&self) -> Result<Vec<u8>> {
+        let data = self.data();
+        if data.len() > AD_STRUCTURE_DATA_MAX_LEN {
+            return Err(Error::InvalidParameter);
+        }
+        let mut array = Vec::with_capacity(data.len() + 2);
+        array.push(data.len() as u8 + 1);
+        array.push(self.ad_type());
+        array.extend(data);
+        Ok(array)
+    }
+}
+
+/// The AD structures of an advertising or scan response payload
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AdvertisingData {
+    structures: Vec<ADStructure>,
+}
+
+impl AdvertisingData {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, ad: ADStructure) -> &mut Self {
+        self.structures.push(ad);
+        self
+    }
+
+    pub fn structures(&self) -> &[ADStructure] {
+        &self.structures
+    }
+
+    pub fn local_name(&self) -> Option<&str> {
+        self.structures.iter().find_map(|ad| match ad {
+            ADStructure::LocalName { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn flags(&self) -> Option<ADFlags> {
+        self.structures.iter().find_map(|ad| match ad {
+            ADStructure::Flags(flags) => Some(*flags),
+            _ => None,
+        })
+    }
+
+    /// Encode for a legacy advertising PDU, at most `LE_ADV_DATA_MAX_LEN` bytes
+    pub fn to_legacy(&self) -> Result<Vec<u8>> {
+        self.encode(LE_ADV_DATA_MAX_LEN)
+    }
+
+    /// Encode for extended advertising, at most `LE_EXT_ADV_DATA_MAX_LEN` bytes
+    pub fn to_extended(&self) -> Result<Vec<u8>> {
+        self.encode(LE_EXT_ADV_DATA_MAX_LEN)
+    }
+
+    fn encode(&self, max_len: usize) -> Result<Vec<u8>> {
+        let mut array = Vec::new();
+        for ad in self.structures.iter() {
+            array.extend(ad.encode()?);
+        }
+        if array.len() > max_len {
+            return Err(Error::InvalidParameter);
+        }
+        Ok(array)
+    }
+
+    /// The structures before the first malformed one, and the error it gave
+    pub fn parse_partial(bytes: &[u8]) -> (Self, Result<()>) {
+        let mut structures = Vec::new();
+        let mut offset = 0;
+        while offset < bytes.len() {
+            let length = bytes[offset] as usize;
+            // a zero length ends the significant part, the rest is padding
+            if length == 0 {
+                break;
+            }
+            let structure = match take_bytes(bytes, offset + 1, length)
+                .and_then(|structure| ADStructure::parse(structure[0], &structure[1..]))
+            {
+                Ok(structure) => structure,
+                Err(err) => return (AdvertisingData { structures }, Err(err)),
+            };
+            structures.push(structure);
+            offset += 1 + length;
+        }
+        (AdvertisingData { structures }, Ok(()))
+    }
+}
+
+impl RBlueFromU8Array for AdvertisingData {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let (data, ret) = Self::parse_partial(bytes);
+        ret.map(|_| data)
+    }
+}
+
+impl TryFrom<&AdvertisingData> for LESetAdvertisingDataCmd {
+    type Error = Error;
+
+    fn try_from(value: &AdvertisingData) -> Result<Self> {
+        let data = value.to_legacy()?;
+        let mut advertising_data = [0; LE_ADV_DATA_MAX_LEN];
+        advertising_data[..data.len()].copy_from_slice(&data);
+        Ok(LESetAdvertisingDataCmd {
+            advertising_data_length: data.len() as u8,
+            advertising_data,
+        })
+    }
+}
+
+impl TryFrom<&AdvertisingData> for LESetScanResponseDataCmd {
+    type Error = Error;
+
+    fn try_from(value: &AdvertisingData) -> Result<Self> {
+        let data = value.to_legacy()?;
+        let mut scan_response_data = [0; LE_ADV_DATA_MAX_LEN];
+        scan_response_data[..data.len()].copy_from_slice(&data);
+        Ok(LESetScanResponseDataCmd {
+            scan_response_data_length: data.len() as u8,
+            scan_response_data,
+        })
+    }
+}
+
+impl LEAdvertisingReport {
+    /// Decode the AD structures carried by the report
+    pub fn advertising_data(&self) -> Result<AdvertisingData> {
+        AdvertisingData::from_u8_array(&self.data)
+    }
+}
+
+fn take_array<const N: usize>(data: &[u8]) -> Result<[u8; N]> {
+    let bytes = take_bytes(data, 0, N)?;
+    bytes.try_into().map_err(|_| Error::InvalidParameter)
+}
+
+fn parse_list<T, const N: usize>(data: &[u8], f: fn([u8; N]) -> T) -> Result<Vec<T>> {
+    if !data.len().is_multiple_of(N) {
+        return Err(Error::InvalidParameter);
+    }
+    data.chunks_exact(N)
+        .map(|chunk| take_array(chunk).map(f))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+    use alloc::vec;
+
+    fn sample() -> AdvertisingData {
+        let mut data = AdvertisingData::new();
+        data.push(ADStructure::Flags(
+            ADFlags::LEGeneralDiscoverable | ADFlags::BREDRNotSupported,
+        ))
+        .push(ADStructure::ServiceUUIDs16 {
+            complete: true,
+            uuids: vec![0x180D, 0x180F],
+        })
+        .push(ADStructure::LocalName {
+            complete: true,
+            name: "rblue".to_string(),
+        })
+        .push(ADStructure::TxPowerLevel(-8));
+        data
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let bytes = sample().to_legacy().unwrap();
+        assert_eq!(
+            bytes,
+            [
+                0x02, 0x01, 0x06, 0x05, 0x03, 0x0D, 0x18, 0x0F, 0x18, 0x06, 0x09, b'r', b'b', b'l',
+                b'u', b'e', 0x02, 0x0A, 0xF8
+            ]
+        );
+        assert_eq!(AdvertisingData::from_u8_array(&bytes).unwrap(), sample());
+    }
+
+    #[test]
+    fn parse_keeps_unknown_types_and_stops_at_padding() {
+        let bytes = [0x03, 0x2A, 0x01, 0x02, 0x00, 0x00, 0x00];
+        let data = AdvertisingData::from_u8_array(&bytes).unwrap();
+        assert_eq!(
+            data.structures(),
+            [ADStructure::Unknown {
+                ad_type: 0x2A,
+                data: vec![0x01, 0x02],
+            }]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_truncated_structure() {
+        assert!(AdvertisingData::from_u8_array(&[0x05, 0x09, b'a']).is_err());
+    }
+
+    #[test]
+    fn structure_data_too_long() {
+        let longest = ADStructure::ManufacturerData {
+            company_identifier: 0x0059,
+            data: vec![0; AD_STRUCTURE_DATA_MAX_LEN - 2],
+        };
+        let bytes = longest.encode().unwrap();
+        assert_eq!(bytes[0], 0xFF);
+        assert_eq!(bytes.len(), 256);
+
+        let too_long = ADStructure::ManufacturerData {
+            company_identifier: 0x0059,
+            data: vec![0; AD_STRUCTURE_DATA_MAX_LEN - 1],
+        };
+        assert!(too_long.encode().is_err());
+        let mut data = AdvertisingData::new();
+        data.push(too_long);
+        assert!(data.to_extended().is_err());
+    }
+
+    #[test]
+    fn legacy_data_too_long() {
+        let mut data = AdvertisingData::new();
+        data.push(ADStructure::LocalName {
+            complete: true,
+            name: "a name that does not fit in 31 bytes".to_string(),
+        });
+        assert!(data.to_legacy().is_err());
+        assert!(data.to_extended().is_ok());
+    }
+}
@@ -0,0 +1,267 @@
+use super::hci_cmd::*;
+use super::*;
+
+use alloc::vec::Vec;
+use pub_fields::pub_fields;
+
+/// Handle of the set the legacy `gap_advertisements_*` config is sent on
+/// when the controller is driven through the extended advertising commands
+pub const LE_LEGACY_ADV_SET_HANDLE: u8 = 0x00;
+pub const LE_ADV_SET_HANDLE_MAX: u8 = 0xEF;
+/// Advertising data carried by one extended advertising data command
+pub const LE_EXT_ADV_DATA_FRAGMENT_LEN: usize = 251;
+/// Advertising data carried by one periodic advertising data command
+pub const LE_PERIODIC_ADV_DATA_FRAGMENT_LEN: usize = 252;
+
+/// Parameters of one extended advertising set
+#[pub_fields]
+#[derive(Clone)]
+pub struct AdvertisingSetParams {
+    event_properties: AdvertisingEventProperties,
+    /// Range: 0x000020 to 0xFFFFFF, unit: 0.625ms
+    interval_min: u32,
+    interval_max: u32,
+    channel_map: u8,
+    own_address_type: LEAddressType,
+    /// Only used by directed advertising
+    peer_address_type: LEAddressType,
+    peer_address: BDAddr,
+    filter_policy: AdvertisingFilterPolicy,
+    /// dBm, 0x7F lets the controller choose
+    tx_power: i8,
+    /// Not LE 2M
+    primary_phy: LEPhy,
+    secondary_max_skip: u8,
+    secondary_phy: LEPhy,
+    /// Range: 0x00 to 0x0F
+    sid: u8,
+    scan_request_notification: bool,
+}
+
+impl Default for AdvertisingSetParams {
+    fn default() -> Self {
+        AdvertisingSetParams {
+            event_properties: AdvertisingEventProperties::Connectable,
+            interval_min: 0x0800,
+            interval_max: 0x0800,
+            channel_map: 0x07,
+            own_address_type: LEAddressType::PublicDevice,
+            peer_address_type: LEAddressType::PublicDevice,
+            peer_address: BDAddr::default(),
+            filter_policy: AdvertisingFilterPolicy::UnFilter,
+            tx_power: 0x7F,
+            primary_phy: LEPhy::LE1M,
+            secondary_max_skip: 0,
+            secondary_phy: LEPhy::LE1M,
+            sid: 0,
+            scan_request_notification: false,
+        }
+    }
+}
+
+impl AdvertisingSetParams {
+    pub fn is_valid(&self) -> bool {
+        use AdvertisingEventProperties as P;
+        let properties = self.event_properties;
+        let properties_valid = if properties.contains(P::Legacy) {
+            // only the PDUs of the legacy advertising types
+            [
+                P::Connectable | P::Scannable,
+                P::Connectable | P::Directed,
+                P::Connectable | P::Directed | P::HighDutyCycleDirected,
+                P::Scannable,
+                P::empty(),
+            ]
+            .contains(&(properties - P::Legacy))
+        } else {
+            !properties.contains(P::Connectable | P::Scannable)
+                && !properties.contains(P::HighDutyCycleDirected)
+                && !properties.contains(P::Anonymous | P::Connectable)
+        };
+        properties_valid
+            && (0x000020..=0xFFFFFF).contains(&self.interval_min)
+            && (self.interval_min..=0xFFFFFF).contains(&self.interval_max)
+            && self.channel_map & 0x07 != 0
+            && self.channel_map & !0x07 == 0
+            && self.primary_phy != LEPhy::LE2M
+            && self.sid <= 0x0F
+    }
+
+    /// Longest advertising data or scan response the PDUs can carry
+    pub fn max_data_len(&self) -> usize {
+        if self
+            .event_properties
+            .contains(AdvertisingEventProperties::Legacy)
+        {
+            LE_ADV_DATA_MAX_LEN
+        } else {
+            adv_data::LE_EXT_ADV_DATA_MAX_LEN
+        }
+    }
+
+    pub fn is_scannable(&self) -> bool {
+        self.event_properties
+            .contains(AdvertisingEventProperties::Scannable)
+    }
+
+    /// Periodic advertising needs non-connectable, non-scannable extended PDUs with an address
+    pub fn supports_periodic(&self) -> bool {
+        use AdvertisingEventProperties as P;
+        !self
+            .event_properties
+            .intersects(P::Legacy | P::Connectable | P::Scannable | P::Anonymous)
+    }
+
+    pub fn to_cmd(&self, advertising_handle: u8) -> LESetExtendedAdvertisingParametersCmd {
+        LESetExtendedAdvertisingParametersCmd {
+            advertising_handle,
+            advertising_event_properties: self.event_properties,
+            primary_advertising_interval_min: self.interval_min,
+            primary_advertising_interval_max: self.interval_max,
+            primary_advertising_channel_map: self.channel_map,
+            own_address_type: self.own_address_type,
+            peer_address_type: LEAddressType2::from(self.peer_address_type),
+            peer_address: self.peer_address,
+            advertising_filter_policy: self.filter_policy.clone(),
+            advertising_tx_power: self.tx_power,
+            primary_advertising_phy: self.primary_phy,
+            secondary_advertising_max_skip: self.secondary_max_skip,
+            secondary_advertising_phy: self.secondary_phy,
+            advertising_sid: self.sid,
+            scan_request_notification_enable: self.scan_request_notification,
+        }
+    }
+}
+
+/// Periodic advertising on top of an extended advertising set
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeriodicAdvertisingParams {
+    /// Range: 0x0006 to 0xFFFF, unit: 1.25ms
+    interval_min: u16,
+    interval_max: u16,
+    include_tx_power: bool,
+}
+
+impl Default for PeriodicAdvertisingParams {
+    fn default() -> Self {
+        PeriodicAdvertisingParams {
+            interval_min: 0x0050,
+            interval_max: 0x0050,
+            include_tx_power: false,
+        }
+    }
+}
+
+impl PeriodicAdvertisingParams {
+    pub fn is_valid(&self) -> bool {
+        self.interval_min >= 0x0006 && self.interval_max >= self.interval_min
+    }
+
+    pub fn to_cmd(&self, advertising_handle: u8) -> LESetPeriodicAdvertisingParametersCmd {
+        LESetPeriodicAdvertisingParametersCmd {
+            advertising_handle,
+            periodic_advertising_interval_min: self.interval_min,
+            periodic_advertising_interval_max: self.interval_max,
+            periodic_advertising_properties: if self.include_tx_power { 1 << 6 } else { 0 },
+        }
+    }
+}
+
+/// An extended advertising set, the todo flags say what the controller has not seen yet
+#[pub_fields]
+pub struct AdvertisingSet {
+    /// Range: 0x01 to `LE_ADV_SET_HANDLE_MAX`
+    handle: u8,
+    params: AdvertisingSetParams,
+    data: Vec<u8>,
+    scan_response_data: Vec<u8>,
+    /// Required when the own address type is random
+    random_address: Option<BDAddr>,
+    /// Unit: 10ms, zero advertises until disabled
+    duration: u16,
+    /// Zero for no limit
+    max_events: u8,
+    /// None until periodic advertising is configured
+    periodic_params: Option<PeriodicAdvertisingParams>,
+    periodic_data: Vec<u8>,
+    state: LEAdvertisementsState,
+    periodic_state: LEAdvertisementsState,
+    todo: LEAdvertisementsTodo,
+}
+
+impl AdvertisingSet {
+    pub fn new(handle: u8, params: AdvertisingSetParams) -> Self {
+        AdvertisingSet {
+            handle,
+            params,
+            data: Vec::new(),
+            scan_response_data: Vec::new(),
+            random_address: None,
+            duration: 0,
+            max_events: 0,
+            periodic_params: None,
+            periodic_data: Vec::new(),
+            state: LEAdvertisementsState::Idle,
+            periodic_state: LEAdvertisementsState::Idle,
+            todo: LEAdvertisementsTodo::SetParams,
+        }
+    }
+
+    pub fn enable_param(&self) -> AdvertisingSetEnable {
+        AdvertisingSetEnable {
+            advertising_handle: self.handle,
+            duration: self.duration,
+            max_extended_advertising_events: self.max_events,
+        }
+    }
+
+    /// The controller has to stop advertising the set before applying the todo flags
+    pub fn needs_stop(&self) -> bool {
+        use LEAdvertisementsTodo as T;
+        let data_fragmented = (self.todo.contains(T::SetAdvData)
+            && self.data.len() > LE_EXT_ADV_DATA_FRAGMENT_LEN)
+            || (self.todo.contains(T::SetScanData)
+                && self.scan_response_data.len() > LE_EXT_ADV_DATA_FRAGMENT_LEN);
+        self.state.contains(LEAdvertisementsState::Active)
+            && (self
+                .todo
+                .intersects(T::SetParams | T::SetAddress | T::RemoveSet)
+                || !self.state.contains(LEAdvertisementsState::Enabled)
+                || data_fragmented)
+    }
+
+    /// Periodic advertising has to stop before its parameters or fragmented data change
+    pub fn periodic_needs_stop(&self) -> bool {
+        use LEAdvertisementsTodo as T;
+        let data_fragmented = self.todo.contains(T::SetPeriodicData)
+            && self.periodic_data.len() > LE_PERIODIC_ADV_DATA_FRAGMENT_LEN;
+        self.periodic_state.contains(LEAdvertisementsState::Active)
+            && (self.todo.intersects(T::SetPeriodicParams | T::RemoveSet)
+                || !self.periodic_state.contains(LEAdvertisementsState::Enabled)
+                || data_fragmented)
+    }
+}
+
+/// Split advertising data into fragments of at most `max_len` bytes for the
+/// extended and periodic data commands, empty data is sent as one complete fragment
+pub fn fragment_advertising_data(
+    data: &[u8],
+    max_len: usize,
+) -> Vec<(AdvertisingDataOperation, &[u8])> {
+    if data.len() <= max_len {
+        return alloc::vec![(AdvertisingDataOperation::Complete, data)];
+    }
+    let count = data.len().div_ceil(max_len);
+    data.chunks(max_len)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let operation = match i {
+                0 => AdvertisingDataOperation::First,
+                i if i == count - 1 => AdvertisingDataOperation::Last,
+                _ => AdvertisingDataOperation::Intermediate,
+            };
+            (operation, chunk)
+        })
+        .collect()
+}
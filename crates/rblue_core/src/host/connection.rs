@@ -0,0 +1,414 @@
+use super::hci_cmd::*;
+use super::*;
+use crate::Result;
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use pub_fields::pub_fields;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConnectionState {
+    /// Accepted by the controller, waiting for the connection complete
+    Pending,
+    Connected,
+    /// Disconnect sent, waiting for the disconnection complete
+    Disconnecting,
+}
+
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LinkParams {
+    /// Range: 0x0006 to 0x0C80, unit: 1.25ms
+    interval: u16,
+    /// Range: 0x0000 to 0x01F3
+    latency: u16,
+    /// Range: 0x000A to 0x0C80, unit: 10ms
+    supervision_timeout: u16,
+}
+
+/// Payload octets and time in us of the LL data PDUs, a new link starts with the minimum
+pub const LE_DATA_LENGTH_MIN_OCTETS: u16 = 0x001B;
+pub const LE_DATA_LENGTH_MAX_OCTETS: u16 = 0x00FB;
+pub const LE_DATA_LENGTH_MIN_TIME: u16 = 0x0148;
+pub const LE_DATA_LENGTH_MAX_TIME: u16 = 0x4290;
+
+/// Longest LL data PDUs in each direction of an LE link
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DataLength {
+    max_tx_octets: u16,
+    /// Unit: us
+    max_tx_time: u16,
+    max_rx_octets: u16,
+    max_rx_time: u16,
+}
+
+impl Default for DataLength {
+    fn default() -> Self {
+        DataLength {
+            max_tx_octets: LE_DATA_LENGTH_MIN_OCTETS,
+            max_tx_time: LE_DATA_LENGTH_MIN_TIME,
+            max_rx_octets: LE_DATA_LENGTH_MIN_OCTETS,
+            max_rx_time: LE_DATA_LENGTH_MIN_TIME,
+        }
+    }
+}
+
+/// Whether the controller accepts `octets` and `time` as data length
+pub fn is_valid_data_length(octets: u16, time: u16) -> bool {
+    (LE_DATA_LENGTH_MIN_OCTETS..=LE_DATA_LENGTH_MAX_OCTETS).contains(&octets)
+        && (LE_DATA_LENGTH_MIN_TIME..=LE_DATA_LENGTH_MAX_TIME).contains(&time)
+}
+
+/// Requested by either side of an LE link, also the payload of the
+/// L2CAP Connection Parameter Update Request
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnectionUpdateParams {
+    /// Range: 0x0006 to 0x0C80, unit: 1.25ms
+    interval_min: u16,
+    interval_max: u16,
+    /// Range: 0x0000 to 0x01F3
+    latency: u16,
+    /// Range: 0x000A to 0x0C80, unit: 10ms
+    supervision_timeout: u16,
+}
+
+impl ConnectionUpdateParams {
+    pub fn is_valid(&self) -> bool {
+        (0x0006..=0x0C80).contains(&self.interval_min)
+            && (self.interval_min..=0x0C80).contains(&self.interval_max)
+            && self.latency <= 0x01F3
+            && (0x000A..=0x0C80).contains(&self.supervision_timeout)
+            // the supervision timeout must cover two of the longest intervals
+            && self.supervision_timeout as u32 * 4
+                > (1 + self.latency as u32) * self.interval_max as u32
+    }
+}
+
+impl RBlueToU8Array for ConnectionUpdateParams {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = Vec::new();
+        array.extend(self.interval_min.to_le_bytes());
+        array.extend(self.interval_max.to_le_bytes());
+        array.extend(self.latency.to_le_bytes());
+        array.extend(self.supervision_timeout.to_le_bytes());
+        array
+    }
+}
+
+impl RBlueFromU8Array for ConnectionUpdateParams {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let bytes = take_bytes(bytes, 0, 8)?;
+        let u16_at = |i: usize| u16::from_le_bytes([bytes[i], bytes[i + 1]]);
+        Ok(ConnectionUpdateParams {
+            interval_min: u16_at(0),
+            interval_max: u16_at(2),
+            latency: u16_at(4),
+            supervision_timeout: u16_at(6),
+        })
+    }
+}
+
+/// Parameters of the connections created as LE central
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LEConnectionParams {
+    /// Range: 0x0004 to 0x4000, unit: 0.625ms
+    scan_interval: u16,
+    /// Range: 0x0004 to 0x4000, unit: 0.625ms
+    scan_window: u16,
+    /// Range: 0x0006 to 0x0C80, unit: 1.25ms
+    interval_min: u16,
+    interval_max: u16,
+    /// Range: 0x0000 to 0x01F3
+    latency: u16,
+    /// Range: 0x000A to 0x0C80, unit: 10ms
+    supervision_timeout: u16,
+    /// Unit: 0.625ms
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl Default for LEConnectionParams {
+    fn default() -> Self {
+        LEConnectionParams {
+            scan_interval: 0x0060,
+            scan_window: 0x0030,
+            interval_min: 0x0008,
+            interval_max: 0x0018,
+            latency: 4,
+            supervision_timeout: 72,
+            min_ce_length: 2,
+            max_ce_length: 0x0030,
+        }
+    }
+}
+
+/// A peer asking for a BR/EDR link, answered by the connection request policy
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ConnectionRequest {
+    remote: BDAddr,
+    /// 24 bits: major service classes, major and minor device class
+    class_of_device: u32,
+    link_type: LinkType,
+}
+
+/// Role switches the host allows on BR/EDR links
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RoleSwitchPreference {
+    /// Every link keeps the role it was created with
+    Never,
+    /// Incoming links stay peripheral, the peer may become central on outgoing ones
+    Allow,
+    /// Incoming links switch to central, the peer may become central on outgoing ones
+    Central,
+}
+
+impl RoleSwitchPreference {
+    /// The Allow_Role_Switch parameter of Create Connection
+    pub fn allow_role_switch(&self) -> u8 {
+        (*self != RoleSwitchPreference::Never) as u8
+    }
+
+    /// The role asked for when accepting a connection request
+    pub fn accept_role(&self) -> Role {
+        match self {
+            RoleSwitchPreference::Central => Role::Central,
+            _ => Role::Peripheral,
+        }
+    }
+}
+
+#[pub_fields]
+pub struct HCIConnection {
+    remote: BDAddr,
+    addr_type: BDAddrType,
+    /// Resolved with a bonded IRK when the peer connected with a resolvable private address
+    identity_address: Option<BDAddr>,
+    role: Role,
+    state: ConnectionState,
+    /// None on BR/EDR links
+    params: Option<LinkParams>,
+    /// LE links start on the LE 1M PHY
+    tx_phy: LEPhy,
+    rx_phy: LEPhy,
+    data_length: DataLength,
+    encrypted: bool,
+    /// ACL packets handed to the controller but not completed yet
+    acl_in_flight: u16,
+}
+
+impl HCIConnection {
+    pub fn new(remote: BDAddr, addr_type: BDAddrType, role: Role, state: ConnectionState) -> Self {
+        HCIConnection {
+            remote,
+            addr_type,
+            identity_address: None,
+            role,
+            state,
+            params: None,
+            tx_phy: LEPhy::LE1M,
+            rx_phy: LEPhy::LE1M,
+            data_length: DataLength::default(),
+            encrypted: false,
+            acl_in_flight: 0,
+        }
+    }
+
+    pub fn is_le(&self) -> bool {
+        self.addr_type != BDAddrType::Classic
+    }
+}
+
+/// Links keyed by connection handle, and the ones still waiting for a handle
+#[derive(Default)]
+pub struct ConnectionTable {
+    links: BTreeMap<u16, HCIConnection>,
+    pending: Vec<HCIConnection>,
+}
+
+impl ConnectionTable {
+    pub fn get(&self, handle: u16) -> Option<&HCIConnection> {
+        self.links.get(&handle)
+    }
+
+    pub fn get_mut(&mut self, handle: u16) -> Option<&mut HCIConnection> {
+        self.links.get_mut(&handle)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (u16, &HCIConnection)> {
+        self.links.iter().map(|(handle, conn)| (*handle, conn))
+    }
+
+    pub fn handles(&self) -> Vec<u16> {
+        self.links.keys().copied().collect()
+    }
+
+    /// Pending or connected to `remote`
+    pub fn contains_remote(&self, remote: &BDAddr) -> bool {
+        self.pending.iter().any(|c| c.remote == *remote)
+            || self.links.values().any(|c| c.remote == *remote)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.links.is_empty() && self.pending.is_empty()
+    }
+
+    pub fn add_pending(&mut self, conn: HCIConnection) {
+        self.pending.push(conn);
+    }
+
+    /// The pending or connected link to `remote`, with the handle once connected
+    pub fn get_remote_mut(&mut self, remote: &BDAddr) -> Option<(Option<u16>, &mut HCIConnection)> {
+        if let Some(conn) = self.pending.iter_mut().find(|c| c.remote == *remote) {
+            return Some((None, conn));
+        }
+        self.links
+            .iter_mut()
+            .find(|(_, c)| c.remote == *remote)
+            .map(|(handle, conn)| (Some(*handle), conn))
+    }
+
+    pub fn take_pending(&mut self, remote: &BDAddr) -> Option<HCIConnection> {
+        let index = self.pending.iter().position(|c| c.remote == *remote)?;
+        Some(self.pending.remove(index))
+    }
+
+    /// The LE link of our create connection, the controller initiates one at a time
+    pub fn take_pending_le_central(&mut self) -> Option<HCIConnection> {
+        let index = self
+            .pending
+            .iter()
+            .position(|c| c.is_le() && c.role == Role::Central)?;
+        Some(self.pending.remove(index))
+    }
+
+    pub fn clear_pending(&mut self) {
+        self.pending.clear();
+    }
+
+    pub fn insert(&mut self, handle: u16, conn: HCIConnection) {
+        self.links.insert(handle, conn);
+    }
+
+    pub fn remove(&mut self, handle: u16) -> Option<HCIConnection> {
+        self.links.remove(&handle)
+    }
+
+    /// Remove everything, returns the links that had a handle
+    pub fn clear(&mut self) -> BTreeMap<u16, HCIConnection> {
+        self.pending.clear();
+        core::mem::take(&mut self.links)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(interval: u16, latency: u16, supervision_timeout: u16) -> ConnectionUpdateParams {
+        ConnectionUpdateParams {
+            interval_min: interval,
+            interval_max: interval,
+            latency,
+            supervision_timeout,
+        }
+    }
+
+    fn le_central(remote: BDAddr) -> HCIConnection {
+        HCIConnection::new(
+            remote,
+            BDAddrType::LEPublic,
+            Role::Central,
+            ConnectionState::Pending,
+        )
+    }
+
+    #[test]
+    fn pending_until_connected() {
+        let mut table = ConnectionTable::default();
+        assert!(table.is_empty());
+        table.add_pending(le_central([1; 6]));
+        assert!(!table.is_empty());
+        assert!(table.contains_remote(&[1; 6]));
+        assert!(matches!(table.get_remote_mut(&[1; 6]), Some((None, _))));
+        assert!(table.handles().is_empty());
+
+        let conn = table.take_pending(&[1; 6]).unwrap();
+        table.insert(0x0040, conn);
+        assert!(table.take_pending(&[1; 6]).is_none());
+        assert!(matches!(
+            table.get_remote_mut(&[1; 6]),
+            Some((Some(0x0040), _))
+        ));
+        assert_eq!(table.handles(), [0x0040]);
+    }
+
+    #[test]
+    fn pending_central_taken_for_another_address() {
+        let mut table = ConnectionTable::default();
+        let mut classic = le_central([2; 6]);
+        classic.addr_type = BDAddrType::Classic;
+        table.add_pending(classic);
+        table.add_pending(le_central([1; 6]));
+
+        // the peer connected with an address other than the requested one
+        assert!(table.take_pending(&[3; 6]).is_none());
+        let conn = table.take_pending_le_central().unwrap();
+        assert_eq!(conn.remote, [1; 6]);
+        assert!(table.take_pending_le_central().is_none());
+        assert!(table.contains_remote(&[2; 6]));
+    }
+
+    #[test]
+    fn lookup_and_remove_by_handle() {
+        let mut table = ConnectionTable::default();
+        table.insert(0x0001, le_central([1; 6]));
+        table.insert(0x0002, le_central([2; 6]));
+        assert_eq!(table.get(0x0002).map(|c| c.remote), Some([2; 6]));
+        assert!(table.get(0x0003).is_none());
+        table.get_mut(0x0001).unwrap().encrypted = true;
+        assert!(table.get(0x0001).unwrap().encrypted);
+
+        assert_eq!(table.remove(0x0001).map(|c| c.remote), Some([1; 6]));
+        assert!(table.remove(0x0001).is_none());
+        assert!(table.get(0x0001).is_none());
+        assert!(!table.contains_remote(&[1; 6]));
+        assert_eq!(table.handles(), [0x0002]);
+
+        table.add_pending(le_central([3; 6]));
+        let links = table.clear();
+        assert_eq!(links.keys().copied().collect::<Vec<_>>(), [0x0002]);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn update_params_interval_range() {
+        assert!(params(0x0006, 0, 0x000A).is_valid());
+        assert!(!params(0x0005, 0, 0x000A).is_valid());
+        assert!(params(0x0C80, 0, 0x0C80).is_valid());
+        assert!(!params(0x0C81, 0, 0x0C80).is_valid());
+
+        let mut reversed = params(0x0010, 0, 0x0064);
+        reversed.interval_min = 0x0011;
+        assert!(!reversed.is_valid());
+    }
+
+    #[test]
+    fn update_params_latency_range() {
+        // 500 intervals of 7.5ms need more than 7.5s of supervision timeout
+        assert!(params(0x0006, 0x01F3, 0x0C80).is_valid());
+        assert!(!params(0x0006, 0x01F4, 0x0C80).is_valid());
+    }
+
+    #[test]
+    fn update_params_timeout_covers_two_intervals() {
+        // (1 + 1) * 100 * 1.25ms * 2 = 500ms
+        assert!(!params(0x0064, 1, 50).is_valid());
+        assert!(params(0x0064, 1, 51).is_valid());
+        assert!(!params(0x0006, 0, 0x0009).is_valid());
+        assert!(!params(0x0006, 0, 0x0C81).is_valid());
+    }
+}
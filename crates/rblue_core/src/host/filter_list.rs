@@ -0,0 +1,197 @@
+use super::*;
+use crate::{Error, Result};
+
+use alloc::vec::Vec;
+use pub_fields::pub_fields;
+
+/// A device on the filter accept list
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterAcceptListEntry {
+    address_type: LEAddressType2,
+    address: BDAddr,
+}
+
+/// A peer the controller resolves, the IRKs are little endian
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ResolvingListEntry {
+    identity_address_type: LEAddressType2,
+    identity_address: BDAddr,
+    peer_irk: [u8; 16],
+    local_irk: [u8; 16],
+}
+
+pub trait ControllerListEntry: Copy + PartialEq {
+    /// Entries with the same identity replace each other
+    fn identity(&self) -> (LEAddressType2, BDAddr);
+}
+
+impl ControllerListEntry for FilterAcceptListEntry {
+    fn identity(&self) -> (LEAddressType2, BDAddr) {
+        (self.address_type, self.address)
+    }
+}
+
+impl ControllerListEntry for ResolvingListEntry {
+    fn identity(&self) -> (LEAddressType2, BDAddr) {
+        (self.identity_address_type, self.identity_address)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ControllerListEntryState {
+    Add,
+    Added,
+    Remove,
+}
+
+/// What has to be sent to bring the controller list up to date
+pub struct ControllerListChanges<T> {
+    clear: bool,
+    removals: Vec<T>,
+    additions: Vec<T>,
+}
+
+impl<T> ControllerListChanges<T> {
+    pub fn clear(&self) -> bool {
+        self.clear
+    }
+
+    pub fn removals(&self) -> &[T] {
+        &self.removals
+    }
+
+    pub fn additions(&self) -> &[T] {
+        &self.additions
+    }
+}
+
+/// The host copy of a controller list, the entry states say what the controller has not seen yet
+pub struct ControllerList<T> {
+    entries: Vec<(T, ControllerListEntryState)>,
+    /// The controller list has to be cleared before the entries are sent
+    clear: bool,
+    /// Read from the controller, zero if unknown
+    size: u8,
+}
+
+impl<T> Default for ControllerList<T> {
+    fn default() -> Self {
+        ControllerList {
+            entries: Vec::new(),
+            clear: false,
+            size: 0,
+        }
+    }
+}
+
+impl<T: ControllerListEntry> ControllerList<T> {
+    /// The entries as they will be on the controller
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries
+            .iter()
+            .filter(|(_, state)| *state != ControllerListEntryState::Remove)
+            .map(|(entry, _)| entry)
+    }
+
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn size(&self) -> u8 {
+        self.size
+    }
+
+    pub fn set_size(&mut self, size: u8) {
+        self.size = size;
+    }
+
+    pub fn get(&self, identity: (LEAddressType2, BDAddr)) -> Option<&T> {
+        self.iter().find(|entry| entry.identity() == identity)
+    }
+
+    /// Replaces the entry with the same identity, fails once the controller list is full
+    pub fn add(&mut self, entry: T) -> Result<()> {
+        if let Some(existing) = self.get(entry.identity()) {
+            if *existing == entry {
+                return Ok(());
+            }
+            self.remove(entry.identity())?;
+        }
+        if self.size != 0 && self.len() >= self.size as usize {
+            return Err(Error::InvalidParameter);
+        }
+        self.entries.push((entry, ControllerListEntryState::Add));
+        Ok(())
+    }
+
+    pub fn remove(&mut self, identity: (LEAddressType2, BDAddr)) -> Result<()> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(entry, state)| {
+                entry.identity() == identity && *state != ControllerListEntryState::Remove
+            })
+            .ok_or(Error::InvalidParameter)?;
+        match self.entries[index].1 {
+            // the controller never saw it
+            ControllerListEntryState::Add => {
+                self.entries.remove(index);
+            }
+            _ => self.entries[index].1 = ControllerListEntryState::Remove,
+        }
+        Ok(())
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.clear = true;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.clear
+            || self
+                .entries
+                .iter()
+                .any(|(_, state)| *state != ControllerListEntryState::Added)
+    }
+
+    /// The changes to send, the entries count as sent afterwards
+    pub fn take_changes(&mut self) -> ControllerListChanges<T> {
+        let mut changes = ControllerListChanges {
+            clear: core::mem::take(&mut self.clear),
+            removals: Vec::new(),
+            additions: Vec::new(),
+        };
+        self.entries.retain(|(entry, state)| match state {
+            ControllerListEntryState::Add => {
+                changes.additions.push(*entry);
+                true
+            }
+            ControllerListEntryState::Remove => {
+                changes.removals.push(*entry);
+                false
+            }
+            ControllerListEntryState::Added => true,
+        });
+        for (_, state) in self.entries.iter_mut() {
+            *state = ControllerListEntryState::Added;
+        }
+        changes
+    }
+
+    /// The controller list is empty after a reset, every entry has to be sent again
+    pub fn reset(&mut self) {
+        self.entries
+            .retain(|(_, state)| *state != ControllerListEntryState::Remove);
+        for (_, state) in self.entries.iter_mut() {
+            *state = ControllerListEntryState::Add;
+        }
+        self.clear = false;
+    }
+}
@@ -1,11 +1,22 @@
+use super::adv_set::*;
+use super::connection::*;
+use super::filter_list::*;
 use super::hci_cmd::*;
+use super::hci_event::*;
+use super::inquiry::*;
+use super::l2cap::*;
+use super::periodic_sync::*;
+use super::privacy::*;
 use super::*;
+use crate::{Error, Result};
 
 use crate::alloc::borrow::ToOwned;
 
-use alloc::collections::LinkedList;
+use alloc::boxed::Box;
+use alloc::collections::{BTreeMap, VecDeque};
 use alloc::vec::Vec;
-use log::info;
+use log::{error, info, warn};
+use pub_fields::pub_fields;
 
 use num::ToPrimitive;
 use num_derive::{FromPrimitive, ToPrimitive};
@@ -29,9 +40,41 @@ pub enum HCICmd {
 #[derive(FromPrimitive)]
 #[repr(u8)]
 pub enum HCIEvent {
+    InquiryComplete = 0x01,
+    InquiryResult,
+    ConnectionComplete,
+    ConnectionRequest,
+    DisconnectionComplete = 0x05,
+    EncryptionChange = 0x08,
     CommandComplete = 0x0E,
+    CommandStatus = 0x0F,
+    HardwareError = 0x10,
+    RoleChange = 0x12,
+    NumberOfCompletedPackets = 0x13,
+    InquiryResultWithRSSI = 0x22,
+    ExtendedInquiryResult = 0x2F,
+    LEMeta = 0x3E,
 }
 
+#[derive(FromPrimitive)]
+#[repr(u8)]
+pub enum LESubevent {
+    ConnectionComplete = 0x01,
+    AdvertisingReport,
+    ConnectionUpdateComplete,
+    ReadRemoteFeaturesComplete,
+    LongTermKeyRequest,
+    RemoteConnectionParameterRequest,
+    DataLengthChange,
+    PHYUpdateComplete = 0x0C,
+    ExtendedAdvertisingReport,
+    PeriodicAdvertisingSyncEstablished,
+    PeriodicAdvertisingReport,
+    PeriodicAdvertisingSyncLost,
+    AdvertisingSetTerminated = 0x12,
+}
+
+#[derive(FromPrimitive, ToPrimitive)]
 #[repr(u16)]
 pub enum LinkControl {
     Inquiry = 0x0001,
@@ -40,16 +83,27 @@ pub enum LinkControl {
     ExitPeriodicInquiryMod,
     CreateConnection,
     Disconnect,
-    AcceptConnectionRequest,
+    AcceptConnectionRequest = 0x0009,
     RejectConnectionRequest,
 }
 
 impl HCICmdOpcode for LinkControl {
     fn get_opcode(&self) -> u16 {
-        match self {
-            _ => {}
-        }
-        0
+        let ogf = HCICmd::LinkControl as u8;
+        into_opcode(ogf, self.to_u16().unwrap())
+    }
+}
+
+#[derive(FromPrimitive, ToPrimitive)]
+#[repr(u16)]
+pub enum LinkPolicy {
+    WriteDefaultLinkPolicySettings = 0x000F,
+}
+
+impl HCICmdOpcode for LinkPolicy {
+    fn get_opcode(&self) -> u16 {
+        let ogf = HCICmd::LinkPolicy as u8;
+        into_opcode(ogf, self.to_u16().unwrap())
     }
 }
 
@@ -58,6 +112,30 @@ impl HCICmdOpcode for LinkControl {
 pub enum ControllerAndBaseband {
     SetEventMask = 0x0001,
     Reset = 0x0003,
+    WritePINType = 0x000A,
+    WriteConnectionAcceptTimeout = 0x0016,
+    WritePageTimeout = 0x0018,
+    WriteScanEnable = 0x001A,
+    WritePageScanActivity = 0x001C,
+    WriteInquiryScanActivity = 0x001E,
+    WriteAuthenticationEnable = 0x0020,
+    WriteClassOfDevice = 0x0024,
+    WriteVoiceSetting = 0x0026,
+    WriteAutomaticFlushTimeout = 0x0028,
+    WriteNumBroadcastRetransmissions = 0x002A,
+    WriteHoldModeActivity = 0x002C,
+    WriteSynchronousFlowControlEnable = 0x002F,
+    WriteLinkSupervisionTimeout = 0x0037,
+    WriteInquiryScanType = 0x0043,
+    WriteInquiryMode = 0x0045,
+    WritePageScanType = 0x0047,
+    WriteDefaultErroneousDataReporting = 0x005B,
+    WriteLEHostSupport = 0x006D,
+    WriteSynchronizationTrainParameters = 0x0078,
+    WriteSecureConnectionsHostSupport = 0x007A,
+    WriteAuthenticatedPayloadTimeout = 0x007C,
+    WriteExtendedPageTimeout = 0x007E,
+    WriteExtendedInquiryLength = 0x0080,
 }
 
 impl HCICmdOpcode for ControllerAndBaseband {
@@ -70,7 +148,8 @@ impl HCICmdOpcode for ControllerAndBaseband {
 #[derive(FromPrimitive, ToPrimitive)]
 #[repr(u16)]
 pub enum InformationalParam {
-    ReadLocalSupportedCommands = 0x0002,
+    ReadLocalVersionInformation = 0x0001,
+    ReadLocalSupportedCommands,
     ReadLocalSupportedFeatures,
     ReadLocalExtendedSupportedFeatures,
     ReadBufferSize,
@@ -96,7 +175,47 @@ pub enum LEController {
     LESetAdvertisingData,
     LESetScanResponseData,
     LESetAdvertisingEnable,
-    LECreateConnection = 0x000D,
+    LESetScanParameters,
+    LESetScanEnable,
+    LECreateConnection,
+    LECreateConnectionCancel,
+    LEReadFilterAcceptListSize,
+    LEClearFilterAcceptList,
+    LEAddDeviceToFilterAcceptList,
+    LERemoveDeviceFromFilterAcceptList,
+    LEConnectionUpdate = 0x0013,
+    LERand = 0x0018,
+    LERemoteConnectionParameterRequestReply = 0x0020,
+    LERemoteConnectionParameterRequestNegativeReply,
+    LESetDataLength = 0x0022,
+    LEReadSuggestedDefaultDataLength,
+    LEWriteSuggestedDefaultDataLength,
+    LEAddDeviceToResolvingList = 0x0027,
+    LERemoveDeviceFromResolvingList,
+    LEClearResolvingList,
+    LEReadResolvingListSize,
+    LESetAddressResolutionEnable = 0x002D,
+    LEReadPHY = 0x0030,
+    LESetDefaultPHY,
+    LESetPHY,
+    LESetAdvertisingSetRandomAddress = 0x0035,
+    LESetExtendedAdvertisingParameters,
+    LESetExtendedAdvertisingData,
+    LESetExtendedScanResponseData,
+    LESetExtendedAdvertisingEnable,
+    LEReadMaximumAdvertisingDataLength,
+    LEReadNumberOfSupportedAdvertisingSets,
+    LERemoveAdvertisingSet,
+    LEClearAdvertisingSets,
+    LESetPeriodicAdvertisingParameters,
+    LESetPeriodicAdvertisingData,
+    LESetPeriodicAdvertisingEnable,
+    LESetExtendedScanParameters,
+    LESetExtendedScanEnable,
+    LEExtendedCreateConnection,
+    LEPeriodicAdvertisingCreateSync,
+    LEPeriodicAdvertisingCreateSyncCancel,
+    LEPeriodicAdvertisingTerminateSync,
 }
 
 impl HCICmdOpcode for LEController {
@@ -106,10 +225,129 @@ impl HCICmdOpcode for LEController {
     }
 }
 
-struct HCIConnection {
-    remote: BDAddr,
-    #[allow(dead_code)]
-    addr_type: BDAddrType,
+/// Position in the supported commands bitmap, octet << 8 | bit mask
+#[derive(Clone, Copy)]
+#[repr(u16)]
+pub enum SupportedCommand {
+    Inquiry = 0x0001,
+    InquiryCancel = 0x0002,
+    PeriodicInquiryMode = 0x0004,
+    ExitPeriodicInquiryMode = 0x0008,
+    CreateConnection = 0x0010,
+    Disconnect = 0x0020,
+    AcceptConnectionRequest = 0x0101,
+    RejectConnectionRequest = 0x0102,
+    WriteDefaultLinkPolicySettings = 0x0510,
+    SetEventMask = 0x0540,
+    Reset = 0x0580,
+    WritePINType = 0x0608,
+    WriteConnectionAcceptTimeout = 0x0708,
+    WritePageTimeout = 0x0720,
+    WriteScanEnable = 0x0780,
+    WritePageScanActivity = 0x0802,
+    WriteInquiryScanActivity = 0x0808,
+    WriteAuthenticationEnable = 0x0820,
+    WriteClassOfDevice = 0x0902,
+    WriteVoiceSetting = 0x0908,
+    WriteAutomaticFlushTimeout = 0x0920,
+    WriteNumBroadcastRetransmissions = 0x0980,
+    WriteHoldModeActivity = 0x0a02,
+    WriteSynchronousFlowControlEnable = 0x0a10,
+    WriteLinkSupervisionTimeout = 0x0b02,
+    WriteInquiryScanType = 0x0c20,
+    WriteInquiryMode = 0x0c80,
+    WritePageScanType = 0x0d02,
+    ReadLocalVersionInformation = 0x0e08,
+    ReadLocalSupportedFeatures = 0x0e20,
+    ReadLocalExtendedFeatures = 0x0e40,
+    ReadBufferSize = 0x0e80,
+    ReadBDAddr = 0x0f02,
+    WriteDefaultErroneousDataReporting = 0x1208,
+    WriteLEHostSupport = 0x1840,
+    LESetEventMask = 0x1901,
+    LEReadBufferSize = 0x1902,
+    LEReadLocalSupportedFeatures = 0x1904,
+    LESetRandomAddress = 0x1910,
+    LESetAdvertisingParameters = 0x1920,
+    LEReadAdvertisingPhysicalChannelTxPower = 0x1940,
+    LESetAdvertisingData = 0x1980,
+    LESetScanResponseData = 0x1a01,
+    LESetAdvertisingEnable = 0x1a02,
+    LESetScanParameters = 0x1a04,
+    LESetScanEnable = 0x1a08,
+    LECreateConnection = 0x1a10,
+    LECreateConnectionCancel = 0x1a20,
+    LEReadFilterAcceptListSize = 0x1a40,
+    LEClearFilterAcceptList = 0x1a80,
+    LEAddDeviceToFilterAcceptList = 0x1b01,
+    LERemoveDeviceFromFilterAcceptList = 0x1b02,
+    LEConnectionUpdate = 0x1b04,
+    LERand = 0x1b80,
+    LERemoteConnectionParameterRequestReply = 0x2110,
+    LERemoteConnectionParameterRequestNegativeReply = 0x2120,
+    WriteSynchronizationTrainParameters = 0x2001,
+    WriteSecureConnectionsHostSupport = 0x2008,
+    WriteAuthenticatedPayloadTimeout = 0x2020,
+    WriteExtendedPageTimeout = 0x2102,
+    WriteExtendedInquiryLength = 0x2108,
+    LESetDataLength = 0x2140,
+    LEReadSuggestedDefaultDataLength = 0x2180,
+    LEWriteSuggestedDefaultDataLength = 0x2201,
+    LEAddDeviceToResolvingList = 0x2208,
+    LERemoveDeviceFromResolvingList = 0x2210,
+    LEClearResolvingList = 0x2220,
+    LEReadResolvingListSize = 0x2240,
+    LESetAddressResolutionEnable = 0x2302,
+    LEReadPHY = 0x2310,
+    LESetDefaultPHY = 0x2320,
+    LESetPHY = 0x2340,
+    LESetAdvertisingSetRandomAddress = 0x2402,
+    LESetExtendedAdvertisingParameters = 0x2404,
+    LESetExtendedAdvertisingData = 0x2408,
+    LESetExtendedScanResponseData = 0x2410,
+    LESetExtendedAdvertisingEnable = 0x2420,
+    LEReadMaximumAdvertisingDataLength = 0x2440,
+    LEReadNumberOfSupportedAdvertisingSets = 0x2480,
+    LERemoveAdvertisingSet = 0x2501,
+    LEClearAdvertisingSets = 0x2502,
+    LESetPeriodicAdvertisingParameters = 0x2504,
+    LESetPeriodicAdvertisingData = 0x2508,
+    LESetPeriodicAdvertisingEnable = 0x2510,
+    LESetExtendedScanParameters = 0x2520,
+    LESetExtendedScanEnable = 0x2540,
+    LEExtendedCreateConnection = 0x2580,
+    LEPeriodicAdvertisingCreateSync = 0x2601,
+    LEPeriodicAdvertisingCreateSyncCancel = 0x2602,
+    LEPeriodicAdvertisingTerminateSync = 0x2604,
+}
+
+/// Config writes sent per connection handle
+const HCI_LINK_CONFIG_WRITES: [HCIConfigWrite; 3] = [
+    HCIConfigWrite::AutomaticFlushTimeout,
+    HCIConfigWrite::LinkSupervisionTimeout,
+    HCIConfigWrite::AuthenticatedPayloadTimeout,
+];
+
+/// Commands the controller has not answered within this time are dropped
+const HCI_CMD_TIMEOUT_MS: u64 = 2000;
+
+/// Links whose Disconnection Complete has not come within this time are
+/// dropped by the reset that ends the halting
+const HALT_DISCONNECT_TIMEOUT_MS: u64 = 5000;
+
+/// An LE connection not established within this time is cancelled
+const LE_CONNECT_TIMEOUT_MS: u64 = 10000;
+
+struct HCIPendingCmd {
+    opcode: u16,
+    param: Option<Vec<u8>>,
+}
+
+struct HCIWaitingCmd {
+    opcode: u16,
+    /// The raw parameters as sent, the answer handlers read the handle or address from them
+    param: Option<Vec<u8>>,
+    deadline: u64,
 }
 
 #[repr(u8)]
@@ -120,91 +358,371 @@ pub enum HCIPacket {
     Event,
 }
 
+#[derive(Clone, Copy, PartialEq)]
+enum LEPeriodicSyncState {
+    Idle,
+    /// Waiting for `run_gap_le` to send the create sync
+    Todo(PeriodicSyncTarget),
+    /// The controller scans for the train until the sync established event
+    W4Established,
+    /// The cancel still has to be sent
+    Cancel,
+    /// Cancel sent, the controller ends with a failed sync established
+    W4Cancelled,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LEConnectState {
+    Idle,
+    /// Waiting for `run_gap_le` to send the create connection
+    Todo(BDAddr, LEAddressType),
+    W4Complete {
+        deadline: u64,
+    },
+    /// The cancel still has to be sent, `timeout` if the host gave up
+    Cancel {
+        timeout: bool,
+    },
+    /// Cancel sent, the controller ends with a failed connection complete
+    W4Cancelled {
+        timeout: bool,
+    },
+}
+
 #[derive(PartialEq, PartialOrd)]
 enum HCIState {
     Off,
     Initializing,
     Working,
-    // Halting,
-    // Sleeping,
-    // FallingSleep,
+    Halting,
+    Sleeping,
+    FallingSleep,
 }
 
-#[derive(PartialEq)]
+/// Steps of the init sequence, in the order they are sent
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HCIInitStep {
+    Reset,
+    ReadLocalSupportedCommands,
+    ReadLocalVersionInformation,
+    ReadLocalSupportedFeatures,
+    SetEventMask,
+    WriteLEHostSupport,
+    LEReadLocalSupportedFeatures,
+    LESetEventMask,
+    LEReadMaximumAdvertisingDataLength,
+    LEReadNumberOfSupportedAdvertisingSets,
+    LEReadFilterAcceptListSize,
+    LEReadResolvingListSize,
+    LEReadBufferSize,
+    ReadBufferSize,
+    ReadBDAddr,
+}
+
+const HCI_INIT_STEPS: [HCIInitStep; 15] = [
+    HCIInitStep::Reset,
+    HCIInitStep::ReadLocalSupportedCommands,
+    HCIInitStep::ReadLocalVersionInformation,
+    HCIInitStep::ReadLocalSupportedFeatures,
+    HCIInitStep::SetEventMask,
+    HCIInitStep::WriteLEHostSupport,
+    HCIInitStep::LEReadLocalSupportedFeatures,
+    HCIInitStep::LESetEventMask,
+    HCIInitStep::LEReadMaximumAdvertisingDataLength,
+    HCIInitStep::LEReadNumberOfSupportedAdvertisingSets,
+    HCIInitStep::LEReadFilterAcceptListSize,
+    HCIInitStep::LEReadResolvingListSize,
+    HCIInitStep::LEReadBufferSize,
+    HCIInitStep::ReadBufferSize,
+    HCIInitStep::ReadBDAddr,
+];
+
+impl HCIInitStep {
+    fn opcode(&self) -> u16 {
+        match self {
+            Self::Reset => ControllerAndBaseband::Reset.get_opcode(),
+            Self::ReadLocalSupportedCommands => {
+                InformationalParam::ReadLocalSupportedCommands.get_opcode()
+            }
+            Self::ReadLocalVersionInformation => {
+                InformationalParam::ReadLocalVersionInformation.get_opcode()
+            }
+            Self::ReadLocalSupportedFeatures => {
+                InformationalParam::ReadLocalSupportedFeatures.get_opcode()
+            }
+            Self::SetEventMask => ControllerAndBaseband::SetEventMask.get_opcode(),
+            Self::WriteLEHostSupport => ControllerAndBaseband::WriteLEHostSupport.get_opcode(),
+            Self::LEReadLocalSupportedFeatures => {
+                LEController::LEReadLocalSupportedFeatures.get_opcode()
+            }
+            Self::LESetEventMask => LEController::LESetEventMask.get_opcode(),
+            Self::LEReadMaximumAdvertisingDataLength => {
+                LEController::LEReadMaximumAdvertisingDataLength.get_opcode()
+            }
+            Self::LEReadNumberOfSupportedAdvertisingSets => {
+                LEController::LEReadNumberOfSupportedAdvertisingSets.get_opcode()
+            }
+            Self::LEReadFilterAcceptListSize => {
+                LEController::LEReadFilterAcceptListSize.get_opcode()
+            }
+            Self::LEReadResolvingListSize => LEController::LEReadResolvingListSize.get_opcode(),
+            Self::LEReadBufferSize => LEController::LEReadBufferSize.get_opcode(),
+            Self::ReadBufferSize => InformationalParam::ReadBufferSize.get_opcode(),
+            Self::ReadBDAddr => InformationalParam::ReadBDAddr.get_opcode(),
+        }
+    }
+
+    /// Without these the host can not work, a failure aborts the init
+    fn is_required(&self) -> bool {
+        matches!(
+            self,
+            Self::Reset
+                | Self::ReadLocalSupportedCommands
+                | Self::LEReadBufferSize
+                | Self::ReadBufferSize
+        )
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
 enum HCISubState {
-    SendReset,
-    W4SendReset,
-    SendReadLocalSupportedCommands,
-    W4SendReadLocalSupportedCommands,
-    SendReadLocalSupportedFeatures,
-    W4SendReadLocalSupportedFeatures,
-    SendSetEventMask,
-    W4SendSetEventMask,
-    SendLESetEventMask,
-    W4SendLESetEventMask,
-    SendLEReadBufferSize,
-    W4SendLEReadBufferSize,
-    SendReadBufferSize,
-    W4SendReadBufferSize,
-    SendLEReadLocalSupportedFeatures,
-    W4SendLEReadLocalSupportedFeatures,
-    SendReadBDAddr,
-    W4SendReadBDAddr,
+    Send(HCIInitStep),
+    W4Send(HCIInitStep),
     End,
+    /// Halting: stop advertising and disconnect every link
+    HaltStopActivities,
+    HaltW4Disconnect {
+        deadline: u64,
+    },
+    HaltW4Reset,
+    /// Falling asleep: stop advertising and wait for the commands in flight
+    SleepStopActivities,
+    SleepW4Commands,
+}
+
+/// Optional steps of the init sequence, takes effect on the next power on
+#[pub_fields]
+pub struct HCIInitConfig {
+    read_local_version_information: bool,
+    /// Set the LE Supported (Host) bit on BR/EDR controllers that support LE
+    write_le_host_support: bool,
+    /// Decides the event masks sent to the controller
+    features: HostFeatures,
+}
+
+impl Default for HCIInitConfig {
+    fn default() -> Self {
+        Self {
+            read_local_version_information: true,
+            write_le_host_support: true,
+            features: HostFeatures::all(),
+        }
+    }
 }
 
+/// For commands the `u16` is the opcode, for ACL data it is the connection handle
+/// with the packet boundary and broadcast flags
+pub type HCISendPacket = Box<dyn Fn(&HCI, HCIPacket, u16, Option<Vec<u8>>)>;
+pub type HCIEventHandler = Box<dyn Fn(&HCI, &AppEvent)>;
+/// Decides whether to accept the parameters a peer asked for on a connection handle
+pub type HCIConnParamPolicy = Box<dyn Fn(&HCI, u16, &ConnectionUpdateParams) -> bool>;
+pub type HCIConnectionRequestPolicy = Box<dyn Fn(&HCI, &ConnectionRequest) -> bool>;
+
 pub struct HCI {
-    // config: HCIConfigParam,
+    config: HCIConfigParam,
     state: HCIState,
     sub_state: HCISubState,
+    init_config: HCIInitConfig,
 
-    send_packet: Option<fn(&Self, HCIPacket, u16, Option<Vec<u8>>)>,
+    send_packet: Option<HCISendPacket>,
+    event_handlers: Vec<HCIEventHandler>,
+    conn_param_policy: Option<HCIConnParamPolicy>,
+    connection_request_policy: Option<HCIConnectionRequestPolicy>,
 
-    connections: LinkedList<HCIConnection>,
+    /// Number of commands the controller can accept right now
+    cmd_credits: u8,
+    cmd_queue: VecDeque<HCIPendingCmd>,
+    cmd_waiting: Vec<HCIWaitingCmd>,
+    /// Monotonic time in ms, as last reported through `poll_timers`
+    now_ms: u64,
 
-    bd_addr: BDAddr,
+    connections: ConnectionTable,
+    /// Applied to the BR/EDR links created or accepted from now on
+    role_switch_preference: RoleSwitchPreference,
 
-    scan_enable: ScanEnable,
+    supported_commands: SupportedCommands,
+    lmp_features: LMPFeatures,
+    le_features: LEFeatures,
+    controller_bd_addr: BDAddr,
+    local_version: Option<ReadLocalVersionInformationRet>,
+
+    acl_packet_length: u16,
+    acl_packets_total: u16,
+    acl_packets_free: u16,
+    /// Zero if the controller shares the ACL buffers with LE
+    le_acl_packet_length: u16,
+    le_acl_packets_total: u16,
+    le_acl_packets_free: u16,
+    /// Fragments waiting for a free controller buffer, the bool marks LE links
+    acl_queue: VecDeque<(ACLHeader, Vec<u8>, bool)>,
+    acl_recombination: Recombination,
+
+    bd_addr: BDAddr,
 
     le_advertisements_interval_min: u16,
     le_advertisements_interval_max: u16,
     le_advertisements_type: AdvertisingType,
     le_own_address_type: LEAddressType,
+    le_own_address_mode: LEOwnAddressMode,
+    /// Little endian, required by `LEOwnAddressMode::Resolvable`
+    le_local_irk: Option<[u8; 16]>,
+    /// None until a private address was generated
+    le_random_address: Option<BDAddr>,
+    le_private_address_timeout_ms: u64,
+    /// When the private address is rotated, None asks for a new one
+    le_private_address_deadline: Option<u64>,
+    /// Waiting for the random number of a new private address
+    le_rand_pending: bool,
+    le_bonds: Vec<BondedDevice>,
+    le_filter_accept_list: ControllerList<FilterAcceptListEntry>,
+    /// Only sent to controllers with LL privacy
+    le_resolving_list: ControllerList<ResolvingListEntry>,
+    le_address_resolution_enabled: bool,
     le_advertisements_peer_address_type: LEAddressType,
     le_advertisements_peer_address: BDAddr,
     le_advertisements_channel_map: u8,
     le_advertisements_filter_policy: AdvertisingFilterPolicy,
+    le_advertisements_data: Vec<u8>,
+    le_scan_response_data: Vec<u8>,
 
     le_advertisements_state: LEAdvertisementsState,
     le_advertisements_todo: LEAdvertisementsTodo,
+
+    /// The legacy config was sent as advertising set 0
+    le_legacy_adv_set_created: bool,
+    /// Only driven when the controller supports extended advertising
+    le_advertising_sets: Vec<AdvertisingSet>,
+    /// Zero until read from the controller
+    le_max_advertising_data_length: u16,
+    le_num_advertising_sets: u8,
+
+    le_scan_type: LEScanType,
+    le_scan_interval: u16,
+    le_scan_window: u16,
+    le_scan_filter_policy: ScanningFilterPolicy,
+    le_scan_filter_duplicates: bool,
+
+    le_scanning_state: LEScanningState,
+    /// The scan parameters have to be written before the next scan enable
+    le_scanning_param_update: bool,
+
+    le_connection_params: LEConnectionParams,
+    le_connect_timeout_ms: u64,
+    /// Tx and rx PHY preferences of the LE links created from now on
+    le_default_phy: Option<(LEPhys, LEPhys)>,
+    /// Suggested max tx octets and time of the LE links created from now on
+    le_default_data_length: Option<(u16, u16)>,
+    /// The connection defaults have to be written to the controller
+    le_connection_defaults_update: bool,
+    le_connect_state: LEConnectState,
+    /// An inquiry runs until the Inquiry Complete event or a cancel
+    inquiry_active: bool,
+    /// Paging parameters of the devices found by inquiries
+    inquiry_cache: InquiryCache,
+    le_periodic_sync_state: LEPeriodicSyncState,
+    /// Keyed by sync handle
+    le_periodic_syncs: BTreeMap<u16, PeriodicSync>,
+    /// Identifier of the next request on the LE signaling channel
+    l2cap_sig_identifier: u8,
 }
 
 impl HCI {
     pub fn new(bd_addr: BDAddr) -> Self {
         HCI {
-            // config: HCIConfigParam::default(),
+            config: HCIConfigParam::default(),
             state: HCIState::Off,
-            sub_state: HCISubState::SendReset,
+            sub_state: HCISubState::Send(HCIInitStep::Reset),
+            init_config: HCIInitConfig::default(),
 
             send_packet: None,
+            event_handlers: Vec::new(),
+            conn_param_policy: None,
+            connection_request_policy: None,
 
-            connections: LinkedList::new(),
+            cmd_credits: 1,
+            cmd_queue: VecDeque::new(),
+            cmd_waiting: Vec::new(),
+            now_ms: 0,
 
-            bd_addr,
+            connections: ConnectionTable::default(),
+            role_switch_preference: RoleSwitchPreference::Allow,
 
-            scan_enable: ScanEnable::NoScansEnable,
+            supported_commands: [0; 64],
+            lmp_features: LMPFeatures::empty(),
+            le_features: LEFeatures::empty(),
+            controller_bd_addr: BDAddr::default(),
+            local_version: None,
+
+            acl_packet_length: 0,
+            acl_packets_total: 0,
+            acl_packets_free: 0,
+            le_acl_packet_length: 0,
+            le_acl_packets_total: 0,
+            le_acl_packets_free: 0,
+            acl_queue: VecDeque::new(),
+            acl_recombination: Recombination::default(),
+
+            bd_addr,
 
             le_advertisements_interval_min: 0x0800,
             le_advertisements_interval_max: 0x0800,
             le_advertisements_type: AdvertisingType::ConnectableAndScannnable,
             le_own_address_type: LEAddressType::PublicDevice,
+            le_own_address_mode: LEOwnAddressMode::Public,
+            le_local_irk: None,
+            le_random_address: None,
+            le_private_address_timeout_ms: LE_PRIVATE_ADDRESS_TIMEOUT_MS,
+            le_private_address_deadline: None,
+            le_rand_pending: false,
+            le_bonds: Vec::new(),
+            le_filter_accept_list: ControllerList::default(),
+            le_resolving_list: ControllerList::default(),
+            le_address_resolution_enabled: false,
             le_advertisements_peer_address_type: LEAddressType::PublicDevice,
             le_advertisements_peer_address: BDAddr::default(),
             le_advertisements_channel_map: 0x07,
             le_advertisements_filter_policy: AdvertisingFilterPolicy::UnFilter,
+            le_advertisements_data: Vec::new(),
+            le_scan_response_data: Vec::new(),
 
             le_advertisements_state: LEAdvertisementsState::Idle,
             le_advertisements_todo: LEAdvertisementsTodo::Idle,
+
+            le_legacy_adv_set_created: false,
+            le_advertising_sets: Vec::new(),
+            le_max_advertising_data_length: 0,
+            le_num_advertising_sets: 0,
+
+            le_scan_type: LEScanType::Active,
+            le_scan_interval: 0x01E0,
+            le_scan_window: 0x0030,
+            le_scan_filter_policy: ScanningFilterPolicy::BasicUnfiltered,
+            le_scan_filter_duplicates: true,
+
+            le_scanning_state: LEScanningState::Idle,
+            le_scanning_param_update: true,
+
+            le_connection_params: LEConnectionParams::default(),
+            le_connect_timeout_ms: LE_CONNECT_TIMEOUT_MS,
+            le_default_phy: None,
+            le_default_data_length: None,
+            le_connection_defaults_update: false,
+            le_connect_state: LEConnectState::Idle,
+            inquiry_active: false,
+            inquiry_cache: InquiryCache::default(),
+            le_periodic_sync_state: LEPeriodicSyncState::Idle,
+            le_periodic_syncs: BTreeMap::new(),
+            l2cap_sig_identifier: 1,
         }
     }
 
@@ -212,155 +730,319 @@ impl HCI {
         return self.bd_addr;
     }
 
-    pub fn set_send_packet(&mut self, send_packet: fn(&Self, HCIPacket, u16, Option<Vec<u8>>)) {
-        self.send_packet = Some(send_packet);
+    /// The public address read from the controller during init
+    pub fn get_controller_bd_addr(&self) -> BDAddr {
+        self.controller_bd_addr
+    }
+
+    /// None if the version was not read during init
+    pub fn get_local_version(&self) -> Option<&ReadLocalVersionInformationRet> {
+        self.local_version.as_ref()
+    }
+
+    pub fn get_connection(&self, handle: u16) -> Option<&HCIConnection> {
+        self.connections.get(handle)
+    }
+
+    /// Connected and disconnecting links with their handle
+    pub fn get_connections(&self) -> impl Iterator<Item = (u16, &HCIConnection)> {
+        self.connections.iter()
+    }
+
+    pub fn is_command_supported(&self, cmd: SupportedCommand) -> bool {
+        let flag = cmd as u16;
+        self.supported_commands[(flag >> 8) as usize] & (flag as u8) != 0
+    }
+
+    pub fn get_lmp_features(&self) -> LMPFeatures {
+        self.lmp_features
+    }
+
+    pub fn is_lmp_feature_supported(&self, feature: LMPFeatures) -> bool {
+        self.lmp_features.contains(feature)
+    }
+
+    pub fn get_le_features(&self) -> LEFeatures {
+        self.le_features
+    }
+
+    pub fn is_le_feature_supported(&self, feature: LEFeatures) -> bool {
+        self.le_features.contains(feature)
     }
 
-    pub fn recv_packet(&mut self, packet: Vec<u8>) {
-        let data = packet[1..].to_owned();
-        match packet[0] {
-            x if x == HCIPacket::Command as u8 => self.recv_ce_data(data),
-            x if x == HCIPacket::ACL as u8 => self.recv_acl_data(data),
-            x if x == HCIPacket::Event as u8 => self.recv_event_data(data),
-            _ => panic!("error packet"),
+    /// The PHYs LE links can use, the LE 1M PHY is always supported
+    pub fn get_supported_phys(&self) -> LEPhys {
+        let mut phys = LEPhys::LE1M;
+        if self.is_le_feature_supported(LEFeatures::LE2MPHY) {
+            phys |= LEPhys::LE2M;
+        }
+        if self.is_le_feature_supported(LEFeatures::LECodedPHY) {
+            phys |= LEPhys::LECoded;
         }
+        phys
+    }
+
+    /// Advertising, scanning and initiating go through the extended commands, otherwise only
+    /// the legacy ones are used. The controller refuses a mix of both
+    pub fn is_extended_advertising_supported(&self) -> bool {
+        self.is_le_feature_supported(LEFeatures::LEExtendedAdvertising)
+            && self.is_command_supported(SupportedCommand::LESetExtendedAdvertisingParameters)
+    }
+
+    pub fn get_advertising_set(&self, handle: u8) -> Option<&AdvertisingSet> {
+        self.le_advertising_sets.iter().find(|s| s.handle == handle)
+    }
+
+    pub fn get_advertising_sets(&self) -> impl Iterator<Item = &AdvertisingSet> {
+        self.le_advertising_sets.iter()
+    }
+
+    pub fn get_periodic_sync(&self, sync_handle: u16) -> Option<&PeriodicSync> {
+        self.le_periodic_syncs.get(&sync_handle)
+    }
+
+    pub fn get_own_address_mode(&self) -> LEOwnAddressMode {
+        self.le_own_address_mode
+    }
+
+    /// The random address advertising, scanning and connecting use, if any
+    pub fn get_random_address(&self) -> Option<BDAddr> {
+        self.le_random_address
+    }
+
+    pub fn get_bonded_devices(&self) -> &[BondedDevice] {
+        &self.le_bonds
+    }
+
+    /// The entries as they will be on the controller once the pending changes are sent
+    pub fn get_filter_accept_list(&self) -> impl Iterator<Item = &FilterAcceptListEntry> {
+        self.le_filter_accept_list.iter()
+    }
+
+    /// Zero if the controller did not report it
+    pub fn get_filter_accept_list_size(&self) -> u8 {
+        self.le_filter_accept_list.size()
+    }
+
+    pub fn get_resolving_list(&self) -> impl Iterator<Item = &ResolvingListEntry> {
+        self.le_resolving_list.iter()
+    }
+
+    /// Zero if the controller did not report it
+    pub fn get_resolving_list_size(&self) -> u8 {
+        self.le_resolving_list.size()
+    }
+
+    /// The controller resolves the peers on the resolving list itself
+    pub fn is_resolving_list_supported(&self) -> bool {
+        self.is_le_feature_supported(LEFeatures::LLPrivacy)
+            && self.is_command_supported(SupportedCommand::LEAddDeviceToResolvingList)
+    }
+
+    /// The bonded device whose IRK generated the resolvable private address `address`
+    pub fn resolve_address(&self, address: &BDAddr) -> Option<&BondedDevice> {
+        self.le_bonds
+            .iter()
+            .find(|bond| resolve_private_address(&bond.irk, address))
+    }
+
+    /// Established syncs with their sync handle
+    pub fn get_periodic_syncs(&self) -> impl Iterator<Item = (u16, &PeriodicSync)> {
+        self.le_periodic_syncs
+            .iter()
+            .map(|(sync_handle, sync)| (*sync_handle, sync))
+    }
+
+    /// (packet length, number of packets) of the controller ACL buffers
+    pub fn get_acl_buffer_size(&self) -> (u16, u16) {
+        (self.acl_packet_length, self.acl_packets_total)
+    }
+
+    /// (packet length, number of packets) of the controller LE ACL buffers,
+    /// zero if LE shares the ACL buffers
+    pub fn get_le_acl_buffer_size(&self) -> (u16, u16) {
+        (self.le_acl_packet_length, self.le_acl_packets_total)
+    }
+
+    /// See `HCISendPacket` for the arguments
+    pub fn set_send_packet<F>(&mut self, send_packet: F)
+    where
+        F: Fn(&Self, HCIPacket, u16, Option<Vec<u8>>) + 'static,
+    {
+        self.send_packet = Some(Box::new(send_packet));
+    }
+
+    pub fn set_init_config(&mut self, config: HCIInitConfig) {
+        self.init_config = config;
+    }
+
+    /// Handlers are called in the order they were added
+    pub fn add_event_handler<F>(&mut self, handler: F)
+    where
+        F: Fn(&Self, &AppEvent) + 'static,
+    {
+        self.event_handlers.push(Box::new(handler));
+    }
+
+    /// Without a policy every valid request of a peer is accepted
+    pub fn set_conn_param_policy<F>(&mut self, policy: F)
+    where
+        F: Fn(&Self, u16, &ConnectionUpdateParams) -> bool + 'static,
+    {
+        self.conn_param_policy = Some(Box::new(policy));
+    }
+
+    pub fn get_config(&self) -> &HCIConfigParam {
+        &self.config
+    }
+
+    /// The settings `update` changed are written to the controller, also after it
+    /// failed on a later setting
+    pub fn update_config<F>(&mut self, update: F) -> Result<()>
+    where
+        F: FnOnce(&mut HCIConfigParam) -> Result<()>,
+    {
+        let ret = update(&mut self.config);
         self.run();
+        ret
     }
 
-    fn run(&mut self) {
-        if self.state == HCIState::Initializing {
-            self.init_process();
-            return;
-        }
+    /// Without a policy every BR/EDR link a peer asks for is accepted
+    pub fn set_connection_request_policy<F>(&mut self, policy: F)
+    where
+        F: Fn(&Self, &ConnectionRequest) -> bool + 'static,
+    {
+        self.connection_request_policy = Some(Box::new(policy));
+    }
 
-        self.run_gap_le();
+    fn emit_app_event(&self, event: AppEvent) {
+        for handler in self.event_handlers.iter() {
+            handler(self, &event);
+        }
     }
 
-    fn init_process(&mut self) {
-        use HCISubState::*;
-        match self.sub_state {
-            SendReset => {
-                self.sub_state = W4SendReset;
-                let arg = ResetCmd {};
-                arg.send(self);
-            }
-            SendReadLocalSupportedCommands => {
-                self.sub_state = W4SendReadLocalSupportedCommands;
-                let arg = ReadLocalSupportedCommandsCmd {};
-                arg.send(self);
-            }
-            SendReadLocalSupportedFeatures => {
-                self.sub_state = W4SendReadLocalSupportedFeatures;
-                let arg = ReadLocalSupportedFeaturesCmd {};
-                arg.send(self);
-            }
-            SendSetEventMask => {
-                self.sub_state = W4SendSetEventMask;
-                let arg = SetEventMaskCmd { event_mask: 0 };
-                arg.send(self);
-            }
-            SendLESetEventMask => {
-                self.sub_state = W4SendLESetEventMask;
-                let arg = LESetEventMaskCmd { le_event_mask: 0 };
-                arg.send(self);
-            }
-            SendLEReadBufferSize => {
-                self.sub_state = W4SendLEReadBufferSize;
-                let arg = LEReadBufferSizeCmd {};
-                arg.send(self);
-            }
-            SendReadBufferSize => {
-                self.sub_state = W4SendReadBufferSize;
-                let arg = ReadBufferSizeCmd {};
-                arg.send(self);
-            }
-            SendLEReadLocalSupportedFeatures => {
-                self.sub_state = W4SendLEReadLocalSupportedFeatures;
-                let arg = LEReadLocalSupportedFeaturesCmd {};
-                arg.send(self);
-            }
-            SendReadBDAddr => {
-                self.sub_state = W4SendReadBDAddr;
-                let arg = ReadBDAddrCmd {};
-                arg.send(self);
-            }
-            End => {
-                self.state = HCIState::Working;
-                info!("HCI init done: {:?}", self.bd_addr);
+    /// Malformed packets are logged and rejected without touching the host state
+    pub fn recv_packet(&mut self, packet: Vec<u8>) -> Result<()> {
+        let ret = match packet.split_first() {
+            None => Err(Error::Truncated {
+                expected: 1,
+                actual: 0,
+            }),
+            Some((&indicator, data)) => {
+                let data = data.to_owned();
+                match indicator {
+                    x if x == HCIPacket::Command as u8 => {
+                        self.recv_ce_data(data);
+                        Ok(())
+                    }
+                    x if x == HCIPacket::ACL as u8 => self.recv_acl_data(data),
+                    x if x == HCIPacket::Event as u8 => self.recv_event_data(data),
+                    x => Err(Error::UnknownPacketType(x)),
+                }
             }
-            _ => {}
+        };
+        if let Err(err) = &ret {
+            warn!("drop packet {:?}: {}", packet, err);
+        }
+        self.run();
+        ret
+    }
+
+    fn run(&mut self) {
+        match self.state {
+            HCIState::Initializing => self.init_process(),
+            HCIState::Working => self.run_gap_le(),
+            HCIState::Halting => self.halting_process(),
+            HCIState::FallingSleep => self.falling_sleep_process(),
+            HCIState::Off | HCIState::Sleeping => {}
         }
     }
 
-    fn init_process_event(&mut self, opcode: u16) {
-        use HCISubState::*;
+    fn halting_process(&mut self) {
         match self.sub_state {
-            W4SendReset => {
-                if opcode == ControllerAndBaseband::Reset.get_opcode() {
-                    self.sub_state = SendReadLocalSupportedCommands;
-                }
-            }
-            W4SendReadLocalSupportedCommands => {
-                if opcode == InformationalParam::ReadLocalSupportedCommands.get_opcode() {
-                    self.sub_state = SendReadLocalSupportedFeatures;
-                }
-            }
-            W4SendReadLocalSupportedFeatures => {
-                if opcode == InformationalParam::ReadLocalSupportedFeatures.get_opcode() {
-                    self.sub_state = SendSetEventMask;
-                }
-            }
-            W4SendSetEventMask => {
-                if opcode == ControllerAndBaseband::SetEventMask.get_opcode() {
-                    self.sub_state = SendLESetEventMask;
-                }
-            }
-            W4SendLESetEventMask => {
-                if opcode == LEController::LESetEventMask.get_opcode() {
-                    self.sub_state = SendLEReadBufferSize;
-                }
-            }
-            W4SendLEReadBufferSize => {
-                if opcode == LEController::LEReadBufferSize.get_opcode() {
-                    self.sub_state = SendReadBufferSize;
+            HCISubState::HaltStopActivities => {
+                self.stop_advertising();
+                self.stop_scanning();
+
+                // pending connections have no handle to disconnect yet,
+                // the reset cancels them on the controller
+                self.connections.clear_pending();
+                for handle in self.connections.handles() {
+                    self.send_disconnect(
+                        handle,
+                        ControllerErrorCode::RemoteDeviceTerminatedConnectionDueToPowerOff,
+                    );
                 }
+                self.sub_state = HCISubState::HaltW4Disconnect {
+                    deadline: self.now_ms + HALT_DISCONNECT_TIMEOUT_MS,
+                };
+                self.halting_process();
             }
-            W4SendReadBufferSize => {
-                if opcode == InformationalParam::ReadBufferSize.get_opcode() {
-                    self.sub_state = SendLEReadLocalSupportedFeatures;
+            HCISubState::HaltW4Disconnect { deadline } => {
+                if !self.connections.is_empty() {
+                    if deadline > self.now_ms {
+                        return;
+                    }
+                    warn!("halting: disconnection timeout, reset anyway");
                 }
+                self.sub_state = HCISubState::HaltW4Reset;
+                ResetCmd {}.send(self);
             }
-            W4SendLEReadLocalSupportedFeatures => {
-                if opcode == LEController::LEReadLocalSupportedFeatures.get_opcode() {
-                    self.sub_state = SendReadBDAddr;
-                }
+            _ => {}
+        }
+    }
+
+    fn falling_sleep_process(&mut self) {
+        match self.sub_state {
+            HCISubState::SleepStopActivities => {
+                self.stop_advertising();
+                self.stop_scanning();
+                self.sub_state = HCISubState::SleepW4Commands;
+                self.falling_sleep_process();
             }
-            W4SendReadBDAddr => {
-                if opcode == InformationalParam::ReadBDAddr.get_opcode() {
-                    self.sub_state = End;
+            HCISubState::SleepW4Commands => {
+                if !self.cmd_queue.is_empty() || !self.cmd_waiting.is_empty() {
+                    return;
                 }
+                self.state = HCIState::Sleeping;
+                info!("HCI sleeping: {:?}", self.bd_addr);
+                self.emit_app_event(AppEvent::PowerModeChanged(HCIPowerMode::Sleep));
             }
             _ => {}
         }
     }
 
-    fn run_gap_le(&mut self) {
-        // Phase 1: collect what to stop
-        let mut advertising_stop = false;
+    /// Advertising stays enabled, it is restored once the host is working again
+    fn stop_advertising(&mut self) {
+        if self.is_extended_advertising_supported() {
+            let mut active = self
+                .le_advertisements_state
+                .contains(LEAdvertisementsState::Active);
+            self.le_advertisements_state
+                .remove(LEAdvertisementsState::Active);
+            let mut periodic = Vec::new();
+            for set in self.le_advertising_sets.iter_mut() {
+                active |= set.state.contains(LEAdvertisementsState::Active);
+                set.state.remove(LEAdvertisementsState::Active);
+                if set.periodic_state.contains(LEAdvertisementsState::Active) {
+                    periodic.push(set.handle);
+                }
+            }
+            self.stop_periodic_advertising(&periodic);
+            if active {
+                // no sets disables all of them
+                let cmd = LESetExtendedAdvertisingEnableCmd {
+                    enable: false,
+                    sets: Vec::new(),
+                };
+                cmd.send(self);
+            }
+            return;
+        }
         if self
             .le_advertisements_state
             .contains(LEAdvertisementsState::Active)
         {
-            if self
-                .le_advertisements_todo
-                .contains(LEAdvertisementsTodo::SetParams)
-            {
-                advertising_stop = true;
-            }
-        }
-
-        // Phase 2: stop everything that should be off during modifications
-        if advertising_stop {
             self.le_advertisements_state
                 .remove(LEAdvertisementsState::Active);
             let cmd = LESetAdvertisingEnableCmd {
@@ -368,187 +1050,3339 @@ impl HCI {
             };
             cmd.send(self);
         }
+    }
 
-        // Phase 3: modify
-        if self
-            .le_advertisements_todo
-            .contains(LEAdvertisementsTodo::SetParams)
-        {
-            self.le_advertisements_todo
-                .remove(LEAdvertisementsTodo::SetParams);
-            let cmd = LESetAdvertisingParametersCmd {
-                advertising_interval_min: self.le_advertisements_interval_min,
-                advertising_interval_max: self.le_advertisements_interval_max,
-                advertising_type: self.le_advertisements_type.clone(),
-                own_address_type: self.le_own_address_type.clone(),
-                peer_address_type: LEAddressType2::from(
-                    self.le_advertisements_peer_address_type.clone(),
-                ),
-                peer_address: self.le_advertisements_peer_address,
-                advertising_channel_map: self.le_advertisements_channel_map,
-                advertising_filter_policy: self.le_advertisements_filter_policy.clone(),
+    /// Enabled by the app, or needed to find the train of a periodic sync being created
+    fn is_scanning_wanted(&self) -> bool {
+        self.le_scanning_state.contains(LEScanningState::Enabled)
+            || matches!(
+                self.le_periodic_sync_state,
+                LEPeriodicSyncState::Todo(..) | LEPeriodicSyncState::W4Established
+            )
+    }
+
+    fn stop_scanning(&mut self) {
+        if self.le_scanning_state.contains(LEScanningState::Active) {
+            self.le_scanning_state.remove(LEScanningState::Active);
+            self.send_scan_enable(false);
+        }
+    }
+
+    fn send_scan_enable(&mut self, enable: bool) {
+        let filter_duplicates = enable && self.le_scan_filter_duplicates;
+        if self.is_extended_advertising_supported() {
+            let cmd = LESetExtendedScanEnableCmd {
+                enable,
+                filter_duplicates,
+                duration: 0,
+                period: 0,
+            };
+            cmd.send(self);
+        } else {
+            let cmd = LESetScanEnableCmd {
+                le_scan_enable: enable,
+                filter_duplicates,
             };
             cmd.send(self);
         }
+    }
 
-        // Phase 4: restore state
-        if self
-            .le_advertisements_state
-            .contains(LEAdvertisementsState::Enabled)
-            && !self
-                .le_advertisements_state
-                .contains(LEAdvertisementsState::Active)
-        {
-            self.le_advertisements_state |= LEAdvertisementsState::Active;
-            let cmd = LESetAdvertisingEnableCmd {
-                advertiseing_enable: true,
+    /// The PHYs advertisers use on the primary channels, scanning and initiating listen on them
+    fn primary_phys(&self) -> LEPhys {
+        self.get_supported_phys().difference(LEPhys::LE2M)
+    }
+
+    fn send_scan_params(&mut self) {
+        if self.is_extended_advertising_supported() {
+            let scanning_phys = self.primary_phys();
+            let params = ExtendedScanPhyParams {
+                scan_type: self.le_scan_type,
+                scan_interval: self.le_scan_interval,
+                scan_window: self.le_scan_window,
+            };
+            let cmd = LESetExtendedScanParametersCmd {
+                own_address_type: self.le_own_address_type,
+                scanning_filter_policy: self.le_scan_filter_policy,
+                scanning_phys,
+                phy_params: alloc::vec![params; scanning_phys.iter().count()],
+            };
+            cmd.send(self);
+        } else {
+            let cmd = LESetScanParametersCmd {
+                le_scan_type: self.le_scan_type,
+                le_scan_interval: self.le_scan_interval,
+                le_scan_window: self.le_scan_window,
+                own_address_type: self.le_own_address_type,
+                scanning_filter_policy: self.le_scan_filter_policy,
             };
             cmd.send(self);
         }
     }
 
-    pub fn power_control(&mut self, control: HCIPowerMode) {
-        match self.state {
-            HCIState::Off => self.power_control_off(control),
-            _ => {}
+    fn send_create_connection(&mut self, peer_address: BDAddr, peer_address_type: LEAddressType) {
+        let params = self.le_connection_params;
+        if self.is_extended_advertising_supported() {
+            let initiating_phys = self.primary_phys();
+            let phy_params = ExtendedConnectionPhyParams {
+                scan_interval: params.scan_interval,
+                scan_window: params.scan_window,
+                conn_interval_min: params.interval_min,
+                conn_interval_max: params.interval_max,
+                max_latency: params.latency,
+                supervision_timeout: params.supervision_timeout,
+                min_ce_length: params.min_ce_length,
+                max_ce_length: params.max_ce_length,
+            };
+            let cmd = LEExtendedCreateConnectionCmd {
+                initiator_filter_policy: false,
+                own_address_type: self.le_own_address_type,
+                peer_address_type,
+                peer_address,
+                initiating_phys,
+                phy_params: alloc::vec![phy_params; initiating_phys.iter().count()],
+            };
+            cmd.send(self);
+        } else {
+            let cmd = LECreateConnectionCmd {
+                le_scan_interval: params.scan_interval,
+                le_scan_window: params.scan_window,
+                initiator_filter_policy: false,
+                peer_address_type,
+                peer_address,
+                own_address_type: self.le_own_address_type,
+                conn_interval_min: params.interval_min,
+                conn_interval_max: params.interval_max,
+                max_latency: params.latency,
+                supervision_timeout: params.supervision_timeout,
+                min_ce_length: params.min_ce_length,
+                max_ce_length: params.max_ce_length,
+            };
+            cmd.send(self);
         }
-        self.run();
     }
 
-    fn power_control_off(&mut self, control: HCIPowerMode) {
-        match control {
-            HCIPowerMode::On => {
-                self.power_enter_initializing_state();
+    fn init_process(&mut self) {
+        match self.sub_state {
+            HCISubState::Send(step) => {
+                self.sub_state = HCISubState::W4Send(step);
+                self.init_send(step);
+            }
+            HCISubState::End => {
+                self.state = HCIState::Working;
+                // queued ahead of anything the app sends once the init is done
+                self.write_config();
+                info!("HCI init done: {:?}", self.bd_addr);
+                self.emit_app_event(AppEvent::InitDone(Ok(())));
+                // restore what was enabled before a power cycle or re-init
+                self.run_gap_le();
             }
             _ => {}
         }
     }
 
-    fn power_enter_initializing_state(&mut self) {
-        self.state = HCIState::Initializing;
-        self.sub_state = HCISubState::SendReset;
-    }
-
-    fn recv_ce_data(&mut self, data: Vec<u8>) {
-        info!("CE {:?}", data);
-    }
-    fn recv_acl_data(&mut self, data: Vec<u8>) {
+    fn init_send(&mut self, step: HCIInitStep) {
+        use HCIInitStep::*;
+        match step {
+            Reset => ResetCmd {}.send(self),
+            ReadLocalSupportedCommands => ReadLocalSupportedCommandsCmd {}.send(self),
+            ReadLocalVersionInformation => ReadLocalVersionInformationCmd {}.send(self),
+            ReadLocalSupportedFeatures => ReadLocalSupportedFeaturesCmd {}.send(self),
+            SetEventMask => SetEventMaskCmd {
+                event_mask: self.event_mask().bits(),
+            }
+            .send(self),
+            WriteLEHostSupport => WriteLEHostSupportCmd {
+                le_supported_host: true,
+                unused: 0,
+            }
+            .send(self),
+            LEReadLocalSupportedFeatures => LEReadLocalSupportedFeaturesCmd {}.send(self),
+            LESetEventMask => LESetEventMaskCmd {
+                le_event_mask: self.le_event_mask().bits(),
+            }
+            .send(self),
+            LEReadMaximumAdvertisingDataLength => {
+                LEReadMaximumAdvertisingDataLengthCmd {}.send(self)
+            }
+            LEReadNumberOfSupportedAdvertisingSets => {
+                LEReadNumberOfSupportedAdvertisingSetsCmd {}.send(self)
+            }
+            LEReadFilterAcceptListSize => LEReadFilterAcceptListSizeCmd {}.send(self),
+            LEReadResolvingListSize => LEReadResolvingListSizeCmd {}.send(self),
+            LEReadBufferSize => LEReadBufferSizeCmd {}.send(self),
+            ReadBufferSize => ReadBufferSizeCmd {}.send(self),
+            ReadBDAddr => ReadBDAddrCmd {}.send(self),
+        }
+    }
+
+    /// The configured inquiry mode, lowered to what the controller supports
+    fn inquiry_mode(&self) -> InquiryMode {
+        let extended = self.is_lmp_feature_supported(LMPFeatures::ExtendedInquiryResponse);
+        let rssi = self.is_lmp_feature_supported(LMPFeatures::RSSIWithInquiryResults);
+        match self.config.inquiry_mode() {
+            InquiryMode::WithRSSIAndExtended if extended => InquiryMode::WithRSSIAndExtended,
+            InquiryMode::WithRSSIAndExtended | InquiryMode::WithRSSI if rssi => {
+                InquiryMode::WithRSSI
+            }
+            _ => InquiryMode::Standard,
+        }
+    }
+
+    /// Sends the Write_* commands of the settings the controller has not seen yet
+    fn write_config(&mut self) {
+        let bredr = !self.is_lmp_feature_supported(LMPFeatures::BREDRNotSupported);
+        for write in self.config.take_pending() {
+            let modified = self.config.is_modified(write);
+            // the controller starts with the standard mode
+            let skip = match write {
+                HCIConfigWrite::InquiryMode => {
+                    !self.init_config.features.contains(HostFeatures::Scanning)
+                        || (!modified && self.inquiry_mode() == InquiryMode::Standard)
+                }
+                HCIConfigWrite::AuthenticatedPayloadTimeout => false,
+                _ => !bredr,
+            };
+            if skip {
+                continue;
+            }
+            if !self.is_command_supported(config_write_command(write)) {
+                if modified {
+                    warn!("controller lacks {:?}, config not written", write);
+                }
+                continue;
+            }
+            if HCI_LINK_CONFIG_WRITES.contains(&write) {
+                for handle in self.connections.handles() {
+                    self.write_link_config(handle, write);
+                }
+                continue;
+            }
+            self.send_config_write(write);
+        }
+    }
+
+    fn send_config_write(&mut self, write: HCIConfigWrite) {
+        let config = &self.config;
+        match write {
+            HCIConfigWrite::ScanEnable => WriteScanEnableCmd {
+                scan_enable: config.scan_enable(),
+            }
+            .send(self),
+            HCIConfigWrite::InquiryScanActivity => {
+                let (interval, window) = config.inquiry_scan_activity();
+                WriteInquiryScanActivityCmd {
+                    inquiry_scan_interval: interval,
+                    inquiry_scan_window: window,
+                }
+                .send(self)
+            }
+            HCIConfigWrite::InquiryScanType => WriteInquiryScanTypeCmd {
+                scan_type: config.inquiry_scan_type(),
+            }
+            .send(self),
+            HCIConfigWrite::InquiryMode => WriteInquiryModeCmd {
+                inquiry_mode: self.inquiry_mode(),
+            }
+            .send(self),
+            HCIConfigWrite::PageTimeout => WritePageTimeoutCmd {
+                page_timeout: config.page_timeout(),
+            }
+            .send(self),
+            HCIConfigWrite::ConnectionAcceptTimeout => WriteConnectionAcceptTimeoutCmd {
+                connection_accept_timeout: config.connection_accept_timeout(),
+            }
+            .send(self),
+            HCIConfigWrite::PageScanActivity => {
+                let (interval, window) = config.page_scan_activity();
+                WritePageScanActivityCmd {
+                    page_scan_interval: interval,
+                    page_scan_window: window,
+                }
+                .send(self)
+            }
+            HCIConfigWrite::PageScanType => WritePageScanTypeCmd {
+                page_scan_type: config.page_scan_type(),
+            }
+            .send(self),
+            HCIConfigWrite::VoiceSetting => WriteVoiceSettingCmd {
+                voice_setting: config.voice_setting(),
+            }
+            .send(self),
+            HCIConfigWrite::PinType => WritePINTypeCmd {
+                pin_type: config.pin_type(),
+            }
+            .send(self),
+            HCIConfigWrite::AuthenticationEnable => WriteAuthenticationEnableCmd {
+                authentication_enable: config.authentication_enable(),
+            }
+            .send(self),
+            HCIConfigWrite::HoldModeActivity => WriteHoldModeActivityCmd {
+                hold_mode_activity: config.hold_mode_activity().bits(),
+            }
+            .send(self),
+            HCIConfigWrite::DefaultLinkPolicySettings => WriteDefaultLinkPolicySettingsCmd {
+                default_link_policy_settings: config.link_policy_settings().bits(),
+            }
+            .send(self),
+            HCIConfigWrite::NumBroadcastRetransmissions => WriteNumBroadcastRetransmissionsCmd {
+                num_broadcast_retransmissions: config.num_broadcast_retransmissions(),
+            }
+            .send(self),
+            HCIConfigWrite::SynchronousFlowControlEnable => WriteSynchronousFlowControlEnableCmd {
+                synchronous_flow_control_enable: config.synchronous_flow_control_enable(),
+            }
+            .send(self),
+            HCIConfigWrite::DefaultErroneousDataReporting => {
+                WriteDefaultErroneousDataReportingCmd {
+                    erroneous_data_reporting: config.erroneous_data_reporting(),
+                }
+                .send(self)
+            }
+            HCIConfigWrite::ClassOfDevice => {
+                let class = config.class_of_device().to_le_bytes();
+                WriteClassOfDeviceCmd {
+                    class_of_device: [class[0], class[1], class[2]],
+                }
+                .send(self)
+            }
+            HCIConfigWrite::SynchronizationTrainParameters => {
+                let (interval, timeout, service_data) = config.sync_train_params();
+                WriteSynchronizationTrainParametersCmd {
+                    interval_min: interval,
+                    interval_max: interval,
+                    sync_train_timeout: timeout,
+                    service_data,
+                }
+                .send(self)
+            }
+            HCIConfigWrite::SecureConnectionsHostSupport => WriteSecureConnectionsHostSupportCmd {
+                secure_connections_host_support: config.secure_connections_host_support(),
+            }
+            .send(self),
+            HCIConfigWrite::ExtendedPageTimeout => WriteExtendedPageTimeoutCmd {
+                extended_page_timeout: config.extended_page_timeout(),
+            }
+            .send(self),
+            HCIConfigWrite::ExtendedInquiryLength => WriteExtendedInquiryLengthCmd {
+                extended_inquiry_length: config.extended_inquiry_length(),
+            }
+            .send(self),
+            HCIConfigWrite::AutomaticFlushTimeout
+            | HCIConfigWrite::LinkSupervisionTimeout
+            | HCIConfigWrite::AuthenticatedPayloadTimeout => {}
+        }
+    }
+
+    /// The flush and supervision timeouts only exist on BR/EDR links, and only the
+    /// central writes the supervision timeout
+    fn write_link_config(&mut self, handle: u16, write: HCIConfigWrite) {
+        let (le, role) = match self.connections.get(handle) {
+            Some(conn) => (conn.is_le(), conn.role),
+            None => return,
+        };
+        let config = &self.config;
+        match write {
+            HCIConfigWrite::AutomaticFlushTimeout if !le => WriteAutomaticFlushTimeoutCmd {
+                connection_handle: handle,
+                flush_timeout: config.flush_timeout(),
+            }
+            .send(self),
+            HCIConfigWrite::LinkSupervisionTimeout if !le && role == Role::Central => {
+                WriteLinkSupervisionTimeoutCmd {
+                    handle,
+                    link_supervision_timeout: config.link_supervision_timeout(),
+                }
+                .send(self)
+            }
+            HCIConfigWrite::AuthenticatedPayloadTimeout => WriteAuthenticatedPayloadTimeoutCmd {
+                connection_handle: handle,
+                authenticated_payload_timeout: config.authenticated_payload_timeout(),
+            }
+            .send(self),
+            _ => {}
+        }
+    }
+
+    /// A new link starts with the controller defaults, the changed per link settings are written
+    fn write_new_link_config(&mut self, handle: u16) {
+        for write in HCI_LINK_CONFIG_WRITES {
+            if self.config.is_modified(write)
+                && self.is_command_supported(config_write_command(write))
+            {
+                self.write_link_config(handle, write);
+            }
+        }
+    }
+
+    /// The ACL packet types of Create Connection, multi-slot ones if the controller has them
+    fn acl_packet_types(&self) -> PacketType {
+        let mut packet_type = PacketType::MayUseDM1 | PacketType::MayUseDH1;
+        if self.is_lmp_feature_supported(LMPFeatures::ThreeSlotPackets) {
+            packet_type |= PacketType::MayUseDM3 | PacketType::MayUseDH3;
+        }
+        if self.is_lmp_feature_supported(LMPFeatures::FiveSlotPackets) {
+            packet_type |= PacketType::MayUseDM5 | PacketType::MayUseDH5;
+        }
+        // EDR packets are allowed unless excluded
+        let edr_2m = self.is_lmp_feature_supported(LMPFeatures::EDRACL2MbpsMode);
+        let edr_3m = self.is_lmp_feature_supported(LMPFeatures::EDRACL3MbpsMode);
+        let edr_3_slot = self.is_lmp_feature_supported(LMPFeatures::ThreeSlotEDRACLPackets);
+        let edr_5_slot = self.is_lmp_feature_supported(LMPFeatures::FiveSlotEDRACLPackets);
+        if !edr_2m {
+            packet_type |= PacketType::NoUse2DH1;
+        }
+        if !edr_3m {
+            packet_type |= PacketType::NoUse3DH1;
+        }
+        if !edr_2m || !edr_3_slot {
+            packet_type |= PacketType::NoUse2DH3;
+        }
+        if !edr_3m || !edr_3_slot {
+            packet_type |= PacketType::NoUse3DH3;
+        }
+        if !edr_2m || !edr_5_slot {
+            packet_type |= PacketType::NoUse2DH5;
+        }
+        if !edr_3m || !edr_5_slot {
+            packet_type |= PacketType::NoUse3DH5;
+        }
+        packet_type
+    }
+
+    /// Only the events of the enabled host features, and the ones the host always needs
+    fn event_mask(&self) -> EventMask {
+        let features = self.init_config.features;
+        let mut mask = EventMask::HardwareError | EventMask::DataBufferOverflow;
+        if features.contains(HostFeatures::Connections) {
+            mask |= EventMask::ConnectionComplete
+                | EventMask::ConnectionRequest
+                | EventMask::DisconnectionComplete
+                | EventMask::ReadRemoteSupportedFeaturesComplete
+                | EventMask::ReadRemoteVersionInformationComplete
+                | EventMask::RoleChange
+                | EventMask::ModeChange
+                | EventMask::MaxSlotsChange;
+        }
+        if features.contains(HostFeatures::Scanning) {
+            mask |= EventMask::InquiryComplete
+                | EventMask::InquiryResult
+                | EventMask::InquiryResultWithRSSI
+                | EventMask::ExtendedInquiryResult
+                | EventMask::RemoteNameRequestComplete;
+        }
+        if features.contains(HostFeatures::Encryption) {
+            mask |= EventMask::AuthenticationComplete
+                | EventMask::EncryptionChange
+                | EventMask::EncryptionKeyRefreshComplete
+                | EventMask::PINCodeRequest
+                | EventMask::LinkKeyRequest
+                | EventMask::LinkKeyNotification
+                | EventMask::IOCapabilityRequest
+                | EventMask::IOCapabilityResponse
+                | EventMask::UserConfirmationRequest
+                | EventMask::UserPasskeyRequest
+                | EventMask::UserPasskeyNotification
+                | EventMask::SimplePairingComplete;
+        }
+        if self.is_command_supported(SupportedCommand::LESetEventMask) {
+            mask |= EventMask::LEMeta;
+        }
+        mask
+    }
+
+    /// Only the subevents the host can decode, limited to what the controller supports
+    fn le_event_mask(&self) -> LEEventMask {
+        let features = self.init_config.features;
+        let mut mask = LEEventMask::empty();
+        if features.contains(HostFeatures::Connections) {
+            mask |= LEEventMask::ConnectionComplete
+                | LEEventMask::ConnectionUpdateComplete
+                | LEEventMask::ReadRemoteFeaturesComplete;
+        }
+        if features.contains(HostFeatures::Scanning) {
+            mask |= LEEventMask::AdvertisingReport;
+            if self.is_extended_advertising_supported() {
+                mask |= LEEventMask::ExtendedAdvertisingReport;
+            }
+        }
+        if features.contains(HostFeatures::Encryption)
+            && self.is_le_feature_supported(LEFeatures::LEEncryption)
+        {
+            mask |= LEEventMask::LongTermKeyRequest;
+        }
+        if features.contains(HostFeatures::Connections)
+            && self.is_le_feature_supported(LEFeatures::ConnectionParametersRequestProcedure)
+        {
+            mask |= LEEventMask::RemoteConnectionParameterRequest;
+        }
+        if features.contains(HostFeatures::Connections)
+            && self.is_le_feature_supported(LEFeatures::LEDataPacketLengthExtension)
+        {
+            mask |= LEEventMask::DataLengthChange;
+        }
+        if features.contains(HostFeatures::Connections) && self.get_supported_phys() != LEPhys::LE1M
+        {
+            mask |= LEEventMask::PHYUpdateComplete;
+        }
+        if self.is_le_feature_supported(LEFeatures::LEExtendedAdvertising) {
+            mask |= LEEventMask::AdvertisingSetTerminated;
+        }
+        if features.contains(HostFeatures::Scanning)
+            && self.is_le_feature_supported(LEFeatures::LEPeriodicAdvertising)
+        {
+            mask |= LEEventMask::PeriodicAdvertisingSyncEstablished
+                | LEEventMask::PeriodicAdvertisingReport
+                | LEEventMask::PeriodicAdvertisingSyncLost;
+        }
+        mask
+    }
+
+    /// Whether `step` applies to this controller and config
+    fn init_step_enabled(&self, step: HCIInitStep) -> bool {
+        use HCIInitStep::*;
+        let bredr = !self.is_lmp_feature_supported(LMPFeatures::BREDRNotSupported);
+        let cmd = match step {
+            // sent before the supported commands are known
+            Reset | ReadLocalSupportedCommands => return true,
+            ReadLocalVersionInformation => {
+                if !self.init_config.read_local_version_information {
+                    return false;
+                }
+                SupportedCommand::ReadLocalVersionInformation
+            }
+            ReadLocalSupportedFeatures => SupportedCommand::ReadLocalSupportedFeatures,
+            SetEventMask => SupportedCommand::SetEventMask,
+            WriteLEHostSupport => {
+                if !self.init_config.write_le_host_support
+                    || !bredr
+                    || !self.is_lmp_feature_supported(LMPFeatures::LESupportedController)
+                {
+                    return false;
+                }
+                SupportedCommand::WriteLEHostSupport
+            }
+            LEReadLocalSupportedFeatures => SupportedCommand::LEReadLocalSupportedFeatures,
+            LESetEventMask => SupportedCommand::LESetEventMask,
+            LEReadMaximumAdvertisingDataLength => {
+                if !self.is_le_feature_supported(LEFeatures::LEExtendedAdvertising) {
+                    return false;
+                }
+                SupportedCommand::LEReadMaximumAdvertisingDataLength
+            }
+            LEReadNumberOfSupportedAdvertisingSets => {
+                if !self.is_le_feature_supported(LEFeatures::LEExtendedAdvertising) {
+                    return false;
+                }
+                SupportedCommand::LEReadNumberOfSupportedAdvertisingSets
+            }
+            LEReadFilterAcceptListSize => SupportedCommand::LEReadFilterAcceptListSize,
+            LEReadResolvingListSize => {
+                if !self.is_le_feature_supported(LEFeatures::LLPrivacy) {
+                    return false;
+                }
+                SupportedCommand::LEReadResolvingListSize
+            }
+            LEReadBufferSize => SupportedCommand::LEReadBufferSize,
+            ReadBufferSize => SupportedCommand::ReadBufferSize,
+            ReadBDAddr => SupportedCommand::ReadBDAddr,
+        };
+        self.is_command_supported(cmd)
+    }
+
+    /// Move on to the first enabled step after `step`
+    fn init_next_step(&mut self, step: HCIInitStep) {
+        let next = HCI_INIT_STEPS
+            .iter()
+            .skip_while(|s| **s != step)
+            .skip(1)
+            .find(|s| self.init_step_enabled(**s));
+        self.sub_state = match next {
+            Some(next) => HCISubState::Send(*next),
+            None => HCISubState::End,
+        };
+    }
+
+    fn init_step_failed(&mut self, step: HCIInitStep, err: Error) {
+        if !step.is_required() {
+            warn!("HCI init skip {:?}: {}", step, err);
+            self.init_next_step(step);
+            return;
+        }
+
+        error!("HCI init failed at {:?}: {}", step, err);
+        self.state = HCIState::Off;
+        self.sub_state = HCISubState::Send(HCIInitStep::Reset);
+        self.emit_app_event(AppEvent::InitDone(Err(err)));
+    }
+
+    /// An init step answered by a failed Command Status or not at all
+    fn init_cmd_failed(&mut self, opcode: u16, err: Error) {
+        if let HCISubState::W4Send(step) = self.sub_state {
+            if self.state == HCIState::Initializing && step.opcode() == opcode {
+                self.init_step_failed(step, err);
+            }
+        }
+    }
+
+    fn init_process_event(&mut self, evt: &CommandCompleteEvt<Vec<u8>>) {
+        let step = match self.sub_state {
+            HCISubState::W4Send(step) if step.opcode() == evt.opcode => step,
+            _ => return,
+        };
+        match self.init_store_result(step, evt) {
+            Ok(()) => self.init_next_step(step),
+            Err(err) => self.init_step_failed(step, err),
+        }
+    }
+
+    fn init_store_result(
+        &mut self,
+        step: HCIInitStep,
+        evt: &CommandCompleteEvt<Vec<u8>>,
+    ) -> Result<()> {
+        use HCIInitStep::*;
+        // every return parameter starts with the status
+        let status = ControllerErrorCode::from_u8_array(&evt.return_param)?;
+        if status != ControllerErrorCode::Ok {
+            return Err(Error::CommandFailed {
+                opcode: evt.opcode,
+                status,
+            });
+        }
+
+        match step {
+            ReadLocalSupportedCommands => {
+                let ret = evt.parse_return_param::<ReadLocalSupportedCommandsRet>()?;
+                self.supported_commands = ret.supported_commands;
+            }
+            ReadLocalVersionInformation => {
+                let ret = evt.parse_return_param::<ReadLocalVersionInformationRet>()?;
+                info!(
+                    "controller version: hci {} lmp {} company {:#06x}",
+                    ret.hci_version, ret.lmp_version, ret.company_identifier
+                );
+                self.local_version = Some(ret);
+            }
+            ReadLocalSupportedFeatures => {
+                let ret = evt.parse_return_param::<ReadLocalSupportedFeaturesRet>()?;
+                self.lmp_features =
+                    LMPFeatures::from_bits_retain(u64::from_le_bytes(ret.lmp_feature));
+            }
+            LEReadLocalSupportedFeatures => {
+                let ret = evt.parse_return_param::<LEReadLocalSupportedFeaturesRet>()?;
+                self.le_features = LEFeatures::from_bits_retain(ret.le_features);
+            }
+            LEReadBufferSize => {
+                let ret = evt.parse_return_param::<LEReadBufferSizeRet>()?;
+                self.le_acl_packet_length = ret.le_acl_data_packet_length;
+                self.le_acl_packets_total = ret.total_num_le_acl_data_packets as u16;
+                self.le_acl_packets_free = self.le_acl_packets_total;
+            }
+            ReadBufferSize => {
+                let ret = evt.parse_return_param::<ReadBufferSizeRet>()?;
+                self.acl_packet_length = ret.acl_data_packet_length;
+                self.acl_packets_total = ret.total_num_acl_data_packets;
+                self.acl_packets_free = self.acl_packets_total;
+            }
+            ReadBDAddr => {
+                let ret = evt.parse_return_param::<ReadBDAddrRet>()?;
+                self.controller_bd_addr = ret.bd_addr;
+            }
+            LEReadMaximumAdvertisingDataLength => {
+                let ret = evt.parse_return_param::<LEReadMaximumAdvertisingDataLengthRet>()?;
+                self.le_max_advertising_data_length = ret.max_advertising_data_length;
+            }
+            LEReadNumberOfSupportedAdvertisingSets => {
+                let ret = evt.parse_return_param::<LEReadNumberOfSupportedAdvertisingSetsRet>()?;
+                self.le_num_advertising_sets = ret.num_supported_advertising_sets;
+            }
+            LEReadFilterAcceptListSize => {
+                let ret = evt.parse_return_param::<LEReadFilterAcceptListSizeRet>()?;
+                self.le_filter_accept_list
+                    .set_size(ret.filter_accept_list_size);
+            }
+            LEReadResolvingListSize => {
+                let ret = evt.parse_return_param::<LEReadResolvingListSizeRet>()?;
+                self.le_resolving_list.set_size(ret.resolving_list_size);
+            }
+            Reset | SetEventMask | WriteLEHostSupport | LESetEventMask => {}
+        }
+        Ok(())
+    }
+
+    fn run_gap_le(&mut self) {
+        self.write_config();
+        // a new private address is generated from a controller random number
+        if self.le_own_address_mode.is_private()
+            && !self.le_rand_pending
+            && self.is_command_supported(SupportedCommand::LERand)
+            && self
+                .le_private_address_deadline
+                .is_none_or(|deadline| deadline <= self.now_ms)
+        {
+            self.le_rand_pending = true;
+            LERandCmd {}.send(self);
+        }
+        // the defaults only apply to the links created afterwards
+        if self.le_connection_defaults_update {
+            self.le_connection_defaults_update = false;
+            self.send_connection_defaults();
+        }
+
+        // Phase 1: collect what to stop
+        // the random address and the resolving list can not change while initiating
+        let initiating = matches!(
+            self.le_connect_state,
+            LEConnectState::W4Complete { .. }
+                | LEConnectState::Cancel { .. }
+                | LEConnectState::W4Cancelled { .. }
+        );
+        let address_update = self
+            .le_advertisements_todo
+            .contains(LEAdvertisementsTodo::SetAddress)
+            && !initiating;
+        let filter_list_update = self.le_filter_accept_list.is_dirty()
+            && self.is_command_supported(SupportedCommand::LEAddDeviceToFilterAcceptList);
+        // with address resolution enabled nothing may advertise or scan, enabling it first
+        // after a reset needs the same
+        let resolving_list_update = self.is_resolving_list_supported()
+            && (self.le_resolving_list.is_dirty() || self.is_address_resolution_enable_pending())
+            && !initiating;
+        let extended = self.is_extended_advertising_supported();
+        // the data can be changed while advertising, the parameters and address can not.
+        // With extended advertising the legacy config uses the address of set 0
+        let advertising_address_update = if extended {
+            self.le_advertisements_todo
+                .contains(LEAdvertisementsTodo::SetAddressSet0)
+        } else {
+            address_update
+        };
+        let advertising_stop = self
+            .le_advertisements_state
+            .contains(LEAdvertisementsState::Active)
+            && (self
+                .le_advertisements_todo
+                .contains(LEAdvertisementsTodo::SetParams)
+                || !self
+                    .le_advertisements_state
+                    .contains(LEAdvertisementsState::Enabled)
+                || advertising_address_update
+                || resolving_list_update
+                || (filter_list_update
+                    && self.le_advertisements_filter_policy != AdvertisingFilterPolicy::UnFilter));
+        let advertising_sets_stop: Vec<u8> = self
+            .le_advertising_sets
+            .iter()
+            .filter(|set| {
+                let lists_update = resolving_list_update
+                    || (filter_list_update
+                        && set.params.filter_policy != AdvertisingFilterPolicy::UnFilter);
+                extended
+                    && (set.needs_stop()
+                        || (set.state.contains(LEAdvertisementsState::Active) && lists_update))
+            })
+            .map(|set| set.handle)
+            .collect();
+        let periodic_stop: Vec<u8> = self
+            .le_advertising_sets
+            .iter()
+            .filter(|set| extended && set.periodic_needs_stop())
+            .map(|set| set.handle)
+            .collect();
+        let scanning_stop = self.le_scanning_state.contains(LEScanningState::Active)
+            && (self.le_scanning_param_update
+                || !self.is_scanning_wanted()
+                || address_update
+                || resolving_list_update
+                || (filter_list_update && self.is_scan_filter_accept_list_used()));
+
+        // Phase 2: stop everything that should be off during modifications
+        if extended {
+            self.stop_periodic_advertising(&periodic_stop);
+            self.stop_advertising_sets(advertising_stop, &advertising_sets_stop);
+        } else if advertising_stop {
+            self.stop_advertising();
+        }
+        if scanning_stop {
+            self.stop_scanning();
+        }
+
+        // Phase 3: modify
+        if address_update {
+            self.le_advertisements_todo
+                .remove(LEAdvertisementsTodo::SetAddress);
+            if !extended {
+                // set 0 only exists with extended advertising
+                self.le_advertisements_todo
+                    .remove(LEAdvertisementsTodo::SetAddressSet0);
+            }
+            if let Some(random_address) = self.le_random_address {
+                self.le_advertisements_todo |= LEAdvertisementsTodo::PrivacyNotify;
+                LESetRandomAddressCmd { random_address }.send(self);
+            }
+        }
+        if filter_list_update {
+            self.update_filter_accept_list();
+        }
+        if resolving_list_update {
+            self.update_resolving_list();
+        }
+        if extended {
+            self.modify_advertising_sets();
+        } else {
+            self.modify_legacy_advertising();
+        }
+        if self.le_scanning_param_update {
+            self.le_scanning_param_update = false;
+            self.send_scan_params();
+        }
+
+        // Phase 4: restore state
+        let address_ready = self.own_address_ready();
+        if extended {
+            self.enable_advertising_sets(address_ready);
+            self.enable_periodic_advertising();
+        } else if address_ready
+            && self
+                .le_advertisements_state
+                .contains(LEAdvertisementsState::Enabled)
+            && !self
+                .le_advertisements_state
+                .contains(LEAdvertisementsState::Active)
+        {
+            self.le_advertisements_state |= LEAdvertisementsState::Active;
+            let cmd = LESetAdvertisingEnableCmd {
+                advertiseing_enable: true,
+            };
+            cmd.send(self);
+        }
+        match self.le_connect_state {
+            LEConnectState::Todo(peer_address, peer_address_type) if address_ready => {
+                self.le_connect_state = LEConnectState::W4Complete {
+                    deadline: self.now_ms + self.le_connect_timeout_ms,
+                };
+                self.send_create_connection(peer_address, peer_address_type);
+            }
+            LEConnectState::Cancel { timeout } => {
+                self.le_connect_state = LEConnectState::W4Cancelled { timeout };
+                LECreateConnectionCancelCmd {}.send(self);
+            }
+            _ => {}
+        }
+        match self.le_periodic_sync_state {
+            LEPeriodicSyncState::Todo(_) if !extended => {
+                // only the extended scanner receives the train
+                self.le_periodic_sync_state = LEPeriodicSyncState::Idle;
+                self.emit_app_event(AppEvent::PeriodicSyncFailed {
+                    status: ControllerErrorCode::UnsupportedFeatureOrParameterValue,
+                });
+            }
+            LEPeriodicSyncState::Todo(target) => {
+                self.le_periodic_sync_state = LEPeriodicSyncState::W4Established;
+                let cmd = LEPeriodicAdvertisingCreateSyncCmd {
+                    options: 0,
+                    advertising_sid: target.sid,
+                    advertiser_address_type: LEAddressType2::from(target.address_type),
+                    advertiser_address: target.address,
+                    skip: target.skip,
+                    sync_timeout: target.sync_timeout,
+                    sync_cte_type: 0,
+                };
+                cmd.send(self);
+            }
+            LEPeriodicSyncState::Cancel => {
+                self.le_periodic_sync_state = LEPeriodicSyncState::W4Cancelled;
+                LEPeriodicAdvertisingCreateSyncCancelCmd {}.send(self);
+            }
+            _ => {}
+        }
+        if address_ready
+            && self.is_scanning_wanted()
+            && !self.le_scanning_state.contains(LEScanningState::Active)
+        {
+            self.le_scanning_state |= LEScanningState::Active;
+            self.send_scan_enable(true);
+        }
+    }
+
+    /// A full resolving list leaves the bond to the host side resolution
+    fn resolving_list_add_bond(&mut self, entry: ResolvingListEntry) {
+        if self.le_resolving_list.add(entry).is_err() {
+            warn!(
+                "resolving list full, {:?} is resolved by the host",
+                entry.identity_address
+            );
+        }
+    }
+
+    fn is_scan_filter_accept_list_used(&self) -> bool {
+        matches!(
+            self.le_scan_filter_policy,
+            ScanningFilterPolicy::BasicFiltered | ScanningFilterPolicy::ExtendedFiltered
+        )
+    }
+
+    fn update_filter_accept_list(&mut self) {
+        let changes = self.le_filter_accept_list.take_changes();
+        if changes.clear() {
+            LEClearFilterAcceptListCmd {}.send(self);
+        }
+        for entry in changes.removals() {
+            let cmd = LERemoveDeviceFromFilterAcceptListCmd {
+                address_type: entry.address_type,
+                address: entry.address,
+            };
+            cmd.send(self);
+        }
+        for entry in changes.additions() {
+            let cmd = LEAddDeviceToFilterAcceptListCmd {
+                address_type: entry.address_type,
+                address: entry.address,
+            };
+            cmd.send(self);
+        }
+    }
+
+    /// Never sent to a controller lacking the command, it keeps resolution disabled
+    fn is_address_resolution_enable_pending(&self) -> bool {
+        !self.le_address_resolution_enabled
+            && self.is_command_supported(SupportedCommand::LESetAddressResolutionEnable)
+    }
+
+    fn update_resolving_list(&mut self) {
+        let changes = self.le_resolving_list.take_changes();
+        if changes.clear() {
+            LEClearResolvingListCmd {}.send(self);
+        }
+        for entry in changes.removals() {
+            let cmd = LERemoveDeviceFromResolvingListCmd {
+                peer_identity_address_type: entry.identity_address_type,
+                peer_identity_address: entry.identity_address,
+            };
+            cmd.send(self);
+        }
+        for entry in changes.additions() {
+            let cmd = LEAddDeviceToResolvingListCmd {
+                peer_identity_address_type: entry.identity_address_type,
+                peer_identity_address: entry.identity_address,
+                peer_irk: entry.peer_irk,
+                local_irk: entry.local_irk,
+            };
+            cmd.send(self);
+        }
+        if self.is_address_resolution_enable_pending() {
+            self.le_address_resolution_enabled = true;
+            let cmd = LESetAddressResolutionEnableCmd {
+                address_resolution_enable: true,
+            };
+            cmd.send(self);
+        }
+    }
+
+    fn send_connection_defaults(&mut self) {
+        if let Some((tx_phys, rx_phys)) = self.le_default_phy {
+            if self.is_command_supported(SupportedCommand::LESetDefaultPHY) {
+                // PHYs the controller lacks would fail the whole command
+                let supported = self.get_supported_phys();
+                let (tx_phys, rx_phys) = (tx_phys & supported, rx_phys & supported);
+                let cmd = LESetDefaultPHYCmd {
+                    all_phys: all_phys(tx_phys, rx_phys),
+                    tx_phys: tx_phys.bits(),
+                    rx_phys: rx_phys.bits(),
+                };
+                cmd.send(self);
+            }
+        }
+        if let Some((tx_octets, tx_time)) = self.le_default_data_length {
+            if self.is_command_supported(SupportedCommand::LEWriteSuggestedDefaultDataLength) {
+                let cmd = LEWriteSuggestedDefaultDataLengthCmd {
+                    suggested_max_tx_octets: tx_octets,
+                    suggested_max_tx_time: tx_time,
+                };
+                cmd.send(self);
+            }
+        }
+    }
+
+    /// A random own address has to be written to the controller before it is used
+    fn own_address_ready(&self) -> bool {
+        self.le_own_address_type != LEAddressType::RandomDevice
+            || (self.le_random_address.is_some()
+                && !self
+                    .le_advertisements_todo
+                    .contains(LEAdvertisementsTodo::SetAddress))
+    }
+
+    fn modify_legacy_advertising(&mut self) {
+        if self
+            .le_advertisements_todo
+            .contains(LEAdvertisementsTodo::SetParams)
+        {
+            self.le_advertisements_todo
+                .remove(LEAdvertisementsTodo::SetParams);
+            let cmd = LESetAdvertisingParametersCmd {
+                advertising_interval_min: self.le_advertisements_interval_min,
+                advertising_interval_max: self.le_advertisements_interval_max,
+                advertising_type: self.le_advertisements_type.clone(),
+                own_address_type: self.le_own_address_type,
+                peer_address_type: LEAddressType2::from(self.le_advertisements_peer_address_type),
+                peer_address: self.le_advertisements_peer_address,
+                advertising_channel_map: self.le_advertisements_channel_map,
+                advertising_filter_policy: self.le_advertisements_filter_policy.clone(),
+            };
+            cmd.send(self);
+        }
+        if self
+            .le_advertisements_todo
+            .contains(LEAdvertisementsTodo::SetAdvData)
+        {
+            self.le_advertisements_todo
+                .remove(LEAdvertisementsTodo::SetAdvData);
+            let mut advertising_data = [0; LE_ADV_DATA_MAX_LEN];
+            advertising_data[..self.le_advertisements_data.len()]
+                .copy_from_slice(&self.le_advertisements_data);
+            let cmd = LESetAdvertisingDataCmd {
+                advertising_data_length: self.le_advertisements_data.len() as u8,
+                advertising_data,
+            };
+            cmd.send(self);
+        }
+        if self
+            .le_advertisements_todo
+            .contains(LEAdvertisementsTodo::SetScanData)
+        {
+            self.le_advertisements_todo
+                .remove(LEAdvertisementsTodo::SetScanData);
+            let mut scan_response_data = [0; LE_ADV_DATA_MAX_LEN];
+            scan_response_data[..self.le_scan_response_data.len()]
+                .copy_from_slice(&self.le_scan_response_data);
+            let cmd = LESetScanResponseDataCmd {
+                scan_response_data_length: self.le_scan_response_data.len() as u8,
+                scan_response_data,
+            };
+            cmd.send(self);
+        }
+    }
+
+    /// The legacy config as advertising set 0, with legacy PDUs
+    fn legacy_advertising_set_params(&self) -> AdvertisingSetParams {
+        AdvertisingSetParams {
+            event_properties: self.le_advertisements_type.event_properties(),
+            interval_min: self.le_advertisements_interval_min as u32,
+            interval_max: self.le_advertisements_interval_max as u32,
+            channel_map: self.le_advertisements_channel_map,
+            own_address_type: self.le_own_address_type,
+            peer_address_type: self.le_advertisements_peer_address_type,
+            peer_address: self.le_advertisements_peer_address,
+            filter_policy: self.le_advertisements_filter_policy.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// Disable the legacy set if `legacy` and the sets in `handles` with one command
+    fn stop_advertising_sets(&mut self, legacy: bool, handles: &[u8]) {
+        let mut sets = Vec::new();
+        if legacy {
+            self.le_advertisements_state
+                .remove(LEAdvertisementsState::Active);
+            sets.push(AdvertisingSetEnable {
+                advertising_handle: LE_LEGACY_ADV_SET_HANDLE,
+                duration: 0,
+                max_extended_advertising_events: 0,
+            });
+        }
+        for set in self.le_advertising_sets.iter_mut() {
+            if handles.contains(&set.handle) {
+                set.state.remove(LEAdvertisementsState::Active);
+                sets.push(set.enable_param());
+            }
+        }
+        if !sets.is_empty() {
+            let cmd = LESetExtendedAdvertisingEnableCmd {
+                enable: false,
+                sets,
+            };
+            cmd.send(self);
+        }
+    }
+
+    fn modify_advertising_sets(&mut self) {
+        use LEAdvertisementsTodo as T;
+        // the legacy set is created on the controller by its first enable
+        if self
+            .le_advertisements_state
+            .intersects(LEAdvertisementsState::Enabled | LEAdvertisementsState::Active)
+        {
+            let params = self.legacy_advertising_set_params();
+            if self.le_advertisements_todo.contains(T::SetParams) || !self.le_legacy_adv_set_created
+            {
+                self.le_advertisements_todo.remove(T::SetParams);
+                self.le_legacy_adv_set_created = true;
+                params.to_cmd(LE_LEGACY_ADV_SET_HANDLE).send(self);
+            }
+            if self.le_advertisements_todo.contains(T::SetAddressSet0) {
+                if let Some(random_address) = self.le_random_address {
+                    self.le_advertisements_todo.remove(T::SetAddressSet0);
+                    let cmd = LESetAdvertisingSetRandomAddressCmd {
+                        advertising_handle: LE_LEGACY_ADV_SET_HANDLE,
+                        random_address,
+                    };
+                    cmd.send(self);
+                }
+            }
+            if self.le_advertisements_todo.contains(T::SetAdvData) {
+                self.le_advertisements_todo.remove(T::SetAdvData);
+                let data = self.le_advertisements_data.clone();
+                self.send_advertising_set_data(LE_LEGACY_ADV_SET_HANDLE, &data, false);
+            }
+            // kept until the advertising type is scannable
+            if self.le_advertisements_todo.contains(T::SetScanData) && params.is_scannable() {
+                self.le_advertisements_todo.remove(T::SetScanData);
+                let data = self.le_scan_response_data.clone();
+                self.send_advertising_set_data(LE_LEGACY_ADV_SET_HANDLE, &data, true);
+            }
+        }
+
+        let mut sets = core::mem::take(&mut self.le_advertising_sets);
+        for set in sets.iter_mut() {
+            if set.todo.contains(T::RemoveSet) {
+                let cmd = LERemoveAdvertisingSetCmd {
+                    advertising_handle: set.handle,
+                };
+                cmd.send(self);
+                continue;
+            }
+            if set.todo.contains(T::SetParams) {
+                set.todo.remove(T::SetParams);
+                set.params.to_cmd(set.handle).send(self);
+            }
+            if set.todo.contains(T::SetAddress) {
+                set.todo.remove(T::SetAddress);
+                if let Some(random_address) = set.random_address {
+                    let cmd = LESetAdvertisingSetRandomAddressCmd {
+                        advertising_handle: set.handle,
+                        random_address,
+                    };
+                    cmd.send(self);
+                }
+            }
+            if set.todo.contains(T::SetAdvData) {
+                set.todo.remove(T::SetAdvData);
+                self.send_advertising_set_data(set.handle, &set.data, false);
+            }
+            if set.todo.contains(T::SetScanData) && set.params.is_scannable() {
+                set.todo.remove(T::SetScanData);
+                self.send_advertising_set_data(set.handle, &set.scan_response_data, true);
+            }
+            if set.todo.contains(T::SetPeriodicParams) {
+                set.todo.remove(T::SetPeriodicParams);
+                if let Some(params) = set.periodic_params {
+                    params.to_cmd(set.handle).send(self);
+                }
+            }
+            // kept until periodic advertising is configured
+            if set.todo.contains(T::SetPeriodicData) && set.periodic_params.is_some() {
+                set.todo.remove(T::SetPeriodicData);
+                for (operation, fragment) in
+                    fragment_advertising_data(&set.periodic_data, LE_PERIODIC_ADV_DATA_FRAGMENT_LEN)
+                {
+                    let cmd = LESetPeriodicAdvertisingDataCmd {
+                        advertising_handle: set.handle,
+                        operation,
+                        advertising_data: fragment.to_vec(),
+                    };
+                    cmd.send(self);
+                }
+            }
+        }
+        sets.retain(|set| !set.todo.contains(T::RemoveSet));
+        self.le_advertising_sets = sets;
+    }
+
+    /// Data longer than one command is fragmented, the set must not be advertising then
+    fn send_advertising_set_data(&mut self, handle: u8, data: &[u8], scan_response: bool) {
+        for (operation, fragment) in fragment_advertising_data(data, LE_EXT_ADV_DATA_FRAGMENT_LEN) {
+            if scan_response {
+                let cmd = LESetExtendedScanResponseDataCmd {
+                    advertising_handle: handle,
+                    operation,
+                    fragment_preference: 0x01,
+                    scan_response_data: fragment.to_vec(),
+                };
+                cmd.send(self);
+            } else {
+                let cmd = LESetExtendedAdvertisingDataCmd {
+                    advertising_handle: handle,
+                    operation,
+                    fragment_preference: 0x01,
+                    advertising_data: fragment.to_vec(),
+                };
+                cmd.send(self);
+            }
+        }
+    }
+
+    fn stop_periodic_advertising(&mut self, handles: &[u8]) {
+        for &advertising_handle in handles {
+            if let Some(set) = self
+                .le_advertising_sets
+                .iter_mut()
+                .find(|set| set.handle == advertising_handle)
+            {
+                set.periodic_state.remove(LEAdvertisementsState::Active);
+            }
+            let cmd = LESetPeriodicAdvertisingEnableCmd {
+                enable: false,
+                advertising_handle,
+            };
+            cmd.send(self);
+        }
+    }
+
+    /// Periodic advertising is only transmitted while its set advertises too
+    fn enable_periodic_advertising(&mut self) {
+        let mut handles = Vec::new();
+        for set in self.le_advertising_sets.iter_mut() {
+            if set.periodic_state.contains(LEAdvertisementsState::Enabled)
+                && !set.periodic_state.contains(LEAdvertisementsState::Active)
+            {
+                set.periodic_state |= LEAdvertisementsState::Active;
+                handles.push(set.handle);
+            }
+        }
+        for advertising_handle in handles {
+            let cmd = LESetPeriodicAdvertisingEnableCmd {
+                enable: true,
+                advertising_handle,
+            };
+            cmd.send(self);
+        }
+    }
+
+    /// Enable the legacy set and every set that is enabled but not advertising with one command,
+    /// the legacy set waits for `own_address_ready`
+    fn enable_advertising_sets(&mut self, own_address_ready: bool) {
+        let mut sets = Vec::new();
+        if own_address_ready
+            && self
+                .le_advertisements_state
+                .contains(LEAdvertisementsState::Enabled)
+            && !self
+                .le_advertisements_state
+                .contains(LEAdvertisementsState::Active)
+        {
+            self.le_advertisements_state |= LEAdvertisementsState::Active;
+            sets.push(AdvertisingSetEnable {
+                advertising_handle: LE_LEGACY_ADV_SET_HANDLE,
+                duration: 0,
+                max_extended_advertising_events: 0,
+            });
+        }
+        for set in self.le_advertising_sets.iter_mut() {
+            if set.state.contains(LEAdvertisementsState::Enabled)
+                && !set.state.contains(LEAdvertisementsState::Active)
+            {
+                set.state |= LEAdvertisementsState::Active;
+                sets.push(set.enable_param());
+            }
+        }
+        if !sets.is_empty() {
+            let cmd = LESetExtendedAdvertisingEnableCmd { enable: true, sets };
+            cmd.send(self);
+        }
+    }
+
+    pub fn power_control(&mut self, control: HCIPowerMode) {
+        match self.state {
+            HCIState::Off => self.power_control_off(control),
+            HCIState::Initializing | HCIState::Working => self.power_control_on(control),
+            HCIState::Sleeping | HCIState::FallingSleep => self.power_control_sleep(control),
+            HCIState::Halting => {}
+        }
+        self.run();
+    }
+
+    fn power_control_off(&mut self, control: HCIPowerMode) {
+        match control {
+            HCIPowerMode::On => {
+                self.power_enter_initializing_state();
+            }
+            _ => {}
+        }
+    }
+
+    fn power_control_on(&mut self, control: HCIPowerMode) {
+        match control {
+            HCIPowerMode::Off => self.power_enter_halting_state(),
+            HCIPowerMode::Sleep => {
+                if self.state == HCIState::Working {
+                    self.state = HCIState::FallingSleep;
+                    self.sub_state = HCISubState::SleepStopActivities;
+                }
+            }
+            HCIPowerMode::On => {}
+        }
+    }
+
+    fn power_control_sleep(&mut self, control: HCIPowerMode) {
+        match control {
+            HCIPowerMode::Off => self.power_enter_halting_state(),
+            HCIPowerMode::On => {
+                // links survive the sleep, advertising is restored by the next run
+                self.state = HCIState::Working;
+                info!("HCI wake up: {:?}", self.bd_addr);
+                self.emit_app_event(AppEvent::PowerModeChanged(HCIPowerMode::On));
+            }
+            HCIPowerMode::Sleep => {}
+        }
+    }
+
+    fn power_enter_halting_state(&mut self) {
+        self.state = HCIState::Halting;
+        self.sub_state = HCISubState::HaltStopActivities;
+    }
+
+    fn power_enter_off_state(&mut self) {
+        self.state = HCIState::Off;
+        self.sub_state = HCISubState::Send(HCIInitStep::Reset);
+        self.drop_links(ControllerErrorCode::ConnectionTerminatedByLocalHost);
+        info!("HCI off: {:?}", self.bd_addr);
+        self.emit_app_event(AppEvent::PowerModeChanged(HCIPowerMode::Off));
+    }
+
+    /// The controller lost its state, start over with the init sequence
+    fn power_reinit(&mut self) {
+        warn!("HCI init again: {:?}", self.bd_addr);
+        self.drop_links(ControllerErrorCode::HardwareFailure);
+        self.power_enter_initializing_state();
+    }
+
+    /// Forget every link and the controller buffers they used
+    fn drop_links(&mut self, reason: ControllerErrorCode) {
+        for handle in self.connections.clear().into_keys() {
+            self.emit_app_event(AppEvent::Disconnected { handle, reason });
+        }
+        self.inquiry_complete(reason);
+        self.acl_queue.clear();
+        self.acl_recombination = Recombination::default();
+        self.acl_packets_free = self.acl_packets_total;
+        self.le_acl_packets_free = self.le_acl_packets_total;
+        self.le_advertisements_state
+            .remove(LEAdvertisementsState::Active);
+        // the controller forgets the advertising and scan parameters on reset
+        self.le_advertisements_todo |= LEAdvertisementsTodo::SetParams
+            | LEAdvertisementsTodo::SetAdvData
+            | LEAdvertisementsTodo::SetScanData;
+        if self.le_random_address.is_some() {
+            self.le_advertisements_todo |=
+                LEAdvertisementsTodo::SetAddress | LEAdvertisementsTodo::SetAddressSet0;
+        }
+        self.le_rand_pending = false;
+        self.le_filter_accept_list.reset();
+        self.le_resolving_list.reset();
+        self.le_address_resolution_enabled = false;
+        self.le_connection_defaults_update =
+            self.le_default_phy.is_some() || self.le_default_data_length.is_some();
+        self.le_legacy_adv_set_created = false;
+        self.le_advertising_sets
+            .retain(|set| !set.todo.contains(LEAdvertisementsTodo::RemoveSet));
+        for set in self.le_advertising_sets.iter_mut() {
+            set.state.remove(LEAdvertisementsState::Active);
+            set.todo |= LEAdvertisementsTodo::SetParams
+                | LEAdvertisementsTodo::SetAdvData
+                | LEAdvertisementsTodo::SetScanData;
+            if set.random_address.is_some() {
+                set.todo |= LEAdvertisementsTodo::SetAddress;
+            }
+            set.periodic_state.remove(LEAdvertisementsState::Active);
+            if set.periodic_params.is_some() {
+                set.todo |=
+                    LEAdvertisementsTodo::SetPeriodicParams | LEAdvertisementsTodo::SetPeriodicData;
+            }
+        }
+        for sync_handle in core::mem::take(&mut self.le_periodic_syncs).into_keys() {
+            self.emit_app_event(AppEvent::PeriodicSyncLost { sync_handle });
+        }
+        self.le_periodic_sync_state = LEPeriodicSyncState::Idle;
+        self.le_scanning_state.remove(LEScanningState::Active);
+        self.le_scanning_param_update = true;
+        self.le_connect_state = LEConnectState::Idle;
+    }
+
+    /// During halting a command that never completes must not block the power off
+    fn halting_cmd_failed(&mut self, cmd: &HCIWaitingCmd) {
+        if self.state != HCIState::Halting {
+            return;
+        }
+        if cmd.opcode == LinkControl::Disconnect.get_opcode() {
+            if let Some(param) = cmd.param.as_deref() {
+                let handle = u16::from_le_bytes([param[0], param[1]]);
+                self.remove_link(handle);
+                self.emit_app_event(AppEvent::Disconnected {
+                    handle,
+                    reason: ControllerErrorCode::ConnectionTerminatedByLocalHost,
+                });
+            }
+        } else if cmd.opcode == ControllerAndBaseband::Reset.get_opcode() {
+            self.power_enter_off_state();
+        }
+    }
+
+    fn power_enter_initializing_state(&mut self) {
+        self.state = HCIState::Initializing;
+        self.sub_state = HCISubState::Send(HCIInitStep::Reset);
+        self.config.reset();
+
+        // the controller accepts exactly one command after power on
+        self.cmd_credits = 1;
+        self.cmd_queue.clear();
+        self.cmd_waiting.clear();
+    }
+
+    /// Drive the host timers, `now_ms` is a monotonic time in ms
+    pub fn poll_timers(&mut self, now_ms: u64) {
+        self.now_ms = now_ms;
+
+        if let LEConnectState::W4Complete { deadline } = self.le_connect_state {
+            if deadline <= now_ms {
+                warn!("le connect timeout");
+                self.le_connect_state = LEConnectState::Cancel { timeout: true };
+                self.run();
+            }
+        }
+        if self.le_own_address_mode.is_private()
+            && self
+                .le_private_address_deadline
+                .is_some_and(|deadline| deadline <= now_ms)
+        {
+            info!("rotate private address");
+            self.le_private_address_deadline = None;
+            self.run();
+        }
+        if let HCISubState::HaltW4Disconnect { deadline } = self.sub_state {
+            if self.state == HCIState::Halting && deadline <= now_ms {
+                self.run();
+            }
+        }
+
+        let (timeout, waiting): (Vec<_>, Vec<_>) = core::mem::take(&mut self.cmd_waiting)
+            .into_iter()
+            .partition(|cmd| cmd.deadline <= now_ms);
+        self.cmd_waiting = waiting;
+        if timeout.is_empty() {
+            return;
+        }
+
+        for cmd in timeout {
+            error!("command {:#06x} timeout", cmd.opcode);
+            if cmd.opcode == LEController::LERand.get_opcode() {
+                self.rand_failed();
+            }
+            self.init_cmd_failed(cmd.opcode, Error::CommandTimeout(cmd.opcode));
+            self.halting_cmd_failed(&cmd);
+        }
+        // give the controller another chance with the remaining commands
+        self.cmd_credits = self.cmd_credits.max(1);
+        self.flush_cmd_queue();
+        self.run();
+    }
+
+    fn recv_ce_data(&mut self, data: Vec<u8>) {
+        info!("CE {:?}", data);
+    }
+    fn recv_acl_data(&mut self, data: Vec<u8>) -> Result<()> {
         info!("ACL {:?}", data);
+        let header = ACLHeader::from_u8_array(&data)?;
+        let payload = take_bytes(
+            &data,
+            HCI_ACL_HEADER_SIZE,
+            header.data_total_length as usize,
+        )?;
+        if let Some(pdu) = self.acl_recombination.push(&header, payload)? {
+            let cid = u16::from_le_bytes([pdu[2], pdu[3]]);
+            info!("l2cap {} cid {:#06x} {:?}", header.handle, cid, &pdu[4..]);
+            let handle = header.handle;
+            let data = pdu[L2CAP_HEADER_SIZE..].to_vec();
+            let le = match self.connections.get(handle) {
+                Some(conn) => conn.is_le(),
+                None => {
+                    warn!("l2cap data for unknown connection {}", handle);
+                    return Ok(());
+                }
+            };
+            // the signaling channel id depends on the link type
+            match cid {
+                L2CAP_CID_ATT => self.emit_app_event(AppEvent::GATTData { handle, data }),
+                L2CAP_CID_LE_SIGNALING if le => self.handle_le_signaling(handle, &data)?,
+                L2CAP_CID_SIGNALING if !le => self.handle_bredr_signaling(handle, &data)?,
+                L2CAP_CID_SM if data.first() == Some(&SM_PAIRING_REQUEST) => {
+                    self.emit_app_event(AppEvent::PairingRequest { handle })
+                }
+                _ => self.emit_app_event(AppEvent::ACLData { handle, cid, data }),
+            }
+        }
+        Ok(())
+    }
+    fn recv_event_data(&mut self, data: Vec<u8>) -> Result<()> {
+        info!("EV {:?}", data);
+        let event = HCIEventPacket::from_u8_array(&data)?;
+        match event {
+            HCIEventPacket::InquiryComplete(evt) => self.inquiry_complete(evt.status),
+            HCIEventPacket::InquiryResult(evt) => self.handle_inquiry_responses(&evt.responses),
+            HCIEventPacket::InquiryResultWithRSSI(evt) => {
+                self.handle_inquiry_responses(&evt.responses)
+            }
+            HCIEventPacket::ExtendedInquiryResult(evt) => {
+                self.handle_inquiry_responses(core::slice::from_ref(&evt.response))
+            }
+            HCIEventPacket::ConnectionComplete(evt) => self.handle_connection_complete(&evt),
+            HCIEventPacket::ConnectionRequest(evt) => self.handle_connection_request(&evt),
+            HCIEventPacket::RoleChange(evt) => self.handle_role_change(&evt),
+            HCIEventPacket::DisconnectionComplete(evt) => self.handle_disconnection_complete(&evt),
+            HCIEventPacket::EncryptionChange(evt) => self.handle_encryption_change(&evt),
+            HCIEventPacket::CommandComplete(evt) => self.handle_command_complete(&evt),
+            HCIEventPacket::CommandStatus(evt) => self.handle_command_status(&evt),
+            HCIEventPacket::HardwareError(evt) => {
+                error!("controller hardware error: {:#04x}", evt.hardware_code);
+                match self.state {
+                    HCIState::Off => {}
+                    HCIState::Halting => self.power_enter_off_state(),
+                    _ => self.power_reinit(),
+                }
+            }
+            HCIEventPacket::NumberOfCompletedPackets(evt) => {
+                self.handle_number_of_completed_packets(&evt)
+            }
+            HCIEventPacket::LEMeta(evt) => self.handle_le_meta_event(&evt),
+        }
+        Ok(())
+    }
+
+    fn handle_command_complete(&mut self, evt: &CommandCompleteEvt<Vec<u8>>) {
+        let cmd = match self.cmd_answered(evt.num_hci_command_packets, evt.opcode) {
+            Some(cmd) => cmd,
+            None => {
+                // a NOP only returns credits, a Reset the host did not send means the
+                // controller restarted underneath us
+                let reset = evt.opcode == ControllerAndBaseband::Reset.get_opcode();
+                if reset && self.state > HCIState::Initializing && self.state != HCIState::Halting {
+                    self.power_reinit();
+                }
+                return;
+            }
+        };
+        match self.state {
+            HCIState::Off | HCIState::Initializing => {
+                self.init_process_event(evt);
+                return;
+            }
+            HCIState::Halting
+                if self.sub_state == HCISubState::HaltW4Reset
+                    && evt.opcode == ControllerAndBaseband::Reset.get_opcode() =>
+            {
+                self.power_enter_off_state();
+                return;
+            }
+            _ => {}
+        }
+
+        let status = ControllerErrorCode::from_u8_array(&evt.return_param);
+        if status != Ok(ControllerErrorCode::Ok) {
+            warn!("command {:#06x} failed: {:?}", evt.opcode, status);
+            if evt.opcode == LEController::LERand.get_opcode() {
+                self.rand_failed();
+            }
+            if let Some(write) = HCI_CONFIG_WRITES
+                .into_iter()
+                .find(|write| config_write_opcode(*write) == evt.opcode)
+            {
+                self.config.write_failed(write);
+                self.emit_app_event(AppEvent::ConfigWriteFailed {
+                    write,
+                    status: status.unwrap_or(ControllerErrorCode::UnspecifiedError),
+                });
+            }
+            return;
+        }
+        if evt.opcode == LEController::LESetAdvertisingEnable.get_opcode()
+            || evt.opcode == LEController::LESetExtendedAdvertisingEnable.get_opcode()
+        {
+            let enable = cmd.param.as_deref().and_then(|p| p.first()) == Some(&1);
+            self.emit_app_event(if enable {
+                AppEvent::AdvertisingStarted
+            } else {
+                AppEvent::AdvertisingStopped
+            });
+        } else if evt.opcode == LEController::LESetScanEnable.get_opcode()
+            || evt.opcode == LEController::LESetExtendedScanEnable.get_opcode()
+        {
+            let enable = cmd.param.as_deref().and_then(|p| p.first()) == Some(&1);
+            self.emit_app_event(if enable {
+                AppEvent::ScanningStarted
+            } else {
+                AppEvent::ScanningStopped
+            });
+        } else if evt.opcode == LinkControl::InquiryCancel.get_opcode() {
+            self.inquiry_complete(ControllerErrorCode::Ok);
+        } else if evt.opcode == LEController::LERand.get_opcode() {
+            self.handle_rand(evt);
+        } else if evt.opcode == LEController::LEReadPHY.get_opcode() {
+            match evt.parse_return_param::<LEReadPHYRet>() {
+                Ok(ret) if ret.status == ControllerErrorCode::Ok => {
+                    self.phy_updated(ret.connection_handle, ret.tx_phy, ret.rx_phy)
+                }
+                Ok(ret) => warn!("le read phy {}: {:?}", ret.connection_handle, ret.status),
+                Err(err) => warn!("le read phy: {}", err),
+            }
+        } else if evt.opcode == LEController::LESetRandomAddress.get_opcode()
+            && self
+                .le_advertisements_todo
+                .contains(LEAdvertisementsTodo::PrivacyNotify)
+        {
+            self.le_advertisements_todo
+                .remove(LEAdvertisementsTodo::PrivacyNotify);
+            if let Some(address) = self.le_random_address {
+                self.emit_app_event(AppEvent::RandomAddressChanged(address));
+            }
+        }
+    }
+
+    /// A new private address from the controller random number, `run_gap_le` writes it
+    fn handle_rand(&mut self, evt: &CommandCompleteEvt<Vec<u8>>) {
+        self.le_rand_pending = false;
+        let ret = match evt.parse_return_param::<LERandRet>() {
+            Ok(ret) => ret,
+            Err(err) => {
+                warn!("le rand: {}", err);
+                self.rand_failed();
+                return;
+            }
+        };
+        let address = match self.le_own_address_mode {
+            LEOwnAddressMode::Resolvable => self
+                .le_local_irk
+                .and_then(|irk| resolvable_private_address(&irk, ret.random_number as u32)),
+            LEOwnAddressMode::NonResolvable => {
+                non_resolvable_private_address(ret.random_number, &self.controller_bd_addr)
+            }
+            // the mode changed while waiting
+            _ => return,
+        };
+        // an unusable address asks for another random number
+        if let Some(address) = address {
+            info!("private address {:?}", address);
+            self.le_random_address = Some(address);
+            self.le_advertisements_todo |=
+                LEAdvertisementsTodo::SetAddress | LEAdvertisementsTodo::SetAddressSet0;
+            self.le_private_address_deadline =
+                Some(self.now_ms + self.le_private_address_timeout_ms);
+        }
+    }
+
+    fn phy_updated(&mut self, handle: u16, tx_phy: LEPhy, rx_phy: LEPhy) {
+        if let Some(conn) = self.connections.get_mut(handle) {
+            conn.tx_phy = tx_phy;
+            conn.rx_phy = rx_phy;
+        }
+        info!(
+            "le connection {} phy: tx {:?} rx {:?}",
+            handle, tx_phy, rx_phy
+        );
+        self.emit_app_event(AppEvent::PhyUpdated {
+            handle,
+            tx_phy,
+            rx_phy,
+        });
+    }
+
+    /// Retried at the next rotation instead of flooding a controller without LE Rand
+    fn rand_failed(&mut self) {
+        self.le_rand_pending = false;
+        self.le_private_address_deadline = Some(self.now_ms + self.le_private_address_timeout_ms);
+    }
+
+    fn handle_command_status(&mut self, evt: &CommandStatusEvt) {
+        let cmd = match self.cmd_answered(evt.num_hci_command_packets, evt.opcode) {
+            Some(cmd) => cmd,
+            None => return,
+        };
+        let remote = match cmd_connection_remote(&cmd) {
+            Ok(remote) => remote,
+            Err(err) => {
+                warn!("command {:#06x}: {}", evt.opcode, err);
+                None
+            }
+        };
+        if evt.status != ControllerErrorCode::Ok {
+            warn!("command {:#06x} failed: {:?}", evt.opcode, evt.status);
+            self.init_cmd_failed(
+                evt.opcode,
+                Error::CommandFailed {
+                    opcode: evt.opcode,
+                    status: evt.status,
+                },
+            );
+            self.halting_cmd_failed(&cmd);
+            if evt.opcode == LinkControl::Disconnect.get_opcode() {
+                self.disconnect_failed(&cmd);
+            }
+            if evt.opcode == LinkControl::Inquiry.get_opcode() {
+                self.inquiry_complete(evt.status);
+            }
+            if evt.opcode == LEController::LERand.get_opcode() {
+                self.rand_failed();
+            }
+            if evt.opcode == LEController::LEConnectionUpdate.get_opcode() {
+                if let Some([lo, hi, ..]) = cmd.param.as_deref() {
+                    self.emit_app_event(AppEvent::ConnectionUpdateFailed {
+                        handle: u16::from_le_bytes([*lo, *hi]),
+                        status: evt.status,
+                    });
+                }
+            }
+            if evt.opcode == LEController::LESetPHY.get_opcode() {
+                if let Some([lo, hi, ..]) = cmd.param.as_deref() {
+                    self.emit_app_event(AppEvent::PhyUpdateFailed {
+                        handle: u16::from_le_bytes([*lo, *hi]),
+                        status: evt.status,
+                    });
+                }
+            }
+            if evt.opcode == LEController::LEPeriodicAdvertisingCreateSync.get_opcode() {
+                self.le_periodic_sync_state = LEPeriodicSyncState::Idle;
+                self.emit_app_event(AppEvent::PeriodicSyncFailed { status: evt.status });
+            }
+            if let Some((remote, ..)) = remote {
+                if evt.opcode == LEController::LECreateConnection.get_opcode()
+                    || evt.opcode == LEController::LEExtendedCreateConnection.get_opcode()
+                {
+                    self.le_connect_state = LEConnectState::Idle;
+                }
+                self.emit_app_event(AppEvent::ConnectionFailed {
+                    remote,
+                    status: evt.status,
+                });
+            }
+            return;
+        }
+        if evt.opcode == LinkControl::Inquiry.get_opcode() {
+            self.emit_app_event(AppEvent::InquiryStarted);
+        }
+        // track the connection once the controller accepted to create it
+        if let Some((remote, addr_type, role)) = remote {
+            self.connections.add_pending(HCIConnection::new(
+                remote,
+                addr_type,
+                role,
+                ConnectionState::Pending,
+            ));
+        }
+    }
+
+    fn handle_inquiry_responses(&mut self, responses: &[InquiryResponse]) {
+        if !self.inquiry_active {
+            return;
+        }
+        for response in responses {
+            let device = DiscoveredDevice::from_response(response);
+            self.inquiry_cache.update(&device);
+            self.emit_app_event(AppEvent::DeviceDiscovered(device));
+        }
+    }
+
+    /// Ends the inquiry once, whether it completed, was cancelled or failed
+    fn inquiry_complete(&mut self, status: ControllerErrorCode) {
+        if !self.inquiry_active {
+            return;
+        }
+        self.inquiry_active = false;
+        self.emit_app_event(AppEvent::InquiryComplete { status });
+    }
+
+    fn handle_connection_complete(&mut self, evt: &ConnectionCompleteEvt) {
+        if evt.link_type != LinkType::ACL {
+            return;
+        }
+        if evt.status != ControllerErrorCode::Ok {
+            warn!("connection to {:?} failed: {:?}", evt.bd_addr, evt.status);
+            self.connections.take_pending(&evt.bd_addr);
+            self.emit_app_event(AppEvent::ConnectionFailed {
+                remote: evt.bd_addr,
+                status: evt.status,
+            });
+            return;
+        }
+
+        info!(
+            "connected {:?} handle {}",
+            evt.bd_addr, evt.connection_handle
+        );
+        // no pending entry if the controller accepted the remote on its own
+        let mut conn = self
+            .connections
+            .take_pending(&evt.bd_addr)
+            .unwrap_or_else(|| {
+                HCIConnection::new(
+                    evt.bd_addr,
+                    BDAddrType::Classic,
+                    Role::Peripheral,
+                    ConnectionState::Connected,
+                )
+            });
+        conn.state = ConnectionState::Connected;
+        conn.encrypted = evt.encryption_enabled;
+        let role = conn.role;
+        self.connections.insert(evt.connection_handle, conn);
+        self.write_new_link_config(evt.connection_handle);
+        self.emit_app_event(AppEvent::Connected {
+            handle: evt.connection_handle,
+            remote: evt.bd_addr,
+            role,
+            params: None,
+        });
+    }
+
+    fn handle_le_connection_complete(&mut self, evt: &LEConnectionCompleteEvt) {
+        if evt.status != ControllerErrorCode::Ok && evt.role != Role::Central {
+            self.le_advertising_failed(evt);
+            return;
+        }
+        // only a central link or failure ends our own create connection
+        let connect_state = if evt.role == Role::Central {
+            core::mem::replace(&mut self.le_connect_state, LEConnectState::Idle)
+        } else {
+            LEConnectState::Idle
+        };
+        if evt.status != ControllerErrorCode::Ok {
+            // the peer address is not valid after a cancel
+            let remote = self
+                .connections
+                .take_pending_le_central()
+                .map_or(evt.peer_address, |c| c.remote);
+            let status = match connect_state {
+                LEConnectState::W4Cancelled { timeout: true } => {
+                    ControllerErrorCode::ConnectionAcceptTimeoutExceeded
+                }
+                _ => evt.status,
+            };
+            warn!("le connection to {:?} failed: {:?}", remote, status);
+            self.emit_app_event(AppEvent::ConnectionFailed { remote, status });
+            return;
+        }
+        info!(
+            "le connected {:?} handle {} as {:?}",
+            evt.peer_address, evt.connection_handle, evt.role
+        );
+        // with the filter accept list or a resolved peer the address differs from the request
+        let pending = self
+            .connections
+            .take_pending(&evt.peer_address)
+            .or_else(|| match evt.role {
+                Role::Central => self.connections.take_pending_le_central(),
+                _ => None,
+            });
+        let mut conn = pending.unwrap_or_else(|| {
+            HCIConnection::new(
+                evt.peer_address,
+                BDAddrType::from(evt.peer_address_type),
+                evt.role,
+                ConnectionState::Connected,
+            )
+        });
+        conn.remote = evt.peer_address;
+        conn.addr_type = BDAddrType::from(evt.peer_address_type);
+        let params = LinkParams {
+            interval: evt.connection_interval,
+            latency: evt.peripheral_latency,
+            supervision_timeout: evt.supervision_timeout,
+        };
+        conn.role = evt.role;
+        conn.state = ConnectionState::Connected;
+        conn.params = Some(params);
+        conn.identity_address = match evt.peer_address_type {
+            LEAddressType::RandomDevice => self
+                .resolve_address(&evt.peer_address)
+                .map(|bond| bond.identity_address),
+            // resolved by the controller
+            LEAddressType::PublicIdentity | LEAddressType::RandomIdentity => Some(evt.peer_address),
+            LEAddressType::PublicDevice => None,
+        };
+        self.connections.insert(evt.connection_handle, conn);
+        self.write_new_link_config(evt.connection_handle);
+        self.emit_app_event(AppEvent::Connected {
+            handle: evt.connection_handle,
+            remote: evt.peer_address,
+            role: evt.role,
+            params: Some(params),
+        });
+    }
+
+    /// Connectable advertising ended without a link, like directed advertising timing out.
+    /// Extended advertising also reports it with the advertising set terminated event
+    fn le_advertising_failed(&mut self, evt: &LEConnectionCompleteEvt) {
+        warn!(
+            "le advertising ended without a connection: {:?}",
+            evt.status
+        );
+        if self.is_extended_advertising_supported() {
+            return;
+        }
+        self.le_advertisements_state = LEAdvertisementsState::Idle;
+        self.emit_app_event(AppEvent::AdvertisingSetTerminated {
+            handle: LE_LEGACY_ADV_SET_HANDLE,
+            status: evt.status,
+            connection_handle: None,
+            completed_events: 0,
+        });
+    }
+
+    fn handle_disconnection_complete(&mut self, evt: &DisconnectionCompleteEvt) {
+        if evt.status != ControllerErrorCode::Ok {
+            warn!(
+                "disconnect {} failed: {:?}",
+                evt.connection_handle, evt.status
+            );
+            if let Some(conn) = self.connections.get_mut(evt.connection_handle) {
+                conn.state = ConnectionState::Connected;
+            }
+            return;
+        }
+        info!(
+            "disconnected handle {}: {:?}",
+            evt.connection_handle, evt.reason
+        );
+        self.remove_link(evt.connection_handle);
+        self.emit_app_event(AppEvent::Disconnected {
+            handle: evt.connection_handle,
+            reason: evt.reason,
+        });
+    }
+
+    fn handle_connection_request(&mut self, evt: &ConnectionRequestEvt) {
+        let request = ConnectionRequest {
+            remote: evt.bd_addr,
+            class_of_device: evt.class_of_device,
+            link_type: evt.link_type,
+        };
+        // SCO and eSCO links are not supported
+        let reason =
+            if evt.link_type != LinkType::ACL || self.connections.contains_remote(&evt.bd_addr) {
+                ControllerErrorCode::ConnectionRejectedDueToLimitedResources
+            } else if !self
+                .connection_request_policy
+                .as_ref()
+                .is_none_or(|policy| policy(self, &request))
+            {
+                ControllerErrorCode::ConnectionRejectedDueToUnacceptableBDAddr
+            } else {
+                let cmd = AcceptConnectionRequestCmd {
+                    bd_addr: evt.bd_addr,
+                    role: self.role_switch_preference.accept_role(),
+                };
+                cmd.send(self);
+                return;
+            };
+        info!("rejected connection of {:?}: {:?}", evt.bd_addr, reason);
+        let cmd = RejectConnectionRequestCmd {
+            bd_addr: evt.bd_addr,
+            reason,
+        };
+        cmd.send(self);
+    }
+
+    fn handle_role_change(&mut self, evt: &RoleChangeEvt) {
+        if evt.status != ControllerErrorCode::Ok {
+            warn!("role change of {:?} failed: {:?}", evt.bd_addr, evt.status);
+            return;
+        }
+        let handle = match self.connections.get_remote_mut(&evt.bd_addr) {
+            Some((handle, conn)) => {
+                conn.role = evt.new_role;
+                handle
+            }
+            None => return,
+        };
+        // a pending link reports its role in `AppEvent::Connected`
+        if let Some(handle) = handle {
+            // only the central writes the supervision timeout
+            let write = HCIConfigWrite::LinkSupervisionTimeout;
+            if evt.new_role == Role::Central
+                && self.config.is_modified(write)
+                && self.is_command_supported(config_write_command(write))
+            {
+                self.write_link_config(handle, write);
+            }
+            self.emit_app_event(AppEvent::RoleChanged {
+                handle,
+                role: evt.new_role,
+            });
+        }
+    }
+
+    fn handle_encryption_change(&mut self, evt: &EncryptionChangeEvt) {
+        if evt.status != ControllerErrorCode::Ok {
+            warn!(
+                "encryption change {} failed: {:?}",
+                evt.connection_handle, evt.status
+            );
+            return;
+        }
+        let enabled = evt.encryption_enabled != 0;
+        if let Some(conn) = self.connections.get_mut(evt.connection_handle) {
+            conn.encrypted = enabled;
+        }
+        self.emit_app_event(AppEvent::EncryptionChanged {
+            handle: evt.connection_handle,
+            enabled,
+        });
+    }
+
+    /// The link stays up, unless halting already dropped it
+    fn disconnect_failed(&mut self, cmd: &HCIWaitingCmd) {
+        let handle = match cmd.param.as_deref() {
+            Some([lo, hi, ..]) => u16::from_le_bytes([*lo, *hi]),
+            _ => return,
+        };
+        if let Some(conn) = self.connections.get_mut(handle) {
+            conn.state = ConnectionState::Connected;
+        }
+    }
+
+    fn send_disconnect(&mut self, handle: u16, reason: ControllerErrorCode) {
+        if let Some(conn) = self.connections.get_mut(handle) {
+            conn.state = ConnectionState::Disconnecting;
+        }
+        let cmd = DisconnectCmd {
+            connection_handle: handle,
+            reason,
+        };
+        cmd.send(self);
+    }
+
+    /// Drop a link and take back the controller buffers it used
+    fn remove_link(&mut self, handle: u16) -> Option<HCIConnection> {
+        let conn = self.connections.remove(handle)?;
+        *self.acl_packets_free(conn.is_le()) += conn.acl_in_flight;
+        self.acl_queue
+            .retain(|(header, _, _)| header.handle != handle);
+        self.acl_recombination.remove(handle);
+        self.flush_acl_queue();
+        Some(conn)
+    }
+
+    fn advertising_report(&mut self, mut report: LEAdvertisingReport) {
+        // scanning only for a periodic sync reports nothing
+        if !self.le_scanning_state.contains(LEScanningState::Enabled) {
+            return;
+        }
+        info!(
+            "adv report {:?} {:?} rssi {} {:?}",
+            report.event_type, report.address, report.rssi, report.data
+        );
+        // bonded peers are reported with their identity, as the controller
+        // would after resolving with its resolving list
+        if report.address_type == LEAddressType::RandomDevice {
+            if let Some(bond) = self.resolve_address(&report.address) {
+                report.address = bond.identity_address;
+                report.address_type = bond.resolved_address_type();
+            }
+        }
+        self.emit_app_event(AppEvent::AdvertisingReport(report));
+    }
+
+    fn handle_le_meta_event(&mut self, evt: &LEMetaEvent) {
+        match evt {
+            LEMetaEvent::ConnectionComplete(evt) => self.handle_le_connection_complete(evt),
+            LEMetaEvent::AdvertisingReport(evt) => {
+                for report in evt.reports.iter() {
+                    self.advertising_report(report.clone());
+                }
+            }
+            LEMetaEvent::ExtendedAdvertisingReport(evt) => {
+                if !self.le_scanning_state.contains(LEScanningState::Enabled) {
+                    return;
+                }
+                for report in evt.reports.iter() {
+                    // legacy PDUs look the same as with a legacy scan
+                    if let Some(report) = report.to_legacy() {
+                        self.advertising_report(report);
+                        continue;
+                    }
+                    info!(
+                        "ext adv report {:?} {:?} sid {:?} rssi {} {:?}",
+                        report.event_type,
+                        report.address,
+                        report.advertising_sid,
+                        report.rssi,
+                        report.data
+                    );
+                    let mut report = report.clone();
+                    if report.address_type == Some(LEAddressType::RandomDevice) {
+                        if let Some(bond) = self.resolve_address(&report.address) {
+                            report.address = bond.identity_address;
+                            report.address_type = Some(bond.resolved_address_type());
+                        }
+                    }
+                    self.emit_app_event(AppEvent::ExtendedAdvertisingReport(report));
+                }
+            }
+            LEMetaEvent::ConnectionUpdateComplete(evt) => {
+                if evt.status != ControllerErrorCode::Ok {
+                    warn!(
+                        "le connection {} update failed: {:?}",
+                        evt.connection_handle, evt.status
+                    );
+                    self.emit_app_event(AppEvent::ConnectionUpdateFailed {
+                        handle: evt.connection_handle,
+                        status: evt.status,
+                    });
+                    return;
+                }
+                let params = LinkParams {
+                    interval: evt.connection_interval,
+                    latency: evt.peripheral_latency,
+                    supervision_timeout: evt.supervision_timeout,
+                };
+                if let Some(conn) = self.connections.get_mut(evt.connection_handle) {
+                    conn.params = Some(params);
+                }
+                info!(
+                    "le connection {} updated: interval {} latency {} timeout {}",
+                    evt.connection_handle,
+                    evt.connection_interval,
+                    evt.peripheral_latency,
+                    evt.supervision_timeout
+                );
+                self.emit_app_event(AppEvent::ConnectionUpdated {
+                    handle: evt.connection_handle,
+                    params,
+                });
+            }
+            LEMetaEvent::ReadRemoteFeaturesComplete(evt) => {
+                info!(
+                    "le remote features {}: {:#018x}",
+                    evt.connection_handle, evt.le_features
+                );
+            }
+            LEMetaEvent::LongTermKeyRequest(evt) => {
+                info!("le long term key request {}", evt.connection_handle);
+            }
+            LEMetaEvent::RemoteConnectionParameterRequest(evt) => {
+                let params = ConnectionUpdateParams {
+                    interval_min: evt.interval_min,
+                    interval_max: evt.interval_max,
+                    latency: evt.max_latency,
+                    supervision_timeout: evt.supervision_timeout,
+                };
+                info!(
+                    "le connection {} parameter request {:?}",
+                    evt.connection_handle, params
+                );
+                if self.conn_param_accepted(evt.connection_handle, &params) {
+                    let cmd = LERemoteConnectionParameterRequestReplyCmd {
+                        connection_handle: evt.connection_handle,
+                        interval_min: params.interval_min,
+                        interval_max: params.interval_max,
+                        max_latency: params.latency,
+                        supervision_timeout: params.supervision_timeout,
+                        min_ce_length: self.le_connection_params.min_ce_length,
+                        max_ce_length: self.le_connection_params.max_ce_length,
+                    };
+                    cmd.send(self);
+                } else {
+                    let cmd = LERemoteConnectionParameterRequestNegativeReplyCmd {
+                        connection_handle: evt.connection_handle,
+                        reason: ControllerErrorCode::UnacceptableConnectionParameters,
+                    };
+                    cmd.send(self);
+                }
+            }
+            LEMetaEvent::DataLengthChange(evt) => {
+                let data_length = DataLength {
+                    max_tx_octets: evt.max_tx_octets,
+                    max_tx_time: evt.max_tx_time,
+                    max_rx_octets: evt.max_rx_octets,
+                    max_rx_time: evt.max_rx_time,
+                };
+                if let Some(conn) = self.connections.get_mut(evt.connection_handle) {
+                    conn.data_length = data_length;
+                }
+                info!(
+                    "le connection {} data length: {:?}",
+                    evt.connection_handle, data_length
+                );
+                self.emit_app_event(AppEvent::DataLengthChanged {
+                    handle: evt.connection_handle,
+                    data_length,
+                });
+            }
+            LEMetaEvent::PHYUpdateComplete(evt) => {
+                if evt.status != ControllerErrorCode::Ok {
+                    warn!(
+                        "le connection {} phy update failed: {:?}",
+                        evt.connection_handle, evt.status
+                    );
+                    self.emit_app_event(AppEvent::PhyUpdateFailed {
+                        handle: evt.connection_handle,
+                        status: evt.status,
+                    });
+                    return;
+                }
+                self.phy_updated(evt.connection_handle, evt.tx_phy, evt.rx_phy);
+            }
+            LEMetaEvent::PeriodicAdvertisingSyncEstablished(evt) => {
+                self.handle_periodic_sync_established(evt)
+            }
+            LEMetaEvent::PeriodicAdvertisingReport(evt) => {
+                if self.le_periodic_syncs.contains_key(&evt.sync_handle) {
+                    self.emit_app_event(AppEvent::PeriodicAdvertisingReport(evt.clone()));
+                }
+            }
+            LEMetaEvent::PeriodicAdvertisingSyncLost(evt) => {
+                if self.le_periodic_syncs.remove(&evt.sync_handle).is_some() {
+                    warn!("periodic sync {} lost", evt.sync_handle);
+                    self.emit_app_event(AppEvent::PeriodicSyncLost {
+                        sync_handle: evt.sync_handle,
+                    });
+                }
+            }
+            LEMetaEvent::AdvertisingSetTerminated(evt) => {
+                self.handle_advertising_set_terminated(evt)
+            }
+        }
+    }
+
+    fn handle_periodic_sync_established(&mut self, evt: &LEPeriodicAdvertisingSyncEstablishedEvt) {
+        self.le_periodic_sync_state = LEPeriodicSyncState::Idle;
+        if evt.status != ControllerErrorCode::Ok {
+            warn!("periodic sync failed: {:?}", evt.status);
+            self.emit_app_event(AppEvent::PeriodicSyncFailed { status: evt.status });
+            return;
+        }
+        let sync = PeriodicSync {
+            sid: evt.advertising_sid,
+            address_type: evt.advertiser_address_type,
+            address: evt.advertiser_address,
+            phy: evt.advertiser_phy,
+            interval: evt.periodic_advertising_interval,
+            clock_accuracy: evt.advertiser_clock_accuracy,
+        };
+        info!("periodic sync {} to {:?}", evt.sync_handle, sync.address);
+        self.le_periodic_syncs.insert(evt.sync_handle, sync);
+        self.emit_app_event(AppEvent::PeriodicSyncEstablished {
+            sync_handle: evt.sync_handle,
+            sync,
+        });
+    }
+
+    /// A connection, the duration or the event limit ended the set, it has to be enabled again
+    fn handle_advertising_set_terminated(&mut self, evt: &LEAdvertisingSetTerminatedEvt) {
+        let handle = evt.advertising_handle;
+        if handle == LE_LEGACY_ADV_SET_HANDLE {
+            self.le_advertisements_state = LEAdvertisementsState::Idle;
+        } else if let Some(set) = self
+            .le_advertising_sets
+            .iter_mut()
+            .find(|set| set.handle == handle)
+        {
+            set.state = LEAdvertisementsState::Idle;
+        }
+        let connection_handle =
+            (evt.status == ControllerErrorCode::Ok).then_some(evt.connection_handle);
+        self.emit_app_event(AppEvent::AdvertisingSetTerminated {
+            handle,
+            status: evt.status,
+            connection_handle,
+            completed_events: evt.num_completed_extended_advertising_events,
+        });
+    }
+
+    fn conn_param_accepted(&self, handle: u16, params: &ConnectionUpdateParams) -> bool {
+        params.is_valid()
+            && self
+                .conn_param_policy
+                .as_ref()
+                .is_none_or(|policy| policy(self, handle, params))
+    }
+
+    fn send_connection_update(&mut self, handle: u16, params: &ConnectionUpdateParams) {
+        let cmd = LEConnectionUpdateCmd {
+            connection_handle: handle,
+            interval_min: params.interval_min,
+            interval_max: params.interval_max,
+            max_latency: params.latency,
+            supervision_timeout: params.supervision_timeout,
+            min_ce_length: self.le_connection_params.min_ce_length,
+            max_ce_length: self.le_connection_params.max_ce_length,
+        };
+        cmd.send(self);
+    }
+
+    fn send_signaling(&mut self, handle: u16, code: u8, identifier: u8, data: Vec<u8>) {
+        let cmd = SignalingCmd {
+            code,
+            identifier,
+            data,
+        };
+        let cid = match self.connections.get(handle) {
+            Some(conn) if !conn.is_le() => L2CAP_CID_SIGNALING,
+            _ => L2CAP_CID_LE_SIGNALING,
+        };
+        if let Err(err) = self.send_acl_data(handle, cmd.to_pdu(cid)) {
+            warn!("l2cap signaling {}: {}", handle, err);
+        }
+    }
+
+    fn next_signaling_identifier(&mut self) -> u8 {
+        let identifier = self.l2cap_sig_identifier;
+        self.l2cap_sig_identifier = self.l2cap_sig_identifier.checked_add(1).unwrap_or(1);
+        identifier
+    }
+
+    fn handle_le_signaling(&mut self, handle: u16, data: &[u8]) -> Result<()> {
+        let cmd = SignalingCmd::from_u8_array(data)?;
+        match cmd.code {
+            L2CAP_SIG_CONN_PARAM_UPDATE_REQUEST => {
+                // only the central can apply the parameters
+                let role = self.connections.get(handle).map(|c| c.role);
+                if role != Some(Role::Central) {
+                    let reason = L2CAP_REJECT_NOT_UNDERSTOOD.to_le_bytes().to_vec();
+                    self.send_signaling(handle, L2CAP_SIG_COMMAND_REJECT, cmd.identifier, reason);
+                    return Ok(());
+                }
+                let params = ConnectionUpdateParams::from_u8_array(&cmd.data)?;
+                let accepted = self.conn_param_accepted(handle, &params);
+                let result = if accepted {
+                    L2CAP_CONN_PARAM_ACCEPTED
+                } else {
+                    L2CAP_CONN_PARAM_REJECTED
+                };
+                self.send_signaling(
+                    handle,
+                    L2CAP_SIG_CONN_PARAM_UPDATE_RESPONSE,
+                    cmd.identifier,
+                    result.to_le_bytes().to_vec(),
+                );
+                if accepted {
+                    self.send_connection_update(handle, &params);
+                }
+            }
+            L2CAP_SIG_CONN_PARAM_UPDATE_RESPONSE => {
+                let result = take_bytes(&cmd.data, 0, 2)?;
+                // accepted updates end with the connection update complete
+                if u16::from_le_bytes([result[0], result[1]]) != L2CAP_CONN_PARAM_ACCEPTED {
+                    self.emit_app_event(AppEvent::ConnectionUpdateFailed {
+                        handle,
+                        status: ControllerErrorCode::UnacceptableConnectionParameters,
+                    });
+                }
+            }
+            L2CAP_SIG_COMMAND_REJECT => {
+                warn!("l2cap signaling {} rejected: {:?}", handle, cmd.data);
+            }
+            _ => {
+                let reason = L2CAP_REJECT_NOT_UNDERSTOOD.to_le_bytes().to_vec();
+                self.send_signaling(handle, L2CAP_SIG_COMMAND_REJECT, cmd.identifier, reason);
+            }
+        }
+        Ok(())
+    }
+
+    /// No channels are offered on BR/EDR links, requests are refused
+    fn handle_bredr_signaling(&mut self, handle: u16, data: &[u8]) -> Result<()> {
+        // one PDU may carry several commands
+        let mut data = data;
+        while !data.is_empty() {
+            let cmd = SignalingCmd::from_u8_array(data)?;
+            data = &data[4 + cmd.data.len()..];
+            match cmd.code {
+                L2CAP_SIG_CONNECTION_REQUEST => {
+                    let request = take_bytes(&cmd.data, 0, 4)?;
+                    // destination cid, source cid, result, status
+                    let mut response = [0, 0, request[2], request[3]].to_vec();
+                    response.extend(L2CAP_CONNECTION_PSM_NOT_SUPPORTED.to_le_bytes());
+                    response.extend([0, 0]);
+                    self.send_signaling(
+                        handle,
+                        L2CAP_SIG_CONNECTION_RESPONSE,
+                        cmd.identifier,
+                        response,
+                    );
+                }
+                L2CAP_SIG_ECHO_REQUEST => {
+                    self.send_signaling(handle, L2CAP_SIG_ECHO_RESPONSE, cmd.identifier, cmd.data);
+                }
+                L2CAP_SIG_INFORMATION_REQUEST => {
+                    let info_type = take_bytes(&cmd.data, 0, 2)?;
+                    let mut response = info_type.to_vec();
+                    response.extend(L2CAP_INFORMATION_NOT_SUPPORTED.to_le_bytes());
+                    self.send_signaling(
+                        handle,
+                        L2CAP_SIG_INFORMATION_RESPONSE,
+                        cmd.identifier,
+                        response,
+                    );
+                }
+                L2CAP_SIG_COMMAND_REJECT => {
+                    warn!("l2cap signaling {} rejected: {:?}", handle, cmd.data);
+                }
+                // responses to requests never sent
+                code if code & 1 == 1 => {
+                    warn!("unexpected l2cap signaling {} code {:#04x}", handle, code);
+                }
+                _ => {
+                    let reason = L2CAP_REJECT_NOT_UNDERSTOOD.to_le_bytes().to_vec();
+                    self.send_signaling(handle, L2CAP_SIG_COMMAND_REJECT, cmd.identifier, reason);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Update the command credits and stop waiting for `opcode`.
+    /// Returns the answered command, None if no command was waiting for this answer.
+    fn cmd_answered(&mut self, num_hci_command_packets: u8, opcode: u16) -> Option<HCIWaitingCmd> {
+        self.cmd_credits = num_hci_command_packets;
+
+        // opcode 0 only carries credits
+        let answered = match self.cmd_waiting.iter().position(|c| c.opcode == opcode) {
+            Some(index) => Some(self.cmd_waiting.remove(index)),
+            None => {
+                if opcode != 0 {
+                    warn!("unexpected answer for command {:#06x}", opcode);
+                }
+                None
+            }
+        };
+
+        self.flush_cmd_queue();
+        answered
+    }
+
+    fn flush_cmd_queue(&mut self) {
+        while self.cmd_credits > 0 {
+            let cmd = match self.cmd_queue.pop_front() {
+                Some(cmd) => cmd,
+                None => break,
+            };
+            self.cmd_credits -= 1;
+            self.cmd_waiting.push(HCIWaitingCmd {
+                opcode: cmd.opcode,
+                param: cmd.param.clone(),
+                deadline: self.now_ms + HCI_CMD_TIMEOUT_MS,
+            });
+            if let Some(send) = &self.send_packet {
+                send(self, HCIPacket::Command, cmd.opcode, cmd.param);
+            }
+        }
+    }
+
+    pub fn send_cmd_no_param(&mut self, ogf: u8, ocf: u16) {
+        info!("send cmd {} {}", ogf, ocf);
+        self.cmd_queue.push_back(HCIPendingCmd {
+            opcode: into_opcode(ogf, ocf),
+            param: None,
+        });
+        self.flush_cmd_queue();
+    }
+    pub fn send_cmd_with_param(&mut self, ogf: u8, ocf: u16, param: Vec<u8>) {
+        info!("send cmd {} {} {:?}", ogf, ocf, param);
+        self.cmd_queue.push_back(HCIPendingCmd {
+            opcode: into_opcode(ogf, ocf),
+            param: Some(param),
+        });
+        self.flush_cmd_queue();
+    }
+
+    /// Send an L2CAP PDU, fragmented to the controller buffer size of the link.
+    /// Fails until the controller reported a buffer size
+    pub fn send_acl_data(&mut self, handle: u16, pdu: Vec<u8>) -> Result<()> {
+        let le = self
+            .connections
+            .get(handle)
+            .map(|c| c.is_le())
+            .ok_or(Error::UnknownConnectionHandle(handle))?;
+        let (max_len, first) = if !le {
+            (self.acl_packet_length, PacketBoundary::FirstFlushable)
+        } else if self.le_acl_packets_total == 0 {
+            (self.acl_packet_length, PacketBoundary::FirstNonFlushable)
+        } else {
+            (self.le_acl_packet_length, PacketBoundary::FirstNonFlushable)
+        };
+        if max_len == 0 {
+            return Err(Error::BufferSizeUnknown);
+        }
+
+        for (header, payload) in fragment(handle, &pdu, max_len as usize, first) {
+            self.acl_queue.push_back((header, payload, le));
+        }
+        self.flush_acl_queue();
+        Ok(())
+    }
+
+    fn acl_packets_free(&mut self, le: bool) -> &mut u16 {
+        if le && self.le_acl_packets_total != 0 {
+            &mut self.le_acl_packets_free
+        } else {
+            &mut self.acl_packets_free
+        }
+    }
+
+    fn flush_acl_queue(&mut self) {
+        // keep the order per buffer pool, a full pool must not block the other one
+        let mut i = 0;
+        while i < self.acl_queue.len() {
+            let le = self.acl_queue[i].2;
+            if *self.acl_packets_free(le) == 0 {
+                i += 1;
+                continue;
+            }
+            let (header, payload, le) = self.acl_queue.remove(i).unwrap();
+            *self.acl_packets_free(le) -= 1;
+            if let Some(conn) = self.connections.get_mut(header.handle) {
+                conn.acl_in_flight += 1;
+            }
+
+            let mut data = header.data_total_length.to_le_bytes().to_vec();
+            data.extend(payload);
+            if let Some(send) = &self.send_packet {
+                send(self, HCIPacket::ACL, header.handle_with_flags(), Some(data));
+            }
+        }
+    }
+
+    fn handle_number_of_completed_packets(&mut self, evt: &NumberOfCompletedPacketsEvt) {
+        for &(handle, num) in evt.completed_packets.iter() {
+            let conn = match self.connections.get_mut(handle) {
+                Some(conn) => conn,
+                None => continue,
+            };
+            let num = num.min(conn.acl_in_flight);
+            conn.acl_in_flight -= num;
+
+            let le = conn.is_le();
+            *self.acl_packets_free(le) += num;
+        }
+        self.flush_acl_queue();
+    }
+}
+
+// gap
+
+// le
+
+pub fn gap_advertisements_set_params(
+    hci: &mut HCI,
+    adv_int_min: u16,
+    adv_int_max: u16,
+    adv_type: AdvertisingType,
+    peer_addr_type: LEAddressType,
+    peer_addr: BDAddr,
+    channel_map: u8,
+    filter_policy: AdvertisingFilterPolicy,
+) {
+    hci.le_advertisements_interval_min = adv_int_min;
+    hci.le_advertisements_interval_max = adv_int_max;
+    hci.le_advertisements_type = adv_type;
+    hci.le_advertisements_peer_address_type = peer_addr_type;
+    hci.le_advertisements_peer_address = peer_addr;
+    hci.le_advertisements_channel_map = channel_map;
+    hci.le_advertisements_filter_policy = filter_policy;
+    hci.le_advertisements_todo |= LEAdvertisementsTodo::SetParams;
+    hci.run();
+}
+
+/// At most `LE_ADV_DATA_MAX_LEN` bytes of AD structures
+pub fn gap_advertisements_set_data(hci: &mut HCI, data: &[u8]) -> Result<()> {
+    if data.len() > LE_ADV_DATA_MAX_LEN {
+        return Err(Error::InvalidParameter);
+    }
+    hci.le_advertisements_data = data.to_vec();
+    hci.le_advertisements_todo |= LEAdvertisementsTodo::SetAdvData;
+    hci.run();
+    Ok(())
+}
+
+/// At most `LE_ADV_DATA_MAX_LEN` bytes of AD structures, sent to active scanners
+pub fn gap_scan_response_set_data(hci: &mut HCI, data: &[u8]) -> Result<()> {
+    if data.len() > LE_ADV_DATA_MAX_LEN {
+        return Err(Error::InvalidParameter);
+    }
+    hci.le_scan_response_data = data.to_vec();
+    hci.le_advertisements_todo |= LEAdvertisementsTodo::SetScanData;
+    hci.run();
+    Ok(())
+}
+
+pub fn gap_advertisements_enable(hci: &mut HCI, enable: bool) {
+    if enable {
+        hci.le_advertisements_state
+            .insert(LEAdvertisementsState::Enabled);
+    } else {
+        hci.le_advertisements_state
+            .remove(LEAdvertisementsState::Enabled);
+    }
+    hci.run();
+}
+
+/// Add an extended advertising set, returns its handle.
+/// Sets are only sent to controllers with extended advertising,
+/// the `gap_advertisements_*` config is the fallback for the others
+pub fn gap_advertising_set_create(hci: &mut HCI, params: AdvertisingSetParams) -> Result<u8> {
+    if !params.is_valid() {
+        return Err(Error::InvalidParameter);
+    }
+    // the legacy config takes one of the controller sets
+    let sets = hci.le_advertising_sets.len() + 1;
+    if hci.le_num_advertising_sets != 0 && sets >= hci.le_num_advertising_sets as usize {
+        return Err(Error::InvalidParameter);
+    }
+    let handle = (1..=LE_ADV_SET_HANDLE_MAX)
+        .find(|handle| hci.get_advertising_set(*handle).is_none())
+        .ok_or(Error::InvalidParameter)?;
+    hci.le_advertising_sets
+        .push(AdvertisingSet::new(handle, params));
+    hci.run();
+    Ok(handle)
+}
+
+fn advertising_set_mut(hci: &mut HCI, handle: u8) -> Result<&mut AdvertisingSet> {
+    hci.le_advertising_sets
+        .iter_mut()
+        .find(|set| set.handle == handle)
+        .filter(|set| !set.todo.contains(LEAdvertisementsTodo::RemoveSet))
+        .ok_or(Error::InvalidParameter)
+}
+
+/// Longest data the set and the controller accept
+fn advertising_set_max_data_len(hci: &HCI, params: &AdvertisingSetParams) -> usize {
+    match hci.le_max_advertising_data_length {
+        0 => params.max_data_len(),
+        max => params.max_data_len().min(max as usize),
+    }
+}
+
+/// The data already set has to fit the new parameters
+pub fn gap_advertising_set_params(
+    hci: &mut HCI,
+    handle: u8,
+    params: AdvertisingSetParams,
+) -> Result<()> {
+    let max_len = advertising_set_max_data_len(hci, &params);
+    let set = advertising_set_mut(hci, handle)?;
+    if !params.is_valid()
+        || set.data.len() > max_len
+        || set.scan_response_data.len() > max_len
+        || (set.periodic_params.is_some() && !params.supports_periodic())
+    {
+        return Err(Error::InvalidParameter);
+    }
+    set.params = params;
+    set.todo |= LEAdvertisementsTodo::SetParams;
+    hci.run();
+    Ok(())
+}
+
+/// AD structures, fragmented over several commands when longer than `LE_EXT_ADV_DATA_FRAGMENT_LEN`
+pub fn gap_advertising_set_data(hci: &mut HCI, handle: u8, data: &[u8]) -> Result<()> {
+    let set = advertising_set_mut(hci, handle)?;
+    let params = set.params.clone();
+    if data.len() > advertising_set_max_data_len(hci, &params) {
+        return Err(Error::InvalidParameter);
+    }
+    let set = advertising_set_mut(hci, handle)?;
+    set.data = data.to_vec();
+    set.todo |= LEAdvertisementsTodo::SetAdvData;
+    hci.run();
+    Ok(())
+}
+
+/// Only sent once the set is scannable
+pub fn gap_advertising_set_scan_response_data(
+    hci: &mut HCI,
+    handle: u8,
+    data: &[u8],
+) -> Result<()> {
+    let set = advertising_set_mut(hci, handle)?;
+    let params = set.params.clone();
+    if data.len() > advertising_set_max_data_len(hci, &params) {
+        return Err(Error::InvalidParameter);
+    }
+    let set = advertising_set_mut(hci, handle)?;
+    set.scan_response_data = data.to_vec();
+    set.todo |= LEAdvertisementsTodo::SetScanData;
+    hci.run();
+    Ok(())
+}
+
+/// Used when the own address type of the set is random
+pub fn gap_advertising_set_random_address(
+    hci: &mut HCI,
+    handle: u8,
+    random_address: BDAddr,
+) -> Result<()> {
+    let set = advertising_set_mut(hci, handle)?;
+    set.random_address = Some(random_address);
+    set.todo |= LEAdvertisementsTodo::SetAddress;
+    hci.run();
+    Ok(())
+}
+
+/// `duration` unit: 10ms, `max_events` limits the advertising events, zero for no limit.
+/// The end of either is reported as `AppEvent::AdvertisingSetTerminated`
+pub fn gap_advertising_set_enable(
+    hci: &mut HCI,
+    handle: u8,
+    enable: bool,
+    duration: u16,
+    max_events: u8,
+) -> Result<()> {
+    let set = advertising_set_mut(hci, handle)?;
+    if enable {
+        // new limits only apply on the next enable
+        if set.state.contains(LEAdvertisementsState::Active)
+            && (set.duration != duration || set.max_events != max_events)
+        {
+            set.state.remove(LEAdvertisementsState::Enabled);
+            hci.run();
+        }
+        let set = advertising_set_mut(hci, handle)?;
+        set.duration = duration;
+        set.max_events = max_events;
+        set.state.insert(LEAdvertisementsState::Enabled);
+    } else {
+        set.state.remove(LEAdvertisementsState::Enabled);
+    }
+    hci.run();
+    Ok(())
+}
+
+pub fn gap_advertising_set_remove(hci: &mut HCI, handle: u8) -> Result<()> {
+    let extended = hci.is_extended_advertising_supported();
+    let set = advertising_set_mut(hci, handle)?;
+    if extended {
+        set.state.remove(LEAdvertisementsState::Enabled);
+        set.todo |= LEAdvertisementsTodo::RemoveSet;
+    } else {
+        // never sent to the controller
+        hci.le_advertising_sets.retain(|set| set.handle != handle);
+    }
+    hci.run();
+    Ok(())
+}
+
+/// The set has to use non-connectable, non-scannable extended advertising
+pub fn gap_periodic_advertising_set_params(
+    hci: &mut HCI,
+    handle: u8,
+    params: PeriodicAdvertisingParams,
+) -> Result<()> {
+    let set = advertising_set_mut(hci, handle)?;
+    if !params.is_valid() || !set.params.supports_periodic() {
+        return Err(Error::InvalidParameter);
+    }
+    set.periodic_params = Some(params);
+    set.todo |= LEAdvertisementsTodo::SetPeriodicParams;
+    hci.run();
+    Ok(())
+}
+
+/// AD structures, fragmented over several commands when longer than `LE_PERIODIC_ADV_DATA_FRAGMENT_LEN`
+pub fn gap_periodic_advertising_set_data(hci: &mut HCI, handle: u8, data: &[u8]) -> Result<()> {
+    let set = advertising_set_mut(hci, handle)?;
+    let params = set.params.clone();
+    if data.len() > advertising_set_max_data_len(hci, &params) {
+        return Err(Error::InvalidParameter);
+    }
+    let set = advertising_set_mut(hci, handle)?;
+    set.periodic_data = data.to_vec();
+    set.todo |= LEAdvertisementsTodo::SetPeriodicData;
+    hci.run();
+    Ok(())
+}
+
+/// Scanners only find the train while the set itself is advertising too
+pub fn gap_periodic_advertising_enable(hci: &mut HCI, handle: u8, enable: bool) -> Result<()> {
+    let set = advertising_set_mut(hci, handle)?;
+    if enable {
+        if set.periodic_params.is_none() {
+            return Err(Error::InvalidParameter);
+        }
+        set.periodic_state.insert(LEAdvertisementsState::Enabled);
+    } else {
+        set.periodic_state.remove(LEAdvertisementsState::Enabled);
+    }
+    hci.run();
+    Ok(())
+}
+
+/// One sync is created at a time, the outcome is reported as `AppEvent::PeriodicSyncEstablished`
+/// or `AppEvent::PeriodicSyncFailed`. The train is only found by the extended scanner, it runs
+/// until the sync is established or failed even with scanning disabled
+pub fn gap_periodic_sync_create(hci: &mut HCI, target: PeriodicSyncTarget) -> Result<()> {
+    if !target.is_valid() {
+        return Err(Error::InvalidParameter);
+    }
+    if hci.le_periodic_sync_state != LEPeriodicSyncState::Idle {
+        return Err(Error::Busy);
+    }
+    hci.le_periodic_sync_state = LEPeriodicSyncState::Todo(target);
+    hci.run();
+    Ok(())
+}
+
+pub fn gap_periodic_sync_cancel(hci: &mut HCI) {
+    match hci.le_periodic_sync_state {
+        LEPeriodicSyncState::Todo(..) => hci.le_periodic_sync_state = LEPeriodicSyncState::Idle,
+        LEPeriodicSyncState::W4Established => {
+            hci.le_periodic_sync_state = LEPeriodicSyncState::Cancel
+        }
+        _ => {}
+    }
+    hci.run();
+}
+
+/// Stop receiving the train, no event follows
+pub fn gap_periodic_sync_terminate(hci: &mut HCI, sync_handle: u16) -> Result<()> {
+    if hci.le_periodic_syncs.remove(&sync_handle).is_none() {
+        return Err(Error::InvalidParameter);
+    }
+    let cmd = LEPeriodicAdvertisingTerminateSyncCmd { sync_handle };
+    cmd.send(hci);
+    Ok(())
+}
+
+/// `interval` and `window` range: 0x0004 to 0x4000, unit: 0.625ms, `window` not larger than `interval`
+pub fn gap_scan_set_params(
+    hci: &mut HCI,
+    scan_type: LEScanType,
+    interval: u16,
+    window: u16,
+    filter_policy: ScanningFilterPolicy,
+    filter_duplicates: bool,
+) {
+    hci.le_scan_type = scan_type;
+    hci.le_scan_interval = interval;
+    hci.le_scan_window = window;
+    hci.le_scan_filter_policy = filter_policy;
+    hci.le_scan_filter_duplicates = filter_duplicates;
+    hci.le_scanning_param_update = true;
+    hci.run();
+}
+
+/// Classic discovery with the general inquiry access code. `duration` range: 0x01 to 0x30,
+/// unit: 1.28s, `max_responses` zero for unlimited. Devices arrive as `AppEvent::DeviceDiscovered`
+/// until `AppEvent::InquiryComplete`
+pub fn gap_inquiry_start(hci: &mut HCI, duration: u8, max_responses: u8) -> Result<()> {
+    if !(1..=INQUIRY_LENGTH_MAX).contains(&duration) {
+        return Err(Error::InvalidParameter);
+    }
+    if hci.inquiry_active {
+        return Err(Error::Busy);
+    }
+    hci.inquiry_active = true;
+    let cmd = InquiryCmd {
+        lap: GIAC,
+        inquiry_length: duration,
+        num_responses: max_responses,
+    };
+    cmd.send(hci);
+    Ok(())
+}
+
+/// `AppEvent::InquiryComplete` follows once the controller stopped
+pub fn gap_inquiry_cancel(hci: &mut HCI) -> Result<()> {
+    if !hci.inquiry_active {
+        return Err(Error::InvalidParameter);
+    }
+    InquiryCancelCmd {}.send(hci);
+    Ok(())
+}
+
+/// Reports arrive as `AppEvent::AdvertisingReport`, extended PDUs as
+/// `AppEvent::ExtendedAdvertisingReport`
+pub fn gap_scan_enable(hci: &mut HCI, enable: bool) {
+    if enable {
+        hci.le_scanning_state.insert(LEScanningState::Enabled);
+    } else {
+        hci.le_scanning_state.remove(LEScanningState::Enabled);
+    }
+    hci.run();
+}
+
+/// Used by the next `gap_connect`
+pub fn gap_set_connection_params(hci: &mut HCI, params: LEConnectionParams) {
+    hci.le_connection_params = params;
+}
+
+/// Cancel a connect that is not established after `timeout_ms`
+pub fn gap_set_connect_timeout(hci: &mut HCI, timeout_ms: u64) {
+    hci.le_connect_timeout_ms = timeout_ms;
+}
+
+/// Connect as LE central, the result arrives as `AppEvent::Connected` or
+/// `AppEvent::ConnectionFailed`. One connect runs at a time.
+pub fn gap_connect(hci: &mut HCI, addr: BDAddr, addr_type: LEAddressType) -> Result<()> {
+    if hci.le_connect_state != LEConnectState::Idle || hci.connections.contains_remote(&addr) {
+        return Err(Error::Busy);
+    }
+    hci.le_connect_state = LEConnectState::Todo(addr, addr_type);
+    hci.run();
+    Ok(())
+}
+
+pub fn gap_connect_cancel(hci: &mut HCI) {
+    match hci.le_connect_state {
+        LEConnectState::Todo(..) => hci.le_connect_state = LEConnectState::Idle,
+        LEConnectState::W4Complete { .. } => {
+            hci.le_connect_state = LEConnectState::Cancel { timeout: false }
+        }
+        _ => {}
+    }
+    hci.run();
+}
+
+/// The link is gone once `AppEvent::Disconnected` reports it
+pub fn gap_disconnect(hci: &mut HCI, handle: u16, reason: ControllerErrorCode) -> Result<()> {
+    if !reason.is_disconnect_reason() {
+        return Err(Error::InvalidParameter);
+    }
+    let conn = hci
+        .connections
+        .get(handle)
+        .ok_or(Error::UnknownConnectionHandle(handle))?;
+    if conn.state == ConnectionState::Disconnecting {
+        return Err(Error::Busy);
     }
-    fn recv_event_data(&mut self, data: Vec<u8>) {
-        info!("EV {:?}", data);
-        let event = num::FromPrimitive::from_u8(data[0]);
-        match event {
-            Some(HCIEvent::CommandComplete) => {
-                let opcode = u16::from_le_bytes(data[3..5].try_into().unwrap());
+    hci.send_disconnect(handle, reason);
+    Ok(())
+}
 
-                if self.state < HCIState::Working {
-                    self.init_process_event(opcode);
-                }
-            }
-            _ => {}
-        }
+/// As central the controller applies `params`, as peripheral the central is asked for
+/// them, over L2CAP if the controller lacks the LL procedure
+pub fn gap_update_connection_params(
+    hci: &mut HCI,
+    handle: u16,
+    params: ConnectionUpdateParams,
+) -> Result<()> {
+    if !params.is_valid() {
+        return Err(Error::InvalidParameter);
     }
+    let conn = le_connection(hci, handle)?;
+    if conn.role == Role::Central
+        || hci.is_le_feature_supported(LEFeatures::ConnectionParametersRequestProcedure)
+    {
+        hci.send_connection_update(handle, &params);
+    } else {
+        let identifier = hci.next_signaling_identifier();
+        hci.send_signaling(
+            handle,
+            L2CAP_SIG_CONN_PARAM_UPDATE_REQUEST,
+            identifier,
+            params.to_u8_array(),
+        );
+    }
+    Ok(())
+}
 
-    pub fn send_cmd_no_param(&mut self, ogf: u8, ocf: u16) {
-        info!("send cmd {} {}", ogf, ocf);
-        if let Some(send) = self.send_packet {
-            send(&self, HCIPacket::Command, into_opcode(ogf, ocf), None);
-        }
+/// The `all_phys` bits of the PHY commands, an empty set has no preference
+fn all_phys(tx_phys: LEPhys, rx_phys: LEPhys) -> u8 {
+    (tx_phys.is_empty() as u8) | (rx_phys.is_empty() as u8) << 1
+}
+
+fn le_connection(hci: &HCI, handle: u16) -> Result<&HCIConnection> {
+    hci.connections
+        .get(handle)
+        .filter(|c| c.is_le())
+        .ok_or(Error::UnknownConnectionHandle(handle))
+}
+
+/// Connect over BR/EDR, paging with what the last inquiries learned about `addr`.
+/// The result arrives as `AppEvent::Connected` or `AppEvent::ConnectionFailed`
+pub fn gap_classic_connect(hci: &mut HCI, addr: BDAddr) -> Result<()> {
+    if hci.connections.contains_remote(&addr) {
+        return Err(Error::Busy);
     }
-    pub fn send_cmd_with_param(&mut self, ogf: u8, ocf: u16, param: Vec<u8>) {
-        info!("send cmd {} {} {:?}", ogf, ocf, param);
-        if let Some(send) = self.send_packet {
-            send(
-                &self,
-                HCIPacket::Command,
-                into_opcode(ogf, ocf),
-                Some(param),
-            );
+    let (page_scan_repetition_mode, clock_offset) = hci.inquiry_cache.paging_params(&addr);
+    let cmd = CreateConnectionCmd {
+        bd_addr: addr,
+        packet_type: hci.acl_packet_types(),
+        page_scan_repetition_mode,
+        reserved: 0,
+        clock_offset,
+        allow_role_switch: hci.role_switch_preference.allow_role_switch(),
+    };
+    cmd.send(hci);
+    Ok(())
+}
+
+/// Used by the BR/EDR links created or accepted from now on
+pub fn gap_set_role_switch_preference(hci: &mut HCI, preference: RoleSwitchPreference) {
+    hci.role_switch_preference = preference;
+}
+
+/// PHYs preferred on the LE links created from now on, an empty set leaves the
+/// choice to the controller. PHYs the controller lacks are ignored
+pub fn gap_set_default_phy(hci: &mut HCI, tx_phys: LEPhys, rx_phys: LEPhys) {
+    hci.le_default_phy = Some((tx_phys, rx_phys));
+    hci.le_connection_defaults_update = true;
+    hci.run();
+}
+
+/// The controller may pick other PHYs, the result arrives as `AppEvent::PhyUpdated`
+/// or `AppEvent::PhyUpdateFailed`. `coded_options` is only used on the LE Coded PHY
+pub fn gap_set_phy(
+    hci: &mut HCI,
+    handle: u16,
+    tx_phys: LEPhys,
+    rx_phys: LEPhys,
+    coded_options: LECodedPHYOptions,
+) -> Result<()> {
+    le_connection(hci, handle)?;
+    if !hci.get_supported_phys().contains(tx_phys | rx_phys) {
+        return Err(Error::InvalidParameter);
+    }
+    let cmd = LESetPHYCmd {
+        connection_handle: handle,
+        all_phys: all_phys(tx_phys, rx_phys),
+        tx_phys: tx_phys.bits(),
+        rx_phys: rx_phys.bits(),
+        phy_options: coded_options as u16,
+    };
+    cmd.send(hci);
+    Ok(())
+}
+
+/// The PHYs arrive as `AppEvent::PhyUpdated`
+pub fn gap_read_phy(hci: &mut HCI, handle: u16) -> Result<()> {
+    le_connection(hci, handle)?;
+    let cmd = LEReadPHYCmd {
+        connection_handle: handle,
+    };
+    cmd.send(hci);
+    Ok(())
+}
+
+/// `tx_octets` range: 0x001B to 0x00FB, `tx_time` range: 0x0148 to 0x4290, unit: us.
+/// A change negotiated with the peer arrives as `AppEvent::DataLengthChanged`
+pub fn gap_set_data_length(hci: &mut HCI, handle: u16, tx_octets: u16, tx_time: u16) -> Result<()> {
+    le_connection(hci, handle)?;
+    if !is_valid_data_length(tx_octets, tx_time) {
+        return Err(Error::InvalidParameter);
+    }
+    let cmd = LESetDataLengthCmd {
+        connection_handle: handle,
+        tx_octets,
+        tx_time,
+    };
+    cmd.send(hci);
+    Ok(())
+}
+
+/// Data length the LE links created from now on ask for, ranges as `gap_set_data_length`
+pub fn gap_set_default_data_length(hci: &mut HCI, tx_octets: u16, tx_time: u16) -> Result<()> {
+    if !is_valid_data_length(tx_octets, tx_time) {
+        return Err(Error::InvalidParameter);
+    }
+    hci.le_default_data_length = Some((tx_octets, tx_time));
+    hci.le_connection_defaults_update = true;
+    hci.run();
+    Ok(())
+}
+
+/// The address advertising, scanning and connecting use. Private addresses are
+/// generated once the host is working and rotated every private address timeout,
+/// each new address is reported as `AppEvent::RandomAddressChanged`
+pub fn gap_random_address_set_mode(hci: &mut HCI, mode: LEOwnAddressMode) -> Result<()> {
+    match mode {
+        LEOwnAddressMode::StaticRandom(address) if !is_static_random_address(&address) => {
+            return Err(Error::InvalidParameter);
+        }
+        LEOwnAddressMode::Resolvable if hci.le_local_irk.is_none() => {
+            return Err(Error::InvalidParameter);
         }
+        _ => {}
+    }
+    hci.le_own_address_mode = mode;
+    hci.le_own_address_type = match mode {
+        LEOwnAddressMode::Public => LEAddressType::PublicDevice,
+        _ => LEAddressType::RandomDevice,
+    };
+    hci.le_random_address = match mode {
+        LEOwnAddressMode::StaticRandom(address) => Some(address),
+        _ => None,
+    };
+    hci.le_private_address_deadline = None;
+    if hci.le_random_address.is_some() {
+        hci.le_advertisements_todo |=
+            LEAdvertisementsTodo::SetAddress | LEAdvertisementsTodo::SetAddressSet0;
+    }
+    // the own address type is part of the advertising and scan parameters
+    hci.le_advertisements_todo |= LEAdvertisementsTodo::SetParams;
+    hci.le_scanning_param_update = true;
+    hci.run();
+    Ok(())
+}
+
+/// The local IRK, little endian. A resolvable private address in use is replaced
+pub fn gap_privacy_set_irk(hci: &mut HCI, irk: [u8; 16]) {
+    hci.le_local_irk = Some(irk);
+    if hci.le_own_address_mode == LEOwnAddressMode::Resolvable {
+        hci.le_private_address_deadline = None;
+    }
+    for i in 0..hci.le_bonds.len() {
+        let entry = hci.le_bonds[i].resolving_list_entry(irk);
+        hci.resolving_list_add_bond(entry);
     }
+    hci.run();
+}
 
-    // fn send_acl_data(&mut self, data: Vec<u8>) {
-    //     if let Some(send) = self.send_packet {
-    //         send(&self, 2, data);
-    //     }
-    // }
+/// Used from the next rotation of the private address, `timeout_ms` ranges from
+/// `LE_PRIVATE_ADDRESS_TIMEOUT_MIN_MS` to `LE_PRIVATE_ADDRESS_TIMEOUT_MAX_MS`
+pub fn gap_set_private_address_timeout(hci: &mut HCI, timeout_ms: u64) -> Result<()> {
+    if !(LE_PRIVATE_ADDRESS_TIMEOUT_MIN_MS..=LE_PRIVATE_ADDRESS_TIMEOUT_MAX_MS)
+        .contains(&timeout_ms)
+    {
+        return Err(Error::InvalidParameter);
+    }
+    hci.le_private_address_timeout_ms = timeout_ms;
+    Ok(())
 }
 
-// gap
+/// Resolvable private addresses of the device are resolved to its identity from now on,
+/// by the controller if it has room on its resolving list.
+/// A bond with the same identity address is replaced
+pub fn gap_bond_add(hci: &mut HCI, bond: BondedDevice) -> Result<()> {
+    if !matches!(
+        bond.identity_address_type,
+        LEAddressType::PublicDevice | LEAddressType::RandomDevice
+    ) {
+        return Err(Error::InvalidParameter);
+    }
+    hci.le_bonds
+        .retain(|b| b.identity_address != bond.identity_address);
+    hci.le_bonds.push(bond);
+    let entry = bond.resolving_list_entry(hci.le_local_irk.unwrap_or_default());
+    hci.resolving_list_add_bond(entry);
+    hci.run();
+    Ok(())
+}
 
-// le
+pub fn gap_bond_remove(hci: &mut HCI, identity_address: BDAddr) -> Result<()> {
+    let index = hci
+        .le_bonds
+        .iter()
+        .position(|b| b.identity_address == identity_address)
+        .ok_or(Error::InvalidParameter)?;
+    let bond = hci.le_bonds.remove(index);
+    // the application may have taken it off the resolving list already
+    let entry = bond.resolving_list_entry([0; 16]);
+    let _ = hci.le_resolving_list.remove(entry.identity());
+    hci.run();
+    Ok(())
+}
 
-pub fn gap_advertisements_set_params(
+/// Changes are sent once nothing uses the list, fails once the controller list is full
+pub fn gap_filter_accept_list_add(
     hci: &mut HCI,
-    adv_int_min: u16,
-    adv_int_max: u16,
-    adv_type: AdvertisingType,
-    peer_addr_type: LEAddressType,
-    peer_addr: BDAddr,
-    channel_map: u8,
-    filter_policy: AdvertisingFilterPolicy,
-) {
-    hci.le_advertisements_interval_min = adv_int_min;
-    hci.le_advertisements_interval_max = adv_int_max;
-    hci.le_advertisements_type = adv_type;
-    hci.le_advertisements_peer_address_type = peer_addr_type;
-    hci.le_advertisements_peer_address = peer_addr;
-    hci.le_advertisements_channel_map = channel_map;
-    hci.le_advertisements_filter_policy = filter_policy;
+    address_type: LEAddressType2,
+    address: BDAddr,
+) -> Result<()> {
+    let entry = FilterAcceptListEntry {
+        address_type,
+        address,
+    };
+    hci.le_filter_accept_list.add(entry)?;
     hci.run();
+    Ok(())
 }
 
-pub fn gap_advertisements_enable(hci: &mut HCI, enable: bool) {
-    if enable {
-        hci.le_advertisements_state
-            .insert(LEAdvertisementsState::Enabled);
-    } else {
-        hci.le_advertisements_state
-            .remove(LEAdvertisementsState::Enabled);
-    }
+pub fn gap_filter_accept_list_remove(
+    hci: &mut HCI,
+    address_type: LEAddressType2,
+    address: BDAddr,
+) -> Result<()> {
+    hci.le_filter_accept_list.remove((address_type, address))?;
+    hci.run();
+    Ok(())
+}
+
+pub fn gap_filter_accept_list_clear(hci: &mut HCI) {
+    hci.le_filter_accept_list.clear();
+    hci.run();
+}
+
+/// Only used by controllers with LL privacy, `gap_bond_add` adds the bonded devices itself.
+/// An entry with the same identity is replaced
+pub fn gap_resolving_list_add(hci: &mut HCI, entry: ResolvingListEntry) -> Result<()> {
+    hci.le_resolving_list.add(entry)?;
+    hci.run();
+    Ok(())
+}
+
+pub fn gap_resolving_list_remove(
+    hci: &mut HCI,
+    identity_address_type: LEAddressType2,
+    identity_address: BDAddr,
+) -> Result<()> {
+    hci.le_resolving_list
+        .remove((identity_address_type, identity_address))?;
+    hci.run();
+    Ok(())
+}
+
+pub fn gap_resolving_list_clear(hci: &mut HCI) {
+    hci.le_resolving_list.clear();
     hci.run();
 }
 
 // api
 
+/// Reported to the handlers added with `HCI::add_event_handler`
+#[derive(Debug)]
+pub enum AppEvent {
+    /// Result of the init sequence after powering on
+    InitDone(Result<()>),
+    /// Powered off, fallen asleep or woken up, powering on reports `InitDone`
+    PowerModeChanged(HCIPowerMode),
+    /// The controller rejected a Write_* command of the config, the setting stays marked
+    /// in `HCIConfigParam::is_failed` until it is changed again
+    ConfigWriteFailed {
+        write: HCIConfigWrite,
+        status: ControllerErrorCode,
+    },
+    AdvertisingStarted,
+    AdvertisingStopped,
+    /// The controller uses a new random address for advertising, scanning and connecting
+    RandomAddressChanged(BDAddr),
+    /// An extended advertising set stopped on its own, handle 0 is the legacy config
+    AdvertisingSetTerminated {
+        handle: u8,
+        /// `AdvertisingTimeout` or `LimitReached` when no connection was created
+        status: ControllerErrorCode,
+        connection_handle: Option<u16>,
+        completed_events: u8,
+    },
+    ScanningStarted,
+    ScanningStopped,
+    AdvertisingReport(LEAdvertisingReport),
+    /// Only with extended advertising, long data arrives over several reports
+    ExtendedAdvertisingReport(LEExtendedAdvertisingReport),
+    InquiryStarted,
+    /// Reported as often as the controller finds the device during one inquiry
+    DeviceDiscovered(DiscoveredDevice),
+    /// The inquiry ended, `status` is Ok after it ran its time, found enough devices or was cancelled
+    InquiryComplete {
+        status: ControllerErrorCode,
+    },
+    PeriodicSyncEstablished {
+        sync_handle: u16,
+        sync: PeriodicSync,
+    },
+    /// Refused by the controller, cancelled, or the train was not found
+    PeriodicSyncFailed {
+        status: ControllerErrorCode,
+    },
+    PeriodicSyncLost {
+        sync_handle: u16,
+    },
+    /// Only for established syncs
+    PeriodicAdvertisingReport(LEPeriodicAdvertisingReportEvt),
+    Connected {
+        handle: u16,
+        remote: BDAddr,
+        role: Role,
+        /// None on BR/EDR links
+        params: Option<LinkParams>,
+    },
+    ConnectionUpdated {
+        handle: u16,
+        params: LinkParams,
+    },
+    /// Refused by the controller or by the peer
+    ConnectionUpdateFailed {
+        handle: u16,
+        status: ControllerErrorCode,
+    },
+    /// Also the answer to `gap_read_phy`
+    PhyUpdated {
+        handle: u16,
+        tx_phy: LEPhy,
+        rx_phy: LEPhy,
+    },
+    PhyUpdateFailed {
+        handle: u16,
+        status: ControllerErrorCode,
+    },
+    DataLengthChanged {
+        handle: u16,
+        data_length: DataLength,
+    },
+    /// A connect that timed out reports `ConnectionAcceptTimeoutExceeded`
+    ConnectionFailed {
+        remote: BDAddr,
+        status: ControllerErrorCode,
+    },
+    Disconnected {
+        handle: u16,
+        reason: ControllerErrorCode,
+    },
+    /// A BR/EDR link switched roles after it was connected
+    RoleChanged {
+        handle: u16,
+        role: Role,
+    },
+    EncryptionChanged {
+        handle: u16,
+        enabled: bool,
+    },
+    /// The remote started LE pairing
+    PairingRequest {
+        handle: u16,
+    },
+    /// An L2CAP PDU on a channel the host does not handle itself
+    ACLData {
+        handle: u16,
+        cid: u16,
+        data: Vec<u8>,
+    },
+    /// An ATT PDU
+    GATTData {
+        handle: u16,
+        data: Vec<u8>,
+    },
+}
+
 #[derive(Debug)]
 pub enum BTCmd {
     On,
     Off,
+    Sleep,
     Connect(BDAddr),
+    Disconnect(u16),
 
     LEAdvtise(bool),
-    LEConnect(BDAddr),
+    LEScan(bool),
+    LEConnect(BDAddr, LEAddressType),
 }
 
 impl BTCmd {
     pub fn exec(&self, hci: &mut HCI) {
         info!("exec {:?}", self);
         match self {
+            BTCmd::On => hci.power_control(HCIPowerMode::On),
+            BTCmd::Off => hci.power_control(HCIPowerMode::Off),
+            BTCmd::Sleep => hci.power_control(HCIPowerMode::Sleep),
             BTCmd::Connect(addr) => {
-                for conn in hci.connections.iter() {
-                    // already connected
-                    if conn.remote == *addr {
-                        return;
-                    }
+                if let Err(err) = gap_classic_connect(hci, *addr) {
+                    warn!("connect {:?}: {}", addr, err);
+                }
+            }
+            BTCmd::Disconnect(handle) => {
+                let reason = ControllerErrorCode::RemoteUserTerminatedConnection;
+                if let Err(err) = gap_disconnect(hci, *handle, reason) {
+                    warn!("disconnect {}: {}", handle, err);
                 }
-                let conn = HCIConnection {
-                    remote: *addr,
-                    addr_type: BDAddrType::Classic,
-                };
-                hci.connections.push_back(conn);
-
-                // create connection
-                let arg = CreateConnectionCmd {
-                    bd_addr: *addr,
-                    packet_type: PacketType::MayUseDH1,
-                    page_scan_repetition_mode: PageScanRepetitionMode::R0,
-                    reserved: 0,
-                    clock_offset: 0,
-                    allow_role_switch: 1,
-                };
-                arg.send(hci);
             }
             BTCmd::LEAdvtise(enable) => {
                 if *enable {
@@ -560,30 +4394,150 @@ impl BTCmd {
                 }
                 hci.run();
             }
-            BTCmd::LEConnect(addr) => {
-                for conn in hci.connections.iter() {
-                    // already connected
-                    if conn.remote == *addr {
-                        return;
-                    }
+            BTCmd::LEScan(enable) => gap_scan_enable(hci, *enable),
+            BTCmd::LEConnect(addr, addr_type) => {
+                if let Err(err) = gap_connect(hci, *addr, *addr_type) {
+                    warn!("le connect {:?}: {}", addr, err);
                 }
-                let arg = LECreateConnectionCmd {
-                    le_scan_interval: 16,
-                    le_scan_window: 16,
-                    initiator_filter_policy: false,
-                    peer_address_type: LEAddressType::PublicDevice,
-                    peer_address: *addr,
-                    own_address_type: LEAddressType::PublicDevice,
-                    conn_interval_min: 6,
-                    conn_interval_max: 7,
-                    max_latency: 0,
-                    supervision_timeout: 10,
-                    min_ce_length: 0,
-                    max_ce_length: 0,
-                };
-                arg.send(hci);
             }
-            _ => {}
+        }
+    }
+}
+
+/// The remote of a create connection or accept connection request command, and
+/// the role the link starts with
+fn cmd_connection_remote(cmd: &HCIWaitingCmd) -> Result<Option<(BDAddr, BDAddrType, Role)>> {
+    let param = cmd.param.as_deref().unwrap_or_default();
+    let (remote, addr_type, role) = if cmd.opcode == LinkControl::CreateConnection.get_opcode() {
+        (take_bytes(param, 0, 6)?, BDAddrType::Classic, Role::Central)
+    } else if cmd.opcode == LinkControl::AcceptConnectionRequest.get_opcode() {
+        // a requested role switch is reported by the Role Change event
+        (
+            take_bytes(param, 0, 6)?,
+            BDAddrType::Classic,
+            Role::Peripheral,
+        )
+    } else if cmd.opcode == LEController::LECreateConnection.get_opcode() {
+        let peer_address_type = LEAddressType::from_u8_array(take_bytes(param, 5, 1)?)?;
+        (
+            take_bytes(param, 6, 6)?,
+            BDAddrType::from(peer_address_type),
+            Role::Central,
+        )
+    } else if cmd.opcode == LEController::LEExtendedCreateConnection.get_opcode() {
+        let peer_address_type = LEAddressType::from_u8_array(take_bytes(param, 2, 1)?)?;
+        (
+            take_bytes(param, 3, 6)?,
+            BDAddrType::from(peer_address_type),
+            Role::Central,
+        )
+    } else {
+        return Ok(None);
+    };
+    let remote = remote.try_into().map_err(|_| Error::InvalidParameter)?;
+    Ok(Some((remote, addr_type, role)))
+}
+
+fn config_write_opcode(write: HCIConfigWrite) -> u16 {
+    match write {
+        HCIConfigWrite::ScanEnable => ControllerAndBaseband::WriteScanEnable.get_opcode(),
+        HCIConfigWrite::InquiryScanActivity => {
+            ControllerAndBaseband::WriteInquiryScanActivity.get_opcode()
+        }
+        HCIConfigWrite::InquiryScanType => ControllerAndBaseband::WriteInquiryScanType.get_opcode(),
+        HCIConfigWrite::InquiryMode => ControllerAndBaseband::WriteInquiryMode.get_opcode(),
+        HCIConfigWrite::PageTimeout => ControllerAndBaseband::WritePageTimeout.get_opcode(),
+        HCIConfigWrite::ConnectionAcceptTimeout => {
+            ControllerAndBaseband::WriteConnectionAcceptTimeout.get_opcode()
+        }
+        HCIConfigWrite::PageScanActivity => {
+            ControllerAndBaseband::WritePageScanActivity.get_opcode()
+        }
+        HCIConfigWrite::PageScanType => ControllerAndBaseband::WritePageScanType.get_opcode(),
+        HCIConfigWrite::VoiceSetting => ControllerAndBaseband::WriteVoiceSetting.get_opcode(),
+        HCIConfigWrite::PinType => ControllerAndBaseband::WritePINType.get_opcode(),
+        HCIConfigWrite::AuthenticationEnable => {
+            ControllerAndBaseband::WriteAuthenticationEnable.get_opcode()
+        }
+        HCIConfigWrite::HoldModeActivity => {
+            ControllerAndBaseband::WriteHoldModeActivity.get_opcode()
+        }
+        HCIConfigWrite::DefaultLinkPolicySettings => {
+            LinkPolicy::WriteDefaultLinkPolicySettings.get_opcode()
+        }
+        HCIConfigWrite::NumBroadcastRetransmissions => {
+            ControllerAndBaseband::WriteNumBroadcastRetransmissions.get_opcode()
+        }
+        HCIConfigWrite::SynchronousFlowControlEnable => {
+            ControllerAndBaseband::WriteSynchronousFlowControlEnable.get_opcode()
+        }
+        HCIConfigWrite::DefaultErroneousDataReporting => {
+            ControllerAndBaseband::WriteDefaultErroneousDataReporting.get_opcode()
+        }
+        HCIConfigWrite::ClassOfDevice => ControllerAndBaseband::WriteClassOfDevice.get_opcode(),
+        HCIConfigWrite::SynchronizationTrainParameters => {
+            ControllerAndBaseband::WriteSynchronizationTrainParameters.get_opcode()
+        }
+        HCIConfigWrite::SecureConnectionsHostSupport => {
+            ControllerAndBaseband::WriteSecureConnectionsHostSupport.get_opcode()
+        }
+        HCIConfigWrite::ExtendedPageTimeout => {
+            ControllerAndBaseband::WriteExtendedPageTimeout.get_opcode()
+        }
+        HCIConfigWrite::ExtendedInquiryLength => {
+            ControllerAndBaseband::WriteExtendedInquiryLength.get_opcode()
+        }
+        HCIConfigWrite::AutomaticFlushTimeout => {
+            ControllerAndBaseband::WriteAutomaticFlushTimeout.get_opcode()
+        }
+        HCIConfigWrite::LinkSupervisionTimeout => {
+            ControllerAndBaseband::WriteLinkSupervisionTimeout.get_opcode()
+        }
+        HCIConfigWrite::AuthenticatedPayloadTimeout => {
+            ControllerAndBaseband::WriteAuthenticatedPayloadTimeout.get_opcode()
+        }
+    }
+}
+
+fn config_write_command(write: HCIConfigWrite) -> SupportedCommand {
+    match write {
+        HCIConfigWrite::ScanEnable => SupportedCommand::WriteScanEnable,
+        HCIConfigWrite::InquiryScanActivity => SupportedCommand::WriteInquiryScanActivity,
+        HCIConfigWrite::InquiryScanType => SupportedCommand::WriteInquiryScanType,
+        HCIConfigWrite::InquiryMode => SupportedCommand::WriteInquiryMode,
+        HCIConfigWrite::PageTimeout => SupportedCommand::WritePageTimeout,
+        HCIConfigWrite::ConnectionAcceptTimeout => SupportedCommand::WriteConnectionAcceptTimeout,
+        HCIConfigWrite::PageScanActivity => SupportedCommand::WritePageScanActivity,
+        HCIConfigWrite::PageScanType => SupportedCommand::WritePageScanType,
+        HCIConfigWrite::VoiceSetting => SupportedCommand::WriteVoiceSetting,
+        HCIConfigWrite::PinType => SupportedCommand::WritePINType,
+        HCIConfigWrite::AuthenticationEnable => SupportedCommand::WriteAuthenticationEnable,
+        HCIConfigWrite::HoldModeActivity => SupportedCommand::WriteHoldModeActivity,
+        HCIConfigWrite::DefaultLinkPolicySettings => {
+            SupportedCommand::WriteDefaultLinkPolicySettings
+        }
+        HCIConfigWrite::NumBroadcastRetransmissions => {
+            SupportedCommand::WriteNumBroadcastRetransmissions
+        }
+        HCIConfigWrite::SynchronousFlowControlEnable => {
+            SupportedCommand::WriteSynchronousFlowControlEnable
+        }
+        HCIConfigWrite::DefaultErroneousDataReporting => {
+            SupportedCommand::WriteDefaultErroneousDataReporting
+        }
+        HCIConfigWrite::ClassOfDevice => SupportedCommand::WriteClassOfDevice,
+        HCIConfigWrite::SynchronizationTrainParameters => {
+            SupportedCommand::WriteSynchronizationTrainParameters
+        }
+        HCIConfigWrite::SecureConnectionsHostSupport => {
+            SupportedCommand::WriteSecureConnectionsHostSupport
+        }
+        HCIConfigWrite::ExtendedPageTimeout => SupportedCommand::WriteExtendedPageTimeout,
+        HCIConfigWrite::ExtendedInquiryLength => SupportedCommand::WriteExtendedInquiryLength,
+        HCIConfigWrite::AutomaticFlushTimeout => SupportedCommand::WriteAutomaticFlushTimeout,
+        HCIConfigWrite::LinkSupervisionTimeout => SupportedCommand::WriteLinkSupervisionTimeout,
+        HCIConfigWrite::AuthenticatedPayloadTimeout => {
+            SupportedCommand::WriteAuthenticatedPayloadTimeout
         }
     }
 }
@@ -599,3 +4553,659 @@ pub fn opcode_to_ogf(opcode: u16) -> u8 {
 pub fn opcode_to_ocf(opcode: u16) -> u16 {
     return opcode & 0x3ff;
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::baseband::ble::ll::AdvPdu;
+    use crate::baseband::Control;
+    use alloc::format;
+    use alloc::rc::Rc;
+    use alloc::string::String;
+    use alloc::vec;
+    use core::cell::RefCell;
+
+    type Packets = Rc<RefCell<Vec<Vec<u8>>>>;
+
+    /// A host wired to the simulated controller, packets are exchanged by `pump`
+    struct Sim {
+        hci: HCI,
+        bb: Control,
+        to_bb: Packets,
+        to_host: Packets,
+        /// PDUs the controller sent on air
+        air: Packets,
+        /// Opcodes of every command the host sent
+        commands: Rc<RefCell<Vec<u16>>>,
+        events: Rc<RefCell<Vec<String>>>,
+    }
+
+    impl Sim {
+        fn new() -> Self {
+            let to_bb = Packets::default();
+            let to_host = Packets::default();
+            let air = Packets::default();
+            let commands = Rc::new(RefCell::new(Vec::new()));
+            let events = Rc::new(RefCell::new(Vec::new()));
+            let mut hci = HCI::new([0x11; 6]);
+            let sent = to_bb.clone();
+            let opcodes = commands.clone();
+            hci.set_send_packet(move |_, packet, opcode, param| {
+                if matches!(packet, HCIPacket::Command) {
+                    opcodes.borrow_mut().push(opcode);
+                }
+                let mut bytes = vec![packet as u8];
+                bytes.extend(opcode.to_le_bytes());
+                bytes.extend(param.unwrap_or_default());
+                sent.borrow_mut().push(bytes);
+            });
+            let seen = events.clone();
+            hci.add_event_handler(move |_, event| seen.borrow_mut().push(format!("{:?}", event)));
+            let mut bb = Control::new(0);
+            let up = to_host.clone();
+            bb.set_upper_send_packet(move |_, packet| up.borrow_mut().push(packet));
+            let down = air.clone();
+            bb.set_lower_send_packet(move |_, packet| down.borrow_mut().push(packet));
+            Sim {
+                hci,
+                bb,
+                to_bb,
+                to_host,
+                air,
+                commands,
+                events,
+            }
+        }
+
+        fn powered_on() -> Self {
+            let mut sim = Sim::new();
+            sim.hci.power_control(HCIPowerMode::On);
+            sim.pump();
+            assert!(sim.hci.state == HCIState::Working);
+            sim
+        }
+
+        fn pump(&mut self) {
+            loop {
+                let to_bb: Vec<_> = self.to_bb.borrow_mut().drain(..).collect();
+                let to_host: Vec<_> = self.to_host.borrow_mut().drain(..).collect();
+                if to_bb.is_empty() && to_host.is_empty() {
+                    break;
+                }
+                for packet in to_bb {
+                    let _ = self.bb.recv_host_packet(packet);
+                }
+                for packet in to_host {
+                    let _ = self.hci.recv_packet(packet);
+                }
+            }
+        }
+
+        /// Like `pump`, but the host's `opcode` commands fail with `status` instead of
+        /// reaching the controller
+        fn pump_failing(&mut self, opcode: u16, status: ControllerErrorCode) {
+            loop {
+                let failing = |p: &Vec<u8>| {
+                    p[0] == HCIPacket::Command as u8 && p[1..3] == opcode.to_le_bytes()
+                };
+                let (failed, to_bb): (Vec<_>, Vec<_>) =
+                    self.to_bb.borrow_mut().drain(..).partition(failing);
+                let to_host: Vec<_> = self.to_host.borrow_mut().drain(..).collect();
+                if failed.is_empty() && to_bb.is_empty() && to_host.is_empty() {
+                    break;
+                }
+                for _ in failed {
+                    let mut param = vec![status as u8, 1];
+                    param.extend(opcode.to_le_bytes());
+                    self.event(HCIEvent::CommandStatus as u8, &param);
+                }
+                for packet in to_bb {
+                    let _ = self.bb.recv_host_packet(packet);
+                }
+                for packet in to_host {
+                    let _ = self.hci.recv_packet(packet);
+                }
+            }
+        }
+
+        /// Feeds an event to the host without the controller seeing the commands it causes
+        fn event(&mut self, code: u8, param: &[u8]) {
+            let mut packet = vec![HCIPacket::Event as u8, code, param.len() as u8];
+            packet.extend(param);
+            self.hci.recv_packet(packet).unwrap();
+        }
+
+        fn has_sent(&self, opcode: u16) -> bool {
+            self.commands.borrow().contains(&opcode)
+        }
+
+        fn has_event(&self, prefix: &str) -> bool {
+            self.events.borrow().iter().any(|e| e.starts_with(prefix))
+        }
+
+        fn connected(&mut self, handle: u16, peer: BDAddr) {
+            let mut param = vec![0];
+            param.extend(handle.to_le_bytes());
+            param.extend(peer);
+            param.extend([LinkType::ACL as u8, 0]);
+            self.event(HCIEvent::ConnectionComplete as u8, &param);
+        }
+
+        /// Feeds a single fragment L2CAP PDU to the host
+        fn l2cap(&mut self, handle: u16, cid: u16, payload: &[u8]) -> Result<()> {
+            let mut packet = vec![HCIPacket::ACL as u8];
+            packet.extend(handle.to_le_bytes());
+            packet.extend((payload.len() as u16 + 4).to_le_bytes());
+            packet.extend((payload.len() as u16).to_le_bytes());
+            packet.extend(cid.to_le_bytes());
+            packet.extend(payload);
+            self.hci.recv_packet(packet)
+        }
+
+        /// Channel id and signaling command of every single fragment PDU the host sent
+        fn signaling_sent(&self) -> Vec<(u16, Vec<u8>)> {
+            self.to_bb
+                .borrow()
+                .iter()
+                .filter(|p| p[0] == HCIPacket::ACL as u8)
+                .map(|p| (u16::from_le_bytes([p[7], p[8]]), p[9..].to_vec()))
+                .collect()
+        }
+
+        fn le_connected(&mut self, handle: u16, role: Role, peer: BDAddr) {
+            let mut param = vec![LESubevent::ConnectionComplete as u8, 0];
+            param.extend(handle.to_le_bytes());
+            param.extend([role as u8, 0]);
+            param.extend(peer);
+            param.extend([0x18, 0, 0, 0, 0x48, 0, 0]);
+            self.event(HCIEvent::LEMeta as u8, &param);
+        }
+    }
+
+    #[test]
+    fn nop_command_complete_keeps_links() {
+        let mut sim = Sim::powered_on();
+        sim.le_connected(0x0040, Role::Central, [0x22; 6]);
+        assert!(sim.hci.get_connection(0x0040).is_some());
+
+        sim.event(HCIEvent::CommandComplete as u8, &[3, 0, 0]);
+        assert!(sim.hci.state == HCIState::Working);
+        assert!(sim.hci.get_connection(0x0040).is_some());
+        assert_eq!(sim.hci.cmd_credits, 3);
+        assert!(!sim.has_event("Disconnected"));
+
+        // a Reset the host did not send starts over
+        sim.event(HCIEvent::CommandComplete as u8, &[1, 0x03, 0x0c, 0]);
+        assert!(sim.hci.state == HCIState::Initializing);
+        assert!(sim.hci.get_connection(0x0040).is_none());
+    }
+
+    const IRK: [u8; 16] = [
+        0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34, 0x02,
+        0xec,
+    ];
+
+    #[test]
+    fn resolvable_address_advertised() {
+        let mut sim = Sim::powered_on();
+        gap_privacy_set_irk(&mut sim.hci, IRK);
+        gap_random_address_set_mode(&mut sim.hci, LEOwnAddressMode::Resolvable).unwrap();
+        gap_advertisements_enable(&mut sim.hci, true);
+        sim.pump();
+        assert!(sim.has_event("RandomAddressChanged"));
+        assert!(sim.has_event("AdvertisingStarted"));
+        let address = sim.hci.le_random_address.unwrap();
+        assert!(resolve_private_address(&IRK, &address));
+
+        sim.bb.poll_timers(1000);
+        let pdu = AdvPdu::from_u8_array(&sim.air.borrow()[0]).unwrap();
+        match pdu {
+            AdvPdu::AdvInd { adv_a, .. } => {
+                assert_eq!(
+                    adv_a,
+                    (LEAddressType2::RandomDeviceOrRandomIdentity, address)
+                )
+            }
+            _ => panic!("unexpected {:?}", pdu),
+        }
+    }
+
+    #[test]
+    fn rand_rejected_retries_at_next_rotation() {
+        let mut sim = Sim::powered_on();
+        gap_privacy_set_irk(&mut sim.hci, IRK);
+        sim.to_bb.borrow_mut().clear();
+        gap_random_address_set_mode(&mut sim.hci, LEOwnAddressMode::Resolvable).unwrap();
+        assert!(sim.hci.le_rand_pending);
+
+        let opcode = LEController::LERand.get_opcode().to_le_bytes();
+        sim.event(
+            HCIEvent::CommandStatus as u8,
+            &[
+                ControllerErrorCode::UnknownHCICommand as u8,
+                1,
+                opcode[0],
+                opcode[1],
+            ],
+        );
+        assert!(!sim.hci.le_rand_pending);
+        assert_eq!(
+            sim.hci.le_private_address_deadline,
+            Some(sim.hci.now_ms + LE_PRIVATE_ADDRESS_TIMEOUT_MS)
+        );
+    }
+
+    #[test]
+    fn address_resolution_enable_needs_its_command() {
+        let enable = LEController::LESetAddressResolutionEnable.get_opcode();
+        let entry = ResolvingListEntry {
+            identity_address_type: LEAddressType2::PublicDeviceOrPublicIdentity,
+            identity_address: [0x22; 6],
+            peer_irk: IRK,
+            local_irk: [0; 16],
+        };
+
+        let mut sim = Sim::powered_on();
+        assert!(sim
+            .hci
+            .is_command_supported(SupportedCommand::LESetAddressResolutionEnable));
+        gap_resolving_list_add(&mut sim.hci, entry).unwrap();
+        sim.pump();
+        assert!(sim.has_sent(enable));
+
+        // a controller without the command, as if it had just been reset
+        let mut sim = Sim::powered_on();
+        sim.hci.supported_commands[0x23] &= !0x02;
+        sim.hci.le_address_resolution_enabled = false;
+        sim.commands.borrow_mut().clear();
+        gap_resolving_list_add(&mut sim.hci, entry).unwrap();
+        sim.pump();
+        assert!(sim.has_sent(LEController::LEAddDeviceToResolvingList.get_opcode()));
+        assert!(!sim.has_sent(enable));
+    }
+
+    #[test]
+    fn acl_data_waits_for_the_buffer_size() {
+        let mut sim = Sim::new();
+        sim.le_connected(0x0040, Role::Central, [0x22; 6]);
+        assert_eq!(
+            sim.hci.send_acl_data(0x0040, vec![0; 8]),
+            Err(Error::BufferSizeUnknown)
+        );
+        assert!(sim.hci.acl_queue.is_empty());
+
+        let mut sim = Sim::powered_on();
+        sim.le_connected(0x0040, Role::Central, [0x22; 6]);
+        assert_eq!(sim.hci.send_acl_data(0x0040, vec![0; 8]), Ok(()));
+    }
+
+    /// Connection Parameter Update Request for interval 30ms to 50ms
+    fn conn_param_update_request(identifier: u8, latency: u16) -> Vec<u8> {
+        let mut cmd = vec![L2CAP_SIG_CONN_PARAM_UPDATE_REQUEST, identifier, 8, 0];
+        cmd.extend([0x18, 0, 0x28, 0]);
+        cmd.extend(latency.to_le_bytes());
+        cmd.extend([0xC8, 0]);
+        cmd
+    }
+
+    #[test]
+    fn conn_param_update_request_accepted() {
+        let mut sim = Sim::powered_on();
+        sim.le_connected(0x0040, Role::Central, [0x22; 6]);
+        sim.l2cap(
+            0x0040,
+            L2CAP_CID_LE_SIGNALING,
+            &conn_param_update_request(7, 4),
+        )
+        .unwrap();
+        assert_eq!(
+            sim.signaling_sent(),
+            [(
+                L2CAP_CID_LE_SIGNALING,
+                vec![L2CAP_SIG_CONN_PARAM_UPDATE_RESPONSE, 7, 2, 0, 0, 0]
+            )]
+        );
+        assert!(sim.has_sent(LEController::LEConnectionUpdate.get_opcode()));
+    }
+
+    #[test]
+    fn conn_param_update_request_rejected() {
+        let mut sim = Sim::powered_on();
+        sim.le_connected(0x0040, Role::Central, [0x22; 6]);
+        sim.l2cap(
+            0x0040,
+            L2CAP_CID_LE_SIGNALING,
+            &conn_param_update_request(7, 0x01F4),
+        )
+        .unwrap();
+        assert_eq!(
+            sim.signaling_sent(),
+            [(
+                L2CAP_CID_LE_SIGNALING,
+                vec![L2CAP_SIG_CONN_PARAM_UPDATE_RESPONSE, 7, 2, 0, 1, 0]
+            )]
+        );
+        assert!(!sim.has_sent(LEController::LEConnectionUpdate.get_opcode()));
+    }
+
+    #[test]
+    fn conn_param_update_request_needs_central() {
+        let mut sim = Sim::powered_on();
+        sim.le_connected(0x0040, Role::Peripheral, [0x22; 6]);
+        sim.l2cap(
+            0x0040,
+            L2CAP_CID_LE_SIGNALING,
+            &conn_param_update_request(7, 4),
+        )
+        .unwrap();
+        assert_eq!(
+            sim.signaling_sent(),
+            [(
+                L2CAP_CID_LE_SIGNALING,
+                vec![L2CAP_SIG_COMMAND_REJECT, 7, 2, 0, 0, 0]
+            )]
+        );
+    }
+
+    #[test]
+    fn conn_param_update_response() {
+        let mut sim = Sim::powered_on();
+        sim.le_connected(0x0040, Role::Peripheral, [0x22; 6]);
+        let accepted = [L2CAP_SIG_CONN_PARAM_UPDATE_RESPONSE, 3, 2, 0, 0, 0];
+        sim.l2cap(0x0040, L2CAP_CID_LE_SIGNALING, &accepted)
+            .unwrap();
+        assert!(!sim.has_event("ConnectionUpdateFailed"));
+
+        let rejected = [L2CAP_SIG_CONN_PARAM_UPDATE_RESPONSE, 4, 2, 0, 1, 0];
+        sim.l2cap(0x0040, L2CAP_CID_LE_SIGNALING, &rejected)
+            .unwrap();
+        assert!(sim.has_event("ConnectionUpdateFailed"));
+        assert!(sim.signaling_sent().is_empty());
+    }
+
+    #[test]
+    fn signaling_channel_follows_link_type() {
+        let mut sim = Sim::powered_on();
+        sim.connected(0x0001, [0x33; 6]);
+        sim.le_connected(0x0040, Role::Central, [0x22; 6]);
+
+        // 0x0005 is no signaling channel on BR/EDR
+        sim.l2cap(
+            0x0001,
+            L2CAP_CID_LE_SIGNALING,
+            &conn_param_update_request(7, 4),
+        )
+        .unwrap();
+        assert!(sim.signaling_sent().is_empty());
+        assert!(!sim.has_sent(LEController::LEConnectionUpdate.get_opcode()));
+        assert!(sim.has_event("ACLData { handle: 1, cid: 5"));
+
+        // and 0x0001 none on LE
+        let echo = [L2CAP_SIG_ECHO_REQUEST, 9, 1, 0, 0xAA];
+        sim.l2cap(0x0040, L2CAP_CID_SIGNALING, &echo).unwrap();
+        assert!(sim.signaling_sent().is_empty());
+        assert!(sim.has_event("ACLData { handle: 64, cid: 1"));
+
+        sim.l2cap(0x0001, L2CAP_CID_SIGNALING, &echo).unwrap();
+        assert_eq!(
+            sim.signaling_sent(),
+            [(
+                L2CAP_CID_SIGNALING,
+                vec![L2CAP_SIG_ECHO_RESPONSE, 9, 1, 0, 0xAA]
+            )]
+        );
+    }
+
+    #[test]
+    fn bredr_signaling_refuses_channels() {
+        let mut sim = Sim::powered_on();
+        sim.connected(0x0001, [0x33; 6]);
+        // a Connection Request for PSM 0x0019 and an Information Request in one PDU
+        let mut cmds = vec![L2CAP_SIG_CONNECTION_REQUEST, 1, 4, 0, 0x19, 0, 0x40, 0];
+        cmds.extend([L2CAP_SIG_INFORMATION_REQUEST, 2, 2, 0, 0x02, 0]);
+        sim.l2cap(0x0001, L2CAP_CID_SIGNALING, &cmds).unwrap();
+
+        let sent = sim.signaling_sent();
+        assert_eq!(sent.len(), 2);
+        assert_eq!(
+            sent[0].1,
+            [
+                L2CAP_SIG_CONNECTION_RESPONSE,
+                1,
+                8,
+                0,
+                0,
+                0,
+                0x40,
+                0,
+                2,
+                0,
+                0,
+                0
+            ]
+        );
+        assert_eq!(
+            sent[1].1,
+            [L2CAP_SIG_INFORMATION_RESPONSE, 2, 4, 0, 0x02, 0, 1, 0]
+        );
+    }
+
+    const READ_BD_ADDR: u16 = 0x1009;
+    const READ_LOCAL_VERSION: u16 = 0x1001;
+
+    #[test]
+    fn commands_wait_for_credits() {
+        let mut sim = Sim::powered_on();
+        // the controller takes no commands for now
+        sim.event(HCIEvent::CommandComplete as u8, &[0, 0, 0]);
+        sim.commands.borrow_mut().clear();
+
+        sim.hci.send_cmd_no_param(4, 9);
+        sim.hci.send_cmd_no_param(4, 1);
+        assert!(sim.commands.borrow().is_empty());
+        assert_eq!(sim.hci.cmd_queue.len(), 2);
+
+        sim.event(HCIEvent::CommandComplete as u8, &[1, 0, 0]);
+        assert_eq!(*sim.commands.borrow(), [READ_BD_ADDR]);
+        assert_eq!(sim.hci.cmd_credits, 0);
+
+        // the answer returns the credit for the next one
+        let mut answer = vec![1];
+        answer.extend(READ_BD_ADDR.to_le_bytes());
+        answer.extend([0; 7]);
+        sim.event(HCIEvent::CommandComplete as u8, &answer);
+        assert_eq!(*sim.commands.borrow(), [READ_BD_ADDR, READ_LOCAL_VERSION]);
+        assert!(sim.hci.cmd_queue.is_empty());
+        assert_eq!(sim.hci.cmd_waiting.len(), 1);
+        assert_eq!(sim.hci.cmd_waiting[0].opcode, READ_LOCAL_VERSION);
+    }
+
+    #[test]
+    fn command_status_releases_its_own_waiter() {
+        let mut sim = Sim::powered_on();
+        sim.event(HCIEvent::CommandComplete as u8, &[2, 0, 0]);
+        sim.hci.send_cmd_no_param(4, 9);
+        sim.hci.send_cmd_no_param(4, 1);
+        assert_eq!(sim.hci.cmd_waiting.len(), 2);
+
+        // status for a command nobody waits for
+        let mut status = vec![0, 1];
+        status.extend(LEController::LECreateConnection.get_opcode().to_le_bytes());
+        sim.event(HCIEvent::CommandStatus as u8, &status);
+        assert_eq!(sim.hci.cmd_waiting.len(), 2);
+        assert_eq!(sim.hci.cmd_credits, 1);
+
+        let mut status = vec![0, 1];
+        status.extend(READ_LOCAL_VERSION.to_le_bytes());
+        sim.event(HCIEvent::CommandStatus as u8, &status);
+        assert_eq!(sim.hci.cmd_waiting.len(), 1);
+        assert_eq!(sim.hci.cmd_waiting[0].opcode, READ_BD_ADDR);
+    }
+
+    #[test]
+    fn command_timeout_fires_once() {
+        let mut sim = Sim::new();
+        sim.hci.power_control(HCIPowerMode::On);
+        let reset = ControllerAndBaseband::Reset.get_opcode();
+        assert_eq!(*sim.commands.borrow(), [reset]);
+        assert_eq!(sim.hci.cmd_credits, 0);
+
+        sim.hci.poll_timers(HCI_CMD_TIMEOUT_MS - 1);
+        assert_eq!(sim.hci.cmd_waiting.len(), 1);
+        assert!(sim.events.borrow().is_empty());
+
+        sim.hci.poll_timers(HCI_CMD_TIMEOUT_MS);
+        sim.hci.poll_timers(HCI_CMD_TIMEOUT_MS + 1);
+        sim.hci.poll_timers(3 * HCI_CMD_TIMEOUT_MS);
+        assert!(sim.hci.cmd_waiting.is_empty());
+        assert_eq!(sim.hci.cmd_credits, 1);
+        let failed = format!(
+            "{:?}",
+            AppEvent::InitDone(Err(Error::CommandTimeout(reset)))
+        );
+        assert_eq!(*sim.events.borrow(), [failed]);
+        assert!(sim.hci.state == HCIState::Off);
+    }
+
+    #[test]
+    fn le_central_connects_to_another_address() {
+        let mut sim = Sim::powered_on();
+        sim.hci.connections.add_pending(HCIConnection::new(
+            [0x22; 6],
+            BDAddrType::LEPublic,
+            Role::Central,
+            ConnectionState::Pending,
+        ));
+        sim.le_connected(0x0040, Role::Central, [0x44; 6]);
+
+        let conn = sim.hci.get_connection(0x0040).unwrap();
+        assert_eq!(conn.remote, [0x44; 6]);
+        assert!(conn.role == Role::Central);
+        assert!(conn.state == ConnectionState::Connected);
+        assert!(!sim.hci.connections.contains_remote(&[0x22; 6]));
+    }
+
+    #[test]
+    fn disconnection_removes_the_link() {
+        let mut sim = Sim::powered_on();
+        sim.le_connected(0x0040, Role::Central, [0x22; 6]);
+        sim.le_connected(0x0041, Role::Central, [0x33; 6]);
+        sim.event(HCIEvent::DisconnectionComplete as u8, &[0, 0x40, 0, 0x13]);
+        assert!(sim.hci.get_connection(0x0040).is_none());
+        assert!(sim.hci.get_connection(0x0041).is_some());
+        assert!(sim.has_event("Disconnected"));
+    }
+
+    fn init_failed_by_status(opcode: u16) -> Sim {
+        let mut sim = Sim::new();
+        sim.hci.power_control(HCIPowerMode::On);
+        sim.pump_failing(opcode, ControllerErrorCode::UnspecifiedError);
+        sim
+    }
+
+    #[test]
+    fn init_fails_on_command_status() {
+        for opcode in [
+            ControllerAndBaseband::Reset.get_opcode(),
+            LEController::LEReadBufferSize.get_opcode(),
+        ] {
+            let sim = init_failed_by_status(opcode);
+            let failed = AppEvent::InitDone(Err(Error::CommandFailed {
+                opcode,
+                status: ControllerErrorCode::UnspecifiedError,
+            }));
+            assert_eq!(*sim.events.borrow(), [format!("{:?}", failed)]);
+            assert!(sim.hci.state == HCIState::Off);
+            assert!(sim.hci.cmd_waiting.is_empty());
+        }
+    }
+
+    #[test]
+    fn init_skips_optional_step_on_command_status() {
+        let sim =
+            init_failed_by_status(InformationalParam::ReadLocalVersionInformation.get_opcode());
+        assert!(sim.hci.state == HCIState::Working);
+        assert!(sim.has_event("InitDone(Ok"));
+    }
+
+    /// A peer connects to the advertising host, the link gets handle 0
+    fn peripheral_link() -> Sim {
+        let mut sim = Sim::powered_on();
+        gap_advertisements_enable(&mut sim.hci, true);
+        sim.pump();
+        sim.bb.poll_timers(1000);
+        let adv_a = match AdvPdu::from_u8_array(&sim.air.borrow()[0]).unwrap() {
+            AdvPdu::AdvInd { adv_a, .. } => adv_a,
+            pdu => panic!("unexpected {:?}", pdu),
+        };
+        let connect = AdvPdu::ConnectInd {
+            init_a: (LEAddressType2::PublicDeviceOrPublicIdentity, [0x22; 6]),
+            adv_a,
+            interval: 0x18,
+            latency: 0,
+            timeout: 0x48,
+        };
+        sim.bb.recv_phy_packet(connect.to_u8_array());
+        sim.pump();
+        assert!(sim.hci.get_connection(0).is_some());
+        sim.events.borrow_mut().clear();
+        sim
+    }
+
+    #[test]
+    fn simulated_link_data_length() {
+        let mut sim = peripheral_link();
+        gap_set_data_length(&mut sim.hci, 0, 251, 2120).unwrap();
+        sim.pump();
+        assert!(sim.has_event("DataLengthChanged"));
+        let data_length = sim.hci.get_connection(0).unwrap().data_length;
+        assert_eq!(
+            (data_length.max_tx_octets, data_length.max_tx_time),
+            (251, 2120)
+        );
+
+        // nothing changes, nothing to report
+        sim.events.borrow_mut().clear();
+        gap_set_data_length(&mut sim.hci, 0, 251, 2120).unwrap();
+        sim.pump();
+        assert!(!sim.has_event("DataLengthChanged"));
+    }
+
+    #[test]
+    fn simulated_link_phy() {
+        let mut sim = peripheral_link();
+        gap_read_phy(&mut sim.hci, 0).unwrap();
+        sim.pump();
+        assert!(sim.has_event("PhyUpdated { handle: 0, tx_phy: LE1M, rx_phy: LE1M }"));
+
+        sim.events.borrow_mut().clear();
+        gap_set_phy(
+            &mut sim.hci,
+            0,
+            LEPhys::LE2M,
+            LEPhys::LECoded,
+            LECodedPHYOptions::NoPreference,
+        )
+        .unwrap();
+        sim.pump();
+        assert!(sim.has_event("PhyUpdated { handle: 0, tx_phy: LE2M, rx_phy: LECoded }"));
+        let conn = sim.hci.get_connection(0).unwrap();
+        assert_eq!((conn.tx_phy, conn.rx_phy), (LEPhy::LE2M, LEPhy::LECoded));
+
+        // the link is gone for the controller
+        gap_disconnect(
+            &mut sim.hci,
+            0,
+            ControllerErrorCode::RemoteUserTerminatedConnection,
+        )
+        .unwrap();
+        sim.pump();
+        sim.events.borrow_mut().clear();
+        LEReadPHYCmd {
+            connection_handle: 0,
+        }
+        .send(&mut sim.hci);
+        sim.pump();
+        assert!(!sim.has_event("PhyUpdated"));
+    }
+}
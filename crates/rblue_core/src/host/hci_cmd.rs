@@ -1,16 +1,25 @@
 use super::*;
+use crate::{Error, Result};
 
 use pub_fields::pub_fields;
 extern crate rblue_proc_macro;
-use rblue_proc_macro::ToU8Array;
 use rblue_proc_macro::FromBytes;
+use rblue_proc_macro::ToU8Array;
 
 pub trait RBlueToU8Array {
     fn to_u8_array(&self) -> Vec<u8>;
 }
 
 pub trait RBlueFromU8Array: Sized {
-    fn from_u8_array(bytes: &[u8]) -> Option<Self>;
+    fn from_u8_array(bytes: &[u8]) -> Result<Self>;
+}
+
+/// Borrow `len` bytes starting at `offset`
+pub fn take_bytes(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    bytes.get(offset..offset + len).ok_or(Error::Truncated {
+        expected: offset + len,
+        actual: bytes.len(),
+    })
 }
 
 #[pub_fields]
@@ -32,6 +41,56 @@ where
     }
 }
 
+impl CommandCompleteEvt<Vec<u8>> {
+    pub fn parse_return_param<T: RBlueFromU8Array>(&self) -> Result<T> {
+        T::from_u8_array(&self.return_param)
+    }
+
+    pub fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        take_bytes(bytes, 0, 3)?;
+        Ok(CommandCompleteEvt {
+            num_hci_command_packets: bytes[0],
+            opcode: u16::from_le_bytes([bytes[1], bytes[2]]),
+            return_param: bytes[3..].to_vec(),
+        })
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct InquiryCmd {
+    /// Inquiry access code, little endian
+    lap: [u8; 3],
+    /// Range: 0x01 to 0x30, unit: 1.28s
+    inquiry_length: u8,
+    /// Zero for unlimited
+    num_responses: u8,
+}
+
+impl HCICmdSend for InquiryCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LinkControl as u8,
+            LinkControl::Inquiry as u16,
+            self.to_u8_array(),
+        )
+    }
+}
+
+pub struct InquiryCancelCmd {}
+
+impl HCICmdSend for InquiryCancelCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(HCICmd::LinkControl as u8, LinkControl::InquiryCancel as u16)
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct InquiryCancelRet {
+    status: ControllerErrorCode,
+}
+
 #[pub_fields]
 #[derive(ToU8Array)]
 pub struct CreateConnectionCmd {
@@ -53,19 +112,97 @@ impl HCICmdSend for CreateConnectionCmd {
     }
 }
 
-// Controller and Baseband Commands
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct DisconnectCmd {
+    connection_handle: u16,
+    reason: ControllerErrorCode,
+}
+
+impl HCICmdSend for DisconnectCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LinkControl as u8,
+            LinkControl::Disconnect as u16,
+            self.to_u8_array(),
+        )
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct AcceptConnectionRequestCmd {
+    bd_addr: BDAddr,
+    /// Central asks for a role switch, peripheral keeps the role of the accepting side
+    role: Role,
+}
+
+impl HCICmdSend for AcceptConnectionRequestCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LinkControl as u8,
+            LinkControl::AcceptConnectionRequest as u16,
+            self.to_u8_array(),
+        )
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct RejectConnectionRequestCmd {
+    bd_addr: BDAddr,
+    /// One of the `ConnectionRejectedDueTo*` codes
+    reason: ControllerErrorCode,
+}
+
+impl HCICmdSend for RejectConnectionRequestCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LinkControl as u8,
+            LinkControl::RejectConnectionRequest as u16,
+            self.to_u8_array(),
+        )
+    }
+}
+
+// Link Policy Commands
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteDefaultLinkPolicySettingsCmd {
+    default_link_policy_settings: u16,
+}
+
+impl HCICmdSend for WriteDefaultLinkPolicySettingsCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LinkPolicy as u8,
+            LinkPolicy::WriteDefaultLinkPolicySettings as u16,
+            self.to_u8_array(),
+        );
+    }
+}
 
 #[derive(ToU8Array)]
 #[pub_fields]
+pub struct WriteDefaultLinkPolicySettingsRet {
+    status: ControllerErrorCode,
+}
+
+// Controller and Baseband Commands
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
 pub struct SetEventMaskCmd {
     event_mask: u64,
 }
 
 impl HCICmdSend for SetEventMaskCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
+        hci.send_cmd_with_param(
             HCICmd::ControllerAndBaseband as u8,
             ControllerAndBaseband::SetEventMask as u16,
+            self.to_u8_array(),
         );
     }
 }
@@ -93,238 +230,836 @@ pub struct ResetRet {
     status: ControllerErrorCode,
 }
 
-// Informational Parameters
-
-pub struct ReadLocalSupportedCommandsCmd {}
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteScanEnableCmd {
+    scan_enable: ScanEnable,
+}
 
-impl HCICmdSend for ReadLocalSupportedCommandsCmd {
+impl HCICmdSend for WriteScanEnableCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
-            HCICmd::InformationalParam as u8,
-            InformationalParam::ReadLocalSupportedCommands as u16,
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteScanEnable as u16,
+            self.to_u8_array(),
         );
     }
 }
 
 #[derive(ToU8Array)]
 #[pub_fields]
-pub struct ReadLocalSupportedCommandsRet {
+pub struct WriteScanEnableRet {
     status: ControllerErrorCode,
-    supported_commands: SupportedCommands,
 }
 
-#[derive(ToU8Array)]
-pub struct ReadLocalSupportedFeaturesCmd {}
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteInquiryModeCmd {
+    inquiry_mode: InquiryMode,
+}
 
-impl HCICmdSend for ReadLocalSupportedFeaturesCmd {
+impl HCICmdSend for WriteInquiryModeCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
-            HCICmd::InformationalParam as u8,
-            InformationalParam::ReadLocalSupportedFeatures as u16,
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteInquiryMode as u16,
+            self.to_u8_array(),
         );
     }
 }
 
 #[derive(ToU8Array)]
 #[pub_fields]
-pub struct ReadLocalSupportedFeaturesRet {
+pub struct WriteInquiryModeRet {
     status: ControllerErrorCode,
-    lmp_feature: LMPFeatures,
 }
 
-#[derive(ToU8Array)]
-pub struct ReadBufferSizeCmd {}
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteLEHostSupportCmd {
+    le_supported_host: bool,
+    /// Simultaneous LE Host, shall be 0
+    unused: u8,
+}
 
-impl HCICmdSend for ReadBufferSizeCmd {
+impl HCICmdSend for WriteLEHostSupportCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
-            HCICmd::InformationalParam as u8,
-            InformationalParam::ReadBufferSize as u16,
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteLEHostSupport as u16,
+            self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct ReadBufferSizeRet {
+#[pub_fields]
+pub struct WriteLEHostSupportRet {
     status: ControllerErrorCode,
-    acl_data_packet_length: u16,
-    synchronous_data_packet_length: u8,
-    total_num_acl_data_packets: u16,
-    total_num_synchronous_data_packets: u16,
 }
 
-#[derive(ToU8Array)]
-pub struct ReadBDAddrCmd {}
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WritePINTypeCmd {
+    pin_type: PinType,
+}
 
-impl HCICmdSend for ReadBDAddrCmd {
+impl HCICmdSend for WritePINTypeCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
-            HCICmd::InformationalParam as u8,
-            InformationalParam::ReadBDAddr as u16,
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WritePINType as u16,
+            self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct ReadBDAddrRet {
+#[pub_fields]
+pub struct WritePINTypeRet {
     status: ControllerErrorCode,
-    bd_addr: BDAddr,
 }
 
-// LE Controller Commands
-
+#[derive(ToU8Array, FromBytes)]
 #[pub_fields]
-#[derive(ToU8Array)]
-pub struct LESetEventMaskCmd {
-    le_event_mask: u64,
+pub struct WriteConnectionAcceptTimeoutCmd {
+    /// Unit: 0.625ms
+    connection_accept_timeout: u16,
 }
 
-impl HCICmdSend for LESetEventMaskCmd {
+impl HCICmdSend for WriteConnectionAcceptTimeoutCmd {
     fn send(&self, hci: &mut HCI) {
         hci.send_cmd_with_param(
-            HCICmd::LEController as u8,
-            LEController::LESetEventMask as u16,
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteConnectionAcceptTimeout as u16,
             self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct LESetEventMaskRet {
+#[pub_fields]
+pub struct WriteConnectionAcceptTimeoutRet {
     status: ControllerErrorCode,
 }
 
-#[derive(ToU8Array)]
-pub struct LEReadBufferSizeCmd {}
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WritePageTimeoutCmd {
+    /// Unit: 0.625ms
+    page_timeout: u16,
+}
 
-impl HCICmdSend for LEReadBufferSizeCmd {
+impl HCICmdSend for WritePageTimeoutCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
-            HCICmd::LEController as u8,
-            LEController::LEReadBufferSize as u16,
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WritePageTimeout as u16,
+            self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct LEReadBufferSizeRet {
+#[pub_fields]
+pub struct WritePageTimeoutRet {
     status: ControllerErrorCode,
-    le_acl_data_packet_length: u16,
-    total_num_le_acl_data_packets: u8,
 }
 
-#[derive(ToU8Array)]
-pub struct LEReadLocalSupportedFeaturesCmd {}
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WritePageScanActivityCmd {
+    /// Unit: 0.625ms
+    page_scan_interval: u16,
+    page_scan_window: u16,
+}
 
-impl HCICmdSend for LEReadLocalSupportedFeaturesCmd {
+impl HCICmdSend for WritePageScanActivityCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
-            HCICmd::LEController as u8,
-            LEController::LEReadLocalSupportedFeatures as u16,
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WritePageScanActivity as u16,
+            self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct LEReadLocalSupportedFeaturesRet {
+#[pub_fields]
+pub struct WritePageScanActivityRet {
     status: ControllerErrorCode,
-    le_features: u64,
 }
 
+#[derive(ToU8Array, FromBytes)]
 #[pub_fields]
-#[derive(ToU8Array)]
-pub struct LESetAdvertisingParametersCmd {
-    advertising_interval_min: u16, // 0x0020 - 0x4000
-    advertising_interval_max: u16, // 0x0020 - 0x4000
-    advertising_type: AdvertisingType,
-    own_address_type: LEAddressType,
-    peer_address_type: LEAddressType2,
-    peer_address: BDAddr,
-    advertising_channel_map: u8,
-    advertising_filter_policy: AdvertisingFilterPolicy,
+pub struct WriteInquiryScanActivityCmd {
+    /// Unit: 0.625ms
+    inquiry_scan_interval: u16,
+    inquiry_scan_window: u16,
 }
 
-impl HCICmdSend for LESetAdvertisingParametersCmd {
+impl HCICmdSend for WriteInquiryScanActivityCmd {
     fn send(&self, hci: &mut HCI) {
         hci.send_cmd_with_param(
-            HCICmd::LEController as u8,
-            LEController::LESetAdvertisingParameters as u16,
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteInquiryScanActivity as u16,
             self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct LESetAdvertisingParametersRet {
+#[pub_fields]
+pub struct WriteInquiryScanActivityRet {
     status: ControllerErrorCode,
 }
 
-#[derive(ToU8Array)]
-pub struct LEReadAdvertisingPhysicalChannelTxPowerCmd {}
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteAuthenticationEnableCmd {
+    authentication_enable: AuthenticationEnable,
+}
 
-impl HCICmdSend for LEReadAdvertisingPhysicalChannelTxPowerCmd {
+impl HCICmdSend for WriteAuthenticationEnableCmd {
     fn send(&self, hci: &mut HCI) {
-        hci.send_cmd_no_param(
-            HCICmd::LEController as u8,
-            LEController::LEReadAdvertisingPhysicalChannelTxPower as u16,
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteAuthenticationEnable as u16,
+            self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct LEReadAdvertisingPhysicalChannelTxPowerRet {
+#[pub_fields]
+pub struct WriteAuthenticationEnableRet {
     status: ControllerErrorCode,
-    tx_power_level: u8,
 }
 
+#[derive(ToU8Array, FromBytes)]
 #[pub_fields]
-#[derive(ToU8Array)]
-pub struct LESetAdvertisingDataCmd {
-    advertising_data_length: u8,
-    advertising_data: LEAdvPacket,
+pub struct WriteClassOfDeviceCmd {
+    class_of_device: [u8; 3],
 }
 
-impl HCICmdSend for LESetAdvertisingDataCmd {
+impl HCICmdSend for WriteClassOfDeviceCmd {
     fn send(&self, hci: &mut HCI) {
         hci.send_cmd_with_param(
-            HCICmd::LEController as u8,
-            LEController::LESetAdvertisingData as u16,
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteClassOfDevice as u16,
             self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
 #[derive(ToU8Array)]
-pub struct LESetAdvertisingDataRet {
+#[pub_fields]
+pub struct WriteClassOfDeviceRet {
     status: ControllerErrorCode,
 }
 
+#[derive(ToU8Array, FromBytes)]
 #[pub_fields]
-#[derive(ToU8Array)]
-pub struct LESetScanResponseDataCmd {
-    scan_response_data_length: u8,
-    scan_response_data: LEAdvPacket,
+pub struct WriteVoiceSettingCmd {
+    voice_setting: u16,
 }
 
-impl HCICmdSend for LESetScanResponseDataCmd {
+impl HCICmdSend for WriteVoiceSettingCmd {
     fn send(&self, hci: &mut HCI) {
         hci.send_cmd_with_param(
-            HCICmd::LEController as u8,
-            LEController::LESetScanResponseData as u16,
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteVoiceSetting as u16,
             self.to_u8_array(),
         );
     }
 }
 
-#[pub_fields]
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteVoiceSettingRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteAutomaticFlushTimeoutCmd {
+    connection_handle: u16,
+    /// Zero never flushes, unit: 0.625ms
+    flush_timeout: u16,
+}
+
+impl HCICmdSend for WriteAutomaticFlushTimeoutCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteAutomaticFlushTimeout as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteAutomaticFlushTimeoutRet {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteNumBroadcastRetransmissionsCmd {
+    num_broadcast_retransmissions: u8,
+}
+
+impl HCICmdSend for WriteNumBroadcastRetransmissionsCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteNumBroadcastRetransmissions as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteNumBroadcastRetransmissionsRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteHoldModeActivityCmd {
+    hold_mode_activity: u8,
+}
+
+impl HCICmdSend for WriteHoldModeActivityCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteHoldModeActivity as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteHoldModeActivityRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteSynchronousFlowControlEnableCmd {
+    synchronous_flow_control_enable: bool,
+}
+
+impl HCICmdSend for WriteSynchronousFlowControlEnableCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteSynchronousFlowControlEnable as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteSynchronousFlowControlEnableRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteLinkSupervisionTimeoutCmd {
+    handle: u16,
+    /// Zero disables it, unit: 0.625ms
+    link_supervision_timeout: u16,
+}
+
+impl HCICmdSend for WriteLinkSupervisionTimeoutCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteLinkSupervisionTimeout as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteLinkSupervisionTimeoutRet {
+    status: ControllerErrorCode,
+    handle: u16,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteInquiryScanTypeCmd {
+    scan_type: ScanType,
+}
+
+impl HCICmdSend for WriteInquiryScanTypeCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteInquiryScanType as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteInquiryScanTypeRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WritePageScanTypeCmd {
+    page_scan_type: ScanType,
+}
+
+impl HCICmdSend for WritePageScanTypeCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WritePageScanType as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WritePageScanTypeRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteDefaultErroneousDataReportingCmd {
+    erroneous_data_reporting: bool,
+}
+
+impl HCICmdSend for WriteDefaultErroneousDataReportingCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteDefaultErroneousDataReporting as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteDefaultErroneousDataReportingRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteSynchronizationTrainParametersCmd {
+    /// Unit: 0.625ms
+    interval_min: u16,
+    interval_max: u16,
+    /// Unit: 0.625ms
+    sync_train_timeout: u32,
+    service_data: u8,
+}
+
+impl HCICmdSend for WriteSynchronizationTrainParametersCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteSynchronizationTrainParameters as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteSynchronizationTrainParametersRet {
+    status: ControllerErrorCode,
+    /// The interval the controller picked
+    sync_train_interval: u16,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteSecureConnectionsHostSupportCmd {
+    secure_connections_host_support: bool,
+}
+
+impl HCICmdSend for WriteSecureConnectionsHostSupportCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteSecureConnectionsHostSupport as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteSecureConnectionsHostSupportRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteAuthenticatedPayloadTimeoutCmd {
+    connection_handle: u16,
+    /// Unit: 10ms
+    authenticated_payload_timeout: u16,
+}
+
+impl HCICmdSend for WriteAuthenticatedPayloadTimeoutCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteAuthenticatedPayloadTimeout as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteAuthenticatedPayloadTimeoutRet {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteExtendedPageTimeoutCmd {
+    /// Unit: 0.625ms
+    extended_page_timeout: u16,
+}
+
+impl HCICmdSend for WriteExtendedPageTimeoutCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteExtendedPageTimeout as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteExtendedPageTimeoutRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct WriteExtendedInquiryLengthCmd {
+    /// Unit: 0.625ms
+    extended_inquiry_length: u16,
+}
+
+impl HCICmdSend for WriteExtendedInquiryLengthCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::ControllerAndBaseband as u8,
+            ControllerAndBaseband::WriteExtendedInquiryLength as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+#[pub_fields]
+pub struct WriteExtendedInquiryLengthRet {
+    status: ControllerErrorCode,
+}
+
+// Informational Parameters
+
+pub struct ReadLocalVersionInformationCmd {}
+
+impl HCICmdSend for ReadLocalVersionInformationCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::InformationalParam as u8,
+            InformationalParam::ReadLocalVersionInformation as u16,
+        );
+    }
+}
+
+#[derive(ToU8Array, FromBytes, Clone, Debug)]
+#[pub_fields]
+pub struct ReadLocalVersionInformationRet {
+    status: ControllerErrorCode,
+    hci_version: u8,
+    hci_subversion: u16,
+    lmp_version: u8,
+    company_identifier: u16,
+    lmp_subversion: u16,
+}
+
+pub struct ReadLocalSupportedCommandsCmd {}
+
+impl HCICmdSend for ReadLocalSupportedCommandsCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::InformationalParam as u8,
+            InformationalParam::ReadLocalSupportedCommands as u16,
+        );
+    }
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct ReadLocalSupportedCommandsRet {
+    status: ControllerErrorCode,
+    supported_commands: SupportedCommands,
+}
+
+#[derive(ToU8Array)]
+pub struct ReadLocalSupportedFeaturesCmd {}
+
+impl HCICmdSend for ReadLocalSupportedFeaturesCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::InformationalParam as u8,
+            InformationalParam::ReadLocalSupportedFeatures as u16,
+        );
+    }
+}
+
+#[derive(ToU8Array, FromBytes)]
+#[pub_fields]
+pub struct ReadLocalSupportedFeaturesRet {
+    status: ControllerErrorCode,
+    lmp_feature: LMPFeaturesBytes,
+}
+
+#[derive(ToU8Array)]
+pub struct ReadBufferSizeCmd {}
+
+impl HCICmdSend for ReadBufferSizeCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::InformationalParam as u8,
+            InformationalParam::ReadBufferSize as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct ReadBufferSizeRet {
+    status: ControllerErrorCode,
+    acl_data_packet_length: u16,
+    synchronous_data_packet_length: u8,
+    total_num_acl_data_packets: u16,
+    total_num_synchronous_data_packets: u16,
+}
+
+#[derive(ToU8Array)]
+pub struct ReadBDAddrCmd {}
+
+impl HCICmdSend for ReadBDAddrCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::InformationalParam as u8,
+            InformationalParam::ReadBDAddr as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct ReadBDAddrRet {
+    status: ControllerErrorCode,
+    bd_addr: BDAddr,
+}
+
+// LE Controller Commands
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetEventMaskCmd {
+    le_event_mask: u64,
+}
+
+impl HCICmdSend for LESetEventMaskCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetEventMask as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetEventMaskRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array)]
+pub struct LEReadBufferSizeCmd {}
+
+impl HCICmdSend for LEReadBufferSizeCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadBufferSize as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadBufferSizeRet {
+    status: ControllerErrorCode,
+    le_acl_data_packet_length: u16,
+    total_num_le_acl_data_packets: u8,
+}
+
+#[derive(ToU8Array)]
+pub struct LEReadLocalSupportedFeaturesCmd {}
+
+impl HCICmdSend for LEReadLocalSupportedFeaturesCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadLocalSupportedFeatures as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadLocalSupportedFeaturesRet {
+    status: ControllerErrorCode,
+    le_features: u64,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetRandomAddressCmd {
+    random_address: BDAddr,
+}
+
+impl HCICmdSend for LESetRandomAddressCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetRandomAddress as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetRandomAddressRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetAdvertisingParametersCmd {
+    advertising_interval_min: u16, // 0x0020 - 0x4000
+    advertising_interval_max: u16, // 0x0020 - 0x4000
+    advertising_type: AdvertisingType,
+    own_address_type: LEAddressType,
+    peer_address_type: LEAddressType2,
+    peer_address: BDAddr,
+    advertising_channel_map: u8,
+    advertising_filter_policy: AdvertisingFilterPolicy,
+}
+
+impl HCICmdSend for LESetAdvertisingParametersCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetAdvertisingParameters as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetAdvertisingParametersRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array)]
+pub struct LEReadAdvertisingPhysicalChannelTxPowerCmd {}
+
+impl HCICmdSend for LEReadAdvertisingPhysicalChannelTxPowerCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadAdvertisingPhysicalChannelTxPower as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEReadAdvertisingPhysicalChannelTxPowerRet {
+    status: ControllerErrorCode,
+    tx_power_level: u8,
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetAdvertisingDataCmd {
+    advertising_data_length: u8,
+    advertising_data: LEAdvPacket,
+}
+
+impl HCICmdSend for LESetAdvertisingDataCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetAdvertisingData as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetAdvertisingDataRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetScanResponseDataCmd {
+    scan_response_data_length: u8,
+    scan_response_data: LEAdvPacket,
+}
+
+impl HCICmdSend for LESetScanResponseDataCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetScanResponseData as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
 #[derive(ToU8Array)]
 pub struct LESetScanResponseDataRet {
     status: ControllerErrorCode,
@@ -332,15 +1067,798 @@ pub struct LESetScanResponseDataRet {
 
 #[pub_fields]
 #[derive(ToU8Array, FromBytes)]
-pub struct LESetAdvertisingEnableCmd {
-    advertiseing_enable: bool,
+pub struct LESetAdvertisingEnableCmd {
+    advertiseing_enable: bool,
+}
+
+impl HCICmdSend for LESetAdvertisingEnableCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetAdvertisingEnable as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetAdvertisingEnableRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetScanParametersCmd {
+    le_scan_type: LEScanType,
+    le_scan_interval: u16, // 0x0004 - 0x4000
+    le_scan_window: u16,   // 0x0004 - 0x4000, not larger than the interval
+    own_address_type: LEAddressType,
+    scanning_filter_policy: ScanningFilterPolicy,
+}
+
+impl HCICmdSend for LESetScanParametersCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetScanParameters as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetScanParametersRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetScanEnableCmd {
+    le_scan_enable: bool,
+    filter_duplicates: bool,
+}
+
+impl HCICmdSend for LESetScanEnableCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetScanEnable as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetScanEnableRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LECreateConnectionCmd {
+    le_scan_interval: u16,
+    le_scan_window: u16,
+    initiator_filter_policy: bool,
+    peer_address_type: LEAddressType,
+    peer_address: BDAddr,
+    own_address_type: LEAddressType,
+    conn_interval_min: u16,
+    conn_interval_max: u16,
+    max_latency: u16,
+    supervision_timeout: u16,
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl HCICmdSend for LECreateConnectionCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LECreateConnection as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+pub struct LECreateConnectionCancelCmd {}
+
+impl HCICmdSend for LECreateConnectionCancelCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LECreateConnectionCancel as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LECreateConnectionCancelRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array)]
+pub struct LEReadFilterAcceptListSizeCmd {}
+
+impl HCICmdSend for LEReadFilterAcceptListSizeCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadFilterAcceptListSize as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadFilterAcceptListSizeRet {
+    status: ControllerErrorCode,
+    filter_accept_list_size: u8,
+}
+
+#[derive(ToU8Array)]
+pub struct LEClearFilterAcceptListCmd {}
+
+impl HCICmdSend for LEClearFilterAcceptListCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEClearFilterAcceptList as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEClearFilterAcceptListRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEAddDeviceToFilterAcceptListCmd {
+    address_type: LEAddressType2,
+    address: BDAddr,
+}
+
+impl HCICmdSend for LEAddDeviceToFilterAcceptListCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LEAddDeviceToFilterAcceptList as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEAddDeviceToFilterAcceptListRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LERemoveDeviceFromFilterAcceptListCmd {
+    address_type: LEAddressType2,
+    address: BDAddr,
+}
+
+impl HCICmdSend for LERemoveDeviceFromFilterAcceptListCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LERemoveDeviceFromFilterAcceptList as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LERemoveDeviceFromFilterAcceptListRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEConnectionUpdateCmd {
+    connection_handle: u16,
+    interval_min: u16,
+    interval_max: u16,
+    max_latency: u16,
+    supervision_timeout: u16,
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl HCICmdSend for LEConnectionUpdateCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LEConnectionUpdate as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+pub struct LERandCmd {}
+
+impl HCICmdSend for LERandCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(HCICmd::LEController as u8, LEController::LERand as u16);
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LERandRet {
+    status: ControllerErrorCode,
+    random_number: u64,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LERemoteConnectionParameterRequestReplyCmd {
+    connection_handle: u16,
+    interval_min: u16,
+    interval_max: u16,
+    max_latency: u16,
+    supervision_timeout: u16,
+    min_ce_length: u16,
+    max_ce_length: u16,
+}
+
+impl HCICmdSend for LERemoteConnectionParameterRequestReplyCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LERemoteConnectionParameterRequestReply as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LERemoteConnectionParameterRequestReplyRet {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LERemoteConnectionParameterRequestNegativeReplyCmd {
+    connection_handle: u16,
+    reason: ControllerErrorCode,
+}
+
+impl HCICmdSend for LERemoteConnectionParameterRequestNegativeReplyCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LERemoteConnectionParameterRequestNegativeReply as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LERemoteConnectionParameterRequestNegativeReplyRet {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetDataLengthCmd {
+    connection_handle: u16,
+    /// Range: 0x001B to 0x00FB
+    tx_octets: u16,
+    /// Range: 0x0148 to 0x4290, unit: us
+    tx_time: u16,
+}
+
+impl HCICmdSend for LESetDataLengthCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetDataLength as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetDataLengthRet {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+}
+
+#[derive(ToU8Array)]
+pub struct LEReadSuggestedDefaultDataLengthCmd {}
+
+impl HCICmdSend for LEReadSuggestedDefaultDataLengthCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadSuggestedDefaultDataLength as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadSuggestedDefaultDataLengthRet {
+    status: ControllerErrorCode,
+    suggested_max_tx_octets: u16,
+    suggested_max_tx_time: u16,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEWriteSuggestedDefaultDataLengthCmd {
+    /// Range: 0x001B to 0x00FB
+    suggested_max_tx_octets: u16,
+    /// Range: 0x0148 to 0x4290, unit: us
+    suggested_max_tx_time: u16,
+}
+
+impl HCICmdSend for LEWriteSuggestedDefaultDataLengthCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LEWriteSuggestedDefaultDataLength as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEWriteSuggestedDefaultDataLengthRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEAddDeviceToResolvingListCmd {
+    peer_identity_address_type: LEAddressType2,
+    peer_identity_address: BDAddr,
+    peer_irk: [u8; 16],
+    local_irk: [u8; 16],
+}
+
+impl HCICmdSend for LEAddDeviceToResolvingListCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LEAddDeviceToResolvingList as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEAddDeviceToResolvingListRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LERemoveDeviceFromResolvingListCmd {
+    peer_identity_address_type: LEAddressType2,
+    peer_identity_address: BDAddr,
+}
+
+impl HCICmdSend for LERemoveDeviceFromResolvingListCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LERemoveDeviceFromResolvingList as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LERemoveDeviceFromResolvingListRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array)]
+pub struct LEClearResolvingListCmd {}
+
+impl HCICmdSend for LEClearResolvingListCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEClearResolvingList as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEClearResolvingListRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array)]
+pub struct LEReadResolvingListSizeCmd {}
+
+impl HCICmdSend for LEReadResolvingListSizeCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadResolvingListSize as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadResolvingListSizeRet {
+    status: ControllerErrorCode,
+    resolving_list_size: u8,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetAddressResolutionEnableCmd {
+    address_resolution_enable: bool,
+}
+
+impl HCICmdSend for LESetAddressResolutionEnableCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetAddressResolutionEnable as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetAddressResolutionEnableRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadPHYCmd {
+    connection_handle: u16,
+}
+
+impl HCICmdSend for LEReadPHYCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadPHY as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadPHYRet {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    tx_phy: LEPhy,
+    rx_phy: LEPhy,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetDefaultPHYCmd {
+    /// Bit 0: no transmitter preference, bit 1: no receiver preference
+    all_phys: u8,
+    tx_phys: u8,
+    rx_phys: u8,
+}
+
+impl HCICmdSend for LESetDefaultPHYCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetDefaultPHY as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetDefaultPHYRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetPHYCmd {
+    connection_handle: u16,
+    /// Bit 0: no transmitter preference, bit 1: no receiver preference
+    all_phys: u8,
+    tx_phys: u8,
+    rx_phys: u8,
+    phy_options: u16,
+}
+
+impl HCICmdSend for LESetPHYCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetPHY as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetAdvertisingSetRandomAddressCmd {
+    advertising_handle: u8,
+    random_address: BDAddr,
+}
+
+impl HCICmdSend for LESetAdvertisingSetRandomAddressCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetAdvertisingSetRandomAddress as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetAdvertisingSetRandomAddressRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+pub struct LESetExtendedAdvertisingParametersCmd {
+    /// Range: 0x00 to 0xEF
+    advertising_handle: u8,
+    advertising_event_properties: AdvertisingEventProperties,
+    /// 3 bytes on the wire, range: 0x000020 to 0xFFFFFF, unit: 0.625ms
+    primary_advertising_interval_min: u32,
+    primary_advertising_interval_max: u32,
+    primary_advertising_channel_map: u8,
+    own_address_type: LEAddressType,
+    peer_address_type: LEAddressType2,
+    peer_address: BDAddr,
+    advertising_filter_policy: AdvertisingFilterPolicy,
+    /// dBm, 0x7F lets the controller choose
+    advertising_tx_power: i8,
+    primary_advertising_phy: LEPhy,
+    secondary_advertising_max_skip: u8,
+    secondary_advertising_phy: LEPhy,
+    /// Range: 0x00 to 0x0F
+    advertising_sid: u8,
+    scan_request_notification_enable: bool,
+}
+
+impl RBlueToU8Array for LESetExtendedAdvertisingParametersCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.advertising_handle];
+        array.extend(self.advertising_event_properties.bits().to_le_bytes());
+        array.extend(&self.primary_advertising_interval_min.to_le_bytes()[..3]);
+        array.extend(&self.primary_advertising_interval_max.to_le_bytes()[..3]);
+        array.push(self.primary_advertising_channel_map);
+        array.extend(self.own_address_type.to_le_bytes());
+        array.extend(self.peer_address_type.to_le_bytes());
+        array.extend(self.peer_address);
+        array.extend(self.advertising_filter_policy.to_le_bytes());
+        array.extend(self.advertising_tx_power.to_le_bytes());
+        array.extend(self.primary_advertising_phy.to_le_bytes());
+        array.push(self.secondary_advertising_max_skip);
+        array.extend(self.secondary_advertising_phy.to_le_bytes());
+        array.push(self.advertising_sid);
+        array.push(self.scan_request_notification_enable as u8);
+        array
+    }
+}
+
+impl HCICmdSend for LESetExtendedAdvertisingParametersCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetExtendedAdvertisingParameters as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetExtendedAdvertisingParametersRet {
+    status: ControllerErrorCode,
+    selected_tx_power: i8,
+}
+
+/// One fragment of the advertising data of a set
+#[pub_fields]
+pub struct LESetExtendedAdvertisingDataCmd {
+    advertising_handle: u8,
+    operation: AdvertisingDataOperation,
+    /// 0x00 the controller may fragment the data, 0x01 it should not
+    fragment_preference: u8,
+    /// At most `LE_EXT_ADV_DATA_FRAGMENT_LEN` bytes
+    advertising_data: Vec<u8>,
+}
+
+impl RBlueToU8Array for LESetExtendedAdvertisingDataCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.advertising_handle];
+        array.extend(self.operation.to_le_bytes());
+        array.push(self.fragment_preference);
+        array.push(self.advertising_data.len() as u8);
+        array.extend(&self.advertising_data);
+        array
+    }
+}
+
+impl HCICmdSend for LESetExtendedAdvertisingDataCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetExtendedAdvertisingData as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetExtendedAdvertisingDataRet {
+    status: ControllerErrorCode,
+}
+
+/// One fragment of the scan response data of a set
+#[pub_fields]
+pub struct LESetExtendedScanResponseDataCmd {
+    advertising_handle: u8,
+    operation: AdvertisingDataOperation,
+    /// 0x00 the controller may fragment the data, 0x01 it should not
+    fragment_preference: u8,
+    /// At most `LE_EXT_ADV_DATA_FRAGMENT_LEN` bytes
+    scan_response_data: Vec<u8>,
+}
+
+impl RBlueToU8Array for LESetExtendedScanResponseDataCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.advertising_handle];
+        array.extend(self.operation.to_le_bytes());
+        array.push(self.fragment_preference);
+        array.push(self.scan_response_data.len() as u8);
+        array.extend(&self.scan_response_data);
+        array
+    }
+}
+
+impl HCICmdSend for LESetExtendedScanResponseDataCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetExtendedScanResponseData as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetExtendedScanResponseDataRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdvertisingSetEnable {
+    advertising_handle: u8,
+    /// Unit: 10ms, zero advertises until disabled
+    duration: u16,
+    /// Zero for no limit
+    max_extended_advertising_events: u8,
+}
+
+#[pub_fields]
+pub struct LESetExtendedAdvertisingEnableCmd {
+    enable: bool,
+    /// Disabling with no sets disables all of them
+    sets: Vec<AdvertisingSetEnable>,
+}
+
+impl RBlueToU8Array for LESetExtendedAdvertisingEnableCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.enable as u8, self.sets.len() as u8];
+        for set in self.sets.iter() {
+            array.push(set.advertising_handle);
+            array.extend(set.duration.to_le_bytes());
+            array.push(set.max_extended_advertising_events);
+        }
+        array
+    }
+}
+
+impl HCICmdSend for LESetExtendedAdvertisingEnableCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetExtendedAdvertisingEnable as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetExtendedAdvertisingEnableRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array)]
+pub struct LEReadMaximumAdvertisingDataLengthCmd {}
+
+impl HCICmdSend for LEReadMaximumAdvertisingDataLengthCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadMaximumAdvertisingDataLength as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadMaximumAdvertisingDataLengthRet {
+    status: ControllerErrorCode,
+    max_advertising_data_length: u16,
 }
 
-impl HCICmdSend for LESetAdvertisingEnableCmd {
+#[derive(ToU8Array)]
+pub struct LEReadNumberOfSupportedAdvertisingSetsCmd {}
+
+impl HCICmdSend for LEReadNumberOfSupportedAdvertisingSetsCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEReadNumberOfSupportedAdvertisingSets as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadNumberOfSupportedAdvertisingSetsRet {
+    status: ControllerErrorCode,
+    num_supported_advertising_sets: u8,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LERemoveAdvertisingSetCmd {
+    advertising_handle: u8,
+}
+
+impl HCICmdSend for LERemoveAdvertisingSetCmd {
     fn send(&self, hci: &mut HCI) {
         hci.send_cmd_with_param(
             HCICmd::LEController as u8,
-            LEController::LESetAdvertisingEnable as u16,
+            LEController::LERemoveAdvertisingSet as u16,
             self.to_u8_array(),
         );
     }
@@ -348,19 +1866,195 @@ impl HCICmdSend for LESetAdvertisingEnableCmd {
 
 #[pub_fields]
 #[derive(ToU8Array)]
-pub struct LESetAdvertisingEnableRet {
+pub struct LERemoveAdvertisingSetRet {
+    status: ControllerErrorCode,
+}
+
+#[derive(ToU8Array)]
+pub struct LEClearAdvertisingSetsCmd {}
+
+impl HCICmdSend for LEClearAdvertisingSetsCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEClearAdvertisingSets as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEClearAdvertisingSetsRet {
     status: ControllerErrorCode,
 }
 
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetPeriodicAdvertisingParametersCmd {
+    advertising_handle: u8,
+    /// Range: 0x0006 to 0xFFFF, unit: 1.25ms
+    periodic_advertising_interval_min: u16,
+    periodic_advertising_interval_max: u16,
+    /// Bit 6 includes the TxPower in the advertising PDU
+    periodic_advertising_properties: u16,
+}
+
+impl HCICmdSend for LESetPeriodicAdvertisingParametersCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetPeriodicAdvertisingParameters as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
 #[pub_fields]
 #[derive(ToU8Array)]
-pub struct LECreateConnectionCmd {
-    le_scan_interval: u16,
-    le_scan_window: u16,
-    initiator_filter_policy: bool,
-    peer_address_type: LEAddressType,
-    peer_address: BDAddr,
+pub struct LESetPeriodicAdvertisingParametersRet {
+    status: ControllerErrorCode,
+}
+
+/// One fragment of the periodic advertising data of a set
+#[pub_fields]
+pub struct LESetPeriodicAdvertisingDataCmd {
+    advertising_handle: u8,
+    operation: AdvertisingDataOperation,
+    /// At most `LE_PERIODIC_ADV_DATA_FRAGMENT_LEN` bytes
+    advertising_data: Vec<u8>,
+}
+
+impl RBlueToU8Array for LESetPeriodicAdvertisingDataCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.advertising_handle];
+        array.extend(self.operation.to_le_bytes());
+        array.push(self.advertising_data.len() as u8);
+        array.extend(&self.advertising_data);
+        array
+    }
+}
+
+impl HCICmdSend for LESetPeriodicAdvertisingDataCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetPeriodicAdvertisingData as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetPeriodicAdvertisingDataRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetPeriodicAdvertisingEnableCmd {
+    enable: bool,
+    advertising_handle: u8,
+}
+
+impl HCICmdSend for LESetPeriodicAdvertisingEnableCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetPeriodicAdvertisingEnable as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetPeriodicAdvertisingEnableRet {
+    status: ControllerErrorCode,
+}
+
+/// Scanning parameters on one PHY
+#[pub_fields]
+#[derive(ToU8Array, Clone, Copy, Debug, PartialEq)]
+pub struct ExtendedScanPhyParams {
+    scan_type: LEScanType,
+    scan_interval: u16, // 0x0004 - 0xFFFF
+    scan_window: u16,   // 0x0004 - 0xFFFF, not larger than the interval
+}
+
+#[pub_fields]
+pub struct LESetExtendedScanParametersCmd {
     own_address_type: LEAddressType,
+    scanning_filter_policy: ScanningFilterPolicy,
+    /// LE 1M and LE Coded only
+    scanning_phys: LEPhys,
+    /// One entry per PHY in `scanning_phys`, lowest bit first
+    phy_params: Vec<ExtendedScanPhyParams>,
+}
+
+impl RBlueToU8Array for LESetExtendedScanParametersCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![];
+        array.extend(self.own_address_type.to_le_bytes());
+        array.extend(self.scanning_filter_policy.to_le_bytes());
+        array.push(self.scanning_phys.bits());
+        for params in self.phy_params.iter() {
+            array.extend(params.to_u8_array());
+        }
+        array
+    }
+}
+
+impl HCICmdSend for LESetExtendedScanParametersCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetExtendedScanParameters as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetExtendedScanParametersRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LESetExtendedScanEnableCmd {
+    enable: bool,
+    filter_duplicates: bool,
+    /// Unit: 10ms, zero scans until disabled
+    duration: u16,
+    /// Unit: 1.28s, zero scans once for `duration`
+    period: u16,
+}
+
+impl HCICmdSend for LESetExtendedScanEnableCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LESetExtendedScanEnable as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LESetExtendedScanEnableRet {
+    status: ControllerErrorCode,
+}
+
+/// Initiating and connection parameters on one PHY
+#[pub_fields]
+#[derive(ToU8Array, Clone, Copy, Debug, PartialEq)]
+pub struct ExtendedConnectionPhyParams {
+    /// Ignored on the LE 2M PHY
+    scan_interval: u16,
+    scan_window: u16,
     conn_interval_min: u16,
     conn_interval_max: u16,
     max_latency: u16,
@@ -369,12 +2063,105 @@ pub struct LECreateConnectionCmd {
     max_ce_length: u16,
 }
 
-impl HCICmdSend for LECreateConnectionCmd {
+/// Answered with a command status like `LECreateConnectionCmd`
+#[pub_fields]
+pub struct LEExtendedCreateConnectionCmd {
+    initiator_filter_policy: bool,
+    own_address_type: LEAddressType,
+    peer_address_type: LEAddressType,
+    peer_address: BDAddr,
+    initiating_phys: LEPhys,
+    /// One entry per PHY in `initiating_phys`, lowest bit first
+    phy_params: Vec<ExtendedConnectionPhyParams>,
+}
+
+impl RBlueToU8Array for LEExtendedCreateConnectionCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.initiator_filter_policy as u8];
+        array.extend(self.own_address_type.to_le_bytes());
+        array.extend(self.peer_address_type.to_le_bytes());
+        array.extend(self.peer_address);
+        array.push(self.initiating_phys.bits());
+        for params in self.phy_params.iter() {
+            array.extend(params.to_u8_array());
+        }
+        array
+    }
+}
+
+impl HCICmdSend for LEExtendedCreateConnectionCmd {
     fn send(&self, hci: &mut HCI) {
         hci.send_cmd_with_param(
             HCICmd::LEController as u8,
-            LEController::LECreateConnection as u16,
+            LEController::LEExtendedCreateConnection as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+/// Answered with a command status, the sync is reported by the sync established event
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEPeriodicAdvertisingCreateSyncCmd {
+    /// Bit 0 uses the periodic advertiser list, bit 1 starts with the reports disabled
+    options: u8,
+    /// Range: 0x00 to 0x0F
+    advertising_sid: u8,
+    advertiser_address_type: LEAddressType2,
+    advertiser_address: BDAddr,
+    /// Periodic advertising events the controller may skip, range: 0x0000 to 0x01F3
+    skip: u16,
+    /// Range: 0x000A to 0x4000, unit: 10ms
+    sync_timeout: u16,
+    sync_cte_type: u8,
+}
+
+impl HCICmdSend for LEPeriodicAdvertisingCreateSyncCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LEPeriodicAdvertisingCreateSync as u16,
+            self.to_u8_array(),
+        );
+    }
+}
+
+#[derive(ToU8Array)]
+pub struct LEPeriodicAdvertisingCreateSyncCancelCmd {}
+
+impl HCICmdSend for LEPeriodicAdvertisingCreateSyncCancelCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_no_param(
+            HCICmd::LEController as u8,
+            LEController::LEPeriodicAdvertisingCreateSyncCancel as u16,
+        );
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEPeriodicAdvertisingCreateSyncCancelRet {
+    status: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEPeriodicAdvertisingTerminateSyncCmd {
+    sync_handle: u16,
+}
+
+impl HCICmdSend for LEPeriodicAdvertisingTerminateSyncCmd {
+    fn send(&self, hci: &mut HCI) {
+        hci.send_cmd_with_param(
+            HCICmd::LEController as u8,
+            LEController::LEPeriodicAdvertisingTerminateSync as u16,
             self.to_u8_array(),
         );
     }
 }
+
+#[pub_fields]
+#[derive(ToU8Array)]
+pub struct LEPeriodicAdvertisingTerminateSyncRet {
+    status: ControllerErrorCode,
+}
@@ -0,0 +1,721 @@
+use super::hci::{HCIEvent, LESubevent};
+use super::hci_cmd::*;
+use super::inquiry::EXTENDED_INQUIRY_RESPONSE_LEN;
+use super::*;
+use crate::{Error, Result};
+
+use pub_fields::pub_fields;
+use rblue_proc_macro::FromBytes;
+use rblue_proc_macro::ToU8Array;
+
+pub enum HCIEventPacket {
+    InquiryComplete(InquiryCompleteEvt),
+    InquiryResult(InquiryResultEvt),
+    ConnectionComplete(ConnectionCompleteEvt),
+    ConnectionRequest(ConnectionRequestEvt),
+    DisconnectionComplete(DisconnectionCompleteEvt),
+    EncryptionChange(EncryptionChangeEvt),
+    CommandComplete(CommandCompleteEvt<Vec<u8>>),
+    CommandStatus(CommandStatusEvt),
+    HardwareError(HardwareErrorEvt),
+    RoleChange(RoleChangeEvt),
+    NumberOfCompletedPackets(NumberOfCompletedPacketsEvt),
+    InquiryResultWithRSSI(InquiryResultWithRSSIEvt),
+    ExtendedInquiryResult(ExtendedInquiryResultEvt),
+    LEMeta(LEMetaEvent),
+}
+
+impl HCIEventPacket {
+    /// `bytes` starts with the event code, followed by the parameter length and the parameters
+    pub fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let header = take_bytes(bytes, 0, 2)?;
+        let param = take_bytes(bytes, 2, header[1] as usize)?;
+        let code = num::FromPrimitive::from_u8(header[0]).ok_or(Error::UnknownEvent(header[0]))?;
+        let event = match code {
+            HCIEvent::InquiryComplete => {
+                Self::InquiryComplete(InquiryCompleteEvt::from_u8_array(param)?)
+            }
+            HCIEvent::InquiryResult => Self::InquiryResult(InquiryResultEvt::from_u8_array(param)?),
+            HCIEvent::ConnectionComplete => {
+                Self::ConnectionComplete(ConnectionCompleteEvt::from_u8_array(param)?)
+            }
+            HCIEvent::ConnectionRequest => {
+                Self::ConnectionRequest(ConnectionRequestEvt::from_u8_array(param)?)
+            }
+            HCIEvent::DisconnectionComplete => {
+                Self::DisconnectionComplete(DisconnectionCompleteEvt::from_u8_array(param)?)
+            }
+            HCIEvent::EncryptionChange => {
+                Self::EncryptionChange(EncryptionChangeEvt::from_u8_array(param)?)
+            }
+            HCIEvent::CommandComplete => {
+                Self::CommandComplete(CommandCompleteEvt::from_u8_array(param)?)
+            }
+            HCIEvent::CommandStatus => Self::CommandStatus(CommandStatusEvt::from_u8_array(param)?),
+            HCIEvent::HardwareError => Self::HardwareError(HardwareErrorEvt::from_u8_array(param)?),
+            HCIEvent::RoleChange => Self::RoleChange(RoleChangeEvt::from_u8_array(param)?),
+            HCIEvent::NumberOfCompletedPackets => {
+                Self::NumberOfCompletedPackets(NumberOfCompletedPacketsEvt::from_u8_array(param)?)
+            }
+            HCIEvent::InquiryResultWithRSSI => {
+                Self::InquiryResultWithRSSI(InquiryResultWithRSSIEvt::from_u8_array(param)?)
+            }
+            HCIEvent::ExtendedInquiryResult => {
+                Self::ExtendedInquiryResult(ExtendedInquiryResultEvt::from_u8_array(param)?)
+            }
+            HCIEvent::LEMeta => Self::LEMeta(LEMetaEvent::from_u8_array(param)?),
+        };
+        Ok(event)
+    }
+}
+
+pub enum LEMetaEvent {
+    ConnectionComplete(LEConnectionCompleteEvt),
+    AdvertisingReport(LEAdvertisingReportEvt),
+    ConnectionUpdateComplete(LEConnectionUpdateCompleteEvt),
+    ReadRemoteFeaturesComplete(LEReadRemoteFeaturesCompleteEvt),
+    LongTermKeyRequest(LELongTermKeyRequestEvt),
+    RemoteConnectionParameterRequest(LERemoteConnectionParameterRequestEvt),
+    DataLengthChange(LEDataLengthChangeEvt),
+    PHYUpdateComplete(LEPHYUpdateCompleteEvt),
+    ExtendedAdvertisingReport(LEExtendedAdvertisingReportEvt),
+    PeriodicAdvertisingSyncEstablished(LEPeriodicAdvertisingSyncEstablishedEvt),
+    PeriodicAdvertisingReport(LEPeriodicAdvertisingReportEvt),
+    PeriodicAdvertisingSyncLost(LEPeriodicAdvertisingSyncLostEvt),
+    AdvertisingSetTerminated(LEAdvertisingSetTerminatedEvt),
+}
+
+impl LEMetaEvent {
+    /// `bytes` starts with the subevent code
+    pub fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let code = take_bytes(bytes, 0, 1)?[0];
+        let param = &bytes[1..];
+        let subevent = num::FromPrimitive::from_u8(code).ok_or(Error::UnknownLESubevent(code))?;
+        let event = match subevent {
+            LESubevent::ConnectionComplete => {
+                Self::ConnectionComplete(LEConnectionCompleteEvt::from_u8_array(param)?)
+            }
+            LESubevent::AdvertisingReport => {
+                Self::AdvertisingReport(LEAdvertisingReportEvt::from_u8_array(param)?)
+            }
+            LESubevent::ConnectionUpdateComplete => {
+                Self::ConnectionUpdateComplete(LEConnectionUpdateCompleteEvt::from_u8_array(param)?)
+            }
+            LESubevent::ReadRemoteFeaturesComplete => Self::ReadRemoteFeaturesComplete(
+                LEReadRemoteFeaturesCompleteEvt::from_u8_array(param)?,
+            ),
+            LESubevent::LongTermKeyRequest => {
+                Self::LongTermKeyRequest(LELongTermKeyRequestEvt::from_u8_array(param)?)
+            }
+            LESubevent::RemoteConnectionParameterRequest => Self::RemoteConnectionParameterRequest(
+                LERemoteConnectionParameterRequestEvt::from_u8_array(param)?,
+            ),
+            LESubevent::DataLengthChange => {
+                Self::DataLengthChange(LEDataLengthChangeEvt::from_u8_array(param)?)
+            }
+            LESubevent::PHYUpdateComplete => {
+                Self::PHYUpdateComplete(LEPHYUpdateCompleteEvt::from_u8_array(param)?)
+            }
+            LESubevent::ExtendedAdvertisingReport => Self::ExtendedAdvertisingReport(
+                LEExtendedAdvertisingReportEvt::from_u8_array(param)?,
+            ),
+            LESubevent::PeriodicAdvertisingSyncEstablished => {
+                Self::PeriodicAdvertisingSyncEstablished(
+                    LEPeriodicAdvertisingSyncEstablishedEvt::from_u8_array(param)?,
+                )
+            }
+            LESubevent::PeriodicAdvertisingReport => Self::PeriodicAdvertisingReport(
+                LEPeriodicAdvertisingReportEvt::from_u8_array(param)?,
+            ),
+            LESubevent::PeriodicAdvertisingSyncLost => Self::PeriodicAdvertisingSyncLost(
+                LEPeriodicAdvertisingSyncLostEvt::from_u8_array(param)?,
+            ),
+            LESubevent::AdvertisingSetTerminated => {
+                Self::AdvertisingSetTerminated(LEAdvertisingSetTerminatedEvt::from_u8_array(param)?)
+            }
+        };
+        Ok(event)
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct InquiryCompleteEvt {
+    status: ControllerErrorCode,
+}
+
+/// One device of the inquiry result events, the fields an event lacks are None or empty
+#[pub_fields]
+#[derive(Clone, Debug)]
+pub struct InquiryResponse {
+    bd_addr: BDAddr,
+    page_scan_repetition_mode: PageScanRepetitionMode,
+    /// 24 bits
+    class_of_device: u32,
+    clock_offset: u16,
+    /// Range: -127 to +20, unit: dBm
+    rssi: Option<i8>,
+    /// AD structures followed by zero padding
+    extended_inquiry_response: Vec<u8>,
+}
+
+/// The responses are stored field by field, `reserved_len` bytes of each response
+/// follow the page scan repetition mode. Returns the responses and the bytes read
+fn parse_inquiry_responses(
+    bytes: &[u8],
+    reserved_len: usize,
+    with_rssi: bool,
+) -> Result<(Vec<InquiryResponse>, usize)> {
+    let num = take_bytes(bytes, 0, 1)?[0] as usize;
+    let response_len = 6 + 1 + reserved_len + 3 + 2 + with_rssi as usize;
+    let data = take_bytes(bytes, 1, num * response_len)?;
+    let bd_addrs = &data[..num * 6];
+    let repetition_modes = &data[num * 6..num * 7];
+    let classes = &data[num * (7 + reserved_len)..num * (10 + reserved_len)];
+    let clock_offsets = &data[num * (10 + reserved_len)..num * (12 + reserved_len)];
+    let rssis = &data[num * (12 + reserved_len)..];
+    let mut responses = Vec::with_capacity(num);
+    for i in 0..num {
+        let class = &classes[i * 3..i * 3 + 3];
+        responses.push(InquiryResponse {
+            bd_addr: bd_addrs[i * 6..i * 6 + 6]
+                .try_into()
+                .map_err(|_| Error::InvalidParameter)?,
+            page_scan_repetition_mode: PageScanRepetitionMode::from_u8_array(
+                &repetition_modes[i..i + 1],
+            )?,
+            class_of_device: u32::from_le_bytes([class[0], class[1], class[2], 0]),
+            clock_offset: u16::from_le_bytes([clock_offsets[i * 2], clock_offsets[i * 2 + 1]]),
+            rssi: rssis.get(i).map(|rssi| *rssi as i8),
+            extended_inquiry_response: Vec::new(),
+        });
+    }
+    Ok((responses, 1 + num * response_len))
+}
+
+#[pub_fields]
+pub struct InquiryResultEvt {
+    responses: Vec<InquiryResponse>,
+}
+
+impl RBlueFromU8Array for InquiryResultEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let (responses, _) = parse_inquiry_responses(bytes, 2, false)?;
+        Ok(InquiryResultEvt { responses })
+    }
+}
+
+#[pub_fields]
+pub struct InquiryResultWithRSSIEvt {
+    responses: Vec<InquiryResponse>,
+}
+
+impl RBlueFromU8Array for InquiryResultWithRSSIEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let (responses, _) = parse_inquiry_responses(bytes, 1, true)?;
+        Ok(InquiryResultWithRSSIEvt { responses })
+    }
+}
+
+/// Always a single response
+#[pub_fields]
+pub struct ExtendedInquiryResultEvt {
+    response: InquiryResponse,
+}
+
+impl RBlueFromU8Array for ExtendedInquiryResultEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let (responses, len) = parse_inquiry_responses(bytes, 1, true)?;
+        let mut response = responses
+            .into_iter()
+            .next()
+            .ok_or(Error::InvalidParameter)?;
+        response.extended_inquiry_response =
+            take_bytes(bytes, len, EXTENDED_INQUIRY_RESPONSE_LEN)?.to_vec();
+        Ok(ExtendedInquiryResultEvt { response })
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct ConnectionCompleteEvt {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    bd_addr: BDAddr,
+    link_type: LinkType,
+    encryption_enabled: bool,
+}
+
+#[pub_fields]
+pub struct ConnectionRequestEvt {
+    bd_addr: BDAddr,
+    /// 24 bits
+    class_of_device: u32,
+    link_type: LinkType,
+}
+
+impl RBlueFromU8Array for ConnectionRequestEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let bytes = take_bytes(bytes, 0, 10)?;
+        Ok(ConnectionRequestEvt {
+            bd_addr: bytes[..6].try_into().map_err(|_| Error::InvalidParameter)?,
+            class_of_device: u32::from_le_bytes([bytes[6], bytes[7], bytes[8], 0]),
+            link_type: LinkType::from_u8_array(&bytes[9..])?,
+        })
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct DisconnectionCompleteEvt {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    reason: ControllerErrorCode,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct EncryptionChangeEvt {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    /// 0x00 off, 0x01 E0 or AES-CCM, 0x02 AES-CCM on BR/EDR
+    encryption_enabled: u8,
+}
+
+/// Also reported while a link is created, before its Connection Complete
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct RoleChangeEvt {
+    status: ControllerErrorCode,
+    bd_addr: BDAddr,
+    new_role: Role,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct CommandStatusEvt {
+    status: ControllerErrorCode,
+    num_hci_command_packets: u8,
+    opcode: u16,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct HardwareErrorEvt {
+    hardware_code: u8,
+}
+
+#[pub_fields]
+pub struct NumberOfCompletedPacketsEvt {
+    /// (connection handle, number of completed packets)
+    completed_packets: Vec<(u16, u16)>,
+}
+
+impl RBlueToU8Array for NumberOfCompletedPacketsEvt {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.completed_packets.len() as u8];
+        for (handle, num) in self.completed_packets.iter() {
+            array.extend(handle.to_le_bytes());
+            array.extend(num.to_le_bytes());
+        }
+        array
+    }
+}
+
+impl RBlueFromU8Array for NumberOfCompletedPacketsEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let num_handles = take_bytes(bytes, 0, 1)?[0] as usize;
+        let data = take_bytes(bytes, 1, num_handles * 4)?;
+        let completed_packets = data
+            .chunks_exact(4)
+            .map(|c| {
+                (
+                    u16::from_le_bytes([c[0], c[1]]),
+                    u16::from_le_bytes([c[2], c[3]]),
+                )
+            })
+            .collect();
+        Ok(NumberOfCompletedPacketsEvt { completed_packets })
+    }
+}
+
+// LE Meta subevents
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEConnectionCompleteEvt {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    role: Role,
+    peer_address_type: LEAddressType,
+    peer_address: BDAddr,
+    /// Range: 0x0006 to 0x0C80, unit: 1.25ms
+    connection_interval: u16,
+    /// Range: 0x0000 to 0x01F3
+    peripheral_latency: u16,
+    /// Range: 0x000A to 0x0C80, unit: 10ms
+    supervision_timeout: u16,
+    central_clock_accuracy: u8,
+}
+
+#[pub_fields]
+#[derive(Clone, Debug)]
+pub struct LEAdvertisingReport {
+    event_type: AdvertisingReportType,
+    address_type: LEAddressType,
+    address: BDAddr,
+    data: Vec<u8>,
+    /// Range: -127 to +20, 127 as not available, unit: dBm
+    rssi: i8,
+}
+
+#[pub_fields]
+pub struct LEAdvertisingReportEvt {
+    reports: Vec<LEAdvertisingReport>,
+}
+
+impl RBlueFromU8Array for LEAdvertisingReportEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let num_reports = take_bytes(bytes, 0, 1)?[0];
+        let mut reports = Vec::new();
+        let mut offset = 1;
+        for _ in 0..num_reports {
+            let header = take_bytes(bytes, offset, 9)?;
+            let data_length = header[8] as usize;
+            let data = take_bytes(bytes, offset + 9, data_length)?;
+            let rssi = take_bytes(bytes, offset + 9 + data_length, 1)?[0];
+            reports.push(LEAdvertisingReport {
+                event_type: AdvertisingReportType::from_u8_array(&header[0..1])?,
+                address_type: LEAddressType::from_u8_array(&header[1..2])?,
+                address: header[2..8]
+                    .try_into()
+                    .map_err(|_| Error::InvalidParameter)?,
+                data: data.to_vec(),
+                rssi: rssi as i8,
+            });
+            offset += 10 + data_length;
+        }
+        Ok(LEAdvertisingReportEvt { reports })
+    }
+}
+
+impl RBlueToU8Array for LEAdvertisingReportEvt {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = alloc::vec![self.reports.len() as u8];
+        for report in self.reports.iter() {
+            array.extend(report.event_type.to_le_bytes());
+            array.extend(report.address_type.to_le_bytes());
+            array.extend(report.address);
+            array.push(report.data.len() as u8);
+            array.extend(&report.data);
+            array.push(report.rssi as u8);
+        }
+        array
+    }
+}
+
+#[pub_fields]
+#[derive(Clone, Debug)]
+pub struct LEExtendedAdvertisingReport {
+    event_type: ExtendedAdvertisingReportType,
+    /// Long data arrives over several reports
+    data_status: PeriodicAdvertisingDataStatus,
+    /// None for anonymous advertisements
+    address_type: Option<LEAddressType>,
+    address: BDAddr,
+    primary_phy: LEPhy,
+    /// None when nothing was received on the secondary advertising channels
+    secondary_phy: Option<LEPhy>,
+    /// None when the advertiser sent no ADI field
+    advertising_sid: Option<u8>,
+    /// Range: -127 to +20, 127 as not available, unit: dBm
+    tx_power: i8,
+    /// Range: -127 to +20, 127 as not available, unit: dBm
+    rssi: i8,
+    /// Zero without periodic advertising, unit: 1.25ms
+    periodic_advertising_interval: u16,
+    /// Only for directed advertisements, 0xFE for an unresolved private address
+    direct_address_type: u8,
+    direct_address: BDAddr,
+    data: Vec<u8>,
+}
+
+impl LEExtendedAdvertisingReport {
+    /// The report a legacy scan gives for the same PDU, None for extended PDUs
+    pub fn to_legacy(&self) -> Option<LEAdvertisingReport> {
+        use ExtendedAdvertisingReportType as T;
+        if !self.event_type.contains(T::Legacy) {
+            return None;
+        }
+        let event_type = if self.event_type.contains(T::ScanResponse) {
+            AdvertisingReportType::ScanResponse
+        } else if self.event_type.contains(T::Directed) {
+            AdvertisingReportType::ConnectableDirected
+        } else if self.event_type.contains(T::Connectable) {
+            AdvertisingReportType::ConnectableAndScannable
+        } else if self.event_type.contains(T::Scannable) {
+            AdvertisingReportType::Scannable
+        } else {
+            AdvertisingReportType::NonConnectable
+        };
+        Some(LEAdvertisingReport {
+            event_type,
+            address_type: self.address_type?,
+            address: self.address,
+            data: self.data.clone(),
+            rssi: self.rssi,
+        })
+    }
+}
+
+#[pub_fields]
+pub struct LEExtendedAdvertisingReportEvt {
+    reports: Vec<LEExtendedAdvertisingReport>,
+}
+
+impl RBlueFromU8Array for LEExtendedAdvertisingReportEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let num_reports = take_bytes(bytes, 0, 1)?[0];
+        let mut reports = Vec::new();
+        let mut offset = 1;
+        for _ in 0..num_reports {
+            let header = take_bytes(bytes, offset, 24)?;
+            let data_length = header[23] as usize;
+            let event_type = u16::from_le_bytes([header[0], header[1]]);
+            let address_type = match header[2] {
+                0xFF => None,
+                _ => Some(LEAddressType::from_u8_array(&header[2..3])?),
+            };
+            let secondary_phy = match header[10] {
+                0x00 => None,
+                _ => Some(LEPhy::from_u8_array(&header[10..11])?),
+            };
+            reports.push(LEExtendedAdvertisingReport {
+                event_type: ExtendedAdvertisingReportType::from_bits_truncate(event_type),
+                data_status: PeriodicAdvertisingDataStatus::from_u8_array(&[(event_type >> 5)
+                    as u8
+                    & 0x03])?,
+                address_type,
+                address: header[3..9]
+                    .try_into()
+                    .map_err(|_| Error::InvalidParameter)?,
+                primary_phy: LEPhy::from_u8_array(&header[9..10])?,
+                secondary_phy,
+                advertising_sid: Some(header[11]).filter(|sid| *sid != 0xFF),
+                tx_power: header[12] as i8,
+                rssi: header[13] as i8,
+                periodic_advertising_interval: u16::from_le_bytes([header[14], header[15]]),
+                direct_address_type: header[16],
+                direct_address: header[17..23]
+                    .try_into()
+                    .map_err(|_| Error::InvalidParameter)?,
+                data: take_bytes(bytes, offset + 24, data_length)?.to_vec(),
+            });
+            offset += 24 + data_length;
+        }
+        Ok(LEExtendedAdvertisingReportEvt { reports })
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEConnectionUpdateCompleteEvt {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    connection_interval: u16,
+    peripheral_latency: u16,
+    supervision_timeout: u16,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEReadRemoteFeaturesCompleteEvt {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    le_features: u64,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LELongTermKeyRequestEvt {
+    connection_handle: u16,
+    random_number: u64,
+    encrypted_diversifier: u16,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LERemoteConnectionParameterRequestEvt {
+    connection_handle: u16,
+    interval_min: u16,
+    interval_max: u16,
+    max_latency: u16,
+    supervision_timeout: u16,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEDataLengthChangeEvt {
+    connection_handle: u16,
+    max_tx_octets: u16,
+    /// Unit: us
+    max_tx_time: u16,
+    max_rx_octets: u16,
+    max_rx_time: u16,
+}
+
+/// Answers LE Set PHY, also sent when the peer changed the PHYs
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEPHYUpdateCompleteEvt {
+    status: ControllerErrorCode,
+    connection_handle: u16,
+    tx_phy: LEPhy,
+    rx_phy: LEPhy,
+}
+
+/// An extended advertising set stopped on its own
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEAdvertisingSetTerminatedEvt {
+    /// Ok if a connection was created, otherwise the duration or event limit was reached
+    status: ControllerErrorCode,
+    advertising_handle: u8,
+    /// Only valid when a connection was created
+    connection_handle: u16,
+    num_completed_extended_advertising_events: u8,
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEPeriodicAdvertisingSyncEstablishedEvt {
+    /// `OperationCancelledByHost` after a create sync cancel
+    status: ControllerErrorCode,
+    sync_handle: u16,
+    advertising_sid: u8,
+    advertiser_address_type: LEAddressType,
+    advertiser_address: BDAddr,
+    advertiser_phy: LEPhy,
+    /// Unit: 1.25ms
+    periodic_advertising_interval: u16,
+    advertiser_clock_accuracy: u8,
+}
+
+#[pub_fields]
+#[derive(Clone, Debug)]
+pub struct LEPeriodicAdvertisingReportEvt {
+    sync_handle: u16,
+    /// Range: -127 to +20, 127 as not available, unit: dBm
+    tx_power: i8,
+    rssi: i8,
+    cte_type: u8,
+    /// Long data arrives over several reports
+    data_status: PeriodicAdvertisingDataStatus,
+    data: Vec<u8>,
+}
+
+impl RBlueFromU8Array for LEPeriodicAdvertisingReportEvt {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let header = take_bytes(bytes, 0, 7)?;
+        let data_length = header[6] as usize;
+        Ok(LEPeriodicAdvertisingReportEvt {
+            sync_handle: u16::from_le_bytes([header[0], header[1]]),
+            tx_power: header[2] as i8,
+            rssi: header[3] as i8,
+            cte_type: header[4],
+            data_status: PeriodicAdvertisingDataStatus::from_u8_array(&header[5..6])?,
+            data: take_bytes(bytes, 7, data_length)?.to_vec(),
+        })
+    }
+}
+
+#[pub_fields]
+#[derive(ToU8Array, FromBytes)]
+pub struct LEPeriodicAdvertisingSyncLostEvt {
+    sync_handle: u16,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    const ADDR_A: BDAddr = [0x01, 0x02, 0x03, 0x04, 0x05, 0x06];
+    const ADDR_B: BDAddr = [0x11, 0x12, 0x13, 0x14, 0x15, 0x16];
+
+    #[test]
+    fn inquiry_result_fields_are_grouped() {
+        let mut bytes = vec![2];
+        bytes.extend(ADDR_A);
+        bytes.extend(ADDR_B);
+        bytes.extend([0x01, 0x02]); // page scan repetition modes
+        bytes.extend([0; 4]); // reserved
+        bytes.extend([0x04, 0x04, 0x20, 0x0C, 0x01, 0x5A]); // classes
+        bytes.extend([0x34, 0x12, 0x78, 0x56]); // clock offsets
+        let evt = InquiryResultEvt::from_u8_array(&bytes).unwrap();
+        assert_eq!(evt.responses.len(), 2);
+        assert_eq!(evt.responses[0].bd_addr, ADDR_A);
+        assert_eq!(evt.responses[1].bd_addr, ADDR_B);
+        assert_eq!(
+            evt.responses[1].page_scan_repetition_mode,
+            PageScanRepetitionMode::R2
+        );
+        assert_eq!(evt.responses[0].class_of_device, 0x200404);
+        assert_eq!(evt.responses[1].class_of_device, 0x5A010C);
+        assert_eq!(evt.responses[1].clock_offset, 0x5678);
+        assert_eq!(evt.responses[0].rssi, None);
+        assert!(evt.responses[0].extended_inquiry_response.is_empty());
+    }
+
+    #[test]
+    fn inquiry_result_with_rssi() {
+        let mut bytes = vec![2];
+        bytes.extend(ADDR_A);
+        bytes.extend(ADDR_B);
+        bytes.extend([0x00, 0x01]);
+        bytes.extend([0; 2]);
+        bytes.extend([0x04, 0x04, 0x20, 0x0C, 0x01, 0x5A]);
+        bytes.extend([0x34, 0x12, 0x78, 0x56]);
+        bytes.extend([0xC4, 0x05]); // -60 and +5 dBm
+        let evt = InquiryResultWithRSSIEvt::from_u8_array(&bytes).unwrap();
+        assert_eq!(evt.responses[1].bd_addr, ADDR_B);
+        assert_eq!(
+            evt.responses[1].page_scan_repetition_mode,
+            PageScanRepetitionMode::R1
+        );
+        assert_eq!(evt.responses[0].clock_offset, 0x1234);
+        assert_eq!(evt.responses[0].rssi, Some(-60));
+        assert_eq!(evt.responses[1].rssi, Some(5));
+    }
+
+    #[test]
+    fn extended_inquiry_result() {
+        let mut bytes = vec![1];
+        bytes.extend(ADDR_A);
+        bytes.extend([0x01, 0x00]);
+        bytes.extend([0x04, 0x04, 0x20]);
+        bytes.extend([0x34, 0x12]);
+        bytes.push(0xC4);
+        let mut eir = [0; EXTENDED_INQUIRY_RESPONSE_LEN];
+        eir[..6].copy_from_slice(&[0x05, 0x09, b'r', b'b', b'l', b'u']);
+        bytes.extend(eir);
+        let evt = ExtendedInquiryResultEvt::from_u8_array(&bytes).unwrap();
+        assert_eq!(evt.response.bd_addr, ADDR_A);
+        assert_eq!(evt.response.class_of_device, 0x200404);
+        assert_eq!(evt.response.rssi, Some(-60));
+        assert_eq!(evt.response.extended_inquiry_response, eir);
+    }
+
+    #[test]
+    fn inquiry_result_truncated() {
+        let mut bytes = vec![2];
+        bytes.extend(ADDR_A);
+        assert!(InquiryResultEvt::from_u8_array(&bytes).is_err());
+
+        let mut bytes = vec![1];
+        bytes.extend(ADDR_A);
+        bytes.extend([0x01, 0x00, 0x04, 0x04, 0x20, 0x34, 0x12, 0xC4]);
+        bytes.extend([0; 10]);
+        assert!(ExtendedInquiryResultEvt::from_u8_array(&bytes).is_err());
+    }
+}
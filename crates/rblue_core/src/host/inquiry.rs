@@ -0,0 +1,124 @@
+use super::adv_data::AdvertisingData;
+use super::hci_event::InquiryResponse;
+use super::*;
+
+use alloc::collections::VecDeque;
+use pub_fields::pub_fields;
+
+/// General inquiry access code, little endian
+pub const GIAC: [u8; 3] = [0x33, 0x8B, 0x9E];
+/// Limited inquiry access code, little endian
+pub const LIAC: [u8; 3] = [0x00, 0x8B, 0x9E];
+/// Longest inquiry, unit: 1.28s
+pub const INQUIRY_LENGTH_MAX: u8 = 0x30;
+/// Size of the extended inquiry response carried by the Extended Inquiry Result event
+pub const EXTENDED_INQUIRY_RESPONSE_LEN: usize = 240;
+/// Devices the inquiry cache remembers
+pub const INQUIRY_CACHE_SIZE: usize = 16;
+
+/// A device found by an inquiry
+#[pub_fields]
+#[derive(Clone, Debug)]
+pub struct DiscoveredDevice {
+    address: BDAddr,
+    /// 24 bits: major service classes, major and minor device class
+    class_of_device: u32,
+    /// Range: -127 to +20, unit: dBm. None if the controller does not report it
+    rssi: Option<i8>,
+    /// Needed to page the device quickly
+    page_scan_repetition_mode: PageScanRepetitionMode,
+    clock_offset: u16,
+    /// The AD structures of the extended inquiry response, empty without one
+    extended_inquiry_response: AdvertisingData,
+}
+
+impl DiscoveredDevice {
+    /// A malformed extended inquiry response keeps the AD structures before the error
+    pub fn from_response(response: &InquiryResponse) -> Self {
+        let (extended_inquiry_response, ret) =
+            AdvertisingData::parse_partial(&response.extended_inquiry_response);
+        if let Err(err) = ret {
+            log::warn!(
+                "extended inquiry response of {:?}: {}",
+                response.bd_addr,
+                err
+            );
+        }
+        DiscoveredDevice {
+            address: response.bd_addr,
+            class_of_device: response.class_of_device,
+            rssi: response.rssi,
+            page_scan_repetition_mode: response.page_scan_repetition_mode,
+            clock_offset: response.clock_offset,
+            extended_inquiry_response,
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        self.extended_inquiry_response.local_name()
+    }
+}
+
+/// What the last inquiries told about paging the devices they found, the
+/// oldest device is dropped when full
+#[derive(Default)]
+pub struct InquiryCache {
+    devices: VecDeque<(BDAddr, PageScanRepetitionMode, u16)>,
+}
+
+impl InquiryCache {
+    pub fn update(&mut self, device: &DiscoveredDevice) {
+        self.devices
+            .retain(|(address, ..)| *address != device.address);
+        if self.devices.len() >= INQUIRY_CACHE_SIZE {
+            self.devices.pop_front();
+        }
+        self.devices.push_back((
+            device.address,
+            device.page_scan_repetition_mode,
+            device.clock_offset,
+        ));
+    }
+
+    /// The page scan repetition mode and clock offset of Create Connection,
+    /// bit 15 of the offset marks it valid. R2 without an offset for unknown devices
+    pub fn paging_params(&self, address: &BDAddr) -> (PageScanRepetitionMode, u16) {
+        self.devices
+            .iter()
+            .find(|(cached, ..)| cached == address)
+            .map(|(_, mode, clock_offset)| (*mode, clock_offset | 0x8000))
+            .unwrap_or((PageScanRepetitionMode::R2, 0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec;
+
+    fn response(extended_inquiry_response: Vec<u8>) -> InquiryResponse {
+        InquiryResponse {
+            bd_addr: [0x01, 0x02, 0x03, 0x04, 0x05, 0x06],
+            page_scan_repetition_mode: PageScanRepetitionMode::R1,
+            class_of_device: 0x200404,
+            clock_offset: 0x1234,
+            rssi: Some(-60),
+            extended_inquiry_response,
+        }
+    }
+
+    #[test]
+    fn malformed_eir_keeps_device() {
+        // a complete name, then a flags structure that runs past the end
+        let device = DiscoveredDevice::from_response(&response(vec![
+            0x03, 0x09, b'h', b'i', 0x05, 0x01, 0x06,
+        ]));
+        assert_eq!(device.address, [0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        assert_eq!(device.name(), Some("hi"));
+        assert_eq!(device.extended_inquiry_response.structures().len(), 1);
+
+        let device = DiscoveredDevice::from_response(&response(vec![0x02, 0x01]));
+        assert!(device.extended_inquiry_response.structures().is_empty());
+        assert_eq!(device.rssi, Some(-60));
+    }
+}
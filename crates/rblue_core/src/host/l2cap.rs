@@ -0,0 +1,323 @@
+use super::hci_cmd::*;
+use crate::{Error, Result};
+
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use pub_fields::pub_fields;
+
+pub const HCI_ACL_HEADER_SIZE: usize = 4;
+/// Length and channel id
+pub const L2CAP_HEADER_SIZE: usize = 4;
+
+/// BR/EDR signaling channel
+pub const L2CAP_CID_SIGNALING: u16 = 0x0001;
+pub const L2CAP_CID_ATT: u16 = 0x0004;
+pub const L2CAP_CID_LE_SIGNALING: u16 = 0x0005;
+pub const L2CAP_CID_SM: u16 = 0x0006;
+
+/// Signaling channel codes, the connection, echo and information ones are BR/EDR only
+pub const L2CAP_SIG_COMMAND_REJECT: u8 = 0x01;
+pub const L2CAP_SIG_CONNECTION_REQUEST: u8 = 0x02;
+pub const L2CAP_SIG_CONNECTION_RESPONSE: u8 = 0x03;
+pub const L2CAP_SIG_ECHO_REQUEST: u8 = 0x08;
+pub const L2CAP_SIG_ECHO_RESPONSE: u8 = 0x09;
+pub const L2CAP_SIG_INFORMATION_REQUEST: u8 = 0x0A;
+pub const L2CAP_SIG_INFORMATION_RESPONSE: u8 = 0x0B;
+pub const L2CAP_SIG_CONN_PARAM_UPDATE_REQUEST: u8 = 0x12;
+pub const L2CAP_SIG_CONN_PARAM_UPDATE_RESPONSE: u8 = 0x13;
+
+/// Command Reject reason
+pub const L2CAP_REJECT_NOT_UNDERSTOOD: u16 = 0x0000;
+/// Connection Parameter Update Response results
+pub const L2CAP_CONN_PARAM_ACCEPTED: u16 = 0x0000;
+pub const L2CAP_CONN_PARAM_REJECTED: u16 = 0x0001;
+/// Connection Response result, no channels are offered
+pub const L2CAP_CONNECTION_PSM_NOT_SUPPORTED: u16 = 0x0002;
+/// Information Response result
+pub const L2CAP_INFORMATION_NOT_SUPPORTED: u16 = 0x0001;
+
+/// Security Manager Pairing Request code
+pub const SM_PAIRING_REQUEST: u8 = 0x01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum PacketBoundary {
+    FirstNonFlushable,
+    Continuing,
+    FirstFlushable,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum BroadcastFlag {
+    PointToPoint,
+    BREDRBroadcast,
+}
+
+#[pub_fields]
+pub struct ACLHeader {
+    /// Range: 0x0000 to 0x0EFF
+    handle: u16,
+    packet_boundary: PacketBoundary,
+    broadcast: BroadcastFlag,
+    data_total_length: u16,
+}
+
+impl ACLHeader {
+    /// Handle with the packet boundary and broadcast flags in the upper bits
+    pub fn handle_with_flags(&self) -> u16 {
+        (self.handle & 0x0fff) | (self.packet_boundary as u16) << 12 | (self.broadcast as u16) << 14
+    }
+}
+
+impl RBlueToU8Array for ACLHeader {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = Vec::new();
+        array.extend(self.handle_with_flags().to_le_bytes());
+        array.extend(self.data_total_length.to_le_bytes());
+        array
+    }
+}
+
+impl RBlueFromU8Array for ACLHeader {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let header = take_bytes(bytes, 0, HCI_ACL_HEADER_SIZE)?;
+        let handle = u16::from_le_bytes([header[0], header[1]]);
+        let packet_boundary = match (handle >> 12) & 0b11 {
+            0b00 => PacketBoundary::FirstNonFlushable,
+            0b01 => PacketBoundary::Continuing,
+            0b10 => PacketBoundary::FirstFlushable,
+            _ => return Err(Error::InvalidParameter),
+        };
+        let broadcast = match handle >> 14 {
+            0b00 => BroadcastFlag::PointToPoint,
+            0b01 => BroadcastFlag::BREDRBroadcast,
+            _ => return Err(Error::InvalidParameter),
+        };
+        Ok(ACLHeader {
+            handle: handle & 0x0fff,
+            packet_boundary,
+            broadcast,
+            data_total_length: u16::from_le_bytes([header[2], header[3]]),
+        })
+    }
+}
+
+/// A command on the signaling channel
+#[pub_fields]
+pub struct SignalingCmd {
+    code: u8,
+    /// Matches a response to its request, never zero
+    identifier: u8,
+    data: Vec<u8>,
+}
+
+impl SignalingCmd {
+    /// The whole L2CAP PDU, ready for `HCI::send_acl_data`
+    pub fn to_pdu(&self, cid: u16) -> Vec<u8> {
+        let payload = self.to_u8_array();
+        let mut pdu = Vec::with_capacity(L2CAP_HEADER_SIZE + payload.len());
+        pdu.extend((payload.len() as u16).to_le_bytes());
+        pdu.extend(cid.to_le_bytes());
+        pdu.extend(payload);
+        pdu
+    }
+}
+
+impl RBlueToU8Array for SignalingCmd {
+    fn to_u8_array(&self) -> Vec<u8> {
+        let mut array = vec![self.code, self.identifier];
+        array.extend((self.data.len() as u16).to_le_bytes());
+        array.extend(&self.data);
+        array
+    }
+}
+
+impl RBlueFromU8Array for SignalingCmd {
+    fn from_u8_array(bytes: &[u8]) -> Result<Self> {
+        let header = take_bytes(bytes, 0, 4)?;
+        let length = u16::from_le_bytes([header[2], header[3]]) as usize;
+        Ok(SignalingCmd {
+            code: header[0],
+            identifier: header[1],
+            data: take_bytes(bytes, 4, length)?.to_vec(),
+        })
+    }
+}
+
+/// Split an L2CAP PDU into ACL payloads of at most `max_len` bytes, which must not be zero.
+/// `first` is the packet boundary of the first fragment, the rest are continuing fragments.
+pub fn fragment(
+    handle: u16,
+    pdu: &[u8],
+    max_len: usize,
+    first: PacketBoundary,
+) -> Vec<(ACLHeader, Vec<u8>)> {
+    pdu.chunks(max_len.max(1))
+        .enumerate()
+        .map(|(i, chunk)| {
+            let header = ACLHeader {
+                handle,
+                packet_boundary: if i == 0 {
+                    first
+                } else {
+                    PacketBoundary::Continuing
+                },
+                broadcast: BroadcastFlag::PointToPoint,
+                data_total_length: chunk.len() as u16,
+            };
+            (header, chunk.to_vec())
+        })
+        .collect()
+}
+
+/// Collects ACL fragments per connection until the L2CAP PDU is complete
+#[derive(Default)]
+pub struct Recombination {
+    pending: BTreeMap<u16, Vec<u8>>,
+}
+
+impl Recombination {
+    /// Returns the whole L2CAP PDU once its last fragment arrived
+    pub fn push(&mut self, header: &ACLHeader, payload: &[u8]) -> Result<Option<Vec<u8>>> {
+        let pdu = match header.packet_boundary {
+            PacketBoundary::FirstNonFlushable | PacketBoundary::FirstFlushable => {
+                if self.pending.remove(&header.handle).is_some() {
+                    log::warn!("drop incomplete l2cap pdu on {}", header.handle);
+                }
+                payload.to_vec()
+            }
+            PacketBoundary::Continuing => {
+                let mut pdu = self
+                    .pending
+                    .remove(&header.handle)
+                    .ok_or(Error::InvalidParameter)?;
+                pdu.extend_from_slice(payload);
+                pdu
+            }
+        };
+
+        if pdu.len() < L2CAP_HEADER_SIZE {
+            self.pending.insert(header.handle, pdu);
+            return Ok(None);
+        }
+        let length = u16::from_le_bytes([pdu[0], pdu[1]]) as usize + L2CAP_HEADER_SIZE;
+        if pdu.len() < length {
+            self.pending.insert(header.handle, pdu);
+            return Ok(None);
+        }
+        if pdu.len() > length {
+            return Err(Error::InvalidParameter);
+        }
+        Ok(Some(pdu))
+    }
+
+    pub fn remove(&mut self, handle: u16) {
+        self.pending.remove(&handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An L2CAP PDU with `len` payload bytes on the ATT channel
+    fn pdu(len: usize) -> Vec<u8> {
+        let mut pdu = Vec::new();
+        pdu.extend((len as u16).to_le_bytes());
+        pdu.extend(L2CAP_CID_ATT.to_le_bytes());
+        pdu.extend((0..len).map(|i| i as u8));
+        pdu
+    }
+
+    fn header(packet_boundary: PacketBoundary, len: usize) -> ACLHeader {
+        ACLHeader {
+            handle: 0x0040,
+            packet_boundary,
+            broadcast: BroadcastFlag::PointToPoint,
+            data_total_length: len as u16,
+        }
+    }
+
+    #[test]
+    fn fragment_exact_multiple() {
+        let pdu = pdu(12);
+        let fragments = fragment(0x0040, &pdu, 8, PacketBoundary::FirstFlushable);
+        assert_eq!(fragments.len(), 2);
+        assert_eq!(
+            fragments[0].0.packet_boundary,
+            PacketBoundary::FirstFlushable
+        );
+        assert_eq!(fragments[1].0.packet_boundary, PacketBoundary::Continuing);
+        assert!(fragments
+            .iter()
+            .all(|(h, p)| h.data_total_length == 8 && p.len() == 8));
+
+        let mut recombination = Recombination::default();
+        assert_eq!(
+            recombination.push(&fragments[0].0, &fragments[0].1),
+            Ok(None)
+        );
+        assert_eq!(
+            recombination.push(&fragments[1].0, &fragments[1].1),
+            Ok(Some(pdu))
+        );
+    }
+
+    #[test]
+    fn fragment_keeps_short_pdu_whole() {
+        let pdu = pdu(3);
+        let fragments = fragment(0x0040, &pdu, 27, PacketBoundary::FirstNonFlushable);
+        assert_eq!(fragments.len(), 1);
+        assert_eq!(fragments[0].1, pdu);
+        assert_eq!(fragments[0].0.handle_with_flags(), 0x0040);
+    }
+
+    #[test]
+    fn continuation_without_start() {
+        let mut recombination = Recombination::default();
+        assert_eq!(
+            recombination.push(&header(PacketBoundary::Continuing, 4), &[0; 4]),
+            Err(Error::InvalidParameter)
+        );
+    }
+
+    #[test]
+    fn overlong_continuation() {
+        let pdu = pdu(6);
+        let mut recombination = Recombination::default();
+        assert_eq!(
+            recombination.push(&header(PacketBoundary::FirstFlushable, 8), &pdu[..8]),
+            Ok(None)
+        );
+        assert_eq!(
+            recombination.push(&header(PacketBoundary::Continuing, 4), &[0; 4]),
+            Err(Error::InvalidParameter)
+        );
+        // the broken PDU is gone, the next one starts clean
+        assert_eq!(
+            recombination.push(&header(PacketBoundary::FirstFlushable, 10), &pdu),
+            Ok(Some(pdu.clone()))
+        );
+    }
+
+    #[test]
+    fn start_mid_reassembly_drops_the_old_pdu() {
+        let old = pdu(20);
+        let new = pdu(2);
+        let mut recombination = Recombination::default();
+        assert_eq!(
+            recombination.push(&header(PacketBoundary::FirstFlushable, 8), &old[..8]),
+            Ok(None)
+        );
+        assert_eq!(
+            recombination.push(&header(PacketBoundary::FirstFlushable, 6), &new),
+            Ok(Some(new))
+        );
+        assert_eq!(
+            recombination.push(&header(PacketBoundary::Continuing, 16), &old[8..]),
+            Err(Error::InvalidParameter)
+        );
+    }
+}
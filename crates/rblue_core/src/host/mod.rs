@@ -1,14 +1,25 @@
+pub mod adv_data;
+pub mod adv_set;
+pub mod connection;
+pub mod crypto;
+pub mod filter_list;
 pub mod hci;
 pub mod hci_cmd;
+pub mod hci_event;
+pub mod inquiry;
+pub mod l2cap;
+pub mod periodic_sync;
+pub mod privacy;
 
 pub use crate::BDAddr;
+use crate::{Error, Result};
 use alloc::vec::Vec;
 pub use hci::HCICmd;
 
-pub use hci::LinkControl;
-// pub use hci::LinkPolicy;
 pub use hci::ControllerAndBaseband;
 pub use hci::InformationalParam;
+pub use hci::LinkControl;
+pub use hci::LinkPolicy;
 // pub use hci::StatusParam;
 // pub use hci::TestingCommand;
 pub use hci::LEController;
@@ -16,18 +27,24 @@ pub use hci::LEController;
 pub use crate::baseband::ControllerErrorCode;
 
 use bitflags::bitflags;
+use hci_cmd::RBlueFromU8Array;
 use rblue_proc_macro::EnumU8ToLeBytes;
+use rblue_proc_macro::FromBytes;
 
 type SupportedCommands = [u8; 64];
-type LMPFeatures = [u8; 8];
-type LEAdvPacket = [u8; 31];
+type LMPFeaturesBytes = [u8; 8];
+type LEAdvPacket = [u8; LE_ADV_DATA_MAX_LEN];
+
+/// Legacy advertising data and scan response size
+pub const LE_ADV_DATA_MAX_LEN: usize = 31;
 
 bitflags! {
+    /// ACL packet types of Create Connection, the EDR bits forbid their packet
     pub struct PacketType: u16 {
-        const NoUse2DH1 = 0x0001;
-        const NoUse3DH1 = 0x0002;
-        const MayUseDM1 = 0x0004;
-        const MayUseDH1 = 0x0008;
+        const NoUse2DH1 = 0x0002;
+        const NoUse3DH1 = 0x0004;
+        const MayUseDM1 = 0x0008;
+        const MayUseDH1 = 0x0010;
         const NoUse2DH3 = 0x0100;
         const NoUse3DH3 = 0x0200;
         const MayUseDM3 = 0x0400;
@@ -45,101 +62,496 @@ impl PacketType {
     }
 }
 
-// pub struct HCIConfigParam {
-//     scan_enable: ScanEnable,
-//     /// Range: 0x0012 to 0x1000; only even values are valid, unit: 0.625ms
-//     inquiry_scan_interval: u16,
-//     /// Range: 0x0011 to 0x1000(Mandatory Range: 0x0011 to Inquiry Scan Interval), unit: 0.625ms
-//     inquiry_scan_window: u16,
-//     inquiry_scan_type: ScanType,
-//     inquiry_mode: InquiryMode,
-//     /// Range: 0x0001 to 0xFFFF(Mandatory Range: 0x0016 to 0xFFFF), unit: 0.625ms
-//     page_timeout: u16,
-//     /// Range: 0x0001 to 0xB540(Mandatory Range: 0x00A0 to 0xB540), unit: 0.625ms
-//     connection_accept_timeout: u16,
-//     /// Range: 0x0012 to 0x1000; only even values are valid, unit: 0.625ms
-//     page_scan_interval: u16,
-//     /// Range: 0x0011 to 0x1000(Mandatory Range: 0x0011 to Inquiry Scan Interval), unit: 0.625ms
-//     page_scan_window: u16,
-//     page_scan_type: ScanType,
-//     voice_setting: u8,
-//     pin_type: PinType,
-//     // link_key:
-
-//     // Todo: reset counter
-//     failed_contact_counter: u16,
-//     authentication_enable: AuthenticationEnable,
-//     hold_mode_activity: HoldModeActivity,
-//     /// bit0:Role switch bit1:Hold mode bit2:Sniff mode
-//     link_policy_settings: u8,
-//     /// zero as ∞, Range: 0x0001 to 0x07FF(Mandatory Range: 0x0002 to 0x07FF), unit: 0.625ms
-//     flush_timeout: u16,
-//     /// Range: 0x00 to 0xFE
-//     num_broadcast_retransmissions: u8,
-//     /// zero or range from 0x0001 to 0xFFFF(Mandatory Range: 0x0190 to 0xFFFF)
-//     link_supervision_timeout: u16,
-//     synchronous_flow_control_enable: bool,
-//     // local_name:
-//     // EXTENDED INQUIRY RESPONSE
-//     errorneous_data_reporting: bool,
-//     class_of_device: u32,
-//     supported_commands: SupportedCommands,
-//     flow_control_mode: FlowControlMode,
-//     le_supported_host: bool,
-//     /// Range: 0x0020 to 0xFFFE; only even values are valid(Mandatory Range: 0x0020 to 0x1000), unit: 0.625ms
-//     sync_train_interval: u16,
-//     /// Range: 0x00000002 to 0x07FFFFFE; only even values are valid, unit: 0.625ms
-//     sync_train_timeout: u32,
-//     service_data: u8,
-//     secure_connections_host_support: bool,
-//     /// Range: 0x0001 to 0xFFFF, unit: 0.625ms
-//     authentication_payload_timeout: u16,
-//     /// Range: 0x0000 to 0xFFFF, unit: 0.625ms
-//     extended_page_timeout: u16,
-//     /// Range: 0x0000 to 0xFFFF
-//     extended_inquiry_length: u16,
-// }
+/// A Write_* command of the config, sent when its fields changed
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum HCIConfigWrite {
+    ScanEnable,
+    InquiryScanActivity,
+    InquiryScanType,
+    InquiryMode,
+    PageTimeout,
+    ConnectionAcceptTimeout,
+    PageScanActivity,
+    PageScanType,
+    VoiceSetting,
+    PinType,
+    AuthenticationEnable,
+    HoldModeActivity,
+    DefaultLinkPolicySettings,
+    NumBroadcastRetransmissions,
+    SynchronousFlowControlEnable,
+    DefaultErroneousDataReporting,
+    ClassOfDevice,
+    SynchronizationTrainParameters,
+    SecureConnectionsHostSupport,
+    ExtendedPageTimeout,
+    ExtendedInquiryLength,
+    /// Written per link, to every link when changed and to new links
+    AutomaticFlushTimeout,
+    LinkSupervisionTimeout,
+    AuthenticatedPayloadTimeout,
+}
 
-// impl Default for HCIConfigParam {
-//     fn default() -> Self {
-//         Self {
-//             scan_enable: ScanEnable::NoScansEnable,
-//             inquiry_scan_interval: 0x1000,
-//             inquiry_scan_window: 0x0012,
-//             inquiry_scan_type: ScanType::Standard,
-//             inquiry_mode: InquiryMode::Standard,
-//             page_timeout: 0x2000,
-//             connection_accept_timeout: 0x1F40,
-//             page_scan_interval: 0x0800,
-//             page_scan_window: 0x0012,
-//             page_scan_type: ScanType::Standard,
-//             voice_setting: 0x00,
-//             pin_type: PinType::Variable,
-//             failed_contact_counter: 0x0000,
-//             authentication_enable: AuthenticationEnable::NotRequired,
-//             hold_mode_activity: HoldModeActivity::PageScan,
-//             link_policy_settings: 0x00,
-//             flush_timeout: 0x0001,
-//             num_broadcast_retransmissions: 0x00,
-//             link_supervision_timeout: 0x7D00,
-//             synchronous_flow_control_enable: false,
-//             errorneous_data_reporting: false,
-//             class_of_device: 0x00000000,
-//             supported_commands: [0; 64],
-//             flow_control_mode: FlowControlMode::PacketBased,
-//             le_supported_host: false,
-//             sync_train_interval: 0x0080,
-//             sync_train_timeout: 0x2EE00,
-//             service_data: 0x00,
-//             secure_connections_host_support: false,
-//             authentication_payload_timeout: 0x0001,
-//             extended_page_timeout: 0x0000,
-//             extended_inquiry_length: 0x0000,
-//         }
-//     }
-// }
+pub const HCI_CONFIG_WRITES: [HCIConfigWrite; 24] = [
+    HCIConfigWrite::ScanEnable,
+    HCIConfigWrite::InquiryScanActivity,
+    HCIConfigWrite::InquiryScanType,
+    HCIConfigWrite::InquiryMode,
+    HCIConfigWrite::PageTimeout,
+    HCIConfigWrite::ConnectionAcceptTimeout,
+    HCIConfigWrite::PageScanActivity,
+    HCIConfigWrite::PageScanType,
+    HCIConfigWrite::VoiceSetting,
+    HCIConfigWrite::PinType,
+    HCIConfigWrite::AuthenticationEnable,
+    HCIConfigWrite::HoldModeActivity,
+    HCIConfigWrite::DefaultLinkPolicySettings,
+    HCIConfigWrite::NumBroadcastRetransmissions,
+    HCIConfigWrite::SynchronousFlowControlEnable,
+    HCIConfigWrite::DefaultErroneousDataReporting,
+    HCIConfigWrite::ClassOfDevice,
+    HCIConfigWrite::SynchronizationTrainParameters,
+    HCIConfigWrite::SecureConnectionsHostSupport,
+    HCIConfigWrite::ExtendedPageTimeout,
+    HCIConfigWrite::ExtendedInquiryLength,
+    HCIConfigWrite::AutomaticFlushTimeout,
+    HCIConfigWrite::LinkSupervisionTimeout,
+    HCIConfigWrite::AuthenticatedPayloadTimeout,
+];
 
-#[derive(EnumU8ToLeBytes)]
+impl HCIConfigWrite {
+    fn bit(&self) -> u32 {
+        1 << *self as u8
+    }
+}
+
+/// BR/EDR settings of the controller. The defaults are the controller defaults after a
+/// reset, only the changed settings are written, after the init and whenever changed again
+#[derive(Clone, Debug)]
+pub struct HCIConfigParam {
+    scan_enable: ScanEnable,
+    /// Range: 0x0012 to 0x1000; only even values are valid, unit: 0.625ms
+    inquiry_scan_interval: u16,
+    /// Range: 0x0011 to 0x1000(Mandatory Range: 0x0011 to Inquiry Scan Interval), unit: 0.625ms
+    inquiry_scan_window: u16,
+    inquiry_scan_type: ScanType,
+    /// Lowered to what the controller supports
+    inquiry_mode: InquiryMode,
+    /// Range: 0x0001 to 0xFFFF(Mandatory Range: 0x0016 to 0xFFFF), unit: 0.625ms
+    page_timeout: u16,
+    /// Range: 0x0001 to 0xB540(Mandatory Range: 0x00A0 to 0xB540), unit: 0.625ms
+    connection_accept_timeout: u16,
+    /// Range: 0x0012 to 0x1000; only even values are valid, unit: 0.625ms
+    page_scan_interval: u16,
+    /// Range: 0x0011 to 0x1000(Mandatory Range: 0x0011 to Page Scan Interval), unit: 0.625ms
+    page_scan_window: u16,
+    page_scan_type: ScanType,
+    /// 10 bits: input coding, data format, sample size, linear PCM bit position, air coding
+    voice_setting: u16,
+    pin_type: PinType,
+    authentication_enable: AuthenticationEnable,
+    hold_mode_activity: HoldModeActivity,
+    link_policy_settings: LinkPolicySettings,
+    /// zero as ∞, Range: 0x0001 to 0x07FF(Mandatory Range: 0x0002 to 0x07FF), unit: 0.625ms
+    flush_timeout: u16,
+    /// Range: 0x00 to 0xFE
+    num_broadcast_retransmissions: u8,
+    /// zero or range from 0x0001 to 0xFFFF(Mandatory Range: 0x0190 to 0xFFFF), unit: 0.625ms
+    link_supervision_timeout: u16,
+    synchronous_flow_control_enable: bool,
+    erroneous_data_reporting: bool,
+    /// 24 bits: major service classes, major and minor device class
+    class_of_device: u32,
+    /// Range: 0x0020 to 0xFFFE; only even values are valid(Mandatory Range: 0x0020 to 0x1000), unit: 0.625ms
+    sync_train_interval: u16,
+    /// Range: 0x00000002 to 0x07FFFFFE; only even values are valid, unit: 0.625ms
+    sync_train_timeout: u32,
+    service_data: u8,
+    secure_connections_host_support: bool,
+    /// Range: 0x0001 to 0xFFFF, unit: 10ms
+    authenticated_payload_timeout: u16,
+    /// Range: 0x0000 to 0xFFFF, unit: 0.625ms
+    extended_page_timeout: u16,
+    /// Range: 0x0000 to 0xFFFF, unit: 0.625ms
+    extended_inquiry_length: u16,
+
+    /// Bits of the writes changed since the defaults, written again after a reset
+    modified: u32,
+    /// Bits of the writes the controller has not seen yet
+    pending: u32,
+    /// Bits of the writes the controller rejected, their values are not in effect
+    failed: u32,
+}
+
+impl Default for HCIConfigParam {
+    fn default() -> Self {
+        Self {
+            scan_enable: ScanEnable::NoScansEnable,
+            inquiry_scan_interval: 0x1000,
+            inquiry_scan_window: 0x0012,
+            inquiry_scan_type: ScanType::Standard,
+            inquiry_mode: InquiryMode::WithRSSIAndExtended,
+            page_timeout: 0x2000,
+            connection_accept_timeout: 0x1F40,
+            page_scan_interval: 0x0800,
+            page_scan_window: 0x0012,
+            page_scan_type: ScanType::Standard,
+            voice_setting: 0x0060,
+            pin_type: PinType::Variable,
+            authentication_enable: AuthenticationEnable::NotRequired,
+            hold_mode_activity: HoldModeActivity::empty(),
+            link_policy_settings: LinkPolicySettings::empty(),
+            flush_timeout: 0x0000,
+            num_broadcast_retransmissions: 0x00,
+            link_supervision_timeout: 0x7D00,
+            synchronous_flow_control_enable: false,
+            erroneous_data_reporting: false,
+            class_of_device: 0x00000000,
+            sync_train_interval: 0x0080,
+            sync_train_timeout: 0x2EE00,
+            service_data: 0x00,
+            secure_connections_host_support: false,
+            authenticated_payload_timeout: 0x0BB8,
+            extended_page_timeout: 0x0000,
+            extended_inquiry_length: 0x0000,
+            modified: 0,
+            // the best inquiry mode is picked unless set
+            pending: HCIConfigWrite::InquiryMode.bit(),
+            failed: 0,
+        }
+    }
+}
+
+/// Scan interval and window of the inquiry and page scan activities
+fn is_valid_scan_activity(interval: u16, window: u16) -> bool {
+    (0x0012..=0x1000).contains(&interval)
+        && interval.is_multiple_of(2)
+        && (0x0011..=interval).contains(&window)
+}
+
+impl HCIConfigParam {
+    fn changed(&mut self, write: HCIConfigWrite) {
+        self.modified |= write.bit();
+        self.pending |= write.bit();
+        self.failed &= !write.bit();
+    }
+
+    /// The writes the controller has not seen yet, they count as sent afterwards
+    pub(crate) fn take_pending(&mut self) -> Vec<HCIConfigWrite> {
+        let pending = core::mem::take(&mut self.pending);
+        HCI_CONFIG_WRITES
+            .into_iter()
+            .filter(|write| pending & write.bit() != 0)
+            .collect()
+    }
+
+    /// The controller is back at its defaults after a reset
+    pub(crate) fn reset(&mut self) {
+        self.pending = self.modified | HCIConfigWrite::InquiryMode.bit();
+        self.failed = 0;
+    }
+
+    /// The controller rejected `write`, it keeps its previous value until the next change
+    pub(crate) fn write_failed(&mut self, write: HCIConfigWrite) {
+        self.failed |= write.bit();
+    }
+
+    /// Whether the controller rejected the last write of `write`
+    pub fn is_failed(&self, write: HCIConfigWrite) -> bool {
+        self.failed & write.bit() != 0
+    }
+
+    /// Whether the setting of `write` was changed since the defaults
+    pub fn is_modified(&self, write: HCIConfigWrite) -> bool {
+        self.modified & write.bit() != 0
+    }
+
+    pub fn scan_enable(&self) -> ScanEnable {
+        self.scan_enable
+    }
+
+    pub fn set_scan_enable(&mut self, scan_enable: ScanEnable) {
+        self.scan_enable = scan_enable;
+        self.changed(HCIConfigWrite::ScanEnable);
+    }
+
+    /// Interval and window
+    pub fn inquiry_scan_activity(&self) -> (u16, u16) {
+        (self.inquiry_scan_interval, self.inquiry_scan_window)
+    }
+
+    pub fn set_inquiry_scan_activity(&mut self, interval: u16, window: u16) -> Result<()> {
+        if !is_valid_scan_activity(interval, window) {
+            return Err(Error::InvalidParameter);
+        }
+        self.inquiry_scan_interval = interval;
+        self.inquiry_scan_window = window;
+        self.changed(HCIConfigWrite::InquiryScanActivity);
+        Ok(())
+    }
+
+    pub fn inquiry_scan_type(&self) -> ScanType {
+        self.inquiry_scan_type
+    }
+
+    pub fn set_inquiry_scan_type(&mut self, scan_type: ScanType) {
+        self.inquiry_scan_type = scan_type;
+        self.changed(HCIConfigWrite::InquiryScanType);
+    }
+
+    pub fn inquiry_mode(&self) -> InquiryMode {
+        self.inquiry_mode
+    }
+
+    pub fn set_inquiry_mode(&mut self, inquiry_mode: InquiryMode) {
+        self.inquiry_mode = inquiry_mode;
+        self.changed(HCIConfigWrite::InquiryMode);
+    }
+
+    pub fn page_timeout(&self) -> u16 {
+        self.page_timeout
+    }
+
+    pub fn set_page_timeout(&mut self, page_timeout: u16) -> Result<()> {
+        if page_timeout == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        self.page_timeout = page_timeout;
+        self.changed(HCIConfigWrite::PageTimeout);
+        Ok(())
+    }
+
+    pub fn connection_accept_timeout(&self) -> u16 {
+        self.connection_accept_timeout
+    }
+
+    pub fn set_connection_accept_timeout(&mut self, timeout: u16) -> Result<()> {
+        if !(0x0001..=0xB540).contains(&timeout) {
+            return Err(Error::InvalidParameter);
+        }
+        self.connection_accept_timeout = timeout;
+        self.changed(HCIConfigWrite::ConnectionAcceptTimeout);
+        Ok(())
+    }
+
+    /// Interval and window
+    pub fn page_scan_activity(&self) -> (u16, u16) {
+        (self.page_scan_interval, self.page_scan_window)
+    }
+
+    pub fn set_page_scan_activity(&mut self, interval: u16, window: u16) -> Result<()> {
+        if !is_valid_scan_activity(interval, window) {
+            return Err(Error::InvalidParameter);
+        }
+        self.page_scan_interval = interval;
+        self.page_scan_window = window;
+        self.changed(HCIConfigWrite::PageScanActivity);
+        Ok(())
+    }
+
+    pub fn page_scan_type(&self) -> ScanType {
+        self.page_scan_type
+    }
+
+    pub fn set_page_scan_type(&mut self, scan_type: ScanType) {
+        self.page_scan_type = scan_type;
+        self.changed(HCIConfigWrite::PageScanType);
+    }
+
+    pub fn voice_setting(&self) -> u16 {
+        self.voice_setting
+    }
+
+    pub fn set_voice_setting(&mut self, voice_setting: u16) -> Result<()> {
+        if voice_setting > 0x03FF {
+            return Err(Error::InvalidParameter);
+        }
+        self.voice_setting = voice_setting;
+        self.changed(HCIConfigWrite::VoiceSetting);
+        Ok(())
+    }
+
+    pub fn pin_type(&self) -> PinType {
+        self.pin_type
+    }
+
+    pub fn set_pin_type(&mut self, pin_type: PinType) {
+        self.pin_type = pin_type;
+        self.changed(HCIConfigWrite::PinType);
+    }
+
+    pub fn authentication_enable(&self) -> AuthenticationEnable {
+        self.authentication_enable
+    }
+
+    pub fn set_authentication_enable(&mut self, enable: AuthenticationEnable) {
+        self.authentication_enable = enable;
+        self.changed(HCIConfigWrite::AuthenticationEnable);
+    }
+
+    pub fn hold_mode_activity(&self) -> HoldModeActivity {
+        self.hold_mode_activity
+    }
+
+    pub fn set_hold_mode_activity(&mut self, activity: HoldModeActivity) {
+        self.hold_mode_activity = activity;
+        self.changed(HCIConfigWrite::HoldModeActivity);
+    }
+
+    pub fn link_policy_settings(&self) -> LinkPolicySettings {
+        self.link_policy_settings
+    }
+
+    /// Used by the links created from now on
+    pub fn set_link_policy_settings(&mut self, settings: LinkPolicySettings) {
+        self.link_policy_settings = settings;
+        self.changed(HCIConfigWrite::DefaultLinkPolicySettings);
+    }
+
+    pub fn flush_timeout(&self) -> u16 {
+        self.flush_timeout
+    }
+
+    pub fn set_flush_timeout(&mut self, flush_timeout: u16) -> Result<()> {
+        if flush_timeout > 0x07FF {
+            return Err(Error::InvalidParameter);
+        }
+        self.flush_timeout = flush_timeout;
+        self.changed(HCIConfigWrite::AutomaticFlushTimeout);
+        Ok(())
+    }
+
+    pub fn num_broadcast_retransmissions(&self) -> u8 {
+        self.num_broadcast_retransmissions
+    }
+
+    pub fn set_num_broadcast_retransmissions(&mut self, num: u8) -> Result<()> {
+        if num > 0xFE {
+            return Err(Error::InvalidParameter);
+        }
+        self.num_broadcast_retransmissions = num;
+        self.changed(HCIConfigWrite::NumBroadcastRetransmissions);
+        Ok(())
+    }
+
+    pub fn link_supervision_timeout(&self) -> u16 {
+        self.link_supervision_timeout
+    }
+
+    /// Only the central of a link can write it
+    pub fn set_link_supervision_timeout(&mut self, timeout: u16) -> Result<()> {
+        if timeout == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        self.link_supervision_timeout = timeout;
+        self.changed(HCIConfigWrite::LinkSupervisionTimeout);
+        Ok(())
+    }
+
+    pub fn synchronous_flow_control_enable(&self) -> bool {
+        self.synchronous_flow_control_enable
+    }
+
+    pub fn set_synchronous_flow_control_enable(&mut self, enable: bool) {
+        self.synchronous_flow_control_enable = enable;
+        self.changed(HCIConfigWrite::SynchronousFlowControlEnable);
+    }
+
+    pub fn erroneous_data_reporting(&self) -> bool {
+        self.erroneous_data_reporting
+    }
+
+    pub fn set_erroneous_data_reporting(&mut self, enable: bool) {
+        self.erroneous_data_reporting = enable;
+        self.changed(HCIConfigWrite::DefaultErroneousDataReporting);
+    }
+
+    pub fn class_of_device(&self) -> u32 {
+        self.class_of_device
+    }
+
+    pub fn set_class_of_device(&mut self, class_of_device: u32) -> Result<()> {
+        if class_of_device > 0xFF_FFFF {
+            return Err(Error::InvalidParameter);
+        }
+        self.class_of_device = class_of_device;
+        self.changed(HCIConfigWrite::ClassOfDevice);
+        Ok(())
+    }
+
+    /// Interval, timeout and service data
+    pub fn sync_train_params(&self) -> (u16, u32, u8) {
+        (
+            self.sync_train_interval,
+            self.sync_train_timeout,
+            self.service_data,
+        )
+    }
+
+    pub fn set_sync_train_params(
+        &mut self,
+        interval: u16,
+        timeout: u32,
+        service_data: u8,
+    ) -> Result<()> {
+        if !(0x0020..=0xFFFE).contains(&interval)
+            || !interval.is_multiple_of(2)
+            || !(0x0000_0002..=0x07FF_FFFE).contains(&timeout)
+            || !timeout.is_multiple_of(2)
+        {
+            return Err(Error::InvalidParameter);
+        }
+        self.sync_train_interval = interval;
+        self.sync_train_timeout = timeout;
+        self.service_data = service_data;
+        self.changed(HCIConfigWrite::SynchronizationTrainParameters);
+        Ok(())
+    }
+
+    pub fn secure_connections_host_support(&self) -> bool {
+        self.secure_connections_host_support
+    }
+
+    pub fn set_secure_connections_host_support(&mut self, support: bool) {
+        self.secure_connections_host_support = support;
+        self.changed(HCIConfigWrite::SecureConnectionsHostSupport);
+    }
+
+    pub fn authenticated_payload_timeout(&self) -> u16 {
+        self.authenticated_payload_timeout
+    }
+
+    /// Written to BR/EDR and LE links
+    pub fn set_authenticated_payload_timeout(&mut self, timeout: u16) -> Result<()> {
+        if timeout == 0 {
+            return Err(Error::InvalidParameter);
+        }
+        self.authenticated_payload_timeout = timeout;
+        self.changed(HCIConfigWrite::AuthenticatedPayloadTimeout);
+        Ok(())
+    }
+
+    pub fn extended_page_timeout(&self) -> u16 {
+        self.extended_page_timeout
+    }
+
+    pub fn set_extended_page_timeout(&mut self, timeout: u16) {
+        self.extended_page_timeout = timeout;
+        self.changed(HCIConfigWrite::ExtendedPageTimeout);
+    }
+
+    pub fn extended_inquiry_length(&self) -> u16 {
+        self.extended_inquiry_length
+    }
+
+    pub fn set_extended_inquiry_length(&mut self, length: u16) {
+        self.extended_inquiry_length = length;
+        self.changed(HCIConfigWrite::ExtendedInquiryLength);
+    }
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum PageScanRepetitionMode {
     R0 = 0,
@@ -147,6 +559,7 @@ pub enum PageScanRepetitionMode {
     R2,
 }
 
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum ScanEnable {
     NoScansEnable,
@@ -155,7 +568,7 @@ pub enum ScanEnable {
     InquiryEnablePageEnable,
 }
 
-#[derive(EnumU8ToLeBytes, Clone)]
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum LEAddressType {
     PublicDevice,
@@ -164,7 +577,7 @@ pub enum LEAddressType {
     RandomIdentity,
 }
 
-#[derive(EnumU8ToLeBytes, Clone)]
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum LEAddressType2 {
     PublicDeviceOrPublicIdentity,
@@ -182,7 +595,7 @@ impl From<LEAddressType> for LEAddressType2 {
     }
 }
 
-#[derive(EnumU8ToLeBytes, Clone)]
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, PartialEq)]
 #[repr(u8)]
 pub enum AdvertisingType {
     ConnectableAndScannnable,
@@ -192,7 +605,7 @@ pub enum AdvertisingType {
     ConnectableLowDuty,
 }
 
-#[derive(EnumU8ToLeBytes, Clone)]
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, PartialEq)]
 #[repr(u8)]
 pub enum AdvertisingFilterPolicy {
     UnFilter,
@@ -201,7 +614,90 @@ pub enum AdvertisingFilterPolicy {
     FilterBoth,
 }
 
-#[derive(EnumU8ToLeBytes)]
+impl AdvertisingType {
+    /// The legacy PDU sent through the extended advertising commands
+    pub fn event_properties(&self) -> AdvertisingEventProperties {
+        use AdvertisingEventProperties as P;
+        let properties = match self {
+            AdvertisingType::ConnectableAndScannnable => P::Connectable | P::Scannable,
+            AdvertisingType::ConnectableHighDuty => {
+                P::Connectable | P::Directed | P::HighDutyCycleDirected
+            }
+            AdvertisingType::Scannable => P::Scannable,
+            AdvertisingType::NonConnectable => P::empty(),
+            AdvertisingType::ConnectableLowDuty => P::Connectable | P::Directed,
+        };
+        properties | P::Legacy
+    }
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum LEPhy {
+    LE1M = 0x01,
+    LE2M,
+    LECoded,
+}
+
+bitflags! {
+    /// PHY preferences, empty for no preference
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct LEPhys: u8 {
+        const LE1M = 1 << 0;
+        const LE2M = 1 << 1;
+        const LECoded = 1 << 2;
+    }
+}
+
+/// Coding the host prefers when transmitting on the LE Coded PHY
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[repr(u16)]
+pub enum LECodedPHYOptions {
+    NoPreference,
+    S2,
+    S8,
+}
+
+/// Which part of the data an extended advertising data command carries
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum AdvertisingDataOperation {
+    Intermediate,
+    First,
+    Last,
+    Complete,
+    /// Only refresh the Advertising DID
+    Unchanged,
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum PeriodicAdvertisingDataStatus {
+    Complete,
+    /// More reports with the rest of the data follow
+    Incomplete,
+    /// The controller dropped the rest of the data
+    Truncated,
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum LEScanType {
+    /// No scan request is sent
+    Passive,
+    Active,
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum ScanningFilterPolicy {
+    BasicUnfiltered,
+    BasicFiltered,
+    ExtendedUnfiltered,
+    ExtendedFiltered,
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum ScanType {
     /// Mandatory Range
@@ -210,7 +706,7 @@ pub enum ScanType {
     Interlaced,
 }
 
-#[derive(EnumU8ToLeBytes)]
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum InquiryMode {
     Standard,
@@ -218,26 +714,38 @@ pub enum InquiryMode {
     WithRSSIAndExtended,
 }
 
-#[derive(EnumU8ToLeBytes)]
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum PinType {
     Variable,
     Fixed,
 }
 
-#[derive(EnumU8ToLeBytes)]
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum AuthenticationEnable {
     NotRequired,
     Required,
 }
 
-#[derive(EnumU8ToLeBytes)]
-#[repr(u8)]
-pub enum HoldModeActivity {
-    PageScan,
-    InquiryScan,
-    PeriodicInquiries,
+bitflags! {
+    /// Activities suspended in hold mode, empty keeps the current power state
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct HoldModeActivity: u8 {
+        const SuspendPageScan = 1 << 0;
+        const SuspendInquiryScan = 1 << 1;
+        const SuspendPeriodicInquiries = 1 << 2;
+    }
+}
+
+bitflags! {
+    /// Link modes the link manager may enter
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct LinkPolicySettings: u16 {
+        const RoleSwitch = 1 << 0;
+        const HoldMode = 1 << 1;
+        const SniffMode = 1 << 2;
+    }
 }
 
 #[derive(EnumU8ToLeBytes)]
@@ -247,7 +755,7 @@ pub enum FlowControlMode {
     DataBlockBased,
 }
 
-#[derive(EnumU8ToLeBytes)]
+#[derive(EnumU8ToLeBytes, Clone, Copy, Debug, PartialEq)]
 #[repr(u8)]
 pub enum BDAddrType {
     LEPublic,
@@ -255,6 +763,53 @@ pub enum BDAddrType {
     Classic,
 }
 
+impl From<LEAddressType> for BDAddrType {
+    fn from(value: LEAddressType) -> Self {
+        match value {
+            LEAddressType::PublicDevice | LEAddressType::PublicIdentity => BDAddrType::LEPublic,
+            LEAddressType::RandomDevice | LEAddressType::RandomIdentity => BDAddrType::LERandom,
+        }
+    }
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum Role {
+    Central,
+    Peripheral,
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum LinkType {
+    SCO,
+    ACL,
+    ESCO,
+}
+
+#[derive(EnumU8ToLeBytes, FromBytes, Clone, Copy, Debug, PartialEq)]
+#[repr(u8)]
+pub enum AdvertisingReportType {
+    ConnectableAndScannable,
+    ConnectableDirected,
+    Scannable,
+    NonConnectable,
+    ScanResponse,
+}
+
+bitflags! {
+    /// Event type of an extended advertising report, the data status bits are kept apart
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct ExtendedAdvertisingReportType: u16 {
+        const Connectable = 1 << 0;
+        const Scannable = 1 << 1;
+        const Directed = 1 << 2;
+        const ScanResponse = 1 << 3;
+        /// Legacy PDUs, reported as `LEAdvertisingReport`
+        const Legacy = 1 << 4;
+    }
+}
+
 bitflags! {
     #[derive(PartialEq)]
     pub struct LEAdvertisementsState: u8 {
@@ -264,6 +819,30 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(PartialEq)]
+    pub struct LEScanningState: u8 {
+        const Idle = 0;
+        const Active = 0x01;
+        const Enabled = 0x02;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub struct AdvertisingEventProperties: u16 {
+        const Connectable = 1 << 0;
+        const Scannable = 1 << 1;
+        const Directed = 1 << 2;
+        const HighDutyCycleDirected = 1 << 3;
+        /// Legacy PDUs, the data is limited to `LE_ADV_DATA_MAX_LEN`
+        const Legacy = 1 << 4;
+        /// Omit the advertiser address
+        const Anonymous = 1 << 5;
+        const IncludeTxPower = 1 << 6;
+    }
+}
+
 bitflags! {
     #[derive(PartialEq)]
     pub struct LEAdvertisementsTodo: u16 {
@@ -280,6 +859,157 @@ bitflags! {
     }
 }
 
+bitflags! {
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct LMPFeatures: u64 {
+        const ThreeSlotPackets = 1 << 0;
+        const FiveSlotPackets = 1 << 1;
+        const Encryption = 1 << 2;
+        const RoleSwitch = 1 << 5;
+        const HoldMode = 1 << 6;
+        const SniffMode = 1 << 7;
+        const EDRACL2MbpsMode = 1 << 25;
+        const EDRACL3MbpsMode = 1 << 26;
+        const InterlacedInquiryScan = 1 << 28;
+        const InterlacedPageScan = 1 << 29;
+        const RSSIWithInquiryResults = 1 << 30;
+        const BREDRNotSupported = 1 << 37;
+        const LESupportedController = 1 << 38;
+        const ThreeSlotEDRACLPackets = 1 << 39;
+        const FiveSlotEDRACLPackets = 1 << 40;
+        const ExtendedInquiryResponse = 1 << 48;
+        const SimultaneousLEAndBREDRController = 1 << 49;
+        const SecureSimplePairingController = 1 << 51;
+        const ExtendedFeatures = 1 << 63;
+    }
+}
+
+bitflags! {
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct LEFeatures: u64 {
+        const LEEncryption = 1 << 0;
+        const ConnectionParametersRequestProcedure = 1 << 1;
+        const ExtendedRejectIndication = 1 << 2;
+        const PeripheralInitiatedFeaturesExchange = 1 << 3;
+        const LEPing = 1 << 4;
+        const LEDataPacketLengthExtension = 1 << 5;
+        const LLPrivacy = 1 << 6;
+        const ExtendedScanningFilterPolicies = 1 << 7;
+        const LE2MPHY = 1 << 8;
+        const StableModulationIndexTransmitter = 1 << 9;
+        const StableModulationIndexReceiver = 1 << 10;
+        const LECodedPHY = 1 << 11;
+        const LEExtendedAdvertising = 1 << 12;
+        const LEPeriodicAdvertising = 1 << 13;
+        const ChannelSelectionAlgorithm2 = 1 << 14;
+        const LEPowerClass1 = 1 << 15;
+        const MinimumNumberOfUsedChannelsProcedure = 1 << 16;
+    }
+}
+
+bitflags! {
+    /// Bit n enables the event with code n + 1,
+    /// Command Complete, Command Status and Number Of Completed Packets are never masked
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct EventMask: u64 {
+        const InquiryComplete = 1 << 0;
+        const InquiryResult = 1 << 1;
+        const ConnectionComplete = 1 << 2;
+        const ConnectionRequest = 1 << 3;
+        const DisconnectionComplete = 1 << 4;
+        const AuthenticationComplete = 1 << 5;
+        const RemoteNameRequestComplete = 1 << 6;
+        const EncryptionChange = 1 << 7;
+        const ChangeConnectionLinkKeyComplete = 1 << 8;
+        const LinkKeyTypeChanged = 1 << 9;
+        const ReadRemoteSupportedFeaturesComplete = 1 << 10;
+        const ReadRemoteVersionInformationComplete = 1 << 11;
+        const QoSSetupComplete = 1 << 12;
+        const HardwareError = 1 << 15;
+        const FlushOccurred = 1 << 16;
+        const RoleChange = 1 << 17;
+        const ModeChange = 1 << 19;
+        const ReturnLinkKeys = 1 << 20;
+        const PINCodeRequest = 1 << 21;
+        const LinkKeyRequest = 1 << 22;
+        const LinkKeyNotification = 1 << 23;
+        const LoopbackCommand = 1 << 24;
+        const DataBufferOverflow = 1 << 25;
+        const MaxSlotsChange = 1 << 26;
+        const ReadClockOffsetComplete = 1 << 27;
+        const ConnectionPacketTypeChanged = 1 << 28;
+        const QoSViolation = 1 << 29;
+        const PageScanRepetitionModeChange = 1 << 31;
+        const FlowSpecificationComplete = 1 << 32;
+        const InquiryResultWithRSSI = 1 << 33;
+        const ReadRemoteExtendedFeaturesComplete = 1 << 34;
+        const SynchronousConnectionComplete = 1 << 43;
+        const SynchronousConnectionChanged = 1 << 44;
+        const SniffSubrating = 1 << 45;
+        const ExtendedInquiryResult = 1 << 46;
+        const EncryptionKeyRefreshComplete = 1 << 47;
+        const IOCapabilityRequest = 1 << 48;
+        const IOCapabilityResponse = 1 << 49;
+        const UserConfirmationRequest = 1 << 50;
+        const UserPasskeyRequest = 1 << 51;
+        const RemoteOOBDataRequest = 1 << 52;
+        const SimplePairingComplete = 1 << 53;
+        const LinkSupervisionTimeoutChanged = 1 << 55;
+        const EnhancedFlushComplete = 1 << 56;
+        const UserPasskeyNotification = 1 << 58;
+        const KeypressNotification = 1 << 59;
+        const RemoteHostSupportedFeaturesNotification = 1 << 60;
+        const LEMeta = 1 << 61;
+    }
+}
+
+impl EventMask {
+    /// Controller default after reset
+    pub const DEFAULT: Self = Self::from_bits_retain(0x0000_1FFF_FFFF_FFFF);
+}
+
+bitflags! {
+    /// Bit n enables the LE Meta subevent with code n + 1
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct LEEventMask: u64 {
+        const ConnectionComplete = 1 << 0;
+        const AdvertisingReport = 1 << 1;
+        const ConnectionUpdateComplete = 1 << 2;
+        const ReadRemoteFeaturesComplete = 1 << 3;
+        const LongTermKeyRequest = 1 << 4;
+        const RemoteConnectionParameterRequest = 1 << 5;
+        const DataLengthChange = 1 << 6;
+        const ReadLocalP256PublicKeyComplete = 1 << 7;
+        const GenerateDHKeyComplete = 1 << 8;
+        const EnhancedConnectionComplete = 1 << 9;
+        const DirectedAdvertisingReport = 1 << 10;
+        const PHYUpdateComplete = 1 << 11;
+        const ExtendedAdvertisingReport = 1 << 12;
+        const PeriodicAdvertisingSyncEstablished = 1 << 13;
+        const PeriodicAdvertisingReport = 1 << 14;
+        const PeriodicAdvertisingSyncLost = 1 << 15;
+        const ScanTimeout = 1 << 16;
+        const AdvertisingSetTerminated = 1 << 17;
+        const ScanRequestReceived = 1 << 18;
+        const ChannelSelectionAlgorithm = 1 << 19;
+    }
+}
+
+impl LEEventMask {
+    /// Controller default after reset
+    pub const DEFAULT: Self = Self::from_bits_retain(0x1F);
+}
+
+bitflags! {
+    /// Parts of the stack the app uses, the controller only reports their events
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    pub struct HostFeatures: u32 {
+        const Connections = 1 << 0;
+        const Scanning = 1 << 1;
+        const Encryption = 1 << 2;
+    }
+}
+
 use crate::host::hci::HCI;
 pub trait HCICmdSend {
     fn send(&self, hci: &mut HCI);
@@ -289,8 +1019,141 @@ pub trait HCICmdSend {
 //     fn discriminant(&self) -> T;
 // }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum HCIPowerMode {
     Off,
     On,
     Sleep,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `set` accepts `min` and `max` and rejects `invalid` without marking `write`
+    fn check_range<T: Copy>(
+        write: HCIConfigWrite,
+        set: fn(&mut HCIConfigParam, T) -> Result<()>,
+        min: T,
+        max: T,
+        invalid: &[T],
+    ) {
+        for value in invalid {
+            let mut config = HCIConfigParam::default();
+            assert_eq!(set(&mut config, *value), Err(Error::InvalidParameter));
+            assert!(!config.is_modified(write));
+        }
+        let mut config = HCIConfigParam::default();
+        assert_eq!(set(&mut config, min), Ok(()));
+        assert_eq!(set(&mut config, max), Ok(()));
+        assert!(config.is_modified(write));
+    }
+
+    #[test]
+    fn scan_activity_range() {
+        type Activity = (u16, u16);
+        let invalid: &[Activity] = &[
+            (0x0010, 0x0010),
+            (0x1002, 0x0011),
+            (0x0013, 0x0011),
+            (0x0020, 0x0021),
+            (0x0020, 0x0010),
+        ];
+        check_range(
+            HCIConfigWrite::InquiryScanActivity,
+            |c, (interval, window): Activity| c.set_inquiry_scan_activity(interval, window),
+            (0x0012, 0x0011),
+            (0x1000, 0x1000),
+            invalid,
+        );
+        check_range(
+            HCIConfigWrite::PageScanActivity,
+            |c, (interval, window): Activity| c.set_page_scan_activity(interval, window),
+            (0x0012, 0x0011),
+            (0x1000, 0x1000),
+            invalid,
+        );
+    }
+
+    #[test]
+    fn timeout_ranges() {
+        check_range(
+            HCIConfigWrite::PageTimeout,
+            HCIConfigParam::set_page_timeout,
+            0x0001,
+            0xFFFF,
+            &[0],
+        );
+        check_range(
+            HCIConfigWrite::ConnectionAcceptTimeout,
+            HCIConfigParam::set_connection_accept_timeout,
+            0x0001,
+            0xB540,
+            &[0, 0xB541],
+        );
+        check_range(
+            HCIConfigWrite::AutomaticFlushTimeout,
+            HCIConfigParam::set_flush_timeout,
+            0x0000,
+            0x07FF,
+            &[0x0800],
+        );
+        check_range(
+            HCIConfigWrite::LinkSupervisionTimeout,
+            HCIConfigParam::set_link_supervision_timeout,
+            0x0001,
+            0xFFFF,
+            &[0],
+        );
+        check_range(
+            HCIConfigWrite::AuthenticatedPayloadTimeout,
+            HCIConfigParam::set_authenticated_payload_timeout,
+            0x0001,
+            0xFFFF,
+            &[0],
+        );
+    }
+
+    #[test]
+    fn value_ranges() {
+        check_range(
+            HCIConfigWrite::VoiceSetting,
+            HCIConfigParam::set_voice_setting,
+            0x0000,
+            0x03FF,
+            &[0x0400],
+        );
+        check_range(
+            HCIConfigWrite::NumBroadcastRetransmissions,
+            HCIConfigParam::set_num_broadcast_retransmissions,
+            0x00,
+            0xFE,
+            &[0xFF],
+        );
+        check_range(
+            HCIConfigWrite::ClassOfDevice,
+            HCIConfigParam::set_class_of_device,
+            0x00_0000,
+            0xFF_FFFF,
+            &[0x100_0000],
+        );
+    }
+
+    #[test]
+    fn sync_train_params_range() {
+        type Params = (u16, u32);
+        check_range(
+            HCIConfigWrite::SynchronizationTrainParameters,
+            |c, (interval, timeout): Params| c.set_sync_train_params(interval, timeout, 0),
+            (0x0020, 0x0000_0002),
+            (0xFFFE, 0x07FF_FFFE),
+            &[
+                (0x001E, 0x0000_0002),
+                (0x0021, 0x0000_0002),
+                (0x0020, 0x0000_0000),
+                (0x0020, 0x0800_0000),
+                (0x0020, 0x0000_0003),
+            ],
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use super::*;
+
+use pub_fields::pub_fields;
+
+/// The periodic advertising train to synchronize to
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeriodicSyncTarget {
+    /// Range: 0x00 to 0x0F
+    sid: u8,
+    address_type: LEAddressType,
+    address: BDAddr,
+    /// Periodic advertising events the controller may skip, range: 0x0000 to 0x01F3
+    skip: u16,
+    /// Range: 0x000A to 0x4000, unit: 10ms
+    sync_timeout: u16,
+}
+
+impl PeriodicSyncTarget {
+    pub fn is_valid(&self) -> bool {
+        self.sid <= 0x0F && self.skip <= 0x01F3 && (0x000A..=0x4000).contains(&self.sync_timeout)
+    }
+}
+
+/// An established periodic advertising sync
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PeriodicSync {
+    sid: u8,
+    address_type: LEAddressType,
+    address: BDAddr,
+    phy: LEPhy,
+    /// Unit: 1.25ms
+    interval: u16,
+    clock_accuracy: u8,
+}
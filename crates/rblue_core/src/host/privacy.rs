@@ -0,0 +1,159 @@
+use super::crypto::aes128_encrypt;
+use super::filter_list::ResolvingListEntry;
+use super::*;
+
+use pub_fields::pub_fields;
+
+/// Default time a resolvable or non-resolvable private address is used, 15 minutes
+pub const LE_PRIVATE_ADDRESS_TIMEOUT_MS: u64 = 900_000;
+/// Range of the private address timeout, as the controller takes it: 1 s to 1 hour
+pub const LE_PRIVATE_ADDRESS_TIMEOUT_MIN_MS: u64 = 1_000;
+pub const LE_PRIVATE_ADDRESS_TIMEOUT_MAX_MS: u64 = 3_600_000;
+
+/// The address the host advertises, scans and connects with
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LEOwnAddressMode {
+    Public,
+    /// Set by the application, the two most significant bits must be 0b11
+    StaticRandom(BDAddr),
+    /// Fresh random address on every rotation, peers can not resolve it
+    NonResolvable,
+    /// Generated from the local IRK on every rotation, bonded peers resolve it
+    Resolvable,
+}
+
+impl LEOwnAddressMode {
+    pub fn is_private(&self) -> bool {
+        matches!(
+            self,
+            LEOwnAddressMode::NonResolvable | LEOwnAddressMode::Resolvable
+        )
+    }
+}
+
+/// A bonded peer whose resolvable private addresses are resolved to its identity
+#[pub_fields]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BondedDevice {
+    identity_address: BDAddr,
+    /// `PublicDevice` or `RandomDevice`
+    identity_address_type: LEAddressType,
+    /// Little endian, as distributed during pairing
+    irk: [u8; 16],
+}
+
+impl BondedDevice {
+    /// The address type a controller reports after resolving the peer
+    pub fn resolved_address_type(&self) -> LEAddressType {
+        match self.identity_address_type {
+            LEAddressType::RandomDevice | LEAddressType::RandomIdentity => {
+                LEAddressType::RandomIdentity
+            }
+            _ => LEAddressType::PublicIdentity,
+        }
+    }
+
+    pub fn resolving_list_entry(&self, local_irk: [u8; 16]) -> ResolvingListEntry {
+        ResolvingListEntry {
+            identity_address_type: LEAddressType2::from(self.identity_address_type),
+            identity_address: self.identity_address,
+            peer_irk: self.irk,
+            local_irk,
+        }
+    }
+}
+
+/// The random address hash function `ah`, `irk` is little endian, only the
+/// lower 24 bits of `prand` are used and of the hash are set
+pub fn ah(irk: &[u8; 16], prand: u32) -> u32 {
+    let mut key = *irk;
+    key.reverse();
+    let mut plaintext = [0; 16];
+    plaintext[13..].copy_from_slice(&prand.to_be_bytes()[1..]);
+    let encrypted = aes128_encrypt(&key, &plaintext);
+    u32::from_be_bytes([0, encrypted[13], encrypted[14], encrypted[15]])
+}
+
+/// Top two bits of the most significant address byte
+fn random_address_kind(address: &BDAddr) -> u8 {
+    address[5] >> 6
+}
+
+pub fn is_resolvable_private_address(address: &BDAddr) -> bool {
+    random_address_kind(address) == 0b01
+}
+
+/// The random part must not be all zeros or all ones
+pub fn is_static_random_address(address: &BDAddr) -> bool {
+    let random = u64::from_le_bytes([
+        address[0], address[1], address[2], address[3], address[4], address[5], 0, 0,
+    ]) & 0x3FFF_FFFF_FFFF;
+    random_address_kind(address) == 0b11 && random != 0 && random != 0x3FFF_FFFF_FFFF
+}
+
+/// `prand` provides the random bits, the type bits are set here.
+/// None if the random part is all zeros or all ones
+pub fn resolvable_private_address(irk: &[u8; 16], prand: u32) -> Option<BDAddr> {
+    let random = prand & 0x3F_FFFF;
+    if random == 0 || random == 0x3F_FFFF {
+        return None;
+    }
+    let prand = random | 0x40_0000;
+    let hash = ah(irk, prand).to_le_bytes();
+    let prand = prand.to_le_bytes();
+    Some([hash[0], hash[1], hash[2], prand[0], prand[1], prand[2]])
+}
+
+/// `random` provides the random bits, the type bits are cleared here.
+/// None if the random part is all zeros or all ones, or equals `public_address`
+pub fn non_resolvable_private_address(random: u64, public_address: &BDAddr) -> Option<BDAddr> {
+    let bytes = (random & 0x3FFF_FFFF_FFFF).to_le_bytes();
+    let address = [bytes[0], bytes[1], bytes[2], bytes[3], bytes[4], bytes[5]];
+    let all_ones = address[..5].iter().all(|b| *b == 0xFF) && address[5] == 0x3F;
+    if address == [0; 6] || all_ones || address == *public_address {
+        return None;
+    }
+    Some(address)
+}
+
+/// Whether `irk` generated the resolvable private address `address`
+pub fn resolve_private_address(irk: &[u8; 16], address: &BDAddr) -> bool {
+    if !is_resolvable_private_address(address) {
+        return false;
+    }
+    let hash = u32::from_le_bytes([address[0], address[1], address[2], 0]);
+    let prand = u32::from_le_bytes([address[3], address[4], address[5], 0]);
+    ah(irk, prand) == hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Core spec Vol 3, Part H, D.7, the IRK there is most significant byte first
+    const IRK: [u8; 16] = [
+        0x9b, 0x7d, 0x39, 0x0a, 0xa6, 0x10, 0x10, 0x34, 0x05, 0xad, 0xc8, 0x57, 0xa3, 0x34, 0x02,
+        0xec,
+    ];
+
+    #[test]
+    fn ah_sample_vector() {
+        assert_eq!(ah(&IRK, 0x708194), 0x0DFBAA);
+    }
+
+    #[test]
+    fn resolvable_address_round_trip() {
+        let address = resolvable_private_address(&IRK, 0x708194).unwrap();
+        assert_eq!(address, [0xAA, 0xFB, 0x0D, 0x94, 0x81, 0x70]);
+        assert!(resolve_private_address(&IRK, &address));
+        assert!(!resolve_private_address(&[0; 16], &address));
+    }
+
+    #[test]
+    fn resolvable_address_rejects_uniform_random_part() {
+        assert_eq!(resolvable_private_address(&IRK, 0), None);
+        assert_eq!(resolvable_private_address(&IRK, 0x40_0000), None);
+        assert_eq!(resolvable_private_address(&IRK, 0x3F_FFFF), None);
+        assert_eq!(resolvable_private_address(&IRK, 0xFFFF_FFFF), None);
+    }
+}
@@ -1,11 +1,14 @@
 #![no_std]
 pub mod host;
 pub mod baseband;
+pub mod error;
 
 extern crate alloc;
 
 pub type BDAddr = [u8; 6];
 
+pub use error::{Error, Result};
+
 
 #[cfg(test)]
 mod tests {
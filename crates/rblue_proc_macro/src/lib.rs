@@ -13,7 +13,14 @@ pub fn derive_to_u8_array(input: TokenStream) -> TokenStream {
         Data::Struct(data_struct) => {
             let fields = match &data_struct.fields {
                 Fields::Named(fields_named) => &fields_named.named,
-                _ => unimplemented!(),
+                fields => {
+                    return syn::Error::new_spanned(
+                        fields,
+                        "#[derive(ToU8Array)] only supports structs with named fields",
+                    )
+                    .to_compile_error()
+                    .into()
+                }
             };
 
             // let field_names = fields.iter().map(|f| &f.ident);
@@ -22,17 +29,14 @@ pub fn derive_to_u8_array(input: TokenStream) -> TokenStream {
                 let name = &f.ident;
                 let ty = &f.ty;
                 match ty {
+                    _ if is_byte_array(ty) => {
+                        quote! {
+                            array.extend(self.#name);
+                        }
+                    }
                     Type::Path(type_path) => {
                         // eprintln!("Type: {:?}", type_path.path.segments.first().unwrap().ident);
-                        if type_path.path.is_ident("BDAddr")
-                            || type_path.path.is_ident("SupportedCommands")
-                            || type_path.path.is_ident("LMPFeatures")
-                            || type_path.path.is_ident("LEAdvPacket")
-                        {
-                            quote! {
-                                array.extend(self.#name);
-                            }
-                        } else if type_path.path.is_ident("bool") {
+                        if type_path.path.is_ident("bool") {
                             quote! {
                                 array.push(self.#name as u8);
                             }
@@ -69,7 +73,14 @@ pub fn derive_to_u8_array(input: TokenStream) -> TokenStream {
                 }
             }
         }
-        _ => unimplemented!(),
+        Data::Union(data_union) => {
+            return syn::Error::new_spanned(
+                data_union.union_token,
+                "#[derive(ToU8Array)] is not defined for unions",
+            )
+            .to_compile_error()
+            .into()
+        }
     };
 
     TokenStream::from(expanded)
@@ -87,7 +98,12 @@ pub fn derive_enum_u8_to_le_bytes(input: TokenStream) -> TokenStream {
     let variants = if let Data::Enum(data_enum) = &input.data {
         &data_enum.variants
     } else {
-        panic!("#[derive(ToLeBytes)] is only defined for enums");
+        return syn::Error::new_spanned(
+            name,
+            "#[derive(EnumU8ToLeBytes)] is only defined for enums",
+        )
+        .to_compile_error()
+        .into();
     };
 
     // Generate match arms for each variant
@@ -113,7 +129,9 @@ pub fn derive_enum_u8_to_le_bytes(input: TokenStream) -> TokenStream {
     TokenStream::from(expanded)
 }
 
-#[proc_macro_derive(FromBytes)]
+/// `#[from_bytes(other)]` on a unit variant of an enum makes it the result
+/// for every value that matches no other variant.
+#[proc_macro_derive(FromBytes, attributes(from_bytes))]
 pub fn from_bytes_derive(input: TokenStream) -> TokenStream {
     // 解析输入的 Rust 代码为 AST
     let input = parse_macro_input!(input as DeriveInput);
@@ -121,48 +139,158 @@ pub fn from_bytes_derive(input: TokenStream) -> TokenStream {
 
     let expanded = match input.data {
         Data::Struct(data) => {
-            let field_parsers = match data.fields {
-                Fields::Named(ref fields) => {
-                    fields.named.iter().enumerate().map(|(i, f)| {
-                        let name = &f.ident;
-                        let ty = &f.ty;
-                        if quote!(#ty).to_string() == "bool" {
-                            quote! {
-                                #name: bytes[#i] != 0
-                            }
-                        } else {
-                            quote! {
-                                #name: <#ty>::from_le_bytes(bytes[#i..#i+core::mem::size_of::<#ty>()].try_into().ok()?)
-                            }
+            let fields = match data.fields {
+                Fields::Named(ref fields) => fields.named.iter().collect::<Vec<_>>(),
+                Fields::Unit => vec![],
+                fields => {
+                    return syn::Error::new_spanned(
+                        fields,
+                        "#[derive(FromBytes)] only supports structs with named fields",
+                    )
+                    .to_compile_error()
+                    .into()
+                }
+            };
+
+            // 字段按声明顺序紧密排列，偏移量逐个累加
+            let field_parsers = fields.iter().map(|f| {
+                let name = &f.ident;
+                let ty = &f.ty;
+                let parser = if is_byte_array(ty) {
+                    quote! {
+                        bytes[offset..offset + size]
+                            .try_into()
+                            .map_err(|_| crate::Error::InvalidParameter)?
+                    }
+                } else if quote!(#ty).to_string() == "bool" {
+                    quote! {
+                        bytes[offset] != 0
+                    }
+                } else if is_primitive(ty) {
+                    quote! {
+                        <#ty>::from_le_bytes(
+                            bytes[offset..offset + size]
+                                .try_into()
+                                .map_err(|_| crate::Error::InvalidParameter)?,
+                        )
+                    }
+                } else {
+                    quote! {
+                        <#ty as RBlueFromU8Array>::from_u8_array(&bytes[offset..offset + size])?
+                    }
+                };
+                quote! {
+                    let size = core::mem::size_of::<#ty>();
+                    let #name = #parser;
+                    offset += size;
+                }
+            });
+            let field_names = fields.iter().map(|f| &f.ident);
+            let field_types = fields.iter().map(|f| &f.ty);
+
+            quote! {
+                impl RBlueFromU8Array for #name {
+                    #[allow(unused_assignments, unused_mut, unused_variables)]
+                    fn from_u8_array(bytes: &[u8]) -> crate::Result<Self> {
+                        let total = 0 #(+ core::mem::size_of::<#field_types>())*;
+                        if bytes.len() < total {
+                            return Err(crate::Error::Truncated {
+                                expected: total,
+                                actual: bytes.len(),
+                            });
                         }
-                    }).collect::<Vec<_>>()
+                        let mut offset = 0;
+                        #(#field_parsers)*
+                        Ok(#name {
+                            #(#field_names),*
+                        })
+                    }
                 }
-                Fields::Unnamed(ref fields) => {
-                    fields.unnamed.iter().enumerate().map(|(i, f)| {
-                        let ty = &f.ty;
-                        quote! {
-                            <#ty>::from_le_bytes(bytes[#i..#i+core::mem::size_of::<#ty>()].try_into().ok()?)
+            }
+        }
+        Data::Enum(data_enum) => {
+            let mut other = None;
+            for variant in &data_enum.variants {
+                for attr in variant
+                    .attrs
+                    .iter()
+                    .filter(|a| a.path().is_ident("from_bytes"))
+                {
+                    let parsed = attr.parse_nested_meta(|meta| {
+                        if meta.path.is_ident("other") {
+                            Ok(())
+                        } else {
+                            Err(meta.error("expected `other`"))
                         }
-                    }).collect::<Vec<_>>()
+                    });
+                    if let Err(e) = parsed {
+                        return e.to_compile_error().into();
+                    }
+                    if other.is_some() {
+                        return syn::Error::new_spanned(attr, "only one variant can be `other`")
+                            .to_compile_error()
+                            .into();
+                    }
+                    other = Some(&variant.ident);
                 }
-                Fields::Unit => vec![],
+            }
+            let match_arms = data_enum.variants.iter().map(|variant| {
+                let ident = &variant.ident;
+                quote! {
+                    x if x == Self::#ident as u8 => Ok(Self::#ident),
+                }
+            });
+            let fallback = match other {
+                Some(ident) => quote! { _ => Ok(Self::#ident), },
+                None => quote! { _ => Err(crate::Error::InvalidParameter), },
             };
 
             quote! {
                 impl RBlueFromU8Array for #name {
-                    fn from_u8_array(bytes: &[u8]) -> Option<Self> {
-                        if bytes.len() != core::mem::size_of::<Self>() {
-                            return None;
+                    fn from_u8_array(bytes: &[u8]) -> crate::Result<Self> {
+                        let value = *bytes.first().ok_or(crate::Error::Truncated {
+                            expected: 1,
+                            actual: 0,
+                        })?;
+                        match value {
+                            #(#match_arms)*
+                            #fallback
                         }
-                        Some(#name {
-                            #(#field_parsers),*
-                        })
                     }
                 }
             }
         }
-        _ => unimplemented!(),
+        Data::Union(data_union) => {
+            return syn::Error::new_spanned(
+                data_union.union_token,
+                "#[derive(FromBytes)] is not defined for unions",
+            )
+            .to_compile_error()
+            .into()
+        }
     };
 
     TokenStream::from(expanded)
 }
+
+fn is_byte_array(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => {
+            type_path.path.is_ident("BDAddr")
+                || type_path.path.is_ident("SupportedCommands")
+                || type_path.path.is_ident("LMPFeaturesBytes")
+                || type_path.path.is_ident("LEAdvPacket")
+        }
+        Type::Array(_) => true,
+        _ => false,
+    }
+}
+
+fn is_primitive(ty: &Type) -> bool {
+    match ty {
+        Type::Path(type_path) => ["u8", "u16", "u32", "u64", "i8", "i16", "i32", "i64"]
+            .iter()
+            .any(|p| type_path.path.is_ident(p)),
+        _ => false,
+    }
+}